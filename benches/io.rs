@@ -0,0 +1,33 @@
+use std::time::{Duration, Instant};
+
+use verter::{Config, File};
+
+fn time<F: FnMut()>(iters: u32, mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iters {
+        f();
+    }
+    start.elapsed() / iters
+}
+
+fn main() {
+    let path = "bench_io.verter";
+    let _ = std::fs::remove_file(path);
+
+    let config = Config {
+        page_size: 4096,
+        ..Config::default()
+    };
+    let mut file = File::open(path, config).unwrap();
+    let data = vec![0xAB; 16 * 1024 * 1024];
+    let ptr = file.alloc().unwrap();
+
+    let write = time(10, || file.write(ptr, &data).unwrap());
+    let read = time(10, || assert_eq!(file.read(ptr).unwrap().len(), data.len()));
+
+    println!("write 16 MiB chain: {:?}", write);
+    println!("read 16 MiB chain:  {:?}", read);
+
+    drop(file);
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,100 @@
+use crate::{Error, File};
+
+impl File {
+
+    /// Copy a chain from another file into this one page by page, returning the pointer to the new chain.
+    /// The pages' payloads, including the chain's metadata and summary, are copied as they are and only the links between them are rewritten,
+    /// so merging projects doesn't have to decode and re-encode every chain.
+    /// Both files must have the same geometry, meaning the same page size, chain format and metadata setting, or this fails with `Error::InvalidConfig`.
+    /// Chains that `ptr` refers to are not adopted along with it, and a chain in the other file's cold tier is copied through its data instead.
+    pub fn adopt_pages(&mut self, other: &mut File, ptr: u64) -> Result<u64, Error> {
+        self.check_for_external_changes()?;
+        other.check_for_external_changes()?;
+        other.check_if_pointer_valid(ptr)?;
+        if self.config.page_size != other.config.page_size || self.config.chain_format != other.config.chain_format || self.meta_size() != other.meta_size() {
+            return Err(Error::InvalidConfig);
+        }
+        if let Some(data) = other.read_cold(ptr)? {
+            return self.alloc_with(&data);
+        }
+
+        let (src_pages, final_size) = other.chain_layout(ptr)?;
+        let page_size = self.config.page_size as u64;
+        let len = ((src_pages.len() as u64 - 1) * page_size + final_size).checked_sub(self.meta_size()).ok_or(Error::CorruptedFile)?;
+        if self.config.max_chain_size.is_some_and(|max_chain_size| len > max_chain_size) {
+            return Err(Error::LimitExceeded);
+        }
+
+        let new_ptr = self.alloc()?;
+        let mut pages = vec![new_ptr];
+        let result = (|| {
+            while pages.len() < src_pages.len() {
+                pages.push(self.alloc_page()?);
+            }
+            let mut chunk = vec![0; self.chunk_size()];
+            for (i, (src, dest)) in src_pages.iter().zip(&pages).enumerate() {
+                let used = if i + 1 == src_pages.len() { final_size } else { page_size };
+                let mut offset = 0;
+                while offset < used {
+                    let n = chunk.len().min((used - offset) as usize);
+                    other.read_at(&[*src], offset, &mut chunk[..n])?;
+                    self.write_at(&[*dest], offset, &chunk[..n])?;
+                    offset += n as u64;
+                }
+            }
+            Ok(())
+        })();
+        self.link_pages(&pages, len)?;
+        if let Err(err) = result {
+            // Free the pages again instead of leaking them
+            self.delete(new_ptr)?;
+            return Err(err);
+        }
+        if other.is_frozen(ptr)? {
+            self.freeze(new_ptr)?;
+        }
+        self.bump_change_counter()?;
+        self.hooks.write(new_ptr, len as usize);
+        Ok(new_ptr)
+    }
+
+}
+
+#[test]
+fn adopt_pages() {
+    use crate::{ChainFormat, Config};
+
+    let config = Config {
+        chain_meta: true,
+        chain_format: ChainFormat::V2 { head_checksum: true },
+        ..Config::default()
+    };
+    let mut a = File::open("adopt_pages_a.verter", config).unwrap();
+    let mut b = File::open("adopt_pages_b.verter", config).unwrap();
+    let texture = (0..5000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let ptr = b.alloc_with(&texture).unwrap();
+    let empty = b.alloc().unwrap();
+    b.freeze(empty).unwrap();
+
+    // Give the adopted pages different positions than in the other file
+    a.alloc_with(&[0; 3000]).unwrap();
+    let adopted = a.adopt_pages(&mut b, ptr).unwrap();
+    assert_eq!(a.read(adopted).unwrap(), texture);
+    assert_eq!(a.chain_summary(adopted).unwrap(), b.chain_summary(ptr).unwrap());
+    assert_eq!(a.chain_meta(adopted).unwrap().created, b.chain_meta(ptr).unwrap().created);
+
+    let adopted_empty = a.adopt_pages(&mut b, empty).unwrap();
+    assert_eq!(a.read(adopted_empty).unwrap(), Vec::<u8>::new());
+    assert!(a.is_frozen(adopted_empty).unwrap());
+    a.validate().unwrap();
+
+    let mut c = File::open("adopt_pages_c.verter", Config { page_size: 512, ..config }).unwrap();
+    match c.adopt_pages(&mut b, ptr) {
+        Err(Error::InvalidConfig) => {},
+        Ok(_) | Err(_) => panic!("should error with invalid config")
+    }
+
+    std::fs::remove_file("adopt_pages_a.verter").unwrap();
+    std::fs::remove_file("adopt_pages_b.verter").unwrap();
+    std::fs::remove_file("adopt_pages_c.verter").unwrap();
+}
@@ -0,0 +1,287 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{Error, File, PageHeader};
+
+/// How free pages are chosen when a chain needs new pages
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AllocPolicy {
+    /// Take the most recently freed page first.
+    /// The cheapest policy, but chains written after many deletions end up scattered across the file.
+    #[default]
+    FirstFree,
+    /// Take the free pages with the lowest addresses, keeping data packed towards the start of the file
+    AddressOrdered,
+    /// Take the smallest run of adjacent free pages that fits the whole allocation, so chains stay contiguous.
+    /// If no run is big enough, the longest runs are used first.
+    BestFitRun,
+    /// Split the allocation into power-of-two extents of adjacent pages, taking each from the smallest free run it fits in,
+    /// like a buddy allocator. Extents that fit nowhere are added at the end of the file.
+    /// Large chains end up as a few contiguous extents, which are read and written with one system call each.
+    Extents,
+    /// Take the page that has been free the longest first, so that writes rotate through every free page
+    /// instead of hammering the few most recently freed ones. Spreads wear on flash storage.
+    Fifo
+}
+
+/// Take runs of adjacent pages of the given sizes from the free runs, each from the smallest run it fits in.
+/// Stops at the first extent that fits nowhere, leaving the rest to be added at the end of the file.
+fn take_extents(runs: &mut [&[u64]], extents: &[usize]) -> Vec<u64> {
+    let mut chosen = Vec::new();
+    for extent in extents {
+        let Some(run) = runs.iter_mut().filter(|run| run.len() >= *extent).min_by_key(|run| run.len()) else {
+            break;
+        };
+        chosen.extend_from_slice(&run[..*extent]);
+        *run = &run[*extent..];
+    }
+    chosen
+}
+
+impl File {
+
+    /// Write data to a page chain, keeping newly allocated pages together in runs of `page_multiplier` adjacent pages.
+    /// Acts like a chain with pages `page_multiplier` times larger, so a large chain can be read and written
+    /// with few system calls without making every chain in the file use larger pages.
+    pub fn write_large(&mut self, ptr: u64, data: &[u8], page_multiplier: usize) -> Result<(), Error> {
+        self.alloc_group = page_multiplier;
+        let result = self.write(ptr, data);
+        self.alloc_group = 1;
+        result
+    }
+
+    /// Allocate pages to extend a chain with, choosing free pages according to `Config::alloc_policy`.
+    /// If allocation fails partway through, the pages allocated so far are freed again.
+    /// Policies other than `AllocPolicy::FirstFree` walk the whole free list on every allocation.
+    pub(crate) fn alloc_pages(&mut self, count: usize) -> Result<Vec<u64>, Error> {
+        let mut pages = Vec::with_capacity(count);
+        let result = match self.config.alloc_policy {
+            AllocPolicy::FirstFree if self.alloc_group <= 1 => (0..count).try_for_each(|_| {
+                pages.push(self.alloc_page()?);
+                Ok(())
+            }),
+            _ => self.alloc_by_address(count, &mut pages)
+        };
+        if let Err(err) = result {
+            // Don't leak the pages allocated so far
+            for page in pages {
+                self.free_pages(page)?;
+            }
+            return Err(err);
+        }
+        Ok(pages)
+    }
+
+    fn alloc_by_address(&mut self, count: usize, pages: &mut Vec<u64>) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        let free_list = self.free_list()?;
+        let chosen = self.choose_free_pages(&free_list, count);
+
+        self.unlink_free_pages(&free_list, &chosen.iter().copied().collect())?;
+
+        for page in chosen {
+            self.zero_if_configured(page)?;
+            self.write_page_header(page, PageHeader::FinalPage(0))?;
+            pages.push(page);
+        }
+        while pages.len() < count {
+            let page = self.append_page()?;
+            self.write_page_header(page, PageHeader::FinalPage(0))?;
+            pages.push(page);
+        }
+
+        self.bump_change_counter()
+    }
+
+    /// Pick up to `count` pages from the free list according to the allocation policy, in the order the chain should use them
+    fn choose_free_pages(&self, free_list: &[u64], count: usize) -> Vec<u64> {
+        if self.config.alloc_policy == AllocPolicy::Fifo && self.alloc_group <= 1 {
+            // Freed pages are pushed onto the front of the list, so the oldest are at the back
+            return free_list.iter().rev().take(count).copied().collect();
+        }
+        let mut sorted = free_list.to_vec();
+        sorted.sort();
+        if self.config.alloc_policy == AllocPolicy::AddressOrdered {
+            sorted.truncate(count);
+            return sorted;
+        }
+
+        let mut runs = Vec::<&[u64]>::new();
+        let mut start = 0;
+        for i in 1..=sorted.len() {
+            if i == sorted.len() || sorted[i] != sorted[i - 1] + self.total_page_size() {
+                runs.push(&sorted[start..i]);
+                start = i;
+            }
+        }
+
+        if self.alloc_group > 1 {
+            let mut extents = vec![self.alloc_group; count / self.alloc_group];
+            if !count.is_multiple_of(self.alloc_group) {
+                extents.push(count % self.alloc_group);
+            }
+            return take_extents(&mut runs, &extents);
+        }
+        if self.config.alloc_policy == AllocPolicy::Extents {
+            // Largest extents first, so they get the first pick of the runs
+            let extents = (0..usize::BITS).rev().filter(|bit| count & (1 << bit) != 0).map(|bit| 1 << bit).collect::<Vec<_>>();
+            return take_extents(&mut runs, &extents);
+        }
+
+        if let Some(best) = runs.iter().filter(|run| run.len() >= count).min_by_key(|run| run.len()) {
+            return best[..count].to_vec();
+        }
+        runs.sort_by_key(|run| std::cmp::Reverse(run.len()));
+        let mut chosen = runs.into_iter().flatten().copied().take(count).collect::<Vec<_>>();
+        chosen.sort();
+        chosen
+    }
+
+    /// The pages in the free list, in list order
+    /// Remove pages from the free list, only rewriting the links that change
+    pub(crate) fn unlink_free_pages(&mut self, free_list: &[u64], removed: &HashSet<u64>) -> Result<(), Error> {
+        let old_next = free_list.iter().enumerate()
+            .map(|(i, page)| (*page, free_list.get(i + 1).copied().unwrap_or(0)))
+            .collect::<HashMap<_, _>>();
+        let remaining = free_list.iter().copied().filter(|page| !removed.contains(page)).collect::<Vec<_>>();
+        for (i, page) in remaining.iter().enumerate() {
+            let next = remaining.get(i + 1).copied().unwrap_or(0);
+            if old_next[page] != next {
+                self.write_page_header(*page, PageHeader::DeletedPage(next))?;
+            }
+        }
+        self.write_u64(self.first_free_page_ptr(), remaining.first().copied().unwrap_or(0))
+    }
+
+    pub(crate) fn free_list(&mut self) -> Result<Vec<u64>, Error> {
+        let max_pages = self.file_size()? / self.total_page_size();
+        let mut pages = Vec::new();
+        let mut free_page = self.first_free_page()?;
+        while free_page != 0 {
+            if pages.len() as u64 >= max_pages {
+                return Err(Error::CorruptedFile);
+            }
+            let PageHeader::DeletedPage(next) = self.read_page_header(free_page)? else {
+                return Err(Error::CorruptedFile);
+            };
+            pages.push(free_page);
+            free_page = next;
+        }
+        Ok(pages)
+    }
+
+}
+
+#[test]
+fn alloc_policies() {
+    use crate::Config;
+
+    for alloc_policy in [AllocPolicy::AddressOrdered, AllocPolicy::BestFitRun] {
+        let config = Config {
+            alloc_policy,
+            ..Config::default()
+        };
+        std::fs::remove_file("alloc_policies.verter").ok();
+        let mut file = File::open("alloc_policies.verter", config).unwrap();
+        let small = file.alloc_with(&[1; 100]).unwrap();
+        let _ = file.alloc_with(&[2; 100]).unwrap();
+        let large = file.alloc_with(&[3; 300]).unwrap();
+        let growing = file.alloc_with(&[4; 100]).unwrap();
+        let small_page = file.chain_pages(small).unwrap()[0];
+        let large_pages = file.chain_pages(large).unwrap();
+        file.delete(small).unwrap();
+        file.delete(large).unwrap();
+
+        file.write(growing, &[5; 300]).unwrap();
+        let pages = file.chain_pages(growing).unwrap()[1..].to_vec();
+        match alloc_policy {
+            // The lowest free pages are used, even though they are not adjacent
+            AllocPolicy::AddressOrdered => assert_eq!(pages, vec![small_page, large_pages[0]]),
+            // The freed run of three pages is the only one that fits both new pages
+            _ => assert_eq!(pages, large_pages[..2].to_vec())
+        }
+        assert_eq!(file.read(growing).unwrap(), vec![5; 300]);
+        file.validate().unwrap();
+    }
+
+    std::fs::remove_file("alloc_policies.verter").unwrap();
+}
+
+#[test]
+fn fifo_alloc() {
+    use crate::Config;
+
+    let config = Config {
+        alloc_policy: AllocPolicy::Fifo,
+        ..Config::default()
+    };
+    let mut file = File::open("fifo_alloc.verter", config).unwrap();
+    let first = file.alloc_with(b"first").unwrap();
+    let second = file.alloc_with(b"second").unwrap();
+    let third = file.alloc_with(b"third").unwrap();
+    file.delete(first).unwrap();
+    file.delete(second).unwrap();
+    file.delete(third).unwrap();
+
+    // Pages come back in the order they were freed, rather than the most recently freed one every time
+    for expected in [first, second, third] {
+        let ptr = file.alloc_with(b"new").unwrap();
+        assert_eq!(ptr, expected);
+        file.delete(ptr).unwrap();
+    }
+    file.validate().unwrap();
+
+    std::fs::remove_file("fifo_alloc.verter").unwrap();
+}
+
+#[test]
+fn extents() {
+    use crate::Config;
+
+    let config = Config {
+        alloc_policy: AllocPolicy::Extents,
+        ..Config::default()
+    };
+    let mut file = File::open("extents.verter", config).unwrap();
+    let gap = file.alloc_with(&[1; 500]).unwrap();
+    let _ = file.alloc_with(&[2; 100]).unwrap();
+    let gap_pages = file.chain_pages(gap).unwrap();
+    file.delete(gap).unwrap();
+
+    // The head page takes the start of the 5 page gap. The other 5 pages are split into extents of 4 and 1 pages,
+    // and the 4 page extent fills the rest of the gap, leaving the last page to be added at the end of the file.
+    let blob = file.alloc_with(&[3; 700]).unwrap();
+    let pages = file.chain_pages(blob).unwrap();
+    assert_eq!(pages[..5], gap_pages);
+    assert_eq!(file.page_runs(&pages).len(), 2);
+    assert_eq!(file.read(blob).unwrap(), vec![3; 700]);
+    file.validate().unwrap();
+
+    std::fs::remove_file("extents.verter").unwrap();
+}
+
+#[test]
+fn write_large() {
+    use crate::Config;
+
+    let mut file = File::open("write_large.verter", Config::default()).unwrap();
+    // Fragment the free list
+    let chains = (0..8).map(|_| file.alloc_with(&[1; 100]).unwrap()).collect::<Vec<_>>();
+    for chain in chains.iter().step_by(2) {
+        file.delete(*chain).unwrap();
+    }
+
+    let blob = file.alloc().unwrap();
+    file.write_large(blob, &[2; 120 * 9], 4).unwrap();
+    let pages = file.chain_pages(blob).unwrap();
+    // The head page comes from the free list, but no gap fits a run of 4 pages, so the rest go at the end of the file
+    assert!(chains.contains(&pages[0]));
+    assert_eq!(file.page_runs(&pages[1..]), vec![0..8]);
+    assert_eq!(file.read(blob).unwrap(), vec![2; 120 * 9]);
+    file.validate().unwrap();
+
+    // Small chains still reuse single free pages
+    let small = file.alloc_with(&[3; 100]).unwrap();
+    assert!(chains.contains(&small));
+
+    std::fs::remove_file("write_large.verter").unwrap();
+}
@@ -0,0 +1,630 @@
+//! An async interface to a file, for use from async applications such as web servers.
+//! Every operation runs on a thread that may block, chosen with `AsyncConfig::io`, and is completed through a future,
+//! so the application's executor is never blocked on file I/O.
+//!
+//! Operations are cancellation safe. An operation is only started when its future is first polled,
+//! and once started it runs to completion even if the future is dropped, so dropping a future never leaves a half-written chain.
+//! A future dropped before it is polled does nothing. Use `AsyncFile::write_batch` for writes to several chains
+//! that must happen together.
+
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread::JoinHandle;
+
+use bytes::Bytes;
+use futures_channel::oneshot;
+use futures_core::{ready, Future, Stream};
+
+use crate::{Error, File};
+
+/// How an `AsyncFile` runs operations on the file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AsyncIo {
+    /// A thread dedicated to the file runs every operation in the order they were started. Works with any executor.
+    #[default]
+    DedicatedThread,
+    /// Every operation runs on tokio's blocking thread pool with `tokio::task::spawn_blocking`, one at a time.
+    /// Futures and streams must be polled inside a tokio runtime.
+    #[cfg(feature = "tokio")]
+    SpawnBlocking
+}
+
+/// Options for how an `AsyncFile` maps operations to blocking I/O
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AsyncConfig {
+    pub io: AsyncIo,
+    /// The most operations that may be in flight at once. Further operations wait, without blocking, until one finishes.
+    /// The page reads of streams are not counted. `None` means no limit.
+    pub max_in_flight: Option<usize>,
+    /// The most bytes of data that writes may hold while they are in flight, so an application that writes faster than the disk
+    /// can keep up with doesn't pile up unwritten data in memory. Further writes wait, without blocking, until earlier ones finish,
+    /// and `AsyncFile::try_write` fails instead. A write larger than the limit waits until no other writes are in flight.
+    /// `None` means no limit. To also bound data written to the file but not yet synced, use `Durability::Periodic`.
+    pub max_dirty_bytes: Option<u64>
+}
+
+enum Command {
+    Run(Box<dyn FnOnce(&mut File) + Send>),
+    Stop
+}
+
+fn stopped() -> Error {
+    Error::IO(std::io::Error::other("async file worker stopped"))
+}
+
+/// Runs operations on the file according to `AsyncIo`
+#[derive(Clone)]
+enum Runner {
+    Thread(Sender<Command>),
+    /// Only the `AsyncFile` itself and running operations keep the file alive, so it can be taken back when closing
+    #[cfg(feature = "tokio")]
+    Blocking(std::sync::Weak<Mutex<File>>)
+}
+
+impl Runner {
+
+    /// Start running a function on the file, returning a receiver for its result
+    fn spawn<R: Send + 'static, F: FnOnce(&mut File) -> Result<R, Error> + Send + 'static>(&self, f: F) -> oneshot::Receiver<Result<R, Error>> {
+        let (sender, receiver) = oneshot::channel();
+        // If the file is gone, the sender is dropped along with the function and the receiver reports it
+        match self {
+            Runner::Thread(commands) => {
+                let _ = commands.send(Command::Run(Box::new(move |file| {
+                    let _ = sender.send(f(file));
+                })));
+            },
+            #[cfg(feature = "tokio")]
+            Runner::Blocking(file) => {
+                if let Some(file) = file.upgrade() {
+                    tokio::task::spawn_blocking(move || {
+                        let mut file = file.lock().unwrap_or_else(PoisonError::into_inner);
+                        let _ = sender.send(f(&mut file));
+                    });
+                }
+            }
+        }
+        receiver
+    }
+
+}
+
+/// Limits the operations, or the bytes of data, in flight, waking waiting operations as others finish
+struct Limit {
+    max: u64,
+    /// How much may still be taken, and the operations waiting to start
+    state: Mutex<(u64, Vec<Waker>)>
+}
+
+impl Limit {
+
+    fn new(max: u64) -> Arc<Self> {
+        Arc::new(Self { max, state: Mutex::new((max, Vec::new())) })
+    }
+
+    /// Wait until `amount` can be taken. Amounts over the limit wait until nothing else is taken.
+    fn acquire(self: &Arc<Self>, amount: u64) -> Acquire {
+        Acquire { limit: self.clone(), amount: amount.min(self.max) }
+    }
+
+    /// Take `amount` if it can be taken right away
+    fn try_acquire(self: &Arc<Self>, amount: u64) -> Option<Permit> {
+        let amount = amount.min(self.max);
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.0 < amount {
+            return None;
+        }
+        state.0 -= amount;
+        Some(Permit { limit: self.clone(), amount })
+    }
+
+}
+
+/// A future waiting for an operation to be allowed to start
+struct Acquire {
+    limit: Arc<Limit>,
+    amount: u64
+}
+
+/// Permission for an operation to run, given back when dropped
+struct Permit {
+    limit: Arc<Limit>,
+    amount: u64
+}
+
+impl Future for Acquire {
+
+    type Output = Permit;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Permit> {
+        let mut state = self.limit.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.0 < self.amount {
+            state.1.push(cx.waker().clone());
+            return Poll::Pending;
+        }
+        state.0 -= self.amount;
+        Poll::Ready(Permit { limit: self.limit.clone(), amount: self.amount })
+    }
+
+}
+
+impl Drop for Permit {
+
+    fn drop(&mut self) {
+        let mut state = self.limit.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.0 += self.amount;
+        for waker in state.1.drain(..) {
+            waker.wake();
+        }
+    }
+
+}
+
+/// A file used through futures, with its operations run on threads that may block.
+/// Dropping the handle waits for the operations already started to finish.
+pub struct AsyncFile {
+    runner: Runner,
+    /// The dedicated thread, along with a way to stop it
+    worker: Option<(Sender<Command>, JoinHandle<File>)>,
+    #[cfg(feature = "tokio")]
+    shared: Option<Arc<Mutex<File>>>,
+    limit: Option<Arc<Limit>>,
+    dirty_limit: Option<Arc<Limit>>
+}
+
+impl AsyncFile {
+
+    /// Move a file into a new worker thread.
+    pub fn new(file: File) -> Self {
+        Self::with_config(file, AsyncConfig::default())
+    }
+
+    /// Make a file usable through futures, running its operations as set in `config`.
+    pub fn with_config(file: File, config: AsyncConfig) -> Self {
+        let limit = config.max_in_flight.map(|max| Limit::new(max as u64));
+        let dirty_limit = config.max_dirty_bytes.map(Limit::new);
+        match config.io {
+            AsyncIo::DedicatedThread => {
+                let (commands, receiver) = channel();
+                let worker = std::thread::spawn(move || {
+                    let mut file = file;
+                    while let Ok(Command::Run(job)) = receiver.recv() {
+                        job(&mut file);
+                    }
+                    file
+                });
+                Self {
+                    runner: Runner::Thread(commands.clone()),
+                    worker: Some((commands, worker)),
+                    #[cfg(feature = "tokio")]
+                    shared: None,
+                    limit,
+                    dirty_limit
+                }
+            },
+            #[cfg(feature = "tokio")]
+            AsyncIo::SpawnBlocking => {
+                let shared = Arc::new(Mutex::new(file));
+                Self {
+                    runner: Runner::Blocking(Arc::downgrade(&shared)),
+                    worker: None,
+                    shared: Some(shared),
+                    limit,
+                    dirty_limit
+                }
+            }
+        }
+    }
+
+    /// Run a function on the file on a thread that may block.
+    /// Fails with an IO error if the file was closed.
+    pub async fn run<R: Send + 'static, F: FnOnce(&mut File) -> Result<R, Error> + Send + 'static>(&self, f: F) -> Result<R, Error> {
+        self.run_dirty(None, f).await
+    }
+
+    /// Run a function on the file, holding a permit for the bytes of data it writes until it finishes
+    async fn run_dirty<R: Send + 'static, F: FnOnce(&mut File) -> Result<R, Error> + Send + 'static>(&self, dirty: Option<Permit>, f: F) -> Result<R, Error> {
+        let permit = match &self.limit {
+            Some(limit) => Some(limit.acquire(1).await),
+            None => None
+        };
+        self.runner.spawn(move |file| {
+            // Hold the permits until the operation finishes, even if its future is dropped
+            let _permits = (permit, dirty);
+            f(file)
+        }).await.unwrap_or_else(|_| Err(stopped()))
+    }
+
+    /// Wait until `bytes` more bytes of data may be in flight. See `AsyncConfig::max_dirty_bytes`.
+    async fn acquire_dirty(&self, bytes: u64) -> Option<Permit> {
+        match &self.dirty_limit {
+            Some(limit) => Some(limit.acquire(bytes).await),
+            None => None
+        }
+    }
+
+    /// Read the data from a chain. See `File::read`.
+    pub async fn read(&self, ptr: u64) -> Result<Vec<u8>, Error> {
+        self.run(move |file| file.read(ptr)).await
+    }
+
+    /// Write data to a chain. See `File::write`.
+    /// Once the future is polled, the write completes even if the future is dropped.
+    /// Waits first if the write would go over `AsyncConfig::max_dirty_bytes`.
+    pub async fn write(&self, ptr: u64, data: Vec<u8>) -> Result<(), Error> {
+        let dirty = self.acquire_dirty(data.len() as u64).await;
+        self.run_dirty(dirty, move |file| file.write(ptr, &data)).await
+    }
+
+    /// Write data to a chain like `AsyncFile::write`, but fail with an IO error of kind `WouldBlock` instead of waiting
+    /// if the write would go over `AsyncConfig::max_dirty_bytes`.
+    pub async fn try_write(&self, ptr: u64, data: Vec<u8>) -> Result<(), Error> {
+        let dirty = match &self.dirty_limit {
+            Some(limit) => Some(limit.try_acquire(data.len() as u64).ok_or_else(|| Error::IO(std::io::ErrorKind::WouldBlock.into()))?),
+            None => None
+        };
+        self.run_dirty(dirty, move |file| file.write(ptr, &data)).await
+    }
+
+    /// Write data to several chains in a single transaction, so that either every write happens or none do.
+    /// Waits first if the writes would go over `AsyncConfig::max_dirty_bytes`.
+    pub async fn write_batch(&self, writes: Vec<(u64, Vec<u8>)>) -> Result<(), Error> {
+        let dirty = self.acquire_dirty(writes.iter().map(|(_, data)| data.len() as u64).sum()).await;
+        self.run_dirty(dirty, move |file| {
+            let mut transaction = file.transaction()?;
+            for (ptr, data) in writes {
+                transaction.write(ptr, &data)?;
+            }
+            transaction.commit()
+        }).await
+    }
+
+    /// Allocate a new chain. See `File::alloc`.
+    pub async fn alloc(&self) -> Result<u64, Error> {
+        self.run(|file| file.alloc()).await
+    }
+
+    /// Delete a chain. See `File::delete`.
+    pub async fn delete(&self, ptr: u64) -> Result<(), Error> {
+        self.run(move |file| file.delete(ptr)).await
+    }
+
+    /// Stream the data of a chain one page at a time, such as to forward it as a chunked HTTP response.
+    /// The next page is only read once the previous one has been taken from the stream.
+    /// Streams outliving the `AsyncFile` end with an IO error.
+    /// If the file is modified while streaming, the stream ends with `Error::ConcurrentModification`.
+    pub fn stream(&self, ptr: u64) -> ChainStream {
+        ChainStream {
+            runner: self.runner.clone(),
+            ptr,
+            layout: None,
+            offset: 0,
+            pending: None,
+            done: false
+        }
+    }
+
+    /// Wait for the operations already started to finish, and take the file back.
+    pub fn close(mut self) -> File {
+        self.shut_down().expect("the file is only taken when closing")
+    }
+
+    fn shut_down(&mut self) -> Option<File> {
+        #[cfg(feature = "tokio")]
+        if let Some(mut shared) = self.shared.take() {
+            // Running operations hold their own reference to the file until they finish
+            loop {
+                match Arc::try_unwrap(shared) {
+                    Ok(file) => return Some(file.into_inner().unwrap_or_else(PoisonError::into_inner)),
+                    Err(still_shared) => shared = still_shared
+                }
+                std::thread::yield_now();
+            }
+        }
+        let (commands, worker) = self.worker.take()?;
+        let _ = commands.send(Command::Stop);
+        worker.join().ok()
+    }
+
+}
+
+impl Drop for AsyncFile {
+
+    fn drop(&mut self) {
+        self.shut_down();
+    }
+
+}
+
+/// Where a streamed chain's pages are, found by the stream's first read
+struct Layout {
+    pages: Arc<Vec<u64>>,
+    /// The length of the chain's data, including its metadata
+    len: u64,
+    page_size: u64,
+    meta_size: u64,
+    /// The file's change counter when the layout was found
+    change_counter: u64
+}
+
+enum Step {
+    Layout(Layout),
+    /// The whole chain, for chains in the cold tier
+    Whole(Vec<u8>),
+    Page(Vec<u8>)
+}
+
+/// A stream of the pages of a chain, returned by `AsyncFile::stream`.
+pub struct ChainStream {
+    runner: Runner,
+    ptr: u64,
+    layout: Option<Layout>,
+    /// The offset of the next byte to stream, including the chain's metadata
+    offset: u64,
+    pending: Option<oneshot::Receiver<Result<Step, Error>>>,
+    done: bool
+}
+
+impl ChainStream {
+
+    /// Send the job for the next step of the stream, or return `None` if everything was streamed
+    fn next_job(&mut self) -> Option<oneshot::Receiver<Result<Step, Error>>> {
+        let ptr = self.ptr;
+        let Some(layout) = &self.layout else {
+            return Some(self.runner.spawn(move |file| {
+                file.check_for_external_changes()?;
+                file.check_if_pointer_valid(ptr)?;
+                if let Some(data) = file.read_cold(ptr)? {
+                    return Ok(Step::Whole(data));
+                }
+                let (pages, final_size) = file.chain_layout(ptr)?;
+                let len = (pages.len() as u64 - 1) * file.config.page_size as u64 + final_size;
+                Ok(Step::Layout(Layout {
+                    pages: Arc::new(pages),
+                    len,
+                    page_size: file.config.page_size as u64,
+                    meta_size: file.meta_size(),
+                    change_counter: file.change_counter
+                }))
+            }));
+        };
+
+        if self.offset >= layout.len {
+            return None;
+        }
+        let (pages, change_counter, offset) = (layout.pages.clone(), layout.change_counter, self.offset);
+        let end = ((offset / layout.page_size + 1) * layout.page_size).min(layout.len);
+        self.offset = end;
+        Some(self.runner.spawn(move |file| {
+            file.check_for_external_changes()?;
+            if file.change_counter != change_counter {
+                return Err(Error::ConcurrentModification);
+            }
+            let mut data = vec![0; (end - offset) as usize];
+            file.read_at(&pages, offset, &mut data)?;
+            Ok(Step::Page(data))
+        }))
+    }
+
+}
+
+impl Stream for ChainStream {
+
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(pending) = &mut self.pending {
+                let result = ready!(Pin::new(pending).poll(cx)).unwrap_or_else(|_| Err(stopped()));
+                self.pending = None;
+                match result {
+                    Ok(Step::Layout(layout)) => {
+                        self.offset = layout.meta_size;
+                        self.layout = Some(layout);
+                    },
+                    Ok(Step::Whole(data)) => {
+                        self.done = true;
+                        return Poll::Ready(Some(Ok(Bytes::from(data))));
+                    },
+                    Ok(Step::Page(data)) => return Poll::Ready(Some(Ok(Bytes::from(data)))),
+                    Err(err) => {
+                        self.done = true;
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                }
+            }
+            if self.done {
+                return Poll::Ready(None);
+            }
+            match self.next_job() {
+                Some(pending) => self.pending = Some(pending),
+                None => {
+                    self.done = true;
+                    return Poll::Ready(None);
+                }
+            }
+        }
+    }
+
+}
+
+#[test]
+fn async_file() {
+    use crate::Config;
+    use futures_executor::{block_on, block_on_stream};
+
+    let config = Config {
+        chain_meta: true,
+        ..Config::default()
+    };
+    let file = AsyncFile::new(File::open("async_file.verter", config).unwrap());
+    let data = (0..1000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let ptr = block_on(file.alloc()).unwrap();
+    block_on(file.write(ptr, data.clone())).unwrap();
+    assert_eq!(block_on(file.read(ptr)).unwrap(), data);
+
+    let pages = block_on_stream(file.stream(ptr)).collect::<Result<Vec<_>, _>>().unwrap();
+    assert!(pages.len() > 1);
+    assert!(pages.iter().all(|page| page.len() <= config.page_size));
+    assert_eq!(pages.concat(), data);
+
+    // Modifying the file while streaming ends the stream with an error
+    let mut stream = block_on_stream(file.stream(ptr));
+    assert_eq!(stream.next().unwrap().unwrap().len(), config.page_size - 20);
+    block_on(file.write(ptr, b"replaced".to_vec())).unwrap();
+    match stream.next() {
+        Some(Err(Error::ConcurrentModification)) => {},
+        Some(_) | None => panic!("should error with concurrent modification")
+    }
+    assert!(stream.next().is_none());
+
+    let mut file = file.close();
+    assert_eq!(file.read(ptr).unwrap(), b"replaced");
+
+    std::fs::remove_file("async_file.verter").unwrap();
+}
+
+#[test]
+fn cancelled_async_write() {
+    use crate::Config;
+    use futures_executor::block_on;
+    use std::task::Waker;
+
+    let file = AsyncFile::new(File::open("cancelled_async_write.verter", Config::default()).unwrap());
+    let ptr = block_on(file.alloc()).unwrap();
+
+    // A write that was polled completes even though its future is dropped
+    let mut write = Box::pin(file.write(ptr, vec![0xCD; 1000]));
+    let _ = write.as_mut().poll(&mut Context::from_waker(Waker::noop()));
+    drop(write);
+    assert_eq!(block_on(file.read(ptr)).unwrap(), vec![0xCD; 1000]);
+
+    // A write that was never polled does nothing
+    drop(file.write(ptr, b"never written".to_vec()));
+    assert_eq!(block_on(file.read(ptr)).unwrap(), vec![0xCD; 1000]);
+
+    // A failed batch leaves every chain as it was
+    let other = block_on(file.alloc()).unwrap();
+    match block_on(file.write_batch(vec![(other, b"written".to_vec()), (ptr + 1, b"invalid".to_vec())])) {
+        Err(Error::InvalidPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with invalid pointer")
+    }
+    assert_eq!(block_on(file.read(other)).unwrap(), b"");
+    block_on(file.write_batch(vec![(other, b"written".to_vec()), (ptr, b"also written".to_vec())])).unwrap();
+    assert_eq!(block_on(file.read(ptr)).unwrap(), b"also written");
+    drop(file);
+
+    std::fs::remove_file("cancelled_async_write.verter").unwrap();
+}
+
+#[test]
+fn max_in_flight() {
+    use crate::Config;
+    use futures_executor::block_on;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let config = AsyncConfig {
+        max_in_flight: Some(1),
+        ..AsyncConfig::default()
+    };
+    let file = AsyncFile::with_config(File::open("max_in_flight.verter", Config::default()).unwrap(), config);
+
+    // Hold the only permit until told to finish
+    let (finish, finished) = channel::<()>();
+    let mut slow = Box::pin(file.run(move |file| {
+        finished.recv().unwrap();
+        file.alloc()
+    }));
+    let _ = slow.as_mut().poll(&mut Context::from_waker(Waker::noop()));
+
+    let started = Arc::new(AtomicBool::new(false));
+    let started_clone = started.clone();
+    let mut waiting = Box::pin(file.run(move |file| {
+        started_clone.store(true, Ordering::Relaxed);
+        file.alloc()
+    }));
+    assert!(waiting.as_mut().poll(&mut Context::from_waker(Waker::noop())).is_pending());
+    assert!(!started.load(Ordering::Relaxed));
+
+    finish.send(()).unwrap();
+    let first = block_on(slow).unwrap();
+    let second = block_on(waiting).unwrap();
+    assert_ne!(first, second);
+    drop(file);
+
+    std::fs::remove_file("max_in_flight.verter").unwrap();
+}
+
+#[test]
+fn max_dirty_bytes() {
+    use crate::Config;
+    use futures_executor::block_on;
+
+    let config = AsyncConfig {
+        max_dirty_bytes: Some(1000),
+        ..AsyncConfig::default()
+    };
+    let file = AsyncFile::with_config(File::open("max_dirty_bytes.verter", Config::default()).unwrap(), config);
+    let ptr = block_on(file.alloc()).unwrap();
+    let other = block_on(file.alloc()).unwrap();
+
+    // Keep the worker busy, so that a polled write stays in flight
+    let (finish, finished) = channel::<()>();
+    let mut busy = Box::pin(file.run(move |_| finished.recv().map_err(|_| stopped())));
+    let _ = busy.as_mut().poll(&mut Context::from_waker(Waker::noop()));
+    let mut first = Box::pin(file.write(ptr, vec![0xAB; 800]));
+    assert!(first.as_mut().poll(&mut Context::from_waker(Waker::noop())).is_pending());
+
+    // Writes that would go over the limit wait, or fail straight away
+    let mut second = Box::pin(file.write(other, vec![0xCD; 800]));
+    assert!(second.as_mut().poll(&mut Context::from_waker(Waker::noop())).is_pending());
+    match block_on(file.try_write(other, vec![0xEF; 800])) {
+        Err(Error::IO(err)) if err.kind() == std::io::ErrorKind::WouldBlock => {},
+        Ok(_) | Err(_) => panic!("should error with would block")
+    }
+
+    finish.send(()).unwrap();
+    block_on(busy).unwrap();
+    block_on(first).unwrap();
+    block_on(second).unwrap();
+    block_on(file.try_write(other, vec![0xEF; 5000])).unwrap();
+    assert_eq!(block_on(file.read(ptr)).unwrap(), vec![0xAB; 800]);
+    assert_eq!(block_on(file.read(other)).unwrap(), vec![0xEF; 5000]);
+    drop(file);
+
+    std::fs::remove_file("max_dirty_bytes.verter").unwrap();
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn spawn_blocking() {
+    use crate::Config;
+
+    let config = AsyncConfig {
+        io: AsyncIo::SpawnBlocking,
+        max_in_flight: Some(4),
+        max_dirty_bytes: Some(1 << 20)
+    };
+    let file = AsyncFile::with_config(File::open("spawn_blocking.verter", Config::default()).unwrap(), config);
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let data = (0..1000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let ptr = runtime.block_on(async {
+        let ptr = file.alloc().await.unwrap();
+        file.write(ptr, data.clone()).await.unwrap();
+        assert_eq!(file.read(ptr).await.unwrap(), data);
+        ptr
+    });
+
+    let mut stream = file.stream(ptr);
+    let mut streamed = Vec::new();
+    while let Some(page) = runtime.block_on(std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx))) {
+        streamed.extend_from_slice(&page.unwrap());
+    }
+    assert_eq!(streamed, data);
+
+    let mut file = file.close();
+    assert_eq!(file.read(ptr).unwrap(), data);
+
+    std::fs::remove_file("spawn_blocking.verter").unwrap();
+}
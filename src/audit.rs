@@ -0,0 +1,122 @@
+use std::time::SystemTime;
+
+use crate::{Error, File};
+
+/// A kind of change recorded in the audit log
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditOp {
+    /// A chain was allocated
+    Alloc,
+    /// A chain's data was replaced
+    Write,
+    /// A chain was deleted
+    Delete
+}
+
+/// A single change to a file, as recorded in the audit log
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// When the change was made
+    pub timestamp: SystemTime,
+    /// What kind of change was made
+    pub operation: AuditOp,
+    /// The chain that was changed
+    pub ptr: u64,
+    /// The number of bytes written, or 0 for allocations and deletions
+    pub bytes: usize,
+    /// The user the change was made on behalf of, if it was made inside `File::audit_session`
+    pub user: Option<String>
+}
+
+/// Where audit records are sent, for deployments that must log who changed what.
+/// Unlike the journal, the audit log is not stored in the file, so the sink decides where records end up.
+/// Set with `File::set_audit_sink`.
+pub trait AuditSink: Send + Sync {
+
+    /// Called after every successful change to a chain, including the chains verter allocates for its own tables.
+    fn record(&mut self, record: &AuditRecord);
+
+}
+
+impl<F: FnMut(&AuditRecord) + Send + Sync> AuditSink for F {
+
+    fn record(&mut self, record: &AuditRecord) {
+        self(record)
+    }
+
+}
+
+/// The audit sink of a file, along with the user of the current session
+#[derive(Default)]
+pub(crate) struct Audit {
+    sink: Option<Box<dyn AuditSink>>,
+    user: Option<String>
+}
+
+impl Audit {
+
+    pub(crate) fn record(&mut self, operation: AuditOp, ptr: u64, bytes: usize) {
+        if let Some(sink) = &mut self.sink {
+            sink.record(&AuditRecord {
+                timestamp: SystemTime::now(),
+                operation,
+                ptr,
+                bytes,
+                user: self.user.clone()
+            });
+        }
+    }
+
+}
+
+impl File {
+
+    /// Send a record of every change to the file to `sink`, or stop auditing if `sink` is `None`.
+    pub fn set_audit_sink(&mut self, sink: Option<Box<dyn AuditSink>>) {
+        self.hooks.audit.sink = sink;
+    }
+
+    /// Make changes to the file on behalf of `user`, who is named in their audit records.
+    /// Sessions can be nested, with the innermost user being recorded.
+    pub fn audit_session<R, F: FnOnce(&mut File) -> Result<R, Error>>(&mut self, user: &str, f: F) -> Result<R, Error> {
+        let outer = self.hooks.audit.user.replace(user.to_owned());
+        let result = f(self);
+        self.hooks.audit.user = outer;
+        result
+    }
+
+}
+
+#[test]
+fn audit() {
+    use std::sync::{Arc, Mutex};
+    use crate::Config;
+
+    let mut file = File::open("audit.verter", Config::default()).unwrap();
+    let records = Arc::new(Mutex::new(Vec::new()));
+    let sink_records = records.clone();
+    file.set_audit_sink(Some(Box::new(move |record: &AuditRecord| sink_records.lock().unwrap().push(record.clone()))));
+
+    let before = SystemTime::now();
+    let ptr = file.alloc_with(b"frame").unwrap();
+    file.audit_session("alice", |file| {
+        file.write(ptr, &[0xAB; 300])?;
+        file.audit_session("bob", |file| file.delete(ptr))
+    }).unwrap();
+
+    let records = records.lock().unwrap();
+    let summary = records.iter().map(|record| (record.operation, record.ptr, record.bytes, record.user.as_deref())).collect::<Vec<_>>();
+    assert_eq!(summary, vec![
+        (AuditOp::Alloc, ptr, 0, None),
+        (AuditOp::Write, ptr, 5, None),
+        (AuditOp::Write, ptr, 300, Some("alice")),
+        (AuditOp::Delete, ptr, 0, Some("bob"))
+    ]);
+    assert!(records.iter().all(|record| record.timestamp >= before));
+    drop(records);
+
+    file.set_audit_sink(None);
+    file.alloc().unwrap();
+
+    std::fs::remove_file("audit.verter").unwrap();
+}
@@ -0,0 +1,141 @@
+//! Debounced saving of the root chain, for applications that update their document on every edit.
+
+use std::time::{Duration, Instant};
+
+use crate::{Error, File};
+
+/// The state of an `Autosaver`'s saves
+#[derive(Debug, Default)]
+pub struct SaveStatus {
+    /// When the root chain was last durably written
+    pub last_saved: Option<Instant>,
+    /// Whether there is root data that has not been written yet
+    pub dirty: bool,
+    /// A description of the error from the most recent save, if it failed. Cleared by the next successful save.
+    pub last_error: Option<String>
+}
+
+/// Collects updates to the root chain and writes them to the file once they stop coming in.
+/// The application calls `Autosaver::tick` regularly, such as once per frame, and the write happens once
+/// `interval` has passed without a new update.
+pub struct Autosaver {
+    file: File,
+    interval: Duration,
+    max_delay: Option<Duration>,
+    pending: Option<Vec<u8>>,
+    /// When the first and the most recent unsaved updates were made
+    first_update: Instant,
+    last_update: Instant,
+    status: SaveStatus
+}
+
+impl Autosaver {
+
+    pub fn new(file: File, interval: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            file,
+            interval,
+            max_delay: None,
+            pending: None,
+            first_update: now,
+            last_update: now,
+            status: SaveStatus::default()
+        }
+    }
+
+    /// Save at most `max_delay` after the first unsaved update, even if updates keep coming in.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = Some(max_delay);
+        self
+    }
+
+    /// Replace the data to be saved to the root chain. Nothing is written until the update is saved.
+    pub fn update(&mut self, data: Vec<u8>) {
+        let now = Instant::now();
+        if self.pending.is_none() {
+            self.first_update = now;
+        }
+        self.last_update = now;
+        self.pending = Some(data);
+        self.status.dirty = true;
+    }
+
+    /// Save the pending update if it is due. Returns whether a save was made.
+    pub fn tick(&mut self) -> bool {
+        if self.pending.is_none() {
+            return false;
+        }
+        let now = Instant::now();
+        let settled = now.duration_since(self.last_update) >= self.interval;
+        let overdue = self.max_delay.is_some_and(|max_delay| now.duration_since(self.first_update) >= max_delay);
+        if !settled && !overdue {
+            return false;
+        }
+        self.save_now().is_ok()
+    }
+
+    /// Save the pending update right away, such as when the user asks to save or the app is closing.
+    pub fn save_now(&mut self) -> Result<(), Error> {
+        let Some(data) = self.pending.take() else {
+            return Ok(());
+        };
+        let result = self.file.write_root(&data).and_then(|()| self.file.file.sync_data().map_err(Error::IO));
+        match result {
+            Ok(()) => {
+                self.status.last_saved = Some(Instant::now());
+                self.status.dirty = false;
+                self.status.last_error = None;
+                Ok(())
+            },
+            Err(err) => {
+                // Keep the data around to try again
+                self.pending = Some(data);
+                self.status.last_error = Some(format!("{:?}", err));
+                Err(err)
+            }
+        }
+    }
+
+    pub fn status(&self) -> &SaveStatus {
+        &self.status
+    }
+
+    /// The file being saved to. Reading the root chain through it gives the last saved data, not the pending update.
+    pub fn file(&mut self) -> &mut File {
+        &mut self.file
+    }
+
+    /// Save any pending update and give back the file.
+    pub fn into_inner(mut self) -> Result<File, Error> {
+        self.save_now()?;
+        Ok(self.file)
+    }
+
+}
+
+#[test]
+fn autosave() {
+    use crate::Config;
+
+    let file = File::open("autosave.verter", Config::default()).unwrap();
+    let mut autosaver = Autosaver::new(file, Duration::from_millis(20)).with_max_delay(Duration::from_millis(200));
+    for i in 0..5u8 {
+        autosaver.update(vec![i; 10]);
+        assert!(!autosaver.tick());
+    }
+    assert!(autosaver.status().dirty);
+    assert_eq!(autosaver.file().read_root().unwrap(), b"");
+
+    std::thread::sleep(Duration::from_millis(25));
+    assert!(autosaver.tick());
+    assert!(!autosaver.status().dirty);
+    assert!(autosaver.status().last_saved.is_some());
+    assert_eq!(autosaver.file().read_root().unwrap(), vec![4; 10]);
+
+    autosaver.update(b"closing".to_vec());
+    let mut file = autosaver.into_inner().unwrap();
+    assert_eq!(file.read_root().unwrap(), b"closing");
+
+    std::fs::remove_file("autosave.verter").unwrap();
+}
@@ -0,0 +1,256 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// The storage a verter file lives in.
+/// Verter reads and writes through this trait, so the same file format can be stored somewhere other than a single file on disk.
+pub trait Backend: Read + Write + Seek + Send + Sync {
+
+    /// The number of bytes currently stored.
+    fn len(&self) -> std::io::Result<u64>;
+
+    /// Whether no bytes are stored yet.
+    fn is_empty(&self) -> std::io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Make sure written data has reached durable storage.
+    fn sync_data(&self) -> std::io::Result<()>;
+
+    /// Fill `buf` with the bytes starting at `offset`, without moving the cursor.
+    /// Takes `&self`, so several threads can read from the same backend at once.
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()>;
+
+    /// Grow or shrink the stored bytes to exactly `len` bytes.
+    fn set_len(&mut self, len: u64) -> std::io::Result<()>;
+
+    /// Take a lock shared with other readers, waiting while another process holds an exclusive lock.
+    /// Backends that can't be shared between processes don't need to lock anything.
+    fn lock_shared(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Take an exclusive lock, waiting while other processes hold any lock.
+    fn lock_exclusive(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Release the lock taken with `Backend::lock_shared` or `Backend::lock_exclusive`.
+    fn unlock(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// The number of bytes still free on the disk the bytes are stored on, or `None` if it isn't known.
+    fn available_space(&self) -> std::io::Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// Copy the first `len` bytes into the empty file `dest` faster than reading and writing them,
+    /// such as by sharing their blocks on a copy-on-write filesystem.
+    /// Returns `false` if the backend has no faster way, leaving `dest` untouched so the bytes can be copied normally.
+    fn clone_into(&self, _dest: &std::fs::File, _len: u64) -> std::io::Result<bool> {
+        Ok(false)
+    }
+
+}
+
+impl Backend for std::fs::File {
+
+    fn len(&self) -> std::io::Result<u64> {
+        self.metadata().map(|metadata| metadata.len())
+    }
+
+    fn sync_data(&self) -> std::io::Result<()> {
+        std::fs::File::sync_data(self)
+    }
+
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        std::fs::File::set_len(self, len)
+    }
+
+    fn lock_shared(&self) -> std::io::Result<()> {
+        std::fs::File::lock_shared(self)
+    }
+
+    fn lock_exclusive(&self) -> std::io::Result<()> {
+        std::fs::File::lock(self)
+    }
+
+    fn unlock(&self) -> std::io::Result<()> {
+        std::fs::File::unlock(self)
+    }
+
+    #[cfg(unix)]
+    fn available_space(&self) -> std::io::Result<Option<u64>> {
+        use std::os::fd::AsRawFd;
+        let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+        // SAFETY: the file descriptor is open for as long as `self` is, and `stats` is only read after fstatvfs fills it in
+        if unsafe { libc::fstatvfs(self.as_raw_fd(), stats.as_mut_ptr()) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let stats = unsafe { stats.assume_init() };
+        // The field types differ between platforms
+        #[allow(clippy::unnecessary_cast)]
+        Ok(Some(stats.f_bavail as u64 * stats.f_frsize as u64))
+    }
+
+    #[cfg(unix)]
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        std::os::unix::fs::FileExt::read_exact_at(self, buf, offset)
+    }
+
+    /// Reflinks the whole file with `FICLONE` on filesystems that support it, such as Btrfs and XFS,
+    /// and otherwise copies it with `copy_file_range`, which still shares blocks on some filesystems and never leaves the kernel
+    #[cfg(target_os = "linux")]
+    fn clone_into(&self, dest: &std::fs::File, len: u64) -> std::io::Result<bool> {
+        use std::os::fd::AsRawFd;
+        if self.len()? == len {
+            // SAFETY: both file descriptors are open for the duration of the call
+            if unsafe { libc::ioctl(dest.as_raw_fd(), libc::FICLONE, self.as_raw_fd()) } == 0 {
+                return Ok(true);
+            }
+        }
+
+        let mut offset_in: libc::loff_t = 0;
+        let mut offset_out: libc::loff_t = 0;
+        while (offset_in as u64) < len {
+            let remaining = (len - offset_in as u64).min(isize::MAX as u64) as usize;
+            // SAFETY: both file descriptors are open for the duration of the call, and the offsets outlive it
+            let copied = unsafe { libc::copy_file_range(self.as_raw_fd(), &mut offset_in, dest.as_raw_fd(), &mut offset_out, remaining, 0) };
+            if copied < 0 {
+                let err = std::io::Error::last_os_error();
+                // Across filesystems, or on kernels and filesystems without support, nothing has been copied yet
+                let unsupported = [libc::EXDEV, libc::ENOSYS, libc::EOPNOTSUPP, libc::EINVAL].contains(&err.raw_os_error().unwrap_or(0));
+                if offset_in == 0 && unsupported {
+                    return Ok(false);
+                }
+                return Err(err);
+            }
+            if copied == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        Ok(true)
+    }
+
+    #[cfg(windows)]
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+        while !buf.is_empty() {
+            match std::os::windows::fs::FileExt::seek_read(self, buf, offset)? {
+                0 => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+                n => {
+                    buf = &mut buf[n..];
+                    offset += n as u64;
+                }
+            }
+        }
+        Ok(())
+    }
+
+}
+
+/// Keeps the whole file in memory, which is useful for scratch files that never need to reach the disk.
+impl Backend for std::io::Cursor<Vec<u8>> {
+
+    fn len(&self) -> std::io::Result<u64> {
+        Ok(self.get_ref().len() as u64)
+    }
+
+    fn sync_data(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        let start = usize::try_from(offset).map_err(|_| std::io::ErrorKind::UnexpectedEof)?;
+        let bytes = self.get_ref().get(start..start + buf.len()).ok_or(std::io::ErrorKind::UnexpectedEof)?;
+        buf.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        self.get_mut().resize(len as usize, 0);
+        Ok(())
+    }
+
+}
+
+/// A read-only backend over bytes embedded in the program, used by `File::open_bytes`
+pub(crate) type StaticBytes = ReadOnlyBytes<&'static [u8]>;
+
+/// A read-only backend over bytes kept in memory, such as bytes embedded in the program or a mapped snapshot
+pub(crate) struct ReadOnlyBytes<B> {
+    bytes: B,
+    pos: u64
+}
+
+impl<B: AsRef<[u8]>> ReadOnlyBytes<B> {
+
+    pub(crate) fn new(bytes: B) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn read_only() -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::PermissionDenied, "file was opened from read-only bytes")
+    }
+
+}
+
+impl<B: AsRef<[u8]>> Read for ReadOnlyBytes<B> {
+
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let bytes = self.bytes.as_ref();
+        let start = (self.pos as usize).min(bytes.len());
+        let n = buf.len().min(bytes.len() - start);
+        buf[..n].copy_from_slice(&bytes[start..start + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+}
+
+impl<B: AsRef<[u8]>> Write for ReadOnlyBytes<B> {
+
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        Err(Self::read_only())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+}
+
+impl<B: AsRef<[u8]>> Seek for ReadOnlyBytes<B> {
+
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => (self.bytes.as_ref().len() as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset)
+        };
+        self.pos = new_pos.ok_or(std::io::ErrorKind::InvalidInput)?;
+        Ok(self.pos)
+    }
+
+}
+
+impl<B: AsRef<[u8]> + Send + Sync> Backend for ReadOnlyBytes<B> {
+
+    fn len(&self) -> std::io::Result<u64> {
+        Ok(self.bytes.as_ref().len() as u64)
+    }
+
+    fn sync_data(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        let start = usize::try_from(offset).map_err(|_| std::io::ErrorKind::UnexpectedEof)?;
+        let bytes = self.bytes.as_ref().get(start..start + buf.len()).ok_or(std::io::ErrorKind::UnexpectedEof)?;
+        buf.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn set_len(&mut self, _len: u64) -> std::io::Result<()> {
+        Err(Self::read_only())
+    }
+
+}
@@ -0,0 +1,285 @@
+//! A worker thread that owns a file and keeps it maintained while the application keeps working with it.
+
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::{Error, File, ScrubReport};
+
+/// How often each maintenance task runs. Tasks set to `None` never run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Schedule {
+    /// Make sure written data has reached durable storage
+    pub flush: Option<Duration>,
+    /// Release old versions of chains with `File::checkpoint`. Only useful in log-structured mode.
+    pub checkpoint: Option<Duration>,
+    /// Check every page with `File::scrub`
+    pub scrub: Option<Duration>,
+    /// Continue building the file's chain index with `File::index_step`, until it is complete
+    pub index: Option<Duration>,
+    /// How hard the tasks may use the disk
+    pub throttle: Throttle
+}
+
+/// Limits on the IO of maintenance tasks, so that they don't make the application drop frames while it plays back or records.
+/// Only scrubbing and indexing can be spread out. Flushes and checkpoints are single operations that always run to completion.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Throttle {
+    /// The most bytes per second a scrub reads
+    pub bytes_per_sec: Option<u64>,
+    /// How many pages a scrub checks, or indexing scans, before pausing to run the application's work, continuing straight after
+    pub pages_between_yields: Option<u64>
+}
+
+impl Throttle {
+
+    /// Sleep long enough that `bytes` read since `start` stay within the rate limit
+    fn pace(&self, start: Instant, bytes: u64) {
+        if let Some(bytes_per_sec) = self.bytes_per_sec.filter(|bytes_per_sec| *bytes_per_sec > 0) {
+            let target = Duration::from_secs_f64(bytes as f64 / bytes_per_sec as f64);
+            std::thread::sleep(target.saturating_sub(start.elapsed()));
+        }
+    }
+
+}
+
+enum Command {
+    Run(Box<dyn FnOnce(&mut Worker) + Send>),
+    Stop
+}
+
+/// The state owned by the worker thread
+struct Worker {
+    file: File,
+    throttle: Throttle,
+    errors: Vec<Error>,
+    last_scrub: Option<ScrubReport>,
+    /// The results so far of a scrub that paused to let the application's work run
+    paused_scrub: Option<ScrubReport>
+}
+
+impl Worker {
+
+    fn run_task(&mut self, task: Task) {
+        let result = match task {
+            Task::Flush => self.file.flush(),
+            Task::Checkpoint => self.file.checkpoint(),
+            Task::Scrub => self.scrub(),
+            Task::Index => self.file.index_step(self.throttle.pages_between_yields.unwrap_or(u64::MAX)).map(|_| ())
+        };
+        if let Err(err) = result {
+            self.paused_scrub = None;
+            self.errors.push(err);
+        }
+    }
+
+    /// Start a scrub, or continue a paused one, until it finishes or has to yield
+    fn scrub(&mut self) -> Result<(), Error> {
+        let mut report = self.paused_scrub.take().unwrap_or_default();
+        let start = Instant::now();
+        let page_size = self.file.total_page_size();
+        let throttle = self.throttle;
+        let mut checked = 0;
+        let progress = |_, _| {
+            checked += 1;
+            throttle.pace(start, checked * page_size);
+            throttle.pages_between_yields.is_none_or(|pages| checked < pages)
+        };
+        let part = match report.resume_from {
+            Some(resume_from) => self.file.scrub_from(resume_from, progress)?,
+            None => self.file.scrub(progress)?
+        };
+        report.checked += part.checked;
+        report.bad_pages.extend(part.bad_pages);
+        report.resume_from = part.resume_from;
+        if report.resume_from.is_some() {
+            self.paused_scrub = Some(report);
+        } else {
+            self.last_scrub = Some(report);
+        }
+        Ok(())
+    }
+
+}
+
+#[derive(Clone, Copy)]
+enum Task {
+    Flush,
+    Checkpoint,
+    Scrub,
+    Index
+}
+
+/// A handle to a worker thread that owns a file and runs maintenance tasks on it according to a `Schedule`.
+/// The application uses the file through `Maintenance::run`, which sends the work to the worker thread.
+/// Dropping the handle stops the worker.
+pub struct Maintenance {
+    commands: Sender<Command>,
+    worker: Option<JoinHandle<File>>
+}
+
+impl Maintenance {
+
+    /// Move a file into a new worker thread, which maintains it according to `schedule`.
+    pub fn spawn(file: File, schedule: Schedule) -> Self {
+        let (commands, receiver) = channel();
+        let worker = std::thread::spawn(move || {
+            let mut worker = Worker { file, throttle: schedule.throttle, errors: Vec::new(), last_scrub: None, paused_scrub: None };
+            let now = Instant::now();
+            let mut due = [(Task::Flush, schedule.flush), (Task::Checkpoint, schedule.checkpoint), (Task::Scrub, schedule.scrub), (Task::Index, schedule.index)]
+                .into_iter()
+                .filter_map(|(task, interval)| Some((task, interval?, now + interval?)))
+                .collect::<Vec<_>>();
+            loop {
+                let next_due = due.iter().map(|(_, _, at)| *at).min();
+                let command = match next_due {
+                    Some(at) => receiver.recv_timeout(at.saturating_duration_since(Instant::now())),
+                    None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
+                };
+                match command {
+                    Ok(Command::Run(f)) => f(&mut worker),
+                    Ok(Command::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+                    Err(RecvTimeoutError::Timeout) => {}
+                }
+                let now = Instant::now();
+                for (task, interval, at) in &mut due {
+                    if *at <= now {
+                        worker.run_task(*task);
+                        // A paused scrub or unfinished index continues as soon as the application's pending work has run
+                        let paused = match task {
+                            Task::Scrub => worker.paused_scrub.is_some(),
+                            Task::Index => !worker.file.index.is_complete(),
+                            Task::Flush | Task::Checkpoint => false
+                        };
+                        *at = if paused { now } else { now + *interval };
+                    }
+                }
+            }
+            worker.file
+        });
+        Self { commands, worker: Some(worker) }
+    }
+
+    /// Run a function on the file in the worker thread, waiting for its result.
+    /// Fails with an IO error if the worker thread has stopped.
+    pub fn run<R: Send + 'static, F: FnOnce(&mut File) -> R + Send + 'static>(&self, f: F) -> Result<R, Error> {
+        self.run_on_worker(move |worker| f(&mut worker.file))
+    }
+
+    /// Take the errors returned by maintenance tasks since the last call.
+    pub fn take_errors(&self) -> Result<Vec<Error>, Error> {
+        self.run_on_worker(|worker| std::mem::take(&mut worker.errors))
+    }
+
+    /// The report of the most recent scrub, if one has finished.
+    pub fn last_scrub(&self) -> Result<Option<ScrubReport>, Error> {
+        self.run_on_worker(|worker| worker.last_scrub.clone())
+    }
+
+    /// Stop the worker thread and take the file back.
+    pub fn stop(mut self) -> File {
+        self.shut_down().expect("worker thread is only taken when stopping")
+    }
+
+    fn run_on_worker<R: Send + 'static, F: FnOnce(&mut Worker) -> R + Send + 'static>(&self, f: F) -> Result<R, Error> {
+        let (sender, receiver) = channel();
+        let command = Command::Run(Box::new(move |worker| {
+            let _ = sender.send(f(worker));
+        }));
+        let stopped = || Error::IO(std::io::Error::other("maintenance thread stopped"));
+        self.commands.send(command).map_err(|_| stopped())?;
+        receiver.recv().map_err(|_| stopped())
+    }
+
+    fn shut_down(&mut self) -> Option<File> {
+        let worker = self.worker.take()?;
+        let _ = self.commands.send(Command::Stop);
+        worker.join().ok()
+    }
+
+}
+
+impl Drop for Maintenance {
+
+    fn drop(&mut self) {
+        self.shut_down();
+    }
+
+}
+
+#[test]
+fn background_maintenance() {
+    use crate::Config;
+
+    let file = File::open("background_maintenance.verter", Config::default()).unwrap();
+    let maintenance = Maintenance::spawn(file, Schedule {
+        flush: Some(Duration::from_millis(1)),
+        scrub: Some(Duration::from_millis(5)),
+        ..Schedule::default()
+    });
+
+    let ptr = maintenance.run(|file| file.alloc_with(b"keyframe")).unwrap().unwrap();
+    while maintenance.last_scrub().unwrap().is_none() {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(maintenance.last_scrub().unwrap().unwrap().bad_pages.is_empty());
+    assert!(maintenance.take_errors().unwrap().is_empty());
+
+    let mut file = maintenance.stop();
+    assert_eq!(file.read(ptr).unwrap(), b"keyframe");
+
+    std::fs::remove_file("background_maintenance.verter").unwrap();
+}
+
+#[test]
+fn throttled_scrub() {
+    use crate::Config;
+
+    let mut file = File::open("throttled_scrub.verter", Config::default()).unwrap();
+    file.alloc_with(&[0xAB; 5000]).unwrap();
+    let pages = (file.file_size().unwrap() - file.header_size()) / file.total_page_size();
+    let maintenance = Maintenance::spawn(file, Schedule {
+        scrub: Some(Duration::from_millis(1)),
+        throttle: Throttle {
+            bytes_per_sec: Some(1_000_000),
+            pages_between_yields: Some(4)
+        },
+        ..Schedule::default()
+    });
+
+    // The application's work runs while the scrub is paused
+    while maintenance.last_scrub().unwrap().is_none() {
+        maintenance.run(|file| file.read_root()).unwrap().unwrap();
+    }
+    assert_eq!(maintenance.last_scrub().unwrap().unwrap().checked, pages);
+    assert!(maintenance.take_errors().unwrap().is_empty());
+    drop(maintenance);
+
+    std::fs::remove_file("throttled_scrub.verter").unwrap();
+}
+
+#[test]
+fn background_index() {
+    use crate::Config;
+
+    let mut file = File::open("background_index.verter", Config::default()).unwrap();
+    let ptr = file.alloc_with(&[0xAB; 5000]).unwrap();
+    let maintenance = Maintenance::spawn(file, Schedule {
+        index: Some(Duration::from_millis(1)),
+        throttle: Throttle {
+            bytes_per_sec: None,
+            pages_between_yields: Some(2)
+        },
+        ..Schedule::default()
+    });
+
+    while !maintenance.run(|file| file.index().unwrap().is_complete()).unwrap() {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let len = maintenance.run(move |file| file.index().unwrap().chain(ptr).unwrap().len).unwrap();
+    assert_eq!(len, 5000);
+    assert!(maintenance.take_errors().unwrap().is_empty());
+    drop(maintenance);
+
+    std::fs::remove_file("background_index.verter").unwrap();
+}
@@ -0,0 +1,239 @@
+//! A persistent cache of byte values, stored in a verter file and kept under a size limit by evicting the least recently used values.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use crate::{Config, Error, File, BYTES_IN_U64};
+
+struct CacheEntry {
+    ptr: u64,
+    len: u64,
+    /// When the value was last put or got, as a tick of the cache's logical clock
+    last_access: u64
+}
+
+/// A persistent key-value cache in a verter file, holding at most `max_bytes` bytes of values.
+/// Once it is full, putting a new value evicts the values that were least recently put or got.
+///
+/// The index of keys lives in the root chain, and is only rewritten after a value's chain is complete,
+/// so a crash never leaves the index pointing at a half-written value.
+/// Values that were being written during a crash are deleted the next time the cache is opened.
+/// Access times are only saved with the index, so gets since the last put, remove or `DiskCache::flush` are forgotten in a crash.
+pub struct DiskCache {
+    file: File,
+    max_bytes: u64,
+    entries: BTreeMap<Vec<u8>, CacheEntry>,
+    /// The total length of every cached value
+    total_bytes: u64,
+    /// The next tick of the logical clock used for access times
+    clock: u64,
+    /// Whether access times changed since the index was last saved
+    dirty: bool
+}
+
+impl DiskCache {
+
+    /// Open the cache stored at `path`, creating it if it doesn't exist.
+    /// If the cache holds more than `max_bytes` bytes, such as when it was last opened with a larger limit, values are evicted straight away.
+    pub fn new<P: AsRef<Path>>(path: P, max_bytes: u64) -> Result<Self, Error> {
+        let mut file = File::open(path, Config::default())?;
+        let (clock, entries) = decode_index(&file.read_root()?)?;
+        let mut cache = Self { file, max_bytes, entries, total_bytes: 0, clock, dirty: false };
+        cache.total_bytes = cache.entries.values().map(|entry| entry.len).sum();
+        cache.delete_orphans()?;
+        cache.evict(0)?;
+        Ok(cache)
+    }
+
+    /// Store a value, replacing the value previously stored under the key.
+    /// A value larger than the whole cache is not stored, and removes the key's previous value.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        if value.len() as u64 > self.max_bytes {
+            self.remove(key)?;
+            return Ok(());
+        }
+        self.evict(value.len() as u64)?;
+        let ptr = self.file.alloc_with(value)?;
+        let entry = CacheEntry { ptr, len: value.len() as u64, last_access: self.tick() };
+        let old = self.entries.insert(key.to_vec(), entry);
+        self.total_bytes += value.len() as u64;
+        if let Some(old) = &old {
+            self.total_bytes -= old.len;
+        }
+        // The old value is only deleted once the index no longer points at it
+        self.save_index()?;
+        if let Some(old) = old {
+            self.file.delete(old.ptr)?;
+        }
+        Ok(())
+    }
+
+    /// Get the value stored under a key, or `None` if it isn't cached.
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let tick = self.tick();
+        let Some(entry) = self.entries.get_mut(key) else {
+            return Ok(None);
+        };
+        entry.last_access = tick;
+        self.dirty = true;
+        let ptr = entry.ptr;
+        self.file.read(ptr).map(Some)
+    }
+
+    /// Remove the value stored under a key, returning whether there was one.
+    pub fn remove(&mut self, key: &[u8]) -> Result<bool, Error> {
+        let Some(entry) = self.entries.remove(key) else {
+            return Ok(false);
+        };
+        self.total_bytes -= entry.len;
+        self.save_index()?;
+        self.file.delete(entry.ptr)?;
+        Ok(true)
+    }
+
+    /// Whether a value is stored under the key, without counting as an access.
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// The number of cached values.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the cache holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The total length of every cached value.
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    /// Save the access times of values that were got since the index was last saved, and sync the file.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if self.dirty {
+            self.save_index()?;
+        }
+        self.file.flush()
+    }
+
+    /// Evict the least recently used values until `incoming` more bytes fit in the cache
+    fn evict(&mut self, incoming: u64) -> Result<(), Error> {
+        if self.total_bytes + incoming <= self.max_bytes {
+            return Ok(());
+        }
+        let mut by_age = self.entries.iter().map(|(key, entry)| (entry.last_access, key.clone())).collect::<Vec<_>>();
+        by_age.sort();
+        let mut evicted = Vec::new();
+        for (_, key) in by_age {
+            if self.total_bytes + incoming <= self.max_bytes {
+                break;
+            }
+            let entry = self.entries.remove(&key).unwrap();
+            self.total_bytes -= entry.len;
+            evicted.push(entry.ptr);
+        }
+        self.save_index()?;
+        self.file.delete_many(&evicted)
+    }
+
+    /// Delete every chain the index doesn't point at, left over from a put interrupted by a crash
+    fn delete_orphans(&mut self) -> Result<(), Error> {
+        let mut kept = self.file.internal_chains()?.into_iter().collect::<HashSet<_>>();
+        kept.extend(self.file.root_chains()?);
+        kept.extend(self.entries.values().map(|entry| entry.ptr));
+        let orphans = self.file.chain_heads()?.into_iter().filter(|ptr| !kept.contains(ptr)).collect::<Vec<_>>();
+        self.file.delete_many(&orphans)
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn save_index(&mut self) -> Result<(), Error> {
+        let mut data = self.clock.to_le_bytes().to_vec();
+        for (key, entry) in &self.entries {
+            data.extend_from_slice(&(key.len() as u64).to_le_bytes());
+            data.extend_from_slice(key);
+            for val in [entry.ptr, entry.len, entry.last_access] {
+                data.extend_from_slice(&val.to_le_bytes());
+            }
+        }
+        self.file.write_root(&data)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+}
+
+impl Drop for DiskCache {
+
+    fn drop(&mut self) {
+        if self.dirty {
+            let _ = self.save_index();
+        }
+    }
+
+}
+
+/// The index is the clock, followed by every entry as the length of its key, its key, and its pointer, length and access time
+fn decode_index(mut data: &[u8]) -> Result<(u64, BTreeMap<Vec<u8>, CacheEntry>), Error> {
+    let mut entries = BTreeMap::new();
+    if data.is_empty() {
+        return Ok((0, entries));
+    }
+    let next_u64 = |data: &mut &[u8]| {
+        let (val, rest) = data.split_at_checked(BYTES_IN_U64 as usize).ok_or(Error::CorruptedFile)?;
+        *data = rest;
+        Ok::<_, Error>(u64::from_le_bytes(val.try_into().unwrap()))
+    };
+    let clock = next_u64(&mut data)?;
+    while !data.is_empty() {
+        let key_len = next_u64(&mut data)? as usize;
+        let (key, rest) = data.split_at_checked(key_len).ok_or(Error::CorruptedFile)?;
+        data = rest;
+        let ptr = next_u64(&mut data)?;
+        let len = next_u64(&mut data)?;
+        let last_access = next_u64(&mut data)?;
+        entries.insert(key.to_vec(), CacheEntry { ptr, len, last_access });
+    }
+    Ok((clock, entries))
+}
+
+#[test]
+fn disk_cache() {
+    let mut cache = DiskCache::new("disk_cache.verter", 1000).unwrap();
+    cache.put(b"thumbnail/1", &[1; 400]).unwrap();
+    cache.put(b"thumbnail/2", &[2; 400]).unwrap();
+    assert_eq!(cache.get(b"thumbnail/1").unwrap(), Some(vec![1; 400]));
+
+    // Thumbnail 2 is the least recently used, so it makes room for thumbnail 3
+    cache.put(b"thumbnail/3", &[3; 400]).unwrap();
+    assert!(!cache.contains(b"thumbnail/2"));
+    assert_eq!(cache.total_bytes(), 800);
+    cache.put(b"too large", &[4; 2000]).unwrap();
+    assert!(!cache.contains(b"too large"));
+    drop(cache);
+
+    // A put interrupted before the index was saved leaves an orphaned chain behind
+    let mut file = File::open("disk_cache.verter", Config::default()).unwrap();
+    let orphan = file.alloc_with(&[5; 400]).unwrap();
+    drop(file);
+
+    let mut cache = DiskCache::new("disk_cache.verter", 1000).unwrap();
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get(b"thumbnail/3").unwrap(), Some(vec![3; 400]));
+    assert!(cache.file.check_if_pointer_valid(orphan).is_err());
+
+    // Reopening with a smaller limit evicts straight away
+    drop(cache);
+    let cache = DiskCache::new("disk_cache.verter", 500).unwrap();
+    assert_eq!(cache.len(), 1);
+    assert!(cache.contains(b"thumbnail/3"));
+    drop(cache);
+
+    std::fs::remove_file("disk_cache.verter").unwrap();
+}
@@ -0,0 +1,36 @@
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+
+use crate::Error;
+
+/// A flag that can be set from another thread to stop a long operation early.
+/// Clones share the same flag, so one clone can be handed to the operation while another is kept to cancel it.
+/// Cancelled operations stop at a point where the file is consistent and return `Error::Cancelled`.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>
+}
+
+impl CancellationToken {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every operation using this token to stop.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Return `Error::Cancelled` if the operation should stop
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        Ok(())
+    }
+
+}
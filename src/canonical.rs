@@ -0,0 +1,251 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::{Cursor, Seek, SeekFrom, Write};
+
+use crate::memory::ScratchFile;
+use crate::{decode_u64s, encode_u64s, Backend, Config, Error, File, PageLayout, BYTES_IN_U64};
+use crate::partition::PartitionEntry;
+
+impl File {
+
+    /// Rewrite the file into a normal form, so that files with the same contents end up byte-for-byte equal.
+    /// Chains are laid out in the order they are first reached from the root chain, then from each partition,
+    /// using the tracer to find the pointers stored inside each chain, like `File::gc`. Unreachable chains follow in address order.
+    /// Pinned chains keep their pointers, and the free list only holds pages that fell between them,
+    /// except in files upgraded from format version 0, whose pinned chains are moved like any other chain.
+    /// Otherwise the file is compacted, leaving the free list empty, and old versions kept by log-structured mode, generations of weak pointers
+    /// and the quarantine list are dropped. Files from older versions of the format are upgraded to the current one, keeping their `PageLayout`.
+    /// Pointers stored inside the data are not rewritten, so use the returned map from old to new pointers to remap them.
+    /// The new contents are built in memory, or in a temporary file if `Config::max_working_memory` is set,
+    /// and then written over the file, so the file is damaged if writing them fails partway.
+    /// Fails with `Error::PreparedTransaction` if a prepared transaction has not been resolved yet.
+    pub fn canonicalize<F: Fn(&[u8]) -> Vec<u64>>(&mut self, tracer: F) -> Result<BTreeMap<u64, u64>, Error> {
+        self.check_for_external_changes()?;
+        self.check_writable()?;
+        if self.has_prepared_transaction()? {
+            return Err(Error::PreparedTransaction);
+        }
+        let (_scratch, backend): (Option<ScratchFile>, Box<dyn Backend>) = match self.config.max_working_memory {
+            Some(_) => {
+                let (scratch, file) = ScratchFile::create()?;
+                (Some(scratch), Box::new(file))
+            },
+            None => (None, Box::new(Cursor::new(Vec::new())))
+        };
+        // The file keeps its page layout, since its stored bytes are replaced while it stays open
+        let page_layout = if self.has_page_maps() { PageLayout::PageMaps } else { PageLayout::Interleaved };
+        let mut dest = File::from_backend(backend, Config { page_layout, ..self.config }, true)?;
+
+        let internal_chains = self.internal_chains()?.into_iter().collect::<HashSet<_>>();
+        let root = self.root_page()?;
+        let user_chains = self.chain_heads()?.into_iter()
+            .filter(|ptr| *ptr != root && !internal_chains.contains(ptr))
+            .collect::<Vec<_>>();
+        let user_chain_set = user_chains.iter().copied().collect::<HashSet<_>>();
+
+        // Find the order chains are first used in, depth-first
+        let mut order = Vec::new();
+        let mut visited = HashSet::new();
+        let mut to_visit = self.root_chains()?;
+        to_visit.reverse();
+        while let Some(chain) = to_visit.pop() {
+            // A lazily created root chain may not exist yet, leaving the root pointer at 0
+            if chain == 0 || !(chain == root || user_chain_set.contains(&chain)) || !visited.insert(chain) {
+                continue;
+            }
+            order.push(chain);
+            let mut children = tracer(&self.read(chain)?);
+            children.reverse();
+            to_visit.extend(children);
+        }
+        order.extend(user_chains.into_iter().filter(|chain| !visited.contains(chain)));
+
+        // Pinned chains keep their pointers, with the other chains laid out around them.
+        // The pages of files upgraded from format version 0 don't line up with those of the current header, so they can't.
+        let pins = self.read_pin_table()?;
+        let keep_pins = self.header_pages.is_empty();
+        if keep_pins {
+            dest.alloc_pinned(&pins)?;
+            dest.write_pin_table(&pins)?;
+        }
+
+        let mut remap = BTreeMap::new();
+        for chain in order {
+            let new_chain = if chain == root {
+                dest.root_page()?
+            } else if keep_pins && pins.contains(&chain) {
+                chain
+            } else {
+                dest.alloc()?
+            };
+            self.copy_chain_raw(&mut dest, chain, new_chain)?;
+            remap.insert(chain, new_chain);
+        }
+        if !keep_pins {
+            dest.write_pin_table(&pins.iter().filter_map(|pin| remap.get(pin).copied()).collect())?;
+        }
+
+        for (field, dest_field) in [(self.undo_chain_ptr(), dest.undo_chain_ptr()), (self.redo_chain_ptr(), dest.redo_chain_ptr())] {
+            let ops = self.read_op_stack(field)?;
+            dest.write_op_stack(dest_field, &ops)?;
+        }
+        let refcounts = self.read_refcount_table()?.into_iter()
+            .filter_map(|(ptr, count)| Some((*remap.get(&ptr)?, count)))
+            .collect();
+        dest.write_refcount_table(&refcounts)?;
+        let dedup = self.read_dedup_table()?.into_iter()
+            .filter_map(|(hash, ptr)| Some((hash, *remap.get(&ptr)?)))
+            .collect::<Vec<_>>();
+        if !dedup.is_empty() {
+            dest.write_dedup_table(&dedup)?;
+        }
+        let cold = self.read_cold_table()?.into_iter()
+            .filter_map(|(ptr, cold_ptr)| Some((*remap.get(&ptr)?, cold_ptr)))
+            .collect::<BTreeMap<_, _>>();
+        if !cold.is_empty() {
+            dest.write_cold_table(&cold)?;
+        }
+
+        // Dictionaries are referred to by their index in the list, so keep them in the same order
+        let dictionaries = self.read_u64(self.dictionaries_ptr())?;
+        if dictionaries != 0 {
+            let mut new_dictionaries = Vec::new();
+            for dictionary in decode_u64s(&self.read_chain(dictionaries)?)? {
+                let new_dictionary = dest.alloc()?;
+                dest.write_chain(new_dictionary, &self.read_chain(dictionary)?)?;
+                new_dictionaries.push(new_dictionary);
+            }
+            let list_chain = dest.alloc()?;
+            dest.write_u64(dest.dictionaries_ptr(), list_chain)?;
+            dest.write_chain(list_chain, &encode_u64s(&new_dictionaries))?;
+        }
+
+        let mut partitions = Vec::new();
+        for entry in self.read_partition_table()? {
+            let members = decode_u64s(&self.read_chain(entry.members)?)?.into_iter()
+                .filter_map(|ptr| remap.get(&ptr).copied())
+                .collect::<Vec<_>>();
+            let members_chain = dest.alloc()?;
+            dest.write_chain(members_chain, &encode_u64s(&members))?;
+            partitions.push(PartitionEntry { name: entry.name, root: remap[&entry.root], members: members_chain });
+        }
+        if !partitions.is_empty() {
+            dest.write_partition_table(&partitions)?;
+        }
+
+        let mut tags = Vec::new();
+        for (name, chain) in self.read_tag_table()? {
+            let new_chain = dest.alloc()?;
+            dest.write_chain(new_chain, &self.read_chain(chain)?)?;
+            tags.push((name, new_chain));
+        }
+        if !tags.is_empty() {
+            dest.write_tag_table(&tags)?;
+        }
+
+        dest.write_u64(dest.schema_version_ptr(), self.read_u64(self.schema_version_ptr())?)?;
+
+        if self.id_counter_chain_if_any()? != 0 {
+            dest.write_id_counters(&self.read_id_counters()?)?;
+        }
+
+        let named_roots = self.read_named_roots()?;
+        if !named_roots.is_empty() {
+            dest.write_named_roots(&named_roots.into_iter().map(|(name, ptr)| (name, remap[&ptr])).collect())?;
+        }
+
+        if self.read_u64(self.journal_ptr())? != 0 {
+            let (next_seq, ops) = self.read_journal()?;
+            dest.write_journal(next_seq, &ops)?;
+        }
+
+        // The metadata of internal chains records when they were written, which differs between otherwise equal files
+        if self.config.chain_meta {
+            for chain in dest.internal_chains()? {
+                dest.file.seek(SeekFrom::Start(chain + BYTES_IN_U64)).map_err(Error::IO)?;
+                dest.file.write_all(&vec![0; (self.meta_size() - self.summary_size()) as usize]).map_err(Error::IO)?;
+            }
+        }
+
+        let mut chunk = vec![0; self.chunk_size()];
+        self.with_stored_bytes(|file| dest.with_stored_bytes(|dest| {
+            let len = dest.file_size()?;
+            file.file.seek(SeekFrom::Start(0)).map_err(Error::IO)?;
+            let mut offset = 0;
+            while offset < len {
+                let n = chunk.len().min((len - offset) as usize);
+                dest.file.read_exact_at(&mut chunk[..n], offset).map_err(Error::IO)?;
+                file.file.write_all(&chunk[..n]).map_err(Error::IO)?;
+                offset += n as u64;
+            }
+            file.file.set_len(len).map_err(Error::IO)
+        }))?;
+        self.file.sync_data().map_err(Error::IO)?;
+        self.magic_bytes = self.config.magic_bytes;
+        // Files from older versions of the format are rewritten in the current one
+        self.format_version = dest.format_version;
+        self.header_pages = Vec::new();
+        // The header may have changed size along with the magic bytes
+        if self.has_page_maps() {
+            self.map_pages();
+        }
+        self.compaction_due = false;
+        self.refresh()?;
+
+        Ok(remap)
+    }
+
+    /// Copy the contents of a chain into a freshly allocated chain of another file a chunk at a time, keeping the chain's metadata as it is
+    fn copy_chain_raw(&mut self, dest: &mut File, ptr: u64, new_ptr: u64) -> Result<(), Error> {
+        let mut pages = vec![new_ptr];
+        let mut offset = 0u64;
+        let len = self.stream_chain(ptr, true, |chunk| {
+            while (pages.len() as u64) < dest.pages_needed(offset + chunk.len() as u64) {
+                pages.push(dest.alloc_page()?);
+            }
+            dest.write_at(&pages, offset, chunk)?;
+            offset += chunk.len() as u64;
+            Ok(())
+        })?;
+        dest.link_pages(&pages, len - self.meta_size())?;
+        if self.is_frozen(ptr)? {
+            dest.freeze(new_ptr)?;
+        }
+        Ok(())
+    }
+
+}
+
+#[test]
+fn canonicalize() {
+    use crate::Config;
+
+    let tracer = |data: &[u8]| decode_u64s(data).unwrap_or_default();
+
+    let mut a = File::open("canonicalize_a.verter", Config::default()).unwrap();
+    let texture = a.alloc_with(&[0xAB; 300]).unwrap();
+    let scene = a.alloc_with(b"scene").unwrap();
+    a.write_root(&encode_u64s(&[scene, texture])).unwrap();
+
+    // The same contents, written in a different order with some churn
+    let mut b = File::open("canonicalize_b.verter", Config::default()).unwrap();
+    let junk = b.alloc_with(&[0; 1000]).unwrap();
+    let scene = b.alloc_with(b"old scene").unwrap();
+    let texture = b.alloc_with(&[0xAB; 300]).unwrap();
+    b.delete(junk).unwrap();
+    b.write(scene, b"scene").unwrap();
+    b.write_root(&encode_u64s(&[scene, texture])).unwrap();
+
+    for file in [&mut a, &mut b] {
+        let remap = file.canonicalize(tracer).unwrap();
+        let root = tracer(&file.read_root().unwrap()).into_iter().map(|ptr| remap[&ptr]).collect::<Vec<_>>();
+        file.write_root(&encode_u64s(&root)).unwrap();
+        assert_eq!(file.read(root[1]).unwrap(), vec![0xAB; 300]);
+        file.validate().unwrap();
+    }
+    drop(a);
+    drop(b);
+    assert_eq!(std::fs::read("canonicalize_a.verter").unwrap(), std::fs::read("canonicalize_b.verter").unwrap());
+
+    std::fs::remove_file("canonicalize_a.verter").unwrap();
+    std::fs::remove_file("canonicalize_b.verter").unwrap();
+}
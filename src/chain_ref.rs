@@ -0,0 +1,104 @@
+use crate::{Error, File};
+
+/// The data of a chain, borrowed from the file by `File::read_ref`
+pub struct ChainRef<'a> {
+    data: &'a [u8],
+    /// The number of bytes of data in the chain's first page, after the chain's metadata
+    first_chunk_len: usize,
+    page_size: usize
+}
+
+impl File {
+
+    /// Read the data from a page chain into a buffer owned by the file, and borrow it.
+    /// The buffer is reused by every call, so reading many chains one after another does not allocate a new `Vec` for each.
+    /// The buffer keeps the capacity of the largest chain read this way.
+    pub fn read_ref(&mut self, ptr: u64) -> Result<ChainRef<'_>, Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+
+        let mut buffer = std::mem::take(&mut self.read_buffer);
+        let start = match self.read_cold(ptr) {
+            Ok(Some(data)) => {
+                buffer.clear();
+                buffer.extend_from_slice(&data);
+                Ok(0)
+            },
+            Ok(None) => self.read_chain_into(ptr, &mut buffer).map(|()| self.meta_size() as usize),
+            Err(err) => Err(err)
+        };
+        self.read_buffer = buffer;
+        let start = start?;
+
+        Ok(ChainRef {
+            data: &self.read_buffer[start..],
+            first_chunk_len: self.config.page_size - start,
+            page_size: self.config.page_size
+        })
+    }
+
+}
+
+impl<'a> ChainRef<'a> {
+
+    /// The chain's data as a single slice.
+    pub fn as_slice(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// The chain's data split up the same way it is split across the chain's pages.
+    pub fn chunks(&self) -> impl Iterator<Item = &'a [u8]> {
+        let (first, rest) = self.data.split_at(self.first_chunk_len.min(self.data.len()));
+        std::iter::once(first).filter(|chunk| !chunk.is_empty()).chain(rest.chunks(self.page_size))
+    }
+
+    /// The number of bytes of data in the chain.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Whether the chain holds no data.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+}
+
+impl std::ops::Deref for ChainRef<'_> {
+
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.data
+    }
+
+}
+
+#[test]
+fn read_ref() {
+    use crate::Config;
+
+    let config = Config {
+        chain_meta: true,
+        ..Config::default()
+    };
+    let mut file = File::open("read_ref.verter", config).unwrap();
+    let data = (0..1000).map(|i| i as u8).collect::<Vec<_>>();
+    let big = file.alloc_with(&data).unwrap();
+    let small = file.alloc_with(b"tiny").unwrap();
+    let empty = file.alloc().unwrap();
+
+    let meta_size = file.meta_size() as usize;
+    let chain = file.read_ref(big).unwrap();
+    assert_eq!(chain.as_slice(), data);
+    let chunks = chain.chunks().collect::<Vec<_>>();
+    assert_eq!(chunks[0].len(), config.page_size - meta_size);
+    assert!(chunks[1..].iter().rev().skip(1).all(|chunk| chunk.len() == config.page_size));
+    assert_eq!(chunks.concat(), data);
+
+    assert_eq!(&*file.read_ref(small).unwrap(), b"tiny");
+    assert!(file.read_ref(empty).unwrap().is_empty());
+    assert_eq!(file.read_ref(empty).unwrap().chunks().count(), 0);
+
+    std::fs::remove_file("read_ref.verter").unwrap();
+}
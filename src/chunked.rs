@@ -0,0 +1,245 @@
+use std::io::{BufRead, Read};
+
+use crate::dedup::content_hash;
+use crate::vfs::to_io_error;
+use crate::{try_zeroed, Error, File};
+
+/// An iterator over a chain's data in fixed-size chunks, returned by `File::read_chunked`.
+/// Yields each chunk as its offset into the chain's data, its bytes, and a hash of its bytes.
+pub struct ChainChunks<'a> {
+    file: &'a mut File,
+    /// The chain's pages, or the chain's data if it is in the cold tier
+    source: ChunkSource,
+    len: u64,
+    offset: u64,
+    chunk_size: usize
+}
+
+/// A buffered reader over a chain's data, returned by `File::chain_reader`.
+/// The chain's pages are looked up once and its data is read a buffer at a time, so a deserializer reading from it,
+/// such as `serde_json::from_reader`, can decode a huge chain without its data ever being in memory twice.
+pub struct ChainReader<'a> {
+    file: &'a mut File,
+    source: ChunkSource,
+    len: u64,
+    /// The offset into the chain's data of the end of `buf`
+    offset: u64,
+    buf: Vec<u8>,
+    /// How many bytes of `buf` have been consumed
+    pos: usize
+}
+
+enum ChunkSource {
+    Pages(Vec<u64>),
+    Cold(Vec<u8>)
+}
+
+impl ChunkSource {
+
+    /// Fill `buf` with the chain's data starting at an offset
+    fn read_into(&self, file: &mut File, offset: u64, buf: &mut [u8]) -> Result<(), Error> {
+        match self {
+            ChunkSource::Cold(data) => buf.copy_from_slice(&data[offset as usize..offset as usize + buf.len()]),
+            ChunkSource::Pages(pages) => file.read_at(pages, file.meta_size() + offset, buf)?
+        }
+        Ok(())
+    }
+
+}
+
+impl File {
+
+    /// Read a chain's data `chunk_size` bytes at a time, such as to upload it to a server in pieces.
+    /// Only the pages covering a chunk are read for it, and each chunk comes with its FNV-1a hash so the receiver can check it arrived intact.
+    /// An upload interrupted partway through can pick up where it left off with `ChainChunks::resume_at`.
+    /// Panics if `chunk_size` is 0.
+    pub fn read_chunked(&mut self, ptr: u64, chunk_size: usize) -> Result<ChainChunks<'_>, Error> {
+        assert!(chunk_size > 0, "chunk size must not be 0");
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        let (source, len) = self.chunk_source(ptr)?;
+        Ok(ChainChunks { file: self, source, len, offset: 0, chunk_size })
+    }
+
+    /// Read a chain's data through `std::io::Read` and `std::io::BufRead`, buffering at most `Config::max_working_memory` bytes of it at a time.
+    /// Unlike `File::chain_file`, the chain's pages are only looked up once, so reading a few bytes at a time stays fast.
+    /// Chains in the cold tier are still read whole.
+    pub fn chain_reader(&mut self, ptr: u64) -> Result<ChainReader<'_>, Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        let (source, len) = self.chunk_source(ptr)?;
+        Ok(ChainReader { file: self, source, len, offset: 0, buf: Vec::new(), pos: 0 })
+    }
+
+    /// Where a chain's data is read from, along with its length
+    fn chunk_source(&mut self, ptr: u64) -> Result<(ChunkSource, u64), Error> {
+        Ok(match self.read_cold(ptr)? {
+            Some(data) => {
+                let len = data.len() as u64;
+                (ChunkSource::Cold(data), len)
+            },
+            None => {
+                let len = self.chain_len(ptr)?;
+                (ChunkSource::Pages(self.chain_layout(ptr)?.0), len)
+            }
+        })
+    }
+
+}
+
+impl ChainChunks<'_> {
+
+    /// The number of bytes of data in the chain.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the chain holds no data.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Continue from the given offset into the chain's data, such as the offset of the first chunk a server did not receive.
+    /// Chunks after that are split at the same offsets as long as the offset is a multiple of the chunk size.
+    pub fn resume_at(mut self, offset: u64) -> Self {
+        self.offset = offset.min(self.len);
+        self
+    }
+
+    fn read_chunk(&mut self, n: usize) -> Result<Vec<u8>, Error> {
+        let mut chunk = try_zeroed(n)?;
+        self.source.read_into(self.file, self.offset, &mut chunk)?;
+        Ok(chunk)
+    }
+
+}
+
+impl Iterator for ChainChunks<'_> {
+
+    type Item = Result<(u64, Vec<u8>, u64), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.len {
+            return None;
+        }
+        let n = (self.len - self.offset).min(self.chunk_size as u64) as usize;
+        let chunk = match self.read_chunk(n) {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                // Stop after an error rather than yielding it again for every remaining chunk
+                self.offset = self.len;
+                return Some(Err(err));
+            }
+        };
+        let offset = self.offset;
+        self.offset += n as u64;
+        let hash = content_hash(&chunk);
+        Some(Ok((offset, chunk, hash)))
+    }
+
+}
+
+impl ChainReader<'_> {
+
+    /// The number of bytes of data in the chain.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the chain holds no data.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+}
+
+impl BufRead for ChainReader<'_> {
+
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.pos == self.buf.len() && self.offset < self.len {
+            let n = (self.len - self.offset).min(self.file.chunk_size() as u64) as usize;
+            self.buf.resize(n, 0);
+            self.source.read_into(self.file, self.offset, &mut self.buf).map_err(to_io_error)?;
+            self.offset += n as u64;
+            self.pos = 0;
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buf.len());
+    }
+
+}
+
+impl Read for ChainReader<'_> {
+
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // Reads at least as large as the buffer skip it
+        if self.pos == self.buf.len() && buf.len() >= self.file.chunk_size() {
+            let n = (self.len - self.offset).min(buf.len() as u64) as usize;
+            self.source.read_into(self.file, self.offset, &mut buf[..n]).map_err(to_io_error)?;
+            self.offset += n as u64;
+            return Ok(n);
+        }
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+
+}
+
+#[test]
+fn read_chunked() {
+    use crate::Config;
+
+    let mut file = File::open("read_chunked.verter", Config::default()).unwrap();
+    let data = (0..3000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let ptr = file.alloc_with(&data).unwrap();
+
+    let chunks = file.read_chunked(ptr, 1024).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(chunks.iter().map(|(offset, chunk, _)| (*offset, chunk.len())).collect::<Vec<_>>(), vec![(0, 1024), (1024, 1024), (2048, 952)]);
+    assert_eq!(chunks.iter().flat_map(|(_, chunk, _)| chunk.clone()).collect::<Vec<_>>(), data);
+    assert_eq!(chunks[1].2, content_hash(&data[1024..2048]));
+
+    // Resuming after the first chunk yields the same remaining chunks
+    let resumed = file.read_chunked(ptr, 1024).unwrap().resume_at(1024).collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(resumed, chunks[1..]);
+
+    let empty = file.alloc().unwrap();
+    assert_eq!(file.read_chunked(empty, 1024).unwrap().count(), 0);
+
+    std::fs::remove_file("read_chunked.verter").unwrap();
+}
+
+#[test]
+fn chain_reader() {
+    use crate::Config;
+
+    let mut file = File::open("chain_reader.verter", Config { max_working_memory: Some(1000), ..Config::default() }).unwrap();
+    let lines = (0..2000).map(|i| format!("stroke {}", i)).collect::<Vec<_>>();
+    let ptr = file.alloc_with(lines.join("\n").as_bytes()).unwrap();
+
+    // Line by line, as a text deserializer would read it
+    let reader = file.chain_reader(ptr).unwrap();
+    assert_eq!(reader.len(), lines.join("\n").len() as u64);
+    assert_eq!(reader.lines().collect::<Result<Vec<_>, _>>().unwrap(), lines);
+
+    // A byte at a time, then the rest in one large read
+    let mut reader = file.chain_reader(ptr).unwrap();
+    let mut start = [0; 5];
+    for byte in &mut start {
+        reader.read_exact(std::slice::from_mut(byte)).unwrap();
+    }
+    assert_eq!(&start, b"strok");
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, lines.join("\n").as_bytes()[5..]);
+
+    let empty = file.alloc().unwrap();
+    assert_eq!(file.chain_reader(empty).unwrap().read_to_end(&mut Vec::new()).unwrap(), 0);
+
+    std::fs::remove_file("chain_reader.verter").unwrap();
+}
@@ -0,0 +1,101 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::{Error, File};
+
+/// What `File::compact` did, for applications to log or display what maintenance accomplished
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompactionReport {
+    /// The number of pages of the root chain and the application's chains that ended up at a different address
+    pub pages_moved: u64,
+    /// How many bytes smaller the file got
+    pub bytes_reclaimed: u64,
+    /// How long the compaction took
+    pub duration: Duration,
+    /// `File::fragmentation` after the compaction
+    pub fragmentation: f64,
+    /// The map from old to new pointers returned by `File::canonicalize`, for remapping the pointers stored inside chains
+    pub remap: BTreeMap<u64, u64>
+}
+
+impl File {
+
+    /// Compact the file with `File::canonicalize`, reporting what it accomplished.
+    /// Finding which pages moved walks every chain before and after the compaction.
+    pub fn compact<F: Fn(&[u8]) -> Vec<u64>>(&mut self, tracer: F) -> Result<CompactionReport, Error> {
+        let start = Instant::now();
+        self.check_for_external_changes()?;
+        let old_size = self.file_size()?;
+        let mut old_pages = HashMap::new();
+        for head in self.chain_heads()? {
+            old_pages.insert(head, self.chain_pages(head)?);
+        }
+
+        let remap = self.canonicalize(tracer)?;
+
+        let mut pages_moved = 0;
+        for (old, new) in &remap {
+            let old_pages = old_pages.get(old).map(Vec::as_slice).unwrap_or_default();
+            let new_pages = self.chain_pages(*new)?;
+            let kept = old_pages.iter().zip(&new_pages).filter(|(old, new)| old == new).count();
+            pages_moved += (new_pages.len() - kept) as u64;
+        }
+        Ok(CompactionReport {
+            pages_moved,
+            bytes_reclaimed: old_size.saturating_sub(self.file_size()?),
+            duration: start.elapsed(),
+            fragmentation: self.fragmentation()?,
+            remap
+        })
+    }
+
+    /// How scattered the file's chains are, from 0 when every chain's pages are adjacent to each other
+    /// to 1 when no page directly follows the previous page of its chain.
+    /// Walks every chain in the file.
+    pub fn fragmentation(&mut self) -> Result<f64, Error> {
+        self.check_for_external_changes()?;
+        let mut links = 0;
+        let mut jumps = 0;
+        for head in self.chain_heads()? {
+            let pages = self.chain_pages(head)?;
+            links += pages.len() - 1;
+            jumps += self.page_runs(&pages).len() - 1;
+        }
+        Ok(if links == 0 { 0.0 } else { jumps as f64 / links as f64 })
+    }
+
+}
+
+#[test]
+fn compact() {
+    use crate::{decode_u64s, encode_u64s, Config};
+
+    let mut file = File::open("compact.verter", Config::default()).unwrap();
+    // Grow the chains in turn so that their pages are interleaved, then delete one to leave holes
+    let chains = (0..3).map(|_| file.alloc().unwrap()).collect::<Vec<_>>();
+    for round in 1..=4 {
+        for ptr in &chains {
+            file.write(*ptr, &vec![round as u8; round * 200]).unwrap();
+        }
+    }
+    file.delete(chains[1]).unwrap();
+    file.write_root(&encode_u64s(&[chains[0], chains[2]])).unwrap();
+    let before = file.fragmentation().unwrap();
+    assert!(before > 0.0);
+    let old_size = std::fs::metadata("compact.verter").unwrap().len();
+
+    let report = file.compact(|data| decode_u64s(data).unwrap_or_default()).unwrap();
+    assert_eq!(report.fragmentation, 0.0);
+    assert!(report.pages_moved > 0);
+    assert_eq!(report.bytes_reclaimed, old_size - std::fs::metadata("compact.verter").unwrap().len());
+    assert!(report.bytes_reclaimed > 0);
+    assert_eq!(report.remap.len(), 3);
+    assert_eq!(file.read(report.remap[&chains[2]]).unwrap(), vec![4; 800]);
+    file.validate().unwrap();
+
+    // Compacting a compact file moves nothing
+    let report = file.compact(|data| decode_u64s(data).unwrap_or_default()).unwrap();
+    assert_eq!((report.pages_moved, report.bytes_reclaimed), (0, 0));
+
+    std::fs::remove_file("compact.verter").unwrap();
+}
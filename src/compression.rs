@@ -0,0 +1,118 @@
+use std::io::{Read, Write};
+
+use crate::{decode_u64s, encode_u64s, Error, File, BYTES_IN_U64};
+
+/// Marks compressed chains that were compressed without a dictionary
+const NO_DICTIONARY: u64 = u64::MAX;
+
+impl File {
+
+    /// Write zstd-compressed data to a chain, using the most recently trained dictionary if there is one.
+    /// The chain must be read back using `File::read_compressed`.
+    pub fn write_compressed(&mut self, ptr: u64, data: &[u8]) -> Result<(), Error> {
+        self.write_compressed_at(ptr, data, zstd::DEFAULT_COMPRESSION_LEVEL)
+    }
+
+    /// Write zstd-compressed data to a chain at the given compression level
+    pub(crate) fn write_compressed_at(&mut self, ptr: u64, data: &[u8], level: i32) -> Result<(), Error> {
+        let dictionaries = self.read_dictionary_list()?;
+        let (dictionary_idx, dictionary) = match dictionaries.last() {
+            Some(dictionary) => (dictionaries.len() as u64 - 1, self.read(*dictionary)?),
+            None => (NO_DICTIONARY, Vec::new())
+        };
+
+        let mut encoder = zstd::stream::Encoder::with_dictionary(Vec::new(), level, &dictionary).map_err(Error::IO)?;
+        encoder.write_all(data).map_err(Error::IO)?;
+        let compressed = encoder.finish().map_err(Error::IO)?;
+
+        let mut chain_data = dictionary_idx.to_le_bytes().to_vec();
+        chain_data.extend_from_slice(&compressed);
+        self.write(ptr, &chain_data)
+    }
+
+    /// Read a chain written with `File::write_compressed`.
+    pub fn read_compressed(&mut self, ptr: u64) -> Result<Vec<u8>, Error> {
+        let chain_data = self.read(ptr)?;
+        let (dictionary_idx, compressed) = chain_data.split_at_checked(BYTES_IN_U64 as usize).ok_or(Error::CorruptedFile)?;
+        let dictionary_idx = u64::from_le_bytes(dictionary_idx.try_into().unwrap());
+
+        let dictionary = if dictionary_idx == NO_DICTIONARY {
+            Vec::new()
+        } else {
+            let dictionaries = self.read_dictionary_list()?;
+            let dictionary = *dictionaries.get(dictionary_idx as usize).ok_or(Error::CorruptedFile)?;
+            self.read(dictionary)?
+        };
+
+        let mut decoder = zstd::stream::Decoder::with_dictionary(compressed, &dictionary).map_err(Error::IO)?;
+        let mut data = Vec::new();
+        decoder.read_to_end(&mut data).map_err(|_| Error::CorruptedFile)?;
+        Ok(data)
+    }
+
+    /// Train a zstd dictionary of at most `max_size` bytes over the contents of every chain in the file, and store it in the file.
+    /// Subsequent calls to `write_compressed` will use the new dictionary.
+    /// Previously trained dictionaries are kept, so that chains compressed with them stay readable.
+    pub fn train_dictionary(&mut self, max_size: usize) -> Result<(), Error> {
+        let internal_chains = self.internal_chains()?;
+        let mut samples = Vec::new();
+        for chain in self.chain_heads()? {
+            if !internal_chains.contains(&chain) {
+                samples.push(self.read(chain)?);
+            }
+        }
+
+        let dictionary = zstd::dict::from_samples(&samples, max_size).map_err(Error::IO)?;
+        let dictionary_chain = self.alloc()?;
+        self.write_chain(dictionary_chain, &dictionary)?;
+
+        let mut dictionaries = self.read_dictionary_list()?;
+        dictionaries.push(dictionary_chain);
+        let mut list_chain = self.read_u64(self.dictionaries_ptr())?;
+        if list_chain == 0 {
+            list_chain = self.alloc()?;
+            self.write_u64(self.dictionaries_ptr(), list_chain)?;
+        }
+        self.write_chain(list_chain, &encode_u64s(&dictionaries))
+    }
+
+    fn read_dictionary_list(&mut self) -> Result<Vec<u64>, Error> {
+        let list_chain = self.read_u64(self.dictionaries_ptr())?;
+        if list_chain == 0 {
+            return Ok(Vec::new());
+        }
+        decode_u64s(&self.read(list_chain)?)
+    }
+
+}
+
+#[test]
+fn dictionary_compression() {
+    use crate::Config;
+
+    let mut file = File::open("dictionary_compression.verter", Config::default()).unwrap();
+    let mut chains = Vec::new();
+    for i in 0..200u32 {
+        let palette = format!("{{\"name\": \"palette {}\", \"colors\": [\"#ff{:04x}\", \"#00{:04x}\", \"#ffffff\"]}}", i, i * 7, i * 13);
+        let chain = file.alloc().unwrap();
+        file.write(chain, palette.as_bytes()).unwrap();
+        chains.push(chain);
+    }
+
+    let palette = b"{\"name\": \"palette 1000\", \"colors\": [\"#ff1b58\", \"#003268\", \"#ffffff\"]}";
+    let before = file.alloc().unwrap();
+    file.write_compressed(before, palette).unwrap();
+
+    file.train_dictionary(1024).unwrap();
+    let after = file.alloc().unwrap();
+    file.write_compressed(after, palette).unwrap();
+    assert!(file.read(after).unwrap().len() < file.read(before).unwrap().len());
+    drop(file);
+
+    // Chains compressed before and after training are both readable
+    let mut file = File::open("dictionary_compression.verter", Config::default()).unwrap();
+    assert_eq!(file.read_compressed(before).unwrap(), palette);
+    assert_eq!(file.read_compressed(after).unwrap(), palette);
+
+    std::fs::remove_file("dictionary_compression.verter").unwrap();
+}
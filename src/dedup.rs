@@ -0,0 +1,97 @@
+use crate::{decode_u64s, encode_u64s, Error, File};
+
+/// 64-bit FNV-1a, used because its output is stable across platforms and Rust versions
+pub(crate) fn content_hash(data: &[u8]) -> u64 {
+    extend_hash(HASH_SEED, data)
+}
+
+/// The hash of no bytes, which `extend_hash` builds on
+pub(crate) const HASH_SEED: u64 = 0xcbf29ce484222325;
+
+/// Continue a `content_hash` with more bytes, for hashing data that arrives in pieces
+pub(crate) fn extend_hash(hash: u64, data: &[u8]) -> u64 {
+    data.iter().fold(hash, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+impl File {
+
+    /// Store data, reusing an existing chain with identical contents if there is one.
+    /// Reused chains get their reference count incremented, so release them with `File::decref`.
+    /// Only chains created by `write_dedup` are candidates for reuse.
+    pub fn write_dedup(&mut self, data: &[u8]) -> Result<u64, Error> {
+        let hash = content_hash(data);
+        let mut table = self.read_dedup_table()?;
+
+        let mut i = 0;
+        while i < table.len() {
+            let (entry_hash, chain) = table[i];
+            if entry_hash != hash {
+                i += 1;
+                continue;
+            }
+            // Chains freed since they were stored are dropped from the table
+            if self.check_if_pointer_valid(chain).is_err() {
+                table.remove(i);
+                continue;
+            }
+            if self.read(chain)? == data {
+                self.write_dedup_table(&table)?;
+                self.incref(chain)?;
+                return Ok(chain);
+            }
+            i += 1;
+        }
+
+        let chain = self.alloc()?;
+        self.write(chain, data)?;
+        table.push((hash, chain));
+        self.write_dedup_table(&table)?;
+        Ok(chain)
+    }
+
+    pub(crate) fn read_dedup_table(&mut self) -> Result<Vec<(u64, u64)>, Error> {
+        let chain = self.read_u64(self.dedup_table_ptr())?;
+        if chain == 0 {
+            return Ok(Vec::new());
+        }
+        let entries = decode_u64s(&self.read(chain)?)?;
+        if !entries.len().is_multiple_of(2) {
+            return Err(Error::CorruptedFile);
+        }
+        Ok(entries.chunks_exact(2).map(|entry| (entry[0], entry[1])).collect())
+    }
+
+    pub(crate) fn write_dedup_table(&mut self, table: &[(u64, u64)]) -> Result<(), Error> {
+        let mut chain = self.read_u64(self.dedup_table_ptr())?;
+        if chain == 0 {
+            chain = self.alloc()?;
+            self.write_u64(self.dedup_table_ptr(), chain)?;
+        }
+        let entries = table.iter().flat_map(|(hash, chain)| [*hash, *chain]).collect::<Vec<_>>();
+        self.write_chain(chain, &encode_u64s(&entries))
+    }
+
+}
+
+#[test]
+fn dedup() {
+    use crate::Config;
+
+    let mut file = File::open("dedup.verter", Config::default()).unwrap();
+    let brush = file.write_dedup(&[0xAB; 300]).unwrap();
+    let palette = file.write_dedup(b"palette").unwrap();
+    assert_ne!(brush, palette);
+    drop(file);
+
+    let mut file = File::open("dedup.verter", Config::default()).unwrap();
+    assert_eq!(file.write_dedup(&[0xAB; 300]).unwrap(), brush);
+    assert_eq!(file.refcount(brush).unwrap(), 2);
+
+    // Once all references are released, the content gets stored again
+    file.decref(palette).unwrap();
+    let new_palette = file.write_dedup(b"palette").unwrap();
+    assert_eq!(file.read(new_palette).unwrap(), b"palette");
+    assert_eq!(file.refcount(new_palette).unwrap(), 1);
+
+    std::fs::remove_file("dedup.verter").unwrap();
+}
@@ -0,0 +1,218 @@
+//! Compact deltas between two versions of a chain's data, found using a rolling hash.
+
+use std::collections::HashMap;
+
+use crate::{Error, File, BYTES_IN_U64};
+
+/// The size of the blocks of old data that can be copied into the new data
+const BLOCK_SIZE: usize = 64;
+
+/// One step in rebuilding the new data
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeltaOp {
+    /// Copy bytes from the old data
+    Copy { offset: u64, len: u64 },
+    /// Insert bytes that do not appear in the old data
+    Insert(Vec<u8>)
+}
+
+/// The changes needed to turn the old version of a chain's data into the new version
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Delta {
+    pub ops: Vec<DeltaOp>
+}
+
+impl Delta {
+
+    const COPY: u8 = 0;
+    const INSERT: u8 = 1;
+
+    /// Serialize the delta, so it can be sent over the network or stored.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for op in &self.ops {
+            match op {
+                DeltaOp::Copy { offset, len } => {
+                    bytes.push(Self::COPY);
+                    bytes.extend_from_slice(&offset.to_le_bytes());
+                    bytes.extend_from_slice(&len.to_le_bytes());
+                },
+                DeltaOp::Insert(data) => {
+                    bytes.push(Self::INSERT);
+                    bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+                    bytes.extend_from_slice(data);
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Deserialize a delta produced by `Delta::to_bytes`.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, Error> {
+        fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], Error> {
+            let (taken, rest) = bytes.split_at_checked(n).ok_or(Error::CorruptedFile)?;
+            *bytes = rest;
+            Ok(taken)
+        }
+        fn take_u64(bytes: &mut &[u8]) -> Result<u64, Error> {
+            Ok(u64::from_le_bytes(take(bytes, BYTES_IN_U64 as usize)?.try_into().unwrap()))
+        }
+
+        let mut ops = Vec::new();
+        while !bytes.is_empty() {
+            match take(&mut bytes, 1)?[0] {
+                Self::COPY => {
+                    let offset = take_u64(&mut bytes)?;
+                    let len = take_u64(&mut bytes)?;
+                    ops.push(DeltaOp::Copy { offset, len });
+                },
+                Self::INSERT => {
+                    let len = take_u64(&mut bytes)?;
+                    ops.push(DeltaOp::Insert(take(&mut bytes, len as usize)?.to_vec()));
+                },
+                _ => return Err(Error::CorruptedFile)
+            }
+        }
+        Ok(Self { ops })
+    }
+
+    /// Rebuild the new data from the old data.
+    pub fn apply(&self, old: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut new = Vec::new();
+        for op in &self.ops {
+            match op {
+                DeltaOp::Copy { offset, len } => {
+                    let range = *offset as usize..(*offset + *len) as usize;
+                    new.extend_from_slice(old.get(range).ok_or(Error::CorruptedFile)?);
+                },
+                DeltaOp::Insert(data) => new.extend_from_slice(data)
+            }
+        }
+        Ok(new)
+    }
+
+    fn copy(&mut self, offset: usize, len: usize) {
+        if let Some(DeltaOp::Copy { offset: prev_offset, len: prev_len }) = self.ops.last_mut() {
+            if *prev_offset + *prev_len == offset as u64 {
+                *prev_len += len as u64;
+                return;
+            }
+        }
+        self.ops.push(DeltaOp::Copy { offset: offset as u64, len: len as u64 });
+    }
+
+    fn insert(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        if let Some(DeltaOp::Insert(prev)) = self.ops.last_mut() {
+            prev.extend_from_slice(data);
+            return;
+        }
+        self.ops.push(DeltaOp::Insert(data.to_vec()));
+    }
+
+}
+
+/// The weak rolling checksum from rsync
+#[derive(Clone, Copy)]
+struct RollingHash {
+    a: u32,
+    b: u32
+}
+
+impl RollingHash {
+
+    fn new(block: &[u8]) -> Self {
+        let mut a = 0u32;
+        let mut b = 0u32;
+        for (i, byte) in block.iter().enumerate() {
+            a = a.wrapping_add(*byte as u32);
+            b = b.wrapping_add((block.len() - i) as u32 * *byte as u32);
+        }
+        Self { a, b }
+    }
+
+    fn roll(&mut self, out: u8, into: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(into as u32);
+        self.b = self.b.wrapping_sub(BLOCK_SIZE as u32 * out as u32).wrapping_add(self.a);
+    }
+
+    fn digest(&self) -> u32 {
+        (self.a & 0xFFFF) | (self.b << 16)
+    }
+
+}
+
+/// Compute the delta between the current contents of a chain and new data.
+pub fn chain_diff(file: &mut File, old_ptr: u64, new_data: &[u8]) -> Result<Delta, Error> {
+    let old_data = file.read(old_ptr)?;
+    Ok(diff(&old_data, new_data))
+}
+
+/// Replace the contents of a chain by applying a delta to them.
+pub fn apply_delta(file: &mut File, ptr: u64, delta: &Delta) -> Result<(), Error> {
+    let old_data = file.read(ptr)?;
+    let new_data = delta.apply(&old_data)?;
+    file.write(ptr, &new_data)
+}
+
+/// Compute the delta between two versions of some data.
+pub fn diff(old: &[u8], new: &[u8]) -> Delta {
+    let mut blocks = HashMap::<u32, Vec<usize>>::new();
+    for (i, block) in old.chunks_exact(BLOCK_SIZE).enumerate() {
+        blocks.entry(RollingHash::new(block).digest()).or_default().push(i * BLOCK_SIZE);
+    }
+
+    let mut delta = Delta::default();
+    let mut literal_start = 0;
+    let mut pos = 0;
+    let mut hash = (new.len() >= BLOCK_SIZE).then(|| RollingHash::new(&new[..BLOCK_SIZE]));
+    while let Some(current) = hash {
+        let window = &new[pos..pos + BLOCK_SIZE];
+        let matched = blocks.get(&current.digest())
+            .and_then(|offsets| offsets.iter().find(|offset| &old[**offset..**offset + BLOCK_SIZE] == window));
+
+        if let Some(offset) = matched {
+            delta.insert(&new[literal_start..pos]);
+            delta.copy(*offset, BLOCK_SIZE);
+            pos += BLOCK_SIZE;
+            literal_start = pos;
+            hash = (pos + BLOCK_SIZE <= new.len()).then(|| RollingHash::new(&new[pos..pos + BLOCK_SIZE]));
+        } else if pos + BLOCK_SIZE < new.len() {
+            let mut next = current;
+            next.roll(new[pos], new[pos + BLOCK_SIZE]);
+            pos += 1;
+            hash = Some(next);
+        } else {
+            hash = None;
+        }
+    }
+    delta.insert(&new[literal_start..]);
+
+    delta
+}
+
+#[test]
+fn rolling_diff() {
+    use crate::Config;
+
+    let mut file = File::open("rolling_diff.verter", Config::default()).unwrap();
+    let old = (0..10000u32).map(|i| (i * 7 % 251) as u8).collect::<Vec<_>>();
+    let ptr = file.alloc().unwrap();
+    file.write(ptr, &old).unwrap();
+
+    let mut new = old.clone();
+    new.splice(3000..3000, b"inserted keyframe".iter().copied());
+    new.drain(7000..7100);
+    new[500] = 0xFF;
+
+    let delta = chain_diff(&mut file, ptr, &new).unwrap();
+    let bytes = delta.to_bytes();
+    assert!(bytes.len() < new.len() / 10);
+
+    apply_delta(&mut file, ptr, &Delta::from_bytes(&bytes).unwrap()).unwrap();
+    assert_eq!(file.read(ptr).unwrap(), new);
+
+    std::fs::remove_file("rolling_diff.verter").unwrap();
+}
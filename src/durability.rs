@@ -0,0 +1,191 @@
+use std::time::{Duration, Instant};
+
+use crate::{Error, File};
+
+/// How often changes to the file are synced to durable storage
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Durability {
+    /// Only sync when `File::flush` is called, or by operations that always sync, such as `File::write_batch`.
+    /// A crash can lose any change since then.
+    #[default]
+    OnFlush,
+    /// Sync at the end of every operation that changes the file. Nothing is lost in a crash, but every write waits for the disk.
+    EveryChange,
+    /// Sync after a change once `max_delay` has passed or `max_bytes` bytes of chain data were written since the last sync,
+    /// so a crash loses at most about that much work. Suits interactive editors that can afford to lose a second of work.
+    /// The check happens when the file is changed, so changes followed by a long pause stay unsynced until the next change or `File::flush`.
+    Periodic {
+        max_delay: Duration,
+        max_bytes: u64
+    }
+}
+
+/// What has been written since the file was last synced
+pub(crate) struct SyncState {
+    last_sync: Instant,
+    unsynced_bytes: u64
+}
+
+impl Default for SyncState {
+
+    fn default() -> Self {
+        Self {
+            last_sync: Instant::now(),
+            unsynced_bytes: 0
+        }
+    }
+
+}
+
+impl File {
+
+    /// Make sure every change made to the file so far has reached durable storage.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.file.sync_data().map_err(Error::IO)?;
+        self.sync_state = SyncState::default();
+        Ok(())
+    }
+
+    /// Note that `bytes` bytes of chain data were written, to count towards `Durability::Periodic::max_bytes`
+    pub(crate) fn note_unsynced(&mut self, bytes: u64) {
+        self.sync_state.unsynced_bytes += bytes;
+    }
+
+    /// Run an operation that changes the file as a single change, bumping the change counter and syncing the file
+    /// if `Config::durability` calls for it once at the end, instead of after every page the operation touches.
+    /// Operations run inside another one are part of the outer operation.
+    pub(crate) fn as_one_change<T, F: FnOnce(&mut Self) -> Result<T, Error>>(&mut self, op: F) -> Result<T, Error> {
+        self.as_one_change_then(op, Self::bump_change_counter)
+    }
+
+    /// Run an operation as a single change like `File::as_one_change`, calling `finish` instead of bumping the change counter once it is done
+    pub(crate) fn as_one_change_then<T, F: FnOnce(&mut Self) -> Result<T, Error>>(&mut self, op: F, finish: fn(&mut Self) -> Result<(), Error>) -> Result<T, Error> {
+        if self.pending_change.is_some() {
+            return op(self);
+        }
+        self.pending_change = Some(false);
+        let result = op(self);
+        // A failed operation may have changed the file part way, so its change is counted too
+        if self.pending_change.take() == Some(true) {
+            finish(self)?;
+        }
+        result
+    }
+
+    /// Sync the file after a change if `Config::durability` calls for it
+    pub(crate) fn sync_if_due(&mut self) -> Result<(), Error> {
+        let due = match self.config.durability {
+            Durability::OnFlush => false,
+            Durability::EveryChange => true,
+            Durability::Periodic { max_delay, max_bytes } => {
+                self.sync_state.last_sync.elapsed() >= max_delay || self.sync_state.unsynced_bytes >= max_bytes
+            }
+        };
+        if due {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+}
+
+#[test]
+fn durability() {
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use crate::{Backend, Config};
+
+    /// An in-memory backend that counts how many times it was synced
+    struct Syncs {
+        bytes: Cursor<Vec<u8>>,
+        syncs: Arc<AtomicU32>
+    }
+
+    impl Read for Syncs {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.bytes.read(buf)
+        }
+    }
+
+    impl Write for Syncs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.bytes.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.bytes.flush()
+        }
+    }
+
+    impl Seek for Syncs {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.bytes.seek(pos)
+        }
+    }
+
+    impl Backend for Syncs {
+        fn len(&self) -> std::io::Result<u64> {
+            self.bytes.len()
+        }
+
+        fn sync_data(&self) -> std::io::Result<()> {
+            self.syncs.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+            self.bytes.read_exact_at(buf, offset)
+        }
+
+        fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+            self.bytes.set_len(len)
+        }
+    }
+
+    let open = |durability| {
+        let syncs = Arc::new(AtomicU32::new(0));
+        let config = Config {
+            durability,
+            ..Config::default()
+        };
+        let file = File::from_backend(Box::new(Syncs { bytes: Cursor::new(Vec::new()), syncs: syncs.clone() }), config, true).unwrap();
+        (file, syncs)
+    };
+
+    let (mut file, syncs) = open(Durability::OnFlush);
+    file.alloc_with(&[0xAB; 100]).unwrap();
+    assert_eq!(syncs.load(Ordering::Relaxed), 0);
+    file.flush().unwrap();
+    assert_eq!(syncs.load(Ordering::Relaxed), 1);
+
+    // Every operation is synced once, however many pages it touches
+    let (mut file, syncs) = open(Durability::EveryChange);
+    let opened = syncs.load(Ordering::Relaxed);
+    let ptr = file.alloc_with(&[0xAB; 100]).unwrap();
+    assert_eq!(syncs.load(Ordering::Relaxed), opened + 1);
+    file.write(ptr, &[0xAB; 100_000]).unwrap();
+    assert_eq!(syncs.load(Ordering::Relaxed), opened + 2);
+    file.delete(ptr).unwrap();
+    assert_eq!(syncs.load(Ordering::Relaxed), opened + 3);
+
+    // A batch of writes is synced once, whatever the durability
+    let (a, b) = (file.alloc().unwrap(), file.alloc().unwrap());
+    let before = syncs.load(Ordering::Relaxed);
+    file.write_batch(&[(a, &[0xCD; 50_000]), (b, &[0xEF; 50_000])]).unwrap();
+    assert_eq!(syncs.load(Ordering::Relaxed), before + 1);
+
+    // Small writes are batched until enough bytes have been written
+    let (mut file, syncs) = open(Durability::Periodic { max_delay: Duration::from_secs(3600), max_bytes: 1000 });
+    let ptr = file.alloc().unwrap();
+    file.write(ptr, &[0xAB; 100]).unwrap();
+    assert_eq!(syncs.load(Ordering::Relaxed), 0);
+    file.write(ptr, &[0xAB; 1000]).unwrap();
+    assert_eq!(syncs.load(Ordering::Relaxed), 1);
+    file.write(ptr, &[0xAB; 100]).unwrap();
+    assert_eq!(syncs.load(Ordering::Relaxed), 1);
+
+    let (mut file, syncs) = open(Durability::Periodic { max_delay: Duration::ZERO, max_bytes: u64::MAX });
+    file.alloc().unwrap();
+    assert!(syncs.load(Ordering::Relaxed) > 0);
+}
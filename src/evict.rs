@@ -0,0 +1,96 @@
+use std::collections::HashSet;
+use std::time::SystemTime;
+
+use crate::{ChainMeta, Error, File};
+
+impl File {
+
+    /// Delete every chain that has not been modified since `expired_before`, returning the pointers to the deleted chains.
+    /// Every chain in the file except the root chains and the chains of partitions is a candidate, so this is meant for files used as caches.
+    /// Requires `Config::chain_meta` to be enabled.
+    pub fn evict(&mut self, expired_before: SystemTime) -> Result<Vec<u64>, Error> {
+        let mut evicted = Vec::new();
+        for (ptr, meta, _) in self.evictable_chains()? {
+            if meta.modified < expired_before {
+                self.delete(ptr)?;
+                evicted.push(ptr);
+            }
+        }
+        Ok(evicted)
+    }
+
+    /// Delete the least recently modified chains until the pages of the remaining chains take up at most `target_bytes`,
+    /// returning the pointers to the deleted chains.
+    /// Every chain in the file except the root chains and the chains of partitions is a candidate, so this is meant for files used as caches.
+    /// Requires `Config::chain_meta` to be enabled.
+    pub fn evict_lru(&mut self, target_bytes: u64) -> Result<Vec<u64>, Error> {
+        let mut chains = self.evictable_chains()?;
+        chains.sort_by_key(|(_, meta, _)| meta.modified);
+        let mut total_bytes = chains.iter().map(|(_, _, bytes)| bytes).sum::<u64>();
+
+        let mut evicted = Vec::new();
+        for (ptr, _, bytes) in chains {
+            if total_bytes <= target_bytes {
+                break;
+            }
+            self.delete(ptr)?;
+            evicted.push(ptr);
+            total_bytes -= bytes;
+        }
+        Ok(evicted)
+    }
+
+    /// Every chain except the root chains, internal chains and the chains of partitions, along with its metadata and the number of bytes its pages take up
+    fn evictable_chains(&mut self) -> Result<Vec<(u64, ChainMeta, u64)>, Error> {
+        self.check_for_external_changes()?;
+        let mut kept = self.internal_chains()?.into_iter().collect::<HashSet<_>>();
+        kept.extend(self.root_chains()?);
+        kept.extend(self.partition_user_chains()?);
+
+        let mut chains = Vec::new();
+        for ptr in self.chain_heads()? {
+            if kept.contains(&ptr) {
+                continue;
+            }
+            let meta = self.chain_meta(ptr)?;
+            let bytes = self.chain_pages(ptr)?.len() as u64 * self.total_page_size();
+            chains.push((ptr, meta, bytes));
+        }
+        Ok(chains)
+    }
+
+}
+
+#[test]
+fn evict() {
+    use crate::Config;
+
+    let config = Config {
+        chain_meta: true,
+        ..Config::default()
+    };
+
+    let mut file = File::open("evict.verter", config).unwrap();
+    // Chains of partitions are only deleted through their partition
+    let plugin = file.partition("plugin").unwrap().alloc_with(b"plugin").unwrap();
+    let old = file.alloc().unwrap();
+    file.write(old, &[1; 300]).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    let cutoff = SystemTime::now();
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    let recent = file.alloc().unwrap();
+    file.write(recent, &[2; 300]).unwrap();
+    let newest = file.alloc().unwrap();
+    file.write(newest, &[3; 100]).unwrap();
+
+    assert_eq!(file.evict(cutoff).unwrap(), vec![old]);
+    assert!(file.evict(cutoff).unwrap().is_empty());
+
+    // Keep only the single page of the newest thumbnail
+    assert_eq!(file.evict_lru(file.total_page_size()).unwrap(), vec![recent]);
+    assert_eq!(file.read(newest).unwrap(), vec![3; 100]);
+    assert_eq!(file.read_root().unwrap(), b"");
+    assert_eq!(file.partition("plugin").unwrap().read(plugin).unwrap(), b"plugin");
+
+    std::fs::remove_file("evict.verter").unwrap();
+}
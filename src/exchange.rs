@@ -0,0 +1,186 @@
+//! The verter exchange format, a simple stable format for moving data between verter files and programs in other languages,
+//! which can read and write it without implementing verter's page layout.
+//!
+//! An exchange stream starts with the 8 magic bytes `VERTERXC`, followed by the format version as a little-endian u32, currently 1.
+//! Then come the entries, each made of:
+//! - a tag byte: 0 for the root chain, 1 for the root chain of a partition, 2 for any other chain, or 255 for the end of the stream
+//! - for partition roots, the length of the partition's name as a little-endian u64 followed by the name in UTF-8,
+//!   and for other chains, the chain's pointer in the source file as a little-endian u64
+//! - the length of the data as a little-endian u64, followed by the data
+//! - the 64-bit FNV-1a hash of the data as a little-endian u64
+//!
+//! The end tag has nothing after it. Pointers only identify chains, so a program writing a stream may number its chains however it likes.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use crate::dedup::{content_hash, extend_hash, HASH_SEED};
+use crate::inspect::{user_chains, ChainKey};
+use crate::{Error, File, BYTES_IN_U64};
+
+const MAGIC: &[u8; 8] = b"VERTERXC";
+const VERSION: u32 = 1;
+
+const ROOT: u8 = 0;
+const PARTITION_ROOT: u8 = 1;
+const CHAIN: u8 = 2;
+const END: u8 = 255;
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, Error> {
+    let mut bytes = [0; BYTES_IN_U64 as usize];
+    reader.read_exact(&mut bytes).map_err(Error::IO)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>, Error> {
+    let len = read_u64(reader)?;
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes).map_err(Error::IO)?;
+    if bytes.len() as u64 != len {
+        return Err(Error::CorruptedFile);
+    }
+    Ok(bytes)
+}
+
+/// Read an entry's data, checking it against the hash after it
+fn read_data<R: Read>(reader: &mut R) -> Result<Vec<u8>, Error> {
+    let data = read_bytes(reader)?;
+    if read_u64(reader)? != content_hash(&data) {
+        return Err(Error::CorruptedFile);
+    }
+    Ok(data)
+}
+
+impl File {
+
+    /// Write every chain holding user data to `writer` in the exchange format.
+    /// The chains verter uses internally, such as the undo history, are left out.
+    pub fn export_exchange<W: Write>(&mut self, writer: &mut W) -> Result<(), Error> {
+        writer.write_all(MAGIC).map_err(Error::IO)?;
+        writer.write_all(&VERSION.to_le_bytes()).map_err(Error::IO)?;
+        for (key, ptr) in user_chains(self)? {
+            let mut entry = Vec::new();
+            match key {
+                ChainKey::Root => entry.push(ROOT),
+                ChainKey::PartitionRoot(name) => {
+                    entry.push(PARTITION_ROOT);
+                    entry.extend_from_slice(&(name.len() as u64).to_le_bytes());
+                    entry.extend_from_slice(name.as_bytes());
+                },
+                ChainKey::Chain(ptr) => {
+                    entry.push(CHAIN);
+                    entry.extend_from_slice(&ptr.to_le_bytes());
+                }
+            }
+            if let Some(data) = self.read_cold(ptr)? {
+                entry.extend_from_slice(&(data.len() as u64).to_le_bytes());
+                entry.extend_from_slice(&data);
+                entry.extend_from_slice(&content_hash(&data).to_le_bytes());
+                writer.write_all(&entry).map_err(Error::IO)?;
+                continue;
+            }
+
+            // Stream the data, so that a large chain is never held in memory
+            entry.extend_from_slice(&self.chain_len(ptr)?.to_le_bytes());
+            writer.write_all(&entry).map_err(Error::IO)?;
+            let mut hash = HASH_SEED;
+            self.stream_chain(ptr, false, |chunk| {
+                hash = extend_hash(hash, chunk);
+                writer.write_all(chunk).map_err(Error::IO)
+            })?;
+            writer.write_all(&hash.to_le_bytes()).map_err(Error::IO)?;
+        }
+        writer.write_all(&[END]).map_err(Error::IO)
+    }
+
+    /// Read a stream in the exchange format from `reader`, writing the root chains it holds over this file's root chains
+    /// and copying every other chain into a new chain, which is not part of any partition.
+    /// Returns where each entry of the stream ended up. Fails with `Error::InvalidFile` if the stream doesn't start with the magic bytes,
+    /// `Error::UnsupportedVersion` if it is from a newer version of the format, and `Error::CorruptedFile` if an entry's hash doesn't match its data.
+    /// Entries before a corrupted one have already been imported.
+    pub fn import_exchange<R: Read>(&mut self, reader: &mut R) -> Result<BTreeMap<ChainKey, u64>, Error> {
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic).map_err(Error::IO)?;
+        if &magic != MAGIC {
+            return Err(Error::InvalidFile);
+        }
+        let mut version = [0; 4];
+        reader.read_exact(&mut version).map_err(Error::IO)?;
+        if u32::from_le_bytes(version) != VERSION {
+            return Err(Error::UnsupportedVersion);
+        }
+
+        let mut imported = BTreeMap::new();
+        loop {
+            let mut tag = [0];
+            reader.read_exact(&mut tag).map_err(Error::IO)?;
+            let key = match tag[0] {
+                ROOT => ChainKey::Root,
+                PARTITION_ROOT => ChainKey::PartitionRoot(String::from_utf8(read_bytes(reader)?).map_err(|_| Error::CorruptedFile)?),
+                CHAIN => ChainKey::Chain(read_u64(reader)?),
+                END => return Ok(imported),
+                _ => return Err(Error::CorruptedFile)
+            };
+            let ptr = match &key {
+                ChainKey::Root => {
+                    self.write_root(&read_data(reader)?)?;
+                    self.root_page()?
+                },
+                ChainKey::PartitionRoot(name) => {
+                    self.partition(name)?.write_root(&read_data(reader)?)?;
+                    self.read_partition_table()?.into_iter()
+                        .find(|entry| entry.name == *name)
+                        .ok_or(Error::CorruptedFile)?
+                        .root
+                },
+                ChainKey::Chain(_) => {
+                    // Streamed into the new chain, so that a large chain is never held in memory
+                    let len = read_u64(reader)?;
+                    let mut hash = HASH_SEED;
+                    let ptr = self.alloc_streamed(reader, len, |chunk| hash = extend_hash(hash, chunk))?;
+                    if read_u64(reader)? != hash {
+                        self.delete(ptr)?;
+                        return Err(Error::CorruptedFile);
+                    }
+                    ptr
+                }
+            };
+            imported.insert(key, ptr);
+        }
+    }
+
+}
+
+#[test]
+fn exchange() {
+    use crate::Config;
+
+    let mut src = File::open("exchange_src.verter", Config::default()).unwrap();
+    src.write_root(b"project").unwrap();
+    let frame = src.alloc_with(&[0xAB; 500]).unwrap();
+    src.partition("cache").unwrap().write_root(b"cache").unwrap();
+    let mut stream = Vec::new();
+    src.export_exchange(&mut stream).unwrap();
+    assert!(stream.starts_with(b"VERTERXC\x01\x00\x00\x00"));
+
+    let mut dest = File::open("exchange_dest.verter", Config::default()).unwrap();
+    let imported = dest.import_exchange(&mut stream.as_slice()).unwrap();
+    assert_eq!(dest.read_root().unwrap(), b"project");
+    assert_eq!(dest.read(imported[&ChainKey::Chain(frame)]).unwrap(), vec![0xAB; 500]);
+    assert_eq!(dest.partition("cache").unwrap().read_root().unwrap(), b"cache");
+
+    // Flip a byte of the frame's data
+    let pos = stream.windows(4).position(|window| window == [0xAB; 4]).unwrap();
+    stream[pos] = 0xAC;
+    match dest.import_exchange(&mut stream.as_slice()) {
+        Err(Error::CorruptedFile) => {},
+        Ok(_) | Err(_) => panic!("should error with corrupted file")
+    }
+    match dest.import_exchange(&mut &b"VERTER__"[..]) {
+        Err(Error::InvalidFile) => {},
+        Ok(_) | Err(_) => panic!("should error with invalid file")
+    }
+
+    std::fs::remove_file("exchange_src.verter").unwrap();
+    std::fs::remove_file("exchange_dest.verter").unwrap();
+}
@@ -0,0 +1,210 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, Receiver};
+
+use crate::{Error, File, PageHeader};
+
+/// How many chunks of file data `File::import_dir` reads ahead of the chunks being written
+const READ_AHEAD_CHUNKS: usize = 4;
+
+/// Reads the data of the files being imported by `File::import_dir` from the thread reading them
+struct ImportReader {
+    chunks: Receiver<std::io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    /// How many bytes of `chunk` have been read
+    pos: usize
+}
+
+impl Read for ImportReader {
+
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            self.chunk = self.chunks.recv().map_err(|_| std::io::ErrorKind::UnexpectedEof)??;
+            self.pos = 0;
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+
+}
+
+impl File {
+
+    /// Copy the data of a chain into a standalone file, replacing the file if it exists.
+    /// The data is streamed, so the whole chain is never held in memory.
+    pub fn export_chain<P: AsRef<Path>>(&mut self, ptr: u64, path: P) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path).map_err(Error::IO)?);
+
+        if self.is_cold(ptr)? {
+            out.write_all(&self.read(ptr)?).map_err(Error::IO)?;
+        } else {
+            self.stream_chain(ptr, false, |chunk| out.write_all(chunk).map_err(Error::IO))?;
+        }
+
+        out.flush().map_err(Error::IO)
+    }
+
+    /// Copy the contents of a standalone file into a new chain, returning the pointer to the chain.
+    /// The data is streamed, so the whole file is never held in memory.
+    pub fn import_file<P: AsRef<Path>>(&mut self, path: P) -> Result<u64, Error> {
+        self.check_for_external_changes()?;
+        let mut input = std::fs::File::open(path).map_err(Error::IO)?;
+        let len = input.metadata().map_err(Error::IO)?.len();
+        self.alloc_streamed(&mut input, len, |_| {})
+    }
+
+    /// Copy every file under a directory into its own chain, such as to ingest a folder of rendered frames.
+    /// Returns the path of every imported file relative to `path`, sorted, along with the pointer to its chain.
+    /// Only the files `filter` accepts, given their relative path, are imported. Every subdirectory is walked, and symlinks are skipped.
+    /// The files are read ahead on another thread while earlier files are written, and each file's pages are allocated at once.
+    /// As with `File::import_file`, no file is ever held in memory whole.
+    /// If any file fails to import, the chains imported so far are deleted again.
+    pub fn import_dir<P: AsRef<Path>, F: FnMut(&Path) -> bool>(&mut self, path: P, mut filter: F) -> Result<Vec<(PathBuf, u64)>, Error> {
+        self.check_for_external_changes()?;
+        let mut files = Vec::new();
+        walk_dir(path.as_ref(), Path::new(""), &mut filter, &mut files).map_err(Error::IO)?;
+
+        let chunk_size = self.chunk_size();
+        let (sender, chunks) = sync_channel(READ_AHEAD_CHUNKS);
+        let mut input = ImportReader { chunks, chunk: Vec::new(), pos: 0 };
+        let mut imported = Vec::with_capacity(files.len());
+        std::thread::scope(|scope| {
+            let paths = files.iter().map(|(relative, len)| (path.as_ref().join(relative), *len)).collect::<Vec<_>>();
+            scope.spawn(move || {
+                for (path, len) in paths {
+                    let result = std::fs::File::open(path).and_then(|mut file| {
+                        let mut offset = 0;
+                        while offset < len {
+                            let mut chunk = vec![0; chunk_size.min((len - offset) as usize)];
+                            file.read_exact(&mut chunk)?;
+                            offset += chunk.len() as u64;
+                            // The importing thread hung up after failing
+                            if sender.send(Ok(chunk)).is_err() {
+                                return Ok(false);
+                            }
+                        }
+                        Ok(true)
+                    });
+                    match result {
+                        Ok(true) => {},
+                        Ok(false) => return,
+                        Err(err) => {
+                            let _ = sender.send(Err(err));
+                            return;
+                        }
+                    }
+                }
+            });
+
+            for (relative, len) in &files {
+                match self.alloc_streamed(&mut input, *len, |_| {}) {
+                    Ok(ptr) => imported.push((relative.clone(), ptr)),
+                    Err(err) => {
+                        // Hang up so the reading thread stops
+                        drop(input);
+                        for (_, ptr) in &imported {
+                            self.delete(*ptr)?;
+                        }
+                        return Err(err);
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        Ok(imported)
+    }
+
+    /// Copy `len` bytes from `reader` into a new chain a chunk at a time, passing each chunk to `f` as it is copied.
+    pub(crate) fn alloc_streamed<R: Read, F: FnMut(&[u8])>(&mut self, reader: &mut R, len: u64, mut f: F) -> Result<u64, Error> {
+        if self.config.max_chain_size.is_some_and(|max_chain_size| len > max_chain_size) {
+            return Err(Error::LimitExceeded);
+        }
+
+        let ptr = self.alloc()?;
+        let meta_size = self.meta_size();
+        let mut pages = vec![ptr];
+        pages.extend(self.alloc_pages(self.pages_needed(meta_size + len) as usize - 1)?);
+
+        let mut chunk = vec![0; self.chunk_size()];
+        let mut offset = 0;
+        while offset < len {
+            let n = chunk.len().min((len - offset) as usize);
+            if let Err(err) = reader.read_exact(&mut chunk[..n]) {
+                // Free the pages again instead of leaking them
+                self.link_pages(&pages, len)?;
+                self.delete(ptr)?;
+                return Err(Error::IO(err));
+            }
+            f(&chunk[..n]);
+            self.write_at(&pages, meta_size + offset, &chunk[..n])?;
+            offset += n as u64;
+        }
+
+        // Link the pages into a chain only once their contents are written
+        self.link_pages(&pages, len)?;
+        self.write_summary(&pages, len)?;
+
+        self.bump_change_counter()?;
+        Ok(ptr)
+    }
+
+    /// Write the page headers linking freshly written pages into a chain holding `len` bytes of data
+    pub(crate) fn link_pages(&mut self, pages: &[u64], len: u64) -> Result<(), Error> {
+        let page_size = self.config.page_size as u64;
+        let total = self.meta_size() + len;
+        for i in 0..pages.len() {
+            let header = match pages.get(i + 1) {
+                Some(next) => PageHeader::NextPage(*next),
+                None => PageHeader::FinalPage(total - (pages.len() as u64 - 1) * page_size)
+            };
+            if i == 0 {
+                self.write_head_page_header(pages[i], header)?;
+            } else {
+                self.write_page_header(pages[i], header)?;
+            }
+        }
+        Ok(())
+    }
+
+}
+
+/// Collect the files under `dir`, whose path relative to the directory being imported is `relative`, along with their lengths
+fn walk_dir<F: FnMut(&Path) -> bool>(dir: &Path, relative: &Path, filter: &mut F, files: &mut Vec<(PathBuf, u64)>) -> std::io::Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = relative.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            walk_dir(&entry.path(), &path, filter, files)?;
+        } else if file_type.is_file() && filter(&path) {
+            files.push((path, entry.metadata()?.len()));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn export_import() {
+    use crate::Config;
+
+    let mut file = File::open("export_import.verter", Config::default()).unwrap();
+    let data = (0..200_000u32).map(|i| (i % 253) as u8).collect::<Vec<_>>();
+    std::fs::write("export_import.png", &data).unwrap();
+
+    let ptr = file.import_file("export_import.png").unwrap();
+    assert_eq!(file.read(ptr).unwrap(), data);
+
+    file.patch(ptr, &[(0, b"edited")]).unwrap();
+    file.export_chain(ptr, "export_import.png").unwrap();
+    let exported = std::fs::read("export_import.png").unwrap();
+    assert_eq!(&exported[..6], b"edited");
+    assert_eq!(exported[6..], data[6..]);
+
+    std::fs::remove_file("export_import.png").unwrap();
+    std::fs::remove_file("export_import.verter").unwrap();
+}
@@ -0,0 +1,170 @@
+use std::io::Write;
+use std::path::Path;
+
+use crate::{Config, Error, File};
+
+impl File {
+
+    /// Copy the whole file to a new file at `path` and open the copy, such as to duplicate a project.
+    /// Where the filesystem supports it, the copy shares its blocks with the original until either is written to, so even huge files fork instantly.
+    /// Otherwise the file is copied a chunk of at most `Config::max_working_memory` bytes at a time.
+    /// Fails with an IO error if something already exists at `path`.
+    pub fn fork_to<P: AsRef<Path>>(&mut self, path: P) -> Result<File, Error> {
+        let dest = self.copy_to_new(path.as_ref())?;
+        File::from_file(dest, self.config)
+    }
+
+    /// Create a new document at `new_path` as a copy of a template file, which is only read, and open it.
+    /// Fails with `Error::GeometryMismatch` if the template's pages aren't `Config::page_size` bytes long, and like `File::fork_to` otherwise,
+    /// sharing the template's blocks instead of copying them where the filesystem supports it.
+    pub fn create_from_template<P: AsRef<Path>, Q: AsRef<Path>>(template_path: P, new_path: Q, config: Config) -> Result<File, Error> {
+        let template = std::fs::File::open(template_path).map_err(Error::IO)?;
+        let mut template = File::from_backend(Box::new(template), Config { heatmap: false, ..config }, false)?;
+        template.set_read_only(true);
+        template.fork_to(new_path)
+    }
+
+    /// Copy the whole file to `path`, replacing any previous backup there.
+    /// The copy is made like `File::fork_to` next to `path` and then moved into place, so a failed backup never destroys the previous one.
+    pub fn backup_to<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = Path::new(&partial);
+        // Left behind by a backup that was interrupted
+        if std::fs::exists(partial).map_err(Error::IO)? {
+            std::fs::remove_file(partial).map_err(Error::IO)?;
+        }
+        drop(self.copy_to_new(partial)?);
+        std::fs::rename(partial, path).map_err(|err| {
+            let _ = std::fs::remove_file(partial);
+            Error::IO(err)
+        })
+    }
+
+    /// Copy the whole file to a new file at `path` like `File::fork_to`, and carry on working with the copy through this handle.
+    /// The file the handle was using is left as it was. Hooks, the cold tier and other settings of the handle are kept,
+    /// but the counts of `File::heatmap` start over.
+    pub fn save_as<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let fork = self.fork_to(path)?;
+        self.file = fork.file;
+        self.heat = fork.heat;
+        self.change_counter = fork.change_counter;
+        self.sync_state = fork.sync_state;
+        Ok(())
+    }
+
+    /// Copy the whole file into a newly created file at `path`, removing the partial copy if copying fails
+    fn copy_to_new(&mut self, path: &Path) -> Result<std::fs::File, Error> {
+        self.check_for_external_changes()?;
+        let mut dest = std::fs::OpenOptions::new().read(true).write(true).create_new(true).open(path).map_err(Error::IO)?;
+        if let Err(err) = self.copy_into(&mut dest) {
+            drop(dest);
+            let _ = std::fs::remove_file(path);
+            return Err(err);
+        }
+        Ok(dest)
+    }
+
+    fn copy_into(&mut self, dest: &mut std::fs::File) -> Result<(), Error> {
+        self.with_stored_bytes(|file| {
+            let len = file.file_size()?;
+            if !file.file.clone_into(dest, len).map_err(Error::IO)? {
+                let mut chunk = vec![0; file.chunk_size()];
+                let mut offset = 0;
+                while offset < len {
+                    let n = chunk.len().min((len - offset) as usize);
+                    file.file.read_exact_at(&mut chunk[..n], offset).map_err(Error::IO)?;
+                    dest.write_all(&chunk[..n]).map_err(Error::IO)?;
+                    offset += n as u64;
+                }
+            }
+            Ok(())
+        })?;
+        dest.sync_data().map_err(Error::IO)
+    }
+
+}
+
+#[test]
+fn fork_to() {
+    let config = Config { max_working_memory: Some(1000), ..Config::default() };
+    let mut file = File::open("fork_to.verter", config).unwrap();
+    let frame = file.alloc_with(&[0xAB; 3000]).unwrap();
+    file.write_root(&frame.to_le_bytes()).unwrap();
+
+    let mut fork = file.fork_to("fork_to_copy.verter").unwrap();
+    assert_eq!(fork.read(frame).unwrap(), vec![0xAB; 3000]);
+
+    // The original and the fork change independently
+    fork.write(frame, b"fork").unwrap();
+    file.write(frame, b"original").unwrap();
+    assert_eq!(fork.read(frame).unwrap(), b"fork");
+    assert_eq!(file.read(frame).unwrap(), b"original");
+    fork.validate().unwrap();
+    drop(fork);
+
+    // Forking over an existing file fails without touching it
+    match file.fork_to("fork_to_copy.verter") {
+        Err(Error::IO(_)) => {},
+        Ok(_) | Err(_) => panic!("should error with io error")
+    }
+    assert_eq!(File::open("fork_to_copy.verter", config).unwrap().read(frame).unwrap(), b"fork");
+
+    // Backends with no fast path are copied a chunk at a time
+    let mut memory = File::open_backend(Box::new(std::io::Cursor::new(Vec::new())), config).unwrap();
+    memory.write_root(&[0xCD; 2500]).unwrap();
+    let mut fork = memory.fork_to("fork_to_memory.verter").unwrap();
+    assert_eq!(fork.read_root().unwrap(), vec![0xCD; 2500]);
+
+    std::fs::remove_file("fork_to.verter").unwrap();
+    std::fs::remove_file("fork_to_copy.verter").unwrap();
+    std::fs::remove_file("fork_to_memory.verter").unwrap();
+}
+
+#[test]
+fn backup_and_save_as() {
+    let mut file = File::open("backup_and_save_as.verter", Config::default()).unwrap();
+    file.write_root(b"first draft").unwrap();
+    file.backup_to("backup_and_save_as_backup.verter").unwrap();
+    file.write_root(b"second draft").unwrap();
+    // Backing up again replaces the previous backup
+    file.backup_to("backup_and_save_as_backup.verter").unwrap();
+    assert_eq!(File::open("backup_and_save_as_backup.verter", Config::default()).unwrap().read_root().unwrap(), b"second draft");
+    assert!(!std::fs::exists("backup_and_save_as_backup.verter.partial").unwrap());
+
+    // After saving as, the handle writes to the new file only
+    file.save_as("backup_and_save_as_renamed.verter").unwrap();
+    file.write_root(b"renamed draft").unwrap();
+    assert_eq!(file.read_root().unwrap(), b"renamed draft");
+    drop(file);
+    assert_eq!(File::open("backup_and_save_as.verter", Config::default()).unwrap().read_root().unwrap(), b"second draft");
+    assert_eq!(File::open("backup_and_save_as_renamed.verter", Config::default()).unwrap().read_root().unwrap(), b"renamed draft");
+
+    std::fs::remove_file("backup_and_save_as.verter").unwrap();
+    std::fs::remove_file("backup_and_save_as_backup.verter").unwrap();
+    std::fs::remove_file("backup_and_save_as_renamed.verter").unwrap();
+}
+
+#[test]
+fn create_from_template() {
+    let mut template = File::open("create_from_template.verter", Config::default()).unwrap();
+    template.write_root(b"empty storyboard").unwrap();
+    drop(template);
+
+    let mut a = File::create_from_template("create_from_template.verter", "create_from_template_a.verter", Config::default()).unwrap();
+    let mut b = File::create_from_template("create_from_template.verter", "create_from_template_b.verter", Config::default()).unwrap();
+    a.write_root(b"first project").unwrap();
+    assert_eq!(b.read_root().unwrap(), b"empty storyboard");
+    assert_eq!(File::open("create_from_template.verter", Config::default()).unwrap().read_root().unwrap(), b"empty storyboard");
+
+    match File::create_from_template("create_from_template.verter", "create_from_template_c.verter", Config { page_size: 256, ..Config::default() }) {
+        Err(Error::GeometryMismatch { .. }) => {},
+        Ok(_) | Err(_) => panic!("should error with geometry mismatch")
+    }
+    assert!(!std::fs::exists("create_from_template_c.verter").unwrap());
+
+    std::fs::remove_file("create_from_template.verter").unwrap();
+    std::fs::remove_file("create_from_template_a.verter").unwrap();
+    std::fs::remove_file("create_from_template_b.verter").unwrap();
+}
@@ -0,0 +1,362 @@
+//! The on-disk format of verter files, as a versioned contract that other code, and other implementations, can rely on.
+//!
+//! A file starts with a header, followed by pages of `Config::page_size` bytes, each prefixed with an 8 byte page header.
+//! The file header is the magic bytes, followed by one little-endian u64 for each of the `header_fields` of the file's format version, in order.
+//! Files upgraded from before the format was versioned keep most of these fields in a chain instead, see `HEADER_CHAIN_FLAG`.
+//! Files created with `PageLayout::PageMaps` store the page headers apart from the pages' data instead, see `PAGE_MAP_FLAG`.
+//! The first field holds the format version in its low 32 bits and, from version 3, the length of the magic bytes in its high 32 bits,
+//! so the end of the magic bytes can be found without knowing them.
+//! A page header is a little-endian u64 holding the page's type in the bits from `PAGE_TYPE_SHIFT` up,
+//! with `HEAD_FLAG` set on the first page of every chain, `FROZEN_FLAG` set on the first page of frozen chains,
+//! and a value in the bits below `PAGE_TYPE_SHIFT`:
+//! the pointer to the next page for `NEXT_PAGE`, the number of bytes used in the page for `FINAL_PAGE`,
+//! and the pointer to the next free page, or 0, for `DELETED_PAGE`.
+//!
+//! Every version of the format, down to version 0 from before it was versioned, has a fixture file in the repository's `fixtures` directory,
+//! which the tests check still opens and is upgraded.
+
+/// The version of the file format written by this version of verter.
+/// Bumped whenever the format changes in a way older versions would misread.
+/// Files from before the format was versioned count as version 0. Version 1 added the format version field and widened the page type,
+/// version 2 added the chain head flag to page headers, version 3 the length of the magic bytes, version 4 the `id_counters` header field,
+/// version 5 the `named_roots` header field, and version 6 the `PAGE_MAP_FLAG`.
+/// Since pages start right after the header, older files can't be given the new fields, and keep their shorter header until `File::canonicalize` rewrites them.
+pub const FORMAT_VERSION: u64 = 6;
+
+/// The longest magic bytes a file can have
+pub const MAX_MAGIC_LEN: usize = 256;
+/// The bit the length of the magic bytes starts at in the format version field
+pub const MAGIC_LEN_SHIFT: u32 = 32;
+/// The bits of the format version field holding the format version
+pub const VERSION_MASK: u64 = (1 << MAGIC_LEN_SHIFT) - 1;
+
+/// The fields of the file header after the magic bytes, in the order they are stored.
+/// Fields holding the pointer to a table are 0 until the table is first used.
+pub const HEADER_FIELDS: &[&str] = &[
+    "format_version",
+    "first_free_page",
+    "root_page",
+    "undo",
+    "redo",
+    "refcount_table",
+    "change_counter",
+    "dedup_table",
+    "dictionaries",
+    "version_table",
+    "cold_table",
+    "generation_table",
+    "partition_table",
+    "quarantine_table",
+    "tag_table",
+    "journal",
+    "prepared_transaction",
+    "schema_version",
+    "pin_table",
+    "id_counters",
+    "named_roots"
+];
+
+/// The fields of the file header in files of a format version, which are `HEADER_FIELDS` without the ones added after it.
+/// Files from before the format was versioned count as format version 0, and have the `UNVERSIONED_FIELDS`.
+pub fn header_fields(format_version: u64) -> &'static [&'static str] {
+    match format_version {
+        0 => UNVERSIONED_FIELDS,
+        1..=3 => &HEADER_FIELDS[..HEADER_FIELDS.len() - 2],
+        4 => &HEADER_FIELDS[..HEADER_FIELDS.len() - 1],
+        _ => HEADER_FIELDS
+    }
+}
+
+/// The fields of the header of files from before the format was versioned, which start right after the magic bytes.
+/// Their page headers hold the page type in the bits from `UNVERSIONED_PAGE_TYPE_SHIFT` up, without any flags.
+/// Such files are upgraded to the current version when they are opened, see `HEADER_CHAIN_FLAG`.
+pub const UNVERSIONED_FIELDS: &[&str] = &["first_free_page", "root_page"];
+/// The bit the page type starts at in the page headers of files from before the format was versioned
+pub const UNVERSIONED_PAGE_TYPE_SHIFT: u32 = 62;
+
+/// Set in the format version field of files upgraded from format version 0.
+/// Their pages start right after the `UNVERSIONED_FIELDS`, leaving no room for a longer header,
+/// and can't be moved without breaking the pointers applications stored in their data.
+/// So their header is only the `HEADER_CHAIN_FIELDS`: the format version field, and a pointer to a chain holding the rest of
+/// the `header_fields` of the format version. Each page of the chain holds as many whole fields as fit, leaving the rest of the page unused.
+pub const HEADER_CHAIN_FLAG: u64 = 1 << 63;
+/// The fields of the file header in files with `HEADER_CHAIN_FLAG` set
+pub const HEADER_CHAIN_FIELDS: &[&str] = &["format_version", "header_chain"];
+
+/// Set in the format version field of files that keep their page headers in page maps instead of right before the data of each page, from version 6.
+/// Their pages are stored in groups of `PAGE_GROUP_LEN` pages, each made of a page map holding the headers of the group's pages in order,
+/// followed by the data of the group's pages in order. So the data of adjacent pages is contiguous, as long as they are in the same group,
+/// and chains can be walked by reading only the page maps. The page map of the last group is always stored whole, with the headers of
+/// the pages past the end of the file zeroed.
+/// Pointers are the same as in files without page maps, with the page at index `i` at `HeaderLayout::page_ptr`,
+/// so only where a page's header and data are stored differs. See `HeaderLayout::page_header_offset` and `HeaderLayout::page_data_offset`.
+pub const PAGE_MAP_FLAG: u64 = 1 << 62;
+/// The number of pages in each group of a file with `PAGE_MAP_FLAG` set, which makes every page map 4 KiB long
+pub const PAGE_GROUP_LEN: u64 = 512;
+
+/// The length of the magic bytes stored in a format version field, which is 0 before version 3
+pub fn stored_magic_len(field: u64) -> u64 {
+    (field & !(HEADER_CHAIN_FLAG | PAGE_MAP_FLAG)) >> MAGIC_LEN_SHIFT
+}
+
+/// The size of a page header, and of every field in the file header
+pub const FIELD_SIZE: u64 = 8;
+
+/// The bit the page type starts at in a page header
+pub const PAGE_TYPE_SHIFT: u32 = 56;
+/// Set in the page header of the first page of every chain
+pub const HEAD_FLAG: u64 = 1 << 63;
+/// Set in the page header of the first page of a chain frozen with `File::freeze`.
+/// Versions of verter from before it was added reject it as an unknown page type, so only files holding frozen chains are unreadable to them.
+pub const FROZEN_FLAG: u64 = 1 << 62;
+/// The page type of a page followed by another page in its chain
+pub const NEXT_PAGE: u64 = 0;
+/// The page type of the last page of a chain
+pub const FINAL_PAGE: u64 = 1;
+/// The page type of a page in the free list
+pub const DELETED_PAGE: u64 = 2;
+
+/// Where things are in a file with magic bytes of a given length
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HeaderLayout {
+    /// The length of the magic bytes at the start of the file
+    pub magic_len: u64,
+    /// The version of the format the file was written with
+    pub format_version: u64,
+    /// Whether the header fields after the format version are stored in a chain, see `HEADER_CHAIN_FLAG`
+    pub header_chain: bool,
+    /// Whether the page headers are stored in page maps, see `PAGE_MAP_FLAG`
+    pub page_maps: bool
+}
+
+impl HeaderLayout {
+
+    /// The fields stored right after the magic bytes
+    fn fields(&self) -> &'static [&'static str] {
+        if self.header_chain {
+            HEADER_CHAIN_FIELDS
+        } else {
+            header_fields(self.format_version)
+        }
+    }
+
+    /// The offset of a field of the file header, or `None` if it isn't one of the fields stored right after the magic bytes,
+    /// which are the `header_fields` of the file's format version, or the `HEADER_CHAIN_FIELDS` in files with a header chain.
+    pub fn field_offset(&self, field: &str) -> Option<u64> {
+        let idx = self.fields().iter().position(|name| *name == field)?;
+        Some(self.magic_len + idx as u64 * FIELD_SIZE)
+    }
+
+    /// The offset of a field within the data of the header chain of a file with pages of `page_size` bytes,
+    /// or `None` if the file has no header chain or the field isn't one of the `header_fields` of its format version.
+    pub fn header_chain_offset(&self, field: &str, page_size: u64) -> Option<u64> {
+        let idx = header_fields(self.format_version)[1..].iter().position(|name| *name == field)? as u64;
+        let fields_per_page = page_size / FIELD_SIZE;
+        (self.header_chain && fields_per_page > 0).then(|| idx / fields_per_page * page_size + idx % fields_per_page * FIELD_SIZE)
+    }
+
+    /// The size of the file header, which is also the pointer to the first page.
+    pub fn size(&self) -> u64 {
+        self.magic_len + self.fields().len() as u64 * FIELD_SIZE
+    }
+
+    /// The pointer to the page with the given index, for pages of `page_size` bytes.
+    pub fn page_ptr(&self, page_size: u64, idx: u64) -> u64 {
+        self.size() + idx * (FIELD_SIZE + page_size)
+    }
+
+    /// Where the header of the page at `ptr` is stored, for pages of `page_size` bytes.
+    /// Right at `ptr`, unless the file has page maps.
+    pub fn page_header_offset(&self, page_size: u64, ptr: u64) -> u64 {
+        if !self.page_maps {
+            return ptr;
+        }
+        page_map_offsets(self.size(), page_size, ptr).0
+    }
+
+    /// Where the data of the page at `ptr` is stored, for pages of `page_size` bytes.
+    /// Right after the page's header, unless the file has page maps.
+    pub fn page_data_offset(&self, page_size: u64, ptr: u64) -> u64 {
+        if !self.page_maps {
+            return ptr + FIELD_SIZE;
+        }
+        page_map_offsets(self.size(), page_size, ptr).1
+    }
+
+    /// The number of bytes a file whose pointers span `len` bytes takes up when stored, for pages of `page_size` bytes
+    pub fn stored_len(&self, page_size: u64, len: u64) -> u64 {
+        if !self.page_maps {
+            return len;
+        }
+        page_map_stored_len(self.size(), page_size, len)
+    }
+
+    /// The number of bytes the pointers of a file span, given the number of bytes it takes up when stored, for pages of `page_size` bytes.
+    /// A page that is only partly stored counts as far as its header or data goes, so cut off files still look cut off.
+    pub fn pointer_len(&self, page_size: u64, stored_len: u64) -> u64 {
+        if !self.page_maps {
+            return stored_len;
+        }
+        page_map_pointer_len(self.size(), page_size, stored_len)
+    }
+
+}
+
+/// Where the header and the data of the page at `ptr` are stored in a file with page maps, whose header is `header_size` bytes long
+pub(crate) fn page_map_offsets(header_size: u64, page_size: u64, ptr: u64) -> (u64, u64) {
+    let idx = (ptr - header_size) / (FIELD_SIZE + page_size);
+    let group = header_size + idx / PAGE_GROUP_LEN * PAGE_GROUP_LEN * (FIELD_SIZE + page_size);
+    let slot = idx % PAGE_GROUP_LEN;
+    (group + slot * FIELD_SIZE, group + PAGE_GROUP_LEN * FIELD_SIZE + slot * page_size)
+}
+
+/// See `HeaderLayout::stored_len`
+pub(crate) fn page_map_stored_len(header_size: u64, page_size: u64, len: u64) -> u64 {
+    if len <= header_size {
+        return len;
+    }
+    let total_page_size = FIELD_SIZE + page_size;
+    let (pages, partial) = ((len - header_size) / total_page_size, (len - header_size) % total_page_size);
+    let group = header_size + pages / PAGE_GROUP_LEN * PAGE_GROUP_LEN * total_page_size;
+    let end = match pages % PAGE_GROUP_LEN {
+        0 => group,
+        slot => group + PAGE_GROUP_LEN * FIELD_SIZE + slot * page_size
+    };
+    let (header, data) = page_map_offsets(header_size, page_size, header_size + pages * total_page_size);
+    match partial {
+        0 => end,
+        1..=FIELD_SIZE => end.max(header + partial),
+        _ => data + partial - FIELD_SIZE
+    }
+}
+
+/// See `HeaderLayout::pointer_len`
+pub(crate) fn page_map_pointer_len(header_size: u64, page_size: u64, stored_len: u64) -> u64 {
+    if stored_len <= header_size {
+        return stored_len;
+    }
+    let total_page_size = FIELD_SIZE + page_size;
+    let group_size = PAGE_GROUP_LEN * total_page_size;
+    let (groups, rest) = ((stored_len - header_size) / group_size, (stored_len - header_size) % group_size);
+    let group = header_size + groups * group_size;
+    // Only part of the page map was stored, so at most the header of the group's first page is there
+    if rest < PAGE_GROUP_LEN * FIELD_SIZE {
+        return group + rest.min(FIELD_SIZE);
+    }
+    // Groups of pages holding no data are only a page map, so this is only reached with pages of at least one byte
+    let data = rest - PAGE_GROUP_LEN * FIELD_SIZE;
+    let partial = data % page_size;
+    group + data / page_size * total_page_size + if partial == 0 { 0 } else { FIELD_SIZE + partial }
+}
+
+#[test]
+fn header_layout() {
+    use crate::{Config, File, PageHeader};
+
+    let mut file = File::open("header_layout.verter", Config::default()).unwrap();
+    let layout = HeaderLayout { magic_len: 8, format_version: FORMAT_VERSION, header_chain: false, page_maps: false };
+    let fields = [
+        ("format_version", file.format_version_ptr()),
+        ("first_free_page", file.first_free_page_ptr()),
+        ("root_page", file.root_page_ptr()),
+        ("undo", file.undo_chain_ptr()),
+        ("redo", file.redo_chain_ptr()),
+        ("refcount_table", file.refcount_table_ptr()),
+        ("change_counter", file.change_counter_ptr()),
+        ("dedup_table", file.dedup_table_ptr()),
+        ("dictionaries", file.dictionaries_ptr()),
+        ("version_table", file.version_table_ptr()),
+        ("cold_table", file.cold_table_ptr()),
+        ("generation_table", file.generation_table_ptr()),
+        ("partition_table", file.partition_table_ptr()),
+        ("quarantine_table", file.quarantine_table_ptr()),
+        ("tag_table", file.tag_table_ptr()),
+        ("journal", file.journal_ptr()),
+        ("prepared_transaction", file.prepared_transaction_ptr()),
+        ("schema_version", file.schema_version_ptr()),
+        ("pin_table", file.pin_table_ptr()),
+        ("id_counters", file.id_counters_ptr()),
+        ("named_roots", file.named_roots_ptr())
+    ];
+    assert_eq!(fields.len(), HEADER_FIELDS.len());
+    for (field, ptr) in fields {
+        assert_eq!(layout.field_offset(field), Some(ptr), "{}", field);
+    }
+    assert_eq!(layout.size(), file.header_size());
+    assert_eq!(layout.page_ptr(Config::default().page_size as u64, 0), file.root_page().unwrap());
+    // Files from older versions keep their shorter header
+    let legacy = HeaderLayout { magic_len: 8, format_version: 3, header_chain: false, page_maps: false };
+    assert_eq!(legacy.field_offset("id_counters"), None);
+    assert_eq!(legacy.size(), file.header_size() - 2 * FIELD_SIZE);
+    let legacy = HeaderLayout { magic_len: 8, format_version: 4, header_chain: false, page_maps: false };
+    assert_eq!(legacy.field_offset("named_roots"), None);
+    assert_eq!(legacy.size(), file.header_size() - FIELD_SIZE);
+
+    // Files upgraded from format version 0 keep the fields after the format version in a chain
+    let bytes = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/v0.verter")).unwrap();
+    let upgraded = File::from_backend(Box::new(std::io::Cursor::new(bytes)), Config::default(), false).unwrap();
+    let layout = HeaderLayout { magic_len: 8, format_version: FORMAT_VERSION, header_chain: true, page_maps: false };
+    assert_eq!(layout.size(), upgraded.header_size());
+    assert_eq!(layout.field_offset("header_chain"), Some(upgraded.header_chain_ptr()));
+    assert_eq!(layout.field_offset("root_page"), None);
+    let page_size = Config::default().page_size as u64;
+    for (idx, field) in HEADER_FIELDS.iter().enumerate().skip(1) {
+        let offset = layout.header_chain_offset(field, page_size).unwrap();
+        let page = upgraded.header_pages[(offset / page_size) as usize];
+        assert_eq!(page + FIELD_SIZE + offset % page_size, upgraded.header_field_ptr(idx), "{}", field);
+    }
+
+    assert_eq!(PageHeader::NextPage(5).to_u64(), (NEXT_PAGE << PAGE_TYPE_SHIFT) | 5);
+    assert_eq!(PageHeader::FinalPage(5).to_head_u64(), HEAD_FLAG | (FINAL_PAGE << PAGE_TYPE_SHIFT) | 5);
+    assert_eq!(PageHeader::DeletedPage(5).to_u64(), (DELETED_PAGE << PAGE_TYPE_SHIFT) | 5);
+
+    std::fs::remove_file("header_layout.verter").unwrap();
+}
+
+#[test]
+fn format_fixtures() {
+    use std::io::Cursor;
+    use crate::{Config, File};
+
+    let data = (0..300).map(|i| i as u8).collect::<Vec<_>>();
+
+    // Files written by every version of the format, and by verter from before the format was versioned, still open,
+    // and are upgraded as they are opened
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+    for version in 0..=FORMAT_VERSION {
+        let path = dir.join(format!("v{version}.verter"));
+        let bytes = std::fs::read(&path).unwrap_or_else(|_| panic!("{} is missing, see `write_fixture`", path.display()));
+        let mut file = File::from_backend(Box::new(Cursor::new(bytes)), Config::default(), false).unwrap();
+        let root = file.read_root().unwrap();
+        let (ptr, text) = root.split_at(8);
+        assert_eq!(text, b"verter fixture");
+        assert_eq!(file.read(u64::from_le_bytes(ptr.try_into().unwrap())).unwrap(), data);
+        file.validate().unwrap();
+
+        let field = file.read_u64(file.format_version_ptr()).unwrap();
+        assert!(field & VERSION_MASK >= 3 && stored_magic_len(field) == 8, "v{version} wasn't upgraded");
+        assert_eq!(field & HEADER_CHAIN_FLAG != 0, version == 0);
+    }
+}
+
+/// Write the fixture for the current format version, to be committed along with the change to the format:
+/// `cargo test --lib format::write_fixture -- --ignored`
+#[test]
+#[ignore]
+fn write_fixture() {
+    use std::io::Cursor;
+    use crate::{Config, File};
+
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(format!("v{}.verter", FORMAT_VERSION));
+    assert!(!path.exists(), "fixtures of released versions must not change");
+    let mut file = File::from_backend(Box::new(Cursor::new(Vec::new())), Config::default(), true).unwrap();
+    let deleted = file.alloc_with(b"deleted").unwrap();
+    let ptr = file.alloc_with(&(0..300).map(|i| i as u8).collect::<Vec<_>>()).unwrap();
+    file.delete(deleted).unwrap();
+    let mut root = ptr.to_le_bytes().to_vec();
+    root.extend_from_slice(b"verter fixture");
+    file.write_root(&root).unwrap();
+    let mut bytes = vec![0; file.file_size().unwrap() as usize];
+    file.file.read_exact_at(&mut bytes, 0).unwrap();
+    std::fs::write(&path, bytes).unwrap();
+}
@@ -0,0 +1,198 @@
+use std::collections::HashSet;
+
+use crate::{Error, File, PageHeader};
+
+/// A problem with the free list found by `File::free_list_stats`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FreeListAnomaly {
+    /// The free list links to a pointer that is not the start of a page
+    InvalidPointer(u64),
+    /// The free list links to a page that is not marked as deleted
+    NotDeleted(u64),
+    /// The free list links back to a page it already contains
+    Cycle(u64),
+    /// A deleted page is missing from the free list, so it can never be reused
+    Unlisted(u64)
+}
+
+/// Statistics about the free list, returned by `File::free_list_stats`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FreeListStats {
+    /// The number of pages in the free list
+    pub len: u64,
+    /// The number of bytes taken up by the pages in the free list, including their headers
+    pub free_bytes: u64,
+    /// Whether the free list is ordered by address, so that new pages are allocated near the start of the file
+    pub address_sorted: bool,
+    /// Problems with the free list. The walk stops at the first anomaly in the list itself.
+    pub anomalies: Vec<FreeListAnomaly>
+}
+
+impl File {
+
+    /// Walk the free list, gathering statistics and checking it for problems.
+    pub fn free_list_stats(&mut self) -> Result<FreeListStats, Error> {
+        self.check_for_external_changes()?;
+        let mut stats = FreeListStats {
+            address_sorted: true,
+            ..FreeListStats::default()
+        };
+
+        let mut listed = HashSet::new();
+        let mut prev = 0;
+        let mut free_page = self.first_free_page()?;
+        while free_page != 0 {
+            if free_page < self.header_size() || !(free_page - self.header_size()).is_multiple_of(self.total_page_size()) || free_page >= self.file_size()? {
+                stats.anomalies.push(FreeListAnomaly::InvalidPointer(free_page));
+                break;
+            }
+            if !listed.insert(free_page) {
+                stats.anomalies.push(FreeListAnomaly::Cycle(free_page));
+                break;
+            }
+            let PageHeader::DeletedPage(next) = self.read_page_header(free_page)? else {
+                stats.anomalies.push(FreeListAnomaly::NotDeleted(free_page));
+                break;
+            };
+            stats.address_sorted &= free_page > prev;
+            prev = free_page;
+            free_page = next;
+        }
+        stats.len = listed.len() as u64;
+        stats.free_bytes = stats.len * self.total_page_size();
+
+        for page in self.deleted_pages()? {
+            if !listed.contains(&page) {
+                stats.anomalies.push(FreeListAnomaly::Unlisted(page));
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Rebuild the free list in address order, so that new pages are allocated near the start of the file, close together.
+    /// Deleted pages missing from the free list are added back to it.
+    pub fn sort_free_list(&mut self) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        let pages = self.deleted_pages()?;
+        let mut next = 0;
+        for page in pages.iter().rev() {
+            self.write_page_header(*page, PageHeader::DeletedPage(next))?;
+            next = *page;
+        }
+        self.write_u64(self.first_free_page_ptr(), next)?;
+        self.bump_change_counter()
+    }
+
+    /// Shrink the file by cutting off the free pages at its end, returning how many pages were cut off.
+    /// Unlinking them walks the free list, so this takes time proportional to its length.
+    /// Afterwards, pointers to chains that were deleted from the cut-off pages fail with `Error::InvalidPointer` instead of `Error::DeletedPointer`.
+    pub fn trim_tail(&mut self) -> Result<u64, Error> {
+        self.check_for_external_changes()?;
+        self.check_writable()?;
+        let total_page_size = self.total_page_size();
+        let file_size = self.file_size()?;
+        // Most of the time the last page is in use, which is found out without walking the free list
+        if file_size < self.header_size() + total_page_size || !matches!(self.read_page_header(file_size - total_page_size)?, PageHeader::DeletedPage(_)) {
+            return Ok(0);
+        }
+
+        let free_list = self.free_list()?;
+        let listed = free_list.iter().copied().collect::<HashSet<_>>();
+        let mut new_size = file_size;
+        while new_size >= self.header_size() + total_page_size && listed.contains(&(new_size - total_page_size)) {
+            new_size -= total_page_size;
+        }
+        if new_size == file_size {
+            return Ok(0);
+        }
+
+        let trimmed = free_list.iter().copied().filter(|page| *page >= new_size).collect();
+        self.unlink_free_pages(&free_list, &trimmed)?;
+        self.file.set_len(new_size).map_err(Error::IO)?;
+        self.bump_change_counter()?;
+        Ok((file_size - new_size) / total_page_size)
+    }
+
+    /// Every page marked as deleted, in address order
+    fn deleted_pages(&mut self) -> Result<Vec<u64>, Error> {
+        let quarantined = self.quarantined_pages()?;
+        let file_size = self.file_size()?;
+        let mut pages = Vec::new();
+        let mut ptr = self.header_size();
+        while ptr + self.total_page_size() <= file_size {
+            if !quarantined.contains(&ptr) && matches!(self.read_page_header(ptr)?, PageHeader::DeletedPage(_)) {
+                pages.push(ptr);
+            }
+            ptr += self.total_page_size();
+        }
+        Ok(pages)
+    }
+
+}
+
+#[test]
+fn free_list() {
+    use crate::Config;
+
+    let mut file = File::open("free_list.verter", Config::default()).unwrap();
+    let chains = (0..4).map(|_| file.alloc_with(&[0xAB; 300]).unwrap()).collect::<Vec<_>>();
+    file.delete(chains[0]).unwrap();
+    file.delete(chains[2]).unwrap();
+
+    let stats = file.free_list_stats().unwrap();
+    assert_eq!(stats.len, 6);
+    assert_eq!(stats.free_bytes, 6 * file.total_page_size());
+    assert!(!stats.address_sorted);
+    assert!(stats.anomalies.is_empty());
+
+    // Leak a page by unlinking it from the free list
+    let first = file.first_free_page().unwrap();
+    let PageHeader::DeletedPage(second) = file.read_page_header(first).unwrap() else { unreachable!() };
+    file.write_u64(file.first_free_page_ptr(), second).unwrap();
+    assert_eq!(file.free_list_stats().unwrap().anomalies, vec![FreeListAnomaly::Unlisted(first)]);
+
+    file.sort_free_list().unwrap();
+    let stats = file.free_list_stats().unwrap();
+    assert_eq!(stats.len, 6);
+    assert!(stats.address_sorted);
+    assert!(stats.anomalies.is_empty());
+    assert_eq!(file.alloc().unwrap(), chains[0]);
+    file.validate().unwrap();
+
+    std::fs::remove_file("free_list.verter").unwrap();
+}
+
+#[test]
+fn trim_tail() {
+    use crate::Config;
+
+    let config = Config { trim_tail: true, ..Config::default() };
+    let mut file = File::open("trim_tail.verter", config).unwrap();
+    let first = file.alloc_with(&[1; 300]).unwrap();
+    let layer = file.alloc_with(b"layer").unwrap();
+    let size = file.file_size().unwrap();
+
+    // Growing and shrinking the last chain over and over doesn't grow the file
+    for _ in 0..5 {
+        file.write(layer, &[2; 2000]).unwrap();
+        file.write(layer, b"layer").unwrap();
+        assert_eq!(file.file_size().unwrap(), size);
+    }
+    file.validate().unwrap();
+
+    // Free pages in the middle of the file stay in the free list
+    file.delete(first).unwrap();
+    assert_eq!(file.trim_tail().unwrap(), 0);
+    file.delete(layer).unwrap();
+    assert_eq!(file.trim_tail().unwrap(), 4);
+    assert_eq!(file.file_size().unwrap(), file.header_size() + file.total_page_size());
+    assert_eq!(file.free_list_stats().unwrap().len, 0);
+    match file.read(layer) {
+        Err(Error::InvalidPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with invalid pointer")
+    }
+    file.validate().unwrap();
+
+    std::fs::remove_file("trim_tail.verter").unwrap();
+}
@@ -0,0 +1,94 @@
+use crate::{Error, File, PageHeader};
+
+impl File {
+
+    /// Freeze a chain, such as a published asset that must never change.
+    /// Writing to, patching or deleting a frozen chain, including with the last `File::decref`, fails with `Error::FrozenChain`
+    /// until it is unfrozen with `File::unfreeze`, and so does `File::gc` if it finds the chain unreachable.
+    /// The flag is stored in the chain's head page, and is kept when the chain is moved by `File::canonicalize` or `migrate::repage`.
+    pub fn freeze(&mut self, ptr: u64) -> Result<(), Error> {
+        self.set_frozen(ptr, true)
+    }
+
+    /// Allow a frozen chain to be changed again. Returns whether the chain was frozen.
+    pub fn unfreeze(&mut self, ptr: u64) -> Result<bool, Error> {
+        let frozen = self.is_frozen(ptr)?;
+        self.check_not_reserved(ptr)?;
+        if frozen {
+            self.set_frozen(ptr, false)?;
+        }
+        Ok(frozen)
+    }
+
+    /// Whether a chain is frozen.
+    pub fn is_frozen(&mut self, ptr: u64) -> Result<bool, Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        Ok(self.read_u64(ptr)? & PageHeader::FROZEN_FLAG != 0)
+    }
+
+    /// Return `Error::FrozenChain` if a valid chain is frozen
+    pub(crate) fn check_not_frozen(&mut self, ptr: u64) -> Result<(), Error> {
+        if self.read_u64(ptr)? & PageHeader::FROZEN_FLAG != 0 {
+            return Err(Error::FrozenChain);
+        }
+        Ok(())
+    }
+
+    fn set_frozen(&mut self, ptr: u64, frozen: bool) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        self.check_not_reserved(ptr)?;
+        let header = self.read_u64(ptr)?;
+        let header = if frozen {
+            header | PageHeader::FROZEN_FLAG
+        } else {
+            header & !PageHeader::FROZEN_FLAG
+        };
+        self.write_u64(ptr, header)?;
+        self.bump_change_counter()
+    }
+
+}
+
+#[test]
+fn freeze() {
+    use crate::Config;
+
+    let mut file = File::open("freeze.verter", Config::default()).unwrap();
+    let asset = file.alloc_with(&[0xAB; 300]).unwrap();
+    file.freeze(asset).unwrap();
+    assert!(file.is_frozen(asset).unwrap());
+    assert_eq!(file.read(asset).unwrap(), vec![0xAB; 300]);
+
+    let results = [
+        file.write(asset, b"changed"),
+        file.patch(asset, &[(0, b"changed")]),
+        file.delete(asset),
+        file.delete_many(&[asset]),
+        file.decref(asset).map(drop),
+        file.gc(&[], |_| Vec::new()).map(drop)
+    ];
+    for result in results {
+        match result {
+            Err(Error::FrozenChain) => {},
+            Ok(_) | Err(_) => panic!("should error with frozen chain")
+        }
+    }
+    assert_eq!(file.read(asset).unwrap(), vec![0xAB; 300]);
+    file.validate().unwrap();
+
+    // The flag survives the chain being moved
+    let remap = file.canonicalize(|_| Vec::new()).unwrap();
+    let asset = remap[&asset];
+    assert!(file.is_frozen(asset).unwrap());
+    drop(file);
+
+    let mut file = File::open("freeze.verter", Config::default()).unwrap();
+    assert!(file.unfreeze(asset).unwrap());
+    assert!(!file.unfreeze(asset).unwrap());
+    file.write(asset, b"changed").unwrap();
+    file.delete(asset).unwrap();
+
+    std::fs::remove_file("freeze.verter").unwrap();
+}
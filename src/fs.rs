@@ -0,0 +1,668 @@
+//! A tiny archive filesystem of nested directories and files, stored inside a verter file.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use crate::{Error, File, BYTES_IN_U64};
+
+/// An entry in a directory listing
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool
+}
+
+#[derive(Clone, Copy)]
+struct Node {
+    is_dir: bool,
+    /// The chain storing the file's data or the directory's entries
+    ptr: u64
+}
+
+type Directory = BTreeMap<String, Node>;
+
+/// The directory at the root of every archive reserved for the archive's own files.
+/// It is left out of listings of the root directory, and paths inside it can be read but not changed.
+pub const RESERVED_DIR: &str = ".verter";
+/// The directory holding the files created with `Archive::alloc_temp`
+pub const TEMP_DIR: &str = ".verter/temp";
+/// Separates the prefix of a temporary file's name from the part that makes it unique, so it can't appear in prefixes
+pub const TEMP_SEPARATOR: char = '~';
+
+/// A hierarchy of directories and files.
+/// Every directory is stored as a chain listing its entries, and every file is stored in its own chain.
+/// Paths use `/` as a separator, and empty components are ignored, so `"assets/"` and `"/assets"` both name the `assets` directory.
+/// Changing anything inside `RESERVED_DIR` fails with `Error::ReservedChain`.
+///
+/// Paths are resolved against the version of the file the `File` they are given shows. To see every path as it was at one moment
+/// while a writer publishes or renames files, such as for a long export, read through a `SnapshotView`'s file, or inside a single
+/// `SharedReader::with` while the writer makes its changes inside `File::exclusive`.
+pub struct Archive {
+    /// Pointer to the root directory's chain
+    root: u64
+}
+
+impl Archive {
+
+    /// Create a new, empty archive in the file.
+    pub fn create(file: &mut File) -> Result<Self, Error> {
+        let root = file.alloc()?;
+        Ok(Self { root })
+    }
+
+    /// Open an archive previously created with `Archive::create`.
+    /// Nothing is written, so archives can be opened in read-only files. See `Archive::open_cleaning_temp` for removing temporary files.
+    pub fn open(file: &mut File, ptr: u64) -> Result<Self, Error> {
+        read_dir(file, ptr)?;
+        Ok(Self { root: ptr })
+    }
+
+    /// Open an archive like `Archive::open`, removing every temporary file an earlier session left behind with exactly this `prefix`
+    /// using `Archive::cleanup_temp`. Each component opens the archive with its own prefix when it starts,
+    /// so the temporary files of components still running under other prefixes are left alone.
+    pub fn open_cleaning_temp(file: &mut File, ptr: u64, prefix: &str) -> Result<Self, Error> {
+        let mut archive = Self::open(file, ptr)?;
+        archive.cleanup_temp(file, prefix)?;
+        Ok(archive)
+    }
+
+    /// The pointer to the archive's root directory, used to reopen the archive.
+    pub fn ptr(&self) -> u64 {
+        self.root
+    }
+
+    /// Create a directory, along with any missing parent directories.
+    pub fn create_dir(&mut self, file: &mut File, path: &str) -> Result<(), Error> {
+        check_not_reserved(path)?;
+        let mut dir = self.root;
+        for name in components(path) {
+            dir = child_dir(file, dir, name)?;
+        }
+        Ok(())
+    }
+
+    /// Store a file, replacing its previous contents if it exists.
+    /// Missing parent directories are created.
+    pub fn put(&mut self, file: &mut File, path: &str, data: &[u8]) -> Result<(), Error> {
+        check_not_reserved(path)?;
+        let (parent, name) = split_path(path)?;
+        let mut dir = self.root;
+        for dir_name in parent {
+            dir = child_dir(file, dir, dir_name)?;
+        }
+
+        let mut entries = read_dir(file, dir)?;
+        match entries.get(name) {
+            Some(Node { is_dir: true, .. }) => Err(Error::InvalidPath),
+            Some(Node { is_dir: false, ptr }) => file.write(*ptr, data),
+            None => {
+                let ptr = file.alloc()?;
+                file.write(ptr, data)?;
+                entries.insert(name.to_owned(), Node { is_dir: false, ptr });
+                write_dir(file, dir, &entries)
+            }
+        }
+    }
+
+    /// Replace a file with a new chain holding `data`, in one transaction that writes the new chain, points the path at it
+    /// and deletes the file's previous chain, so no reader can see the path pointing at a half-written or deleted chain.
+    /// Unlike `Archive::put`, which writes over the file's chain, the file moves to a new chain. Returns the pointer to the new chain.
+    /// Missing parent directories are created first, and are kept even if publishing fails.
+    pub fn publish(&mut self, file: &mut File, path: &str, data: &[u8]) -> Result<u64, Error> {
+        check_not_reserved(path)?;
+        let (parent, name) = split_path(path)?;
+        let mut dir = self.root;
+        for dir_name in parent {
+            dir = child_dir(file, dir, dir_name)?;
+        }
+
+        let mut entries = read_dir(file, dir)?;
+        if let Some(Node { is_dir: true, .. }) = entries.get(name) {
+            return Err(Error::InvalidPath);
+        }
+        let mut transaction = file.transaction()?;
+        let ptr = transaction.alloc()?;
+        transaction.write(ptr, data)?;
+        if let Some(old) = entries.insert(name.to_owned(), Node { is_dir: false, ptr }) {
+            transaction.delete(old.ptr)?;
+        }
+        transaction.write(dir, &encode_dir(&entries))?;
+        transaction.commit()?;
+        Ok(ptr)
+    }
+
+    /// Move a file or directory to a new path, in one transaction that updates both directories,
+    /// so no reader sees it at both paths or at neither. A file already at `to` is replaced and its chain deleted.
+    /// Missing parent directories of `to` are created first, and are kept even if the rename fails.
+    /// Returns `false` if nothing exists at `from`. Fails with `Error::InvalidPath` if `to` is a directory or is inside `from`.
+    pub fn rename(&mut self, file: &mut File, from: &str, to: &str) -> Result<bool, Error> {
+        check_not_reserved(from)?;
+        check_not_reserved(to)?;
+        let (from_parent, from_name) = split_path(from)?;
+        let (to_parent, to_name) = split_path(to)?;
+        if components(to).collect::<Vec<_>>().starts_with(&components(from).collect::<Vec<_>>()) {
+            return Err(Error::InvalidPath);
+        }
+        let Some(Node { is_dir: true, ptr: from_dir }) = self.lookup(file, &from_parent.join("/"))? else {
+            return Ok(false);
+        };
+        let mut to_dir = self.root;
+        for dir_name in to_parent {
+            to_dir = child_dir(file, to_dir, dir_name)?;
+        }
+
+        let mut from_entries = read_dir(file, from_dir)?;
+        let Some(node) = from_entries.remove(from_name) else {
+            return Ok(false);
+        };
+        let mut to_entries = if to_dir == from_dir { std::mem::take(&mut from_entries) } else { read_dir(file, to_dir)? };
+        if let Some(Node { is_dir: true, .. }) = to_entries.get(to_name) {
+            return Err(Error::InvalidPath);
+        }
+        let mut transaction = file.transaction()?;
+        if let Some(old) = to_entries.insert(to_name.to_owned(), node) {
+            transaction.delete(old.ptr)?;
+        }
+        if to_dir != from_dir {
+            transaction.write(from_dir, &encode_dir(&from_entries))?;
+        }
+        transaction.write(to_dir, &encode_dir(&to_entries))?;
+        transaction.commit()?;
+        Ok(true)
+    }
+
+    /// Import every file under a directory on disk with `File::import_dir`, storing each one in the archive at its relative path under `dir`.
+    /// Files already at those paths are replaced. Returns what `File::import_dir` returns.
+    pub fn import_dir<P: AsRef<Path>, F: FnMut(&Path) -> bool>(&mut self, file: &mut File, dir: &str, path: P, filter: F) -> Result<Vec<(std::path::PathBuf, u64)>, Error> {
+        check_not_reserved(dir)?;
+        let imported = file.import_dir(path, filter)?;
+        for (relative, ptr) in &imported {
+            let mut path = dir.to_owned();
+            for name in relative.iter() {
+                path.push('/');
+                path.push_str(&name.to_string_lossy());
+            }
+            self.link(file, &path, *ptr)?;
+        }
+        Ok(imported)
+    }
+
+    /// Store an existing chain as the file at a path, deleting the chain of any file it replaces.
+    fn link(&mut self, file: &mut File, path: &str, ptr: u64) -> Result<(), Error> {
+        let (parent, name) = split_path(path)?;
+        let mut dir = self.root;
+        for dir_name in parent {
+            dir = child_dir(file, dir, dir_name)?;
+        }
+
+        let mut entries = read_dir(file, dir)?;
+        if let Some(Node { is_dir: true, .. }) = entries.get(name) {
+            return Err(Error::InvalidPath);
+        }
+        if let Some(old) = entries.insert(name.to_owned(), Node { is_dir: false, ptr }) {
+            file.delete(old.ptr)?;
+        }
+        write_dir(file, dir, &entries)
+    }
+
+    /// Create an empty temporary file with a unique name made of `prefix`, `TEMP_SEPARATOR` and a suffix,
+    /// such as for a render that is still in progress, returning its path and the pointer to its chain.
+    /// Temporary files are kept in `TEMP_DIR` until they are removed with `Archive::cleanup_temp`, or when the archive is next opened
+    /// with `Archive::open_cleaning_temp` and the same prefix. Move the data elsewhere to keep it.
+    /// Fails with `Error::InvalidPath` if `prefix` contains a `/` or `TEMP_SEPARATOR`.
+    pub fn alloc_temp(&mut self, file: &mut File, prefix: &str) -> Result<(String, u64), Error> {
+        if prefix.contains(['/', TEMP_SEPARATOR]) {
+            return Err(Error::InvalidPath);
+        }
+        let mut dir = self.root;
+        for name in components(TEMP_DIR) {
+            dir = child_dir(file, dir, name)?;
+        }
+        let ptr = file.alloc()?;
+        // No other chain has the same pointer while this one exists, so neither does any other temporary file,
+        // unless its chain was deleted without removing it, in which case a counter is added to the name
+        let mut entries = read_dir(file, dir)?;
+        let mut name = format!("{prefix}{TEMP_SEPARATOR}{ptr:x}");
+        let mut retry = 0;
+        while entries.contains_key(&name) {
+            retry += 1;
+            name = format!("{prefix}{TEMP_SEPARATOR}{ptr:x}-{retry}");
+        }
+        entries.insert(name.clone(), Node { is_dir: false, ptr });
+        write_dir(file, dir, &entries)?;
+        Ok((format!("{TEMP_DIR}/{name}"), ptr))
+    }
+
+    /// Remove the temporary files created with `Archive::alloc_temp` with exactly this `prefix`, leaving those of other prefixes alone.
+    /// Call it when a component starts, to remove what an earlier session of it left behind, and when it shuts down.
+    /// Returns how many files were removed.
+    pub fn cleanup_temp(&mut self, file: &mut File, prefix: &str) -> Result<usize, Error> {
+        let Some(Node { is_dir: true, ptr: dir }) = self.lookup(file, TEMP_DIR)? else {
+            return Ok(0);
+        };
+        let mut entries = read_dir(file, dir)?;
+        let names = entries.keys()
+            .filter(|name| name.strip_prefix(prefix).is_some_and(|rest| rest.starts_with(TEMP_SEPARATOR)))
+            .cloned()
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            return Ok(0);
+        }
+        let removed = names.iter().filter_map(|name| entries.remove(name)).collect::<Vec<_>>();
+        write_dir(file, dir, &entries)?;
+        // A stale name may share its pointer with a newer temporary file that reused the chain's pages, which must be kept
+        let mut freed = entries.values().map(|node| node.ptr).collect::<HashSet<_>>();
+        for node in &removed {
+            if !freed.insert(node.ptr) {
+                continue;
+            }
+            match free_node(file, *node) {
+                // Files whose chain was deleted behind the archive's back are only unlisted
+                Ok(()) | Err(Error::DeletedPointer) => {},
+                Err(err) => return Err(err)
+            }
+        }
+        Ok(removed.len())
+    }
+
+    /// The pointer to the chain of the file at a path, or `None` if the file does not exist.
+    pub fn resolve(&self, file: &mut File, path: &str) -> Result<Option<u64>, Error> {
+        match self.lookup(file, path)? {
+            Some(Node { is_dir: false, ptr }) => Ok(Some(ptr)),
+            Some(Node { is_dir: true, .. }) => Err(Error::InvalidPath),
+            None => Ok(None)
+        }
+    }
+
+    /// Read a file. Returns `None` if the file does not exist.
+    pub fn get(&self, file: &mut File, path: &str) -> Result<Option<Vec<u8>>, Error> {
+        match self.lookup(file, path)? {
+            Some(Node { is_dir: false, ptr }) => file.read(ptr).map(Some),
+            Some(Node { is_dir: true, .. }) => Err(Error::InvalidPath),
+            None => Ok(None)
+        }
+    }
+
+    /// Check whether a file or directory exists.
+    pub fn exists(&self, file: &mut File, path: &str) -> Result<bool, Error> {
+        Ok(self.lookup(file, path)?.is_some())
+    }
+
+    /// List the entries of a directory, sorted by name. The root directory's listing leaves out `RESERVED_DIR`.
+    pub fn list(&self, file: &mut File, path: &str) -> Result<Vec<DirEntry>, Error> {
+        let dir = match self.lookup(file, path)? {
+            Some(Node { is_dir: true, ptr }) => ptr,
+            Some(Node { is_dir: false, .. }) | None => return Err(Error::InvalidPath)
+        };
+        Ok(read_dir(file, dir)?.into_iter()
+            .filter(|(name, _)| dir != self.root || name != RESERVED_DIR)
+            .map(|(name, node)| DirEntry { name, is_dir: node.is_dir })
+            .collect())
+    }
+
+    /// Remove a file, or a directory along with everything inside of it.
+    /// Returns `false` if nothing exists at the path.
+    pub fn remove(&mut self, file: &mut File, path: &str) -> Result<bool, Error> {
+        check_not_reserved(path)?;
+        let (parent, name) = split_path(path)?;
+        let mut dir = self.root;
+        for dir_name in parent {
+            match read_dir(file, dir)?.get(dir_name) {
+                Some(Node { is_dir: true, ptr }) => dir = *ptr,
+                _ => return Ok(false)
+            }
+        }
+
+        let mut entries = read_dir(file, dir)?;
+        let Some(node) = entries.remove(name) else {
+            return Ok(false);
+        };
+        write_dir(file, dir, &entries)?;
+        free_node(file, node)?;
+        Ok(true)
+    }
+
+    fn lookup(&self, file: &mut File, path: &str) -> Result<Option<Node>, Error> {
+        let mut node = Node { is_dir: true, ptr: self.root };
+        for name in components(path) {
+            if !node.is_dir {
+                return Ok(None);
+            }
+            match read_dir(file, node.ptr)?.get(name) {
+                Some(child) => node = *child,
+                None => return Ok(None)
+            }
+        }
+        Ok(Some(node))
+    }
+
+}
+
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|name| !name.is_empty())
+}
+
+/// Return `Error::ReservedChain` if a path is inside `RESERVED_DIR`
+fn check_not_reserved(path: &str) -> Result<(), Error> {
+    if components(path).next() == Some(RESERVED_DIR) {
+        return Err(Error::ReservedChain);
+    }
+    Ok(())
+}
+
+/// Split a path into its parent directories and its final component
+fn split_path(path: &str) -> Result<(Vec<&str>, &str), Error> {
+    let mut names = components(path).collect::<Vec<_>>();
+    let name = names.pop().ok_or(Error::InvalidPath)?;
+    Ok((names, name))
+}
+
+/// Get the subdirectory of a directory, creating it if it does not exist
+fn child_dir(file: &mut File, dir: u64, name: &str) -> Result<u64, Error> {
+    let mut entries = read_dir(file, dir)?;
+    match entries.get(name) {
+        Some(Node { is_dir: true, ptr }) => Ok(*ptr),
+        Some(Node { is_dir: false, .. }) => Err(Error::InvalidPath),
+        None => {
+            let ptr = file.alloc()?;
+            entries.insert(name.to_owned(), Node { is_dir: true, ptr });
+            write_dir(file, dir, &entries)?;
+            Ok(ptr)
+        }
+    }
+}
+
+fn free_node(file: &mut File, node: Node) -> Result<(), Error> {
+    if node.is_dir {
+        for child in read_dir(file, node.ptr)?.into_values() {
+            free_node(file, child)?;
+        }
+    }
+    file.delete(node.ptr)
+}
+
+/// Directories are stored as a list of entries, each encoded as (is dir, pointer, name length, name bytes)
+fn read_dir(file: &mut File, ptr: u64) -> Result<Directory, Error> {
+    let data = file.read(ptr)?;
+    let mut data = data.as_slice();
+    let mut entries = Directory::new();
+    while !data.is_empty() {
+        let mut words = [0; 3];
+        for word in &mut words {
+            let (bytes, rest) = data.split_at_checked(BYTES_IN_U64 as usize).ok_or(Error::CorruptedFile)?;
+            *word = u64::from_le_bytes(bytes.try_into().unwrap());
+            data = rest;
+        }
+        let [is_dir, ptr, name_len] = words;
+        let (name, rest) = data.split_at_checked(name_len as usize).ok_or(Error::CorruptedFile)?;
+        let name = String::from_utf8(name.to_vec()).map_err(|_| Error::CorruptedFile)?;
+        entries.insert(name, Node { is_dir: is_dir != 0, ptr });
+        data = rest;
+    }
+    Ok(entries)
+}
+
+fn write_dir(file: &mut File, ptr: u64, entries: &Directory) -> Result<(), Error> {
+    file.write(ptr, &encode_dir(entries))
+}
+
+fn encode_dir(entries: &Directory) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (name, node) in entries {
+        data.extend_from_slice(&(node.is_dir as u64).to_le_bytes());
+        data.extend_from_slice(&node.ptr.to_le_bytes());
+        data.extend_from_slice(&(name.len() as u64).to_le_bytes());
+        data.extend_from_slice(name.as_bytes());
+    }
+    data
+}
+
+#[test]
+fn archive() {
+    use crate::Config;
+
+    let mut file = File::open("archive.verter", Config::default()).unwrap();
+    let mut archive = Archive::create(&mut file).unwrap();
+    archive.create_dir(&mut file, "assets/textures").unwrap();
+    archive.put(&mut file, "assets/textures/wood.png", &[0xAB; 400]).unwrap();
+    archive.put(&mut file, "assets/textures/stone.png", b"stone").unwrap();
+    archive.put(&mut file, "scenes/intro", b"scene").unwrap();
+    let ptr = archive.ptr();
+    drop(file);
+
+    let mut file = File::open("archive.verter", Config::default()).unwrap();
+    let mut archive = Archive::open(&mut file, ptr).unwrap();
+    assert_eq!(archive.list(&mut file, "/").unwrap(), vec![
+        DirEntry { name: "assets".to_owned(), is_dir: true },
+        DirEntry { name: "scenes".to_owned(), is_dir: true }
+    ]);
+    assert_eq!(archive.list(&mut file, "assets/textures/").unwrap().len(), 2);
+    assert_eq!(archive.get(&mut file, "assets/textures/wood.png").unwrap().unwrap(), vec![0xAB; 400]);
+
+    assert!(archive.remove(&mut file, "assets").unwrap());
+    assert!(!archive.exists(&mut file, "assets/textures/stone.png").unwrap());
+    assert_eq!(archive.get(&mut file, "scenes/intro").unwrap().unwrap(), b"scene");
+    match archive.put(&mut file, "scenes", b"not a directory") {
+        Err(Error::InvalidPath) => {},
+        Ok(_) | Err(_) => panic!("should error with invalid path")
+    }
+
+    std::fs::remove_file("archive.verter").unwrap();
+}
+
+#[test]
+fn publish() {
+    use crate::Config;
+
+    let mut file = File::open("publish.verter", Config::default()).unwrap();
+    let mut archive = Archive::create(&mut file).unwrap();
+    let first = archive.publish(&mut file, "renders/shot_1.mp4", b"first render").unwrap();
+    assert_eq!(archive.get(&mut file, "renders/shot_1.mp4").unwrap().unwrap(), b"first render");
+
+    // Publishing again moves the path to a new chain and frees the old one
+    let second = archive.publish(&mut file, "renders/shot_1.mp4", b"second render").unwrap();
+    assert_ne!(first, second);
+    assert_eq!(archive.get(&mut file, "renders/shot_1.mp4").unwrap().unwrap(), b"second render");
+    match file.read(first) {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+
+    // A failed publish leaves the previous version in place
+    match archive.publish(&mut file, "renders", b"not a directory") {
+        Err(Error::InvalidPath) => {},
+        Ok(_) | Err(_) => panic!("should error with invalid path")
+    }
+    assert_eq!(archive.get(&mut file, "renders/shot_1.mp4").unwrap().unwrap(), b"second render");
+    file.validate().unwrap();
+
+    std::fs::remove_file("publish.verter").unwrap();
+}
+
+#[test]
+fn rename() {
+    use crate::Config;
+
+    let mut file = File::open("rename.verter", Config::default()).unwrap();
+    let mut archive = Archive::create(&mut file).unwrap();
+    archive.put(&mut file, "scenes/intro", b"intro").unwrap();
+    archive.put(&mut file, "scenes/outro", b"outro").unwrap();
+    archive.put(&mut file, "acts/finale", b"old finale").unwrap();
+    let old_finale = archive.resolve(&mut file, "acts/finale").unwrap().unwrap();
+
+    // Files keep their chain when they move
+    let intro = archive.resolve(&mut file, "scenes/intro").unwrap().unwrap();
+    assert!(archive.rename(&mut file, "scenes/intro", "scenes/opening").unwrap());
+    assert_eq!(archive.resolve(&mut file, "scenes/opening").unwrap(), Some(intro));
+    assert!(!archive.exists(&mut file, "scenes/intro").unwrap());
+
+    // Moving onto a file replaces it, and moving a directory moves everything inside it
+    assert!(archive.rename(&mut file, "scenes/outro", "acts/finale").unwrap());
+    assert_eq!(archive.get(&mut file, "acts/finale").unwrap().unwrap(), b"outro");
+    match file.read(old_finale) {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+    assert!(archive.rename(&mut file, "scenes", "old/scenes").unwrap());
+    assert_eq!(archive.get(&mut file, "old/scenes/opening").unwrap().unwrap(), b"intro");
+    assert!(!archive.rename(&mut file, "scenes/missing", "scenes/found").unwrap());
+
+    for (from, to) in [("old", "old/scenes/old"), ("acts/finale", "old/scenes")] {
+        match archive.rename(&mut file, from, to) {
+            Err(Error::InvalidPath) => {},
+            Ok(_) | Err(_) => panic!("should error with invalid path")
+        }
+    }
+    match archive.rename(&mut file, "acts/finale", TEMP_DIR) {
+        Err(Error::ReservedChain) => {},
+        Ok(_) | Err(_) => panic!("should error with reserved chain")
+    }
+    assert_eq!(archive.list(&mut file, "/").unwrap(), vec![
+        DirEntry { name: "acts".to_owned(), is_dir: true },
+        DirEntry { name: "old".to_owned(), is_dir: true }
+    ]);
+    file.validate().unwrap();
+
+    std::fs::remove_file("rename.verter").unwrap();
+}
+
+#[test]
+fn snapshot_resolution() {
+    use crate::{Config, SharedReader};
+
+    let mut file = File::open("snapshot_resolution.verter", Config::default()).unwrap();
+    let mut archive = Archive::create(&mut file).unwrap();
+    archive.publish(&mut file, "shots/1", b"shot 1, take 1").unwrap();
+    archive.publish(&mut file, "shots/2", b"shot 2, take 1").unwrap();
+    let mut view = file.readonly_view().unwrap();
+    let mut reader = SharedReader::open("snapshot_resolution.verter", Config::default()).unwrap();
+
+    file.exclusive(|file| {
+        archive.publish(file, "shots/1", b"shot 1, take 2")?;
+        archive.rename(file, "shots/2", "cut/2").map(drop)
+    }).unwrap();
+
+    // The view resolves every path as it was when it was taken, even though the chain it names was deleted since
+    let snapshot = Archive::open(view.file(), archive.ptr()).unwrap();
+    assert_eq!(snapshot.get(view.file(), "shots/1").unwrap().unwrap(), b"shot 1, take 1");
+    assert!(snapshot.exists(view.file(), "shots/2").unwrap());
+    assert!(!snapshot.exists(view.file(), "cut/2").unwrap());
+
+    // A shared reader sees all of the writer's changes at once
+    let latest = reader.with(|file| Ok((archive.get(file, "shots/1")?, archive.exists(file, "shots/2")?, archive.get(file, "cut/2")?))).unwrap();
+    assert_eq!(latest, (Some(b"shot 1, take 2".to_vec()), false, Some(b"shot 2, take 1".to_vec())));
+
+    std::fs::remove_file("snapshot_resolution.verter").unwrap();
+}
+
+#[test]
+fn alloc_temp() {
+    use crate::Config;
+
+    let mut file = File::open("alloc_temp.verter", Config::default()).unwrap();
+    let mut archive = Archive::create(&mut file).unwrap();
+    archive.put(&mut file, "scene", b"scene").unwrap();
+    let (first, first_ptr) = archive.alloc_temp(&mut file, "render").unwrap();
+    let (second, _) = archive.alloc_temp(&mut file, "render").unwrap();
+    let (preview, _) = archive.alloc_temp(&mut file, "render-preview").unwrap();
+    assert_ne!(first, second);
+    assert!(first.starts_with(".verter/temp/render~"));
+    file.write(first_ptr, b"frame").unwrap();
+    assert_eq!(archive.get(&mut file, &first).unwrap().unwrap(), b"frame");
+
+    // Only the files with exactly the prefix are removed
+    assert_eq!(archive.cleanup_temp(&mut file, "render").unwrap(), 2);
+    assert!(!archive.exists(&mut file, &second).unwrap());
+    assert!(archive.exists(&mut file, &preview).unwrap());
+    // Temporary files are hidden from the root directory's listing, and can't be changed through their paths
+    assert_eq!(archive.list(&mut file, "/").unwrap(), vec![DirEntry { name: "scene".to_owned(), is_dir: false }]);
+    assert_eq!(archive.list(&mut file, TEMP_DIR).unwrap().len(), 1);
+    for result in [archive.put(&mut file, &preview, b"oops"), archive.remove(&mut file, RESERVED_DIR).map(drop), archive.create_dir(&mut file, "/.verter/renders")] {
+        match result {
+            Err(Error::ReservedChain) => {},
+            Ok(_) | Err(_) => panic!("should error with reserved chain")
+        }
+    }
+    for prefix in ["renders/", "render~1"] {
+        match archive.alloc_temp(&mut file, prefix) {
+            Err(Error::InvalidPath) => {},
+            Ok(_) | Err(_) => panic!("should error with invalid path")
+        }
+    }
+
+    // A name still listed for a chain deleted behind the archive's back isn't handed out again
+    let (stale, stale_ptr) = archive.alloc_temp(&mut file, "stale").unwrap();
+    file.delete(stale_ptr).unwrap();
+    let (fresh, fresh_ptr) = archive.alloc_temp(&mut file, "stale").unwrap();
+    assert_eq!(fresh_ptr, stale_ptr);
+    assert_ne!(fresh, stale);
+    file.write(fresh_ptr, b"fresh").unwrap();
+    assert_eq!(archive.get(&mut file, &fresh).unwrap().unwrap(), b"fresh");
+    // Removing stale names leaves the chain alone once another temporary file reuses its pointer
+    file.delete(fresh_ptr).unwrap();
+    let (kept, kept_ptr) = archive.alloc_temp(&mut file, "kept").unwrap();
+    assert_eq!(kept_ptr, stale_ptr);
+    file.write(kept_ptr, b"kept").unwrap();
+    assert_eq!(archive.cleanup_temp(&mut file, "stale").unwrap(), 2);
+    assert!(!archive.exists(&mut file, &stale).unwrap());
+    assert_eq!(archive.get(&mut file, &kept).unwrap().unwrap(), b"kept");
+    assert_eq!(archive.cleanup_temp(&mut file, "kept").unwrap(), 1);
+
+    // Opening the archive leaves temporary files alone, and works in read-only files
+    let (other, _) = archive.alloc_temp(&mut file, "other").unwrap();
+    let ptr = archive.ptr();
+    drop(file);
+    let mut file = File::open("alloc_temp.verter", Config::default()).unwrap();
+    file.set_read_only(true);
+    let archive = Archive::open(&mut file, ptr).unwrap();
+    assert!(archive.exists(&mut file, &preview).unwrap());
+    // Opening it with a prefix removes what the last session left under that prefix only
+    file.set_read_only(false);
+    let archive = Archive::open_cleaning_temp(&mut file, ptr, "render-preview").unwrap();
+    assert!(!archive.exists(&mut file, &preview).unwrap());
+    assert!(archive.exists(&mut file, &other).unwrap());
+    assert_eq!(archive.get(&mut file, "scene").unwrap().unwrap(), b"scene");
+    file.validate().unwrap();
+
+    std::fs::remove_file("alloc_temp.verter").unwrap();
+}
+
+#[test]
+fn import_dir() {
+    use crate::Config;
+
+    std::fs::create_dir_all("import_dir/frames/shot_1").unwrap();
+    std::fs::write("import_dir/frames/shot_1/0001.png", [1; 5000]).unwrap();
+    std::fs::write("import_dir/frames/shot_1/0002.png", [2; 100]).unwrap();
+    std::fs::write("import_dir/frames/empty.png", []).unwrap();
+    std::fs::write("import_dir/notes.txt", b"notes").unwrap();
+
+    let mut file = File::open("import_dir.verter", Config { max_working_memory: Some(1000), ..Config::default() }).unwrap();
+    let pngs = file.import_dir("import_dir", |path| path.extension().is_some_and(|ext| ext == "png")).unwrap();
+    assert_eq!(pngs.iter().map(|(path, _)| path.clone()).collect::<Vec<_>>(), [
+        Path::new("frames/empty.png"),
+        Path::new("frames/shot_1/0001.png"),
+        Path::new("frames/shot_1/0002.png")
+    ]);
+    assert_eq!(file.read(pngs[0].1).unwrap(), b"");
+    assert_eq!(file.read(pngs[1].1).unwrap(), [1; 5000]);
+    assert_eq!(file.read(pngs[2].1).unwrap(), [2; 100]);
+
+    // Importing into an archive makes the files available by their paths
+    let mut archive = Archive::create(&mut file).unwrap();
+    archive.import_dir(&mut file, "assets", "import_dir", |_| true).unwrap();
+    assert_eq!(archive.get(&mut file, "assets/notes.txt").unwrap().unwrap(), b"notes");
+    assert_eq!(archive.get(&mut file, "assets/frames/shot_1/0001.png").unwrap().unwrap(), [1; 5000]);
+    std::fs::write("import_dir/notes.txt", b"new notes").unwrap();
+    archive.import_dir(&mut file, "assets", "import_dir", |path| path.ends_with("notes.txt")).unwrap();
+    assert_eq!(archive.get(&mut file, "assets/notes.txt").unwrap().unwrap(), b"new notes");
+    file.validate().unwrap();
+
+    match file.import_dir("import_dir_missing", |_| true) {
+        Err(Error::IO(_)) => {},
+        Ok(_) | Err(_) => panic!("should error with io error")
+    }
+
+    std::fs::remove_dir_all("import_dir").unwrap();
+    std::fs::remove_file("import_dir.verter").unwrap();
+}
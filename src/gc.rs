@@ -0,0 +1,135 @@
+use std::collections::HashSet;
+
+use crate::{CancellationToken, Error, File, PageHeader};
+
+/// Chains found by `File::find_unreachable`
+#[derive(Debug, Default)]
+pub struct UnreachableReport {
+    /// Pointers to the unreachable chains, in address order
+    pub chains: Vec<u64>,
+    /// The number of pages occupied by the unreachable chains
+    pub pages: u64,
+    /// The number of bytes of data stored in the unreachable chains
+    pub bytes: u64
+}
+
+impl File {
+
+    /// Free every chain that is not reachable from the given roots.
+    /// The tracer is given the data of each reachable chain and must return the pointers stored inside of it.
+    /// The root chain and the chains of every partition are always treated as roots. Pointers returned by the tracer that do not point to the start of a chain are ignored.
+    /// Returns the number of pages that were reclaimed.
+    pub fn gc<F: Fn(&[u8]) -> Vec<u64>>(&mut self, roots: &[u64], tracer: F) -> Result<u64, Error> {
+        self.gc_cancellable(roots, tracer, &CancellationToken::new())
+    }
+
+    /// Like `File::gc`, but stops with `Error::Cancelled` once `cancel` is cancelled.
+    /// Chains are freed one at a time, so the chains freed before stopping stay freed and the rest are left untouched.
+    pub fn gc_cancellable<F: Fn(&[u8]) -> Vec<u64>>(&mut self, roots: &[u64], tracer: F, cancel: &CancellationToken) -> Result<u64, Error> {
+        let unreachable = self.unreachable_chains(roots, tracer)?;
+        self.as_one_change(|file| {
+            let mut reclaimed = 0;
+            for chain in unreachable {
+                cancel.check()?;
+                reclaimed += file.chain_pages(chain)?.len() as u64;
+                file.delete(chain)?;
+            }
+            Ok(reclaimed)
+        })
+    }
+
+    /// Find the chains that are not reachable from the given roots, without freeing them.
+    /// Uses the same rules for reachability as `File::gc`.
+    pub fn find_unreachable<F: Fn(&[u8]) -> Vec<u64>>(&mut self, roots: &[u64], tracer: F) -> Result<UnreachableReport, Error> {
+        let mut report = UnreachableReport::default();
+        for chain in self.unreachable_chains(roots, tracer)? {
+            let pages = self.chain_pages(chain)?;
+            // The metadata stored in front of the chain's data isn't counted
+            if let PageHeader::FinalPage(size) = self.read_page_header(*pages.last().unwrap())? {
+                report.bytes += ((pages.len() as u64 - 1) * self.config.page_size as u64 + size).saturating_sub(self.meta_size());
+            }
+            report.pages += pages.len() as u64;
+            report.chains.push(chain);
+        }
+        Ok(report)
+    }
+
+    /// Find the chains that are not reachable from the given roots, in address order.
+    pub(crate) fn unreachable_chains<F: Fn(&[u8]) -> Vec<u64>>(&mut self, roots: &[u64], tracer: F) -> Result<Vec<u64>, Error> {
+        let heads = self.chain_heads()?;
+        let head_set = heads.iter().copied().collect::<HashSet<_>>();
+
+        let mut reachable = self.internal_chains()?.into_iter().collect::<HashSet<_>>();
+        let mut to_visit = roots.to_vec();
+        to_visit.extend(self.root_chains()?);
+
+        while let Some(chain) = to_visit.pop() {
+            if !head_set.contains(&chain) || !reachable.insert(chain) {
+                continue;
+            }
+            let data = self.read(chain)?;
+            to_visit.extend(tracer(&data));
+        }
+
+        Ok(heads.into_iter().filter(|head| !reachable.contains(head)).collect())
+    }
+
+}
+
+#[test]
+fn gc() {
+    use crate::{Config, decode_u64s, encode_u64s};
+
+    let mut file = File::open("gc.verter", Config::default()).unwrap();
+    let leaf = file.alloc().unwrap();
+    file.write(leaf, &[0xAB; 300]).unwrap();
+    let node = file.alloc().unwrap();
+    file.write(node, &encode_u64s(&[leaf])).unwrap();
+    file.write_root(&encode_u64s(&[node])).unwrap();
+
+    let leaked = file.alloc().unwrap();
+    file.write(leaked, &[0xCD; 300]).unwrap();
+
+    let report = file.find_unreachable(&[], |data| decode_u64s(data).unwrap_or_default()).unwrap();
+    assert_eq!(report.chains, vec![leaked]);
+    assert_eq!(report.pages, 3);
+    assert_eq!(report.bytes, 300);
+
+    let reclaimed = file.gc(&[], |data| decode_u64s(data).unwrap_or_default()).unwrap();
+    assert_eq!(reclaimed, 3);
+    assert_eq!(file.read(leaf).unwrap(), vec![0xAB; 300]);
+    match file.read(leaked) {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+
+    std::fs::remove_file("gc.verter").unwrap();
+
+    // The bytes of data don't include the chain's metadata
+    let mut file = File::open("gc_meta.verter", Config { chain_meta: true, ..Config::default() }).unwrap();
+    let leaked = file.alloc_with(&[0xCD; 300]).unwrap();
+    let report = file.find_unreachable(&[], |_| Vec::new()).unwrap();
+    assert_eq!(report.chains, vec![leaked]);
+    assert_eq!(report.bytes, 300);
+    std::fs::remove_file("gc_meta.verter").unwrap();
+}
+
+#[test]
+fn cancelled_gc() {
+    use crate::Config;
+
+    let mut file = File::open("cancelled_gc.verter", Config::default()).unwrap();
+    let leaked = file.alloc().unwrap();
+    file.write(leaked, &[0xCD; 300]).unwrap();
+
+    let cancel = CancellationToken::new();
+    cancel.clone().cancel();
+    match file.gc_cancellable(&[], |_| Vec::new(), &cancel) {
+        Err(Error::Cancelled) => {},
+        Ok(_) | Err(_) => panic!("should error with cancelled")
+    }
+    assert_eq!(file.read(leaked).unwrap(), vec![0xCD; 300]);
+    file.validate().unwrap();
+
+    std::fs::remove_file("cancelled_gc.verter").unwrap();
+}
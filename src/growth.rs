@@ -0,0 +1,99 @@
+use crate::{AllocPolicy, Error, File, PageHeader};
+
+impl File {
+
+    /// Append data to the end of a chain.
+    /// If the chain has a growth hint, see `File::set_growth_hint`, the file is extended by more pages than the chain needs.
+    pub fn append(&mut self, ptr: u64, data: &[u8]) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        let len = self.chain_len(ptr)?;
+        self.patch(ptr, &[(len, data)])
+    }
+
+    /// Over-allocate when a chain that is appended to often, such as an onion-skin cache, grows past the end of the file.
+    /// Like a `Vec`, the file is extended to `factor` times the chain's current page count at once,
+    /// and the pages the chain doesn't need yet are put at the front of the free list in address order, so its next appends take them.
+    /// The spare pages are ordinary free pages, so other chains may take them, and nothing is leaked if the process crashes.
+    /// A factor of 1 or less removes the hint. Hints are kept by this handle only, and are forgotten when the chain is deleted.
+    /// Only `AllocPolicy::FirstFree` honors hints, since the other policies choose pages by address anyway.
+    pub fn set_growth_hint(&mut self, ptr: u64, factor: u32) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        if factor > 1 {
+            self.growth_hints.insert(ptr, factor);
+        } else {
+            self.growth_hints.remove(&ptr);
+        }
+        Ok(())
+    }
+
+    /// The growth factor of a chain set with `File::set_growth_hint`, if any.
+    pub fn growth_hint(&self, ptr: u64) -> Option<u32> {
+        self.growth_hints.get(&ptr).copied()
+    }
+
+    /// Before a chain with a growth hint is extended from `pages` to `needed` pages, add its spare pages to the end of the file.
+    /// Does nothing if the free list already has pages to take, or if the spare pages would go over `Config::max_file_size`.
+    pub(crate) fn reserve_growth(&mut self, ptr: u64, pages: usize, needed: usize) -> Result<(), Error> {
+        let Some(factor) = self.growth_hint(ptr) else {
+            return Ok(());
+        };
+        if needed <= pages || self.config.alloc_policy != AllocPolicy::FirstFree || self.first_free_page()? != 0 {
+            return Ok(());
+        }
+        let new_pages = (pages * factor as usize).max(needed) - pages;
+        let start = self.file_size()?;
+        let end = start + new_pages as u64 * self.total_page_size();
+        if self.check_file_size_limit(end).is_err() {
+            return Ok(());
+        }
+
+        self.check_writable()?;
+        self.file.set_len(end).map_err(Error::IO)?;
+        for i in 0..new_pages as u64 {
+            let page = start + i * self.total_page_size();
+            let next = if i + 1 < new_pages as u64 { page + self.total_page_size() } else { 0 };
+            self.write_page_header(page, PageHeader::DeletedPage(next))?;
+        }
+        self.write_u64(self.first_free_page_ptr(), start)?;
+        self.bump_change_counter()
+    }
+
+}
+
+#[test]
+fn growth_hint() {
+    use crate::Config;
+
+    let mut file = File::open("growth_hint.verter", Config::default()).unwrap();
+    let onion_skin = file.alloc().unwrap();
+    file.set_growth_hint(onion_skin, 2).unwrap();
+    assert_eq!(file.growth_hint(onion_skin), Some(2));
+
+    let mut expected = Vec::new();
+    let mut sizes = Vec::new();
+    for frame in 0..40u8 {
+        let data = [frame; 300];
+        file.append(onion_skin, &data).unwrap();
+        expected.extend_from_slice(&data);
+        sizes.push(std::fs::metadata("growth_hint.verter").unwrap().len());
+    }
+    assert_eq!(file.read(onion_skin).unwrap(), expected);
+    // The chain's 100 pages are added as its capacity doubles, instead of a few pages per append
+    sizes.dedup();
+    assert!(sizes.len() < 16);
+    // The chain's pages were added in address order, so it is a single run
+    let pages = file.chain_pages(onion_skin).unwrap();
+    assert_eq!(file.page_runs(&pages).len(), 1);
+    file.validate().unwrap();
+
+    file.set_growth_hint(onion_skin, 1).unwrap();
+    assert_eq!(file.growth_hint(onion_skin), None);
+    let other = file.alloc().unwrap();
+    file.set_growth_hint(other, 4).unwrap();
+    file.delete(other).unwrap();
+    assert_eq!(file.growth_hint(other), None);
+
+    std::fs::remove_file("growth_hint.verter").unwrap();
+}
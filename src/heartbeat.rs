@@ -0,0 +1,285 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{Error, File};
+
+/// How many heartbeats a writer may miss before it is considered to have crashed
+const MISSED_HEARTBEATS: u32 = 3;
+
+/// How many times claiming the record is retried when other writers keep changing it
+const CLAIM_ATTEMPTS: u32 = 4;
+
+/// Tells apart the heartbeats of the handles in this process
+static NEXT_HANDLE: AtomicU64 = AtomicU64::new(0);
+
+/// The process recorded as writing to a file opened with `Config::writer_heartbeat`, as told by `File::writer_status`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WriterStatus {
+    /// The ID of the writing process
+    pub pid: u32,
+    /// When the writer's heartbeat was last refreshed
+    pub last_active: SystemTime,
+    /// How often the writer refreshes its heartbeat
+    pub interval: Duration
+}
+
+impl WriterStatus {
+
+    /// How long ago the writer's heartbeat was last refreshed, such as for showing "locked by PID 1234, last active 3s ago".
+    pub fn since_last_active(&self) -> Duration {
+        self.last_active.elapsed().unwrap_or_default()
+    }
+
+    /// Whether the writer missed enough heartbeats that it must have crashed or hung, so the file can safely be taken over.
+    pub fn is_stale(&self) -> bool {
+        self.since_last_active() > self.interval * MISSED_HEARTBEATS
+    }
+
+    /// Stored as the process ID, the time of the last heartbeat in milliseconds since the Unix epoch and the interval in milliseconds,
+    /// one per line
+    pub(crate) fn encode(&self) -> String {
+        let millis = |duration: Duration| duration.as_millis();
+        format!("{}\n{}\n{}\n", self.pid, millis(self.last_active.duration_since(UNIX_EPOCH).unwrap_or_default()), millis(self.interval))
+    }
+
+    pub(crate) fn decode(text: &str) -> Option<Self> {
+        let mut fields = text.lines().map(str::parse::<u64>);
+        let pid = fields.next()?.ok()?.try_into().ok()?;
+        let last_active = UNIX_EPOCH + Duration::from_millis(fields.next()?.ok()?);
+        let interval = Duration::from_millis(fields.next()?.ok()?);
+        Some(Self { pid, last_active, interval })
+    }
+
+}
+
+/// The record of a writer, along with the handle in its process that wrote it.
+/// Records written before handles were recorded have no handle, and don't belong to any handle of this process.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Record {
+    status: WriterStatus,
+    handle: Option<u64>
+}
+
+impl Record {
+
+    /// Whether the record was written by the given handle of this process
+    fn is_owned_by(&self, handle: u64) -> bool {
+        self.status.pid == std::process::id() && self.handle == Some(handle)
+    }
+
+}
+
+/// Keeps the heartbeat of a writer fresh on a worker thread, and withdraws the writer's record once dropped
+pub(crate) struct Heartbeat {
+    path: PathBuf,
+    handle: u64,
+    stop: Option<Sender<()>>,
+    worker: Option<JoinHandle<()>>
+}
+
+impl Heartbeat {
+
+    /// Record this handle as the writer of the file at `path`.
+    /// Fails with `Error::WriterActive` if another writer's heartbeat is still fresh, even if it is another handle in this process.
+    pub(crate) fn start(path: &Path, interval: Duration) -> Result<Self, Error> {
+        let path = heartbeat_path(path);
+        let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+        let partial = scratch_path(&path, handle, "partial");
+        std::fs::write(&partial, encode_record(interval, handle)).map_err(Error::IO)?;
+        let claimed = claim(&path, &partial, handle);
+        let _ = std::fs::remove_file(&partial);
+        claimed?;
+
+        let (stop, stopped) = channel();
+        let worker_path = path.clone();
+        let worker = std::thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                // Once the lock was broken, the record belongs to someone else and must be left alone
+                match read_record(&worker_path) {
+                    Ok(Some(record)) if record.is_owned_by(handle) => {},
+                    _ => break
+                }
+                // A missed heartbeat only makes the writer look stale sooner, so there's nothing better to do with the error
+                let _ = write_record(&worker_path, interval, handle);
+            }
+        });
+        Ok(Self { path, handle, stop: Some(stop), worker: Some(worker) })
+    }
+
+}
+
+impl Drop for Heartbeat {
+
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        if let Ok(Some(record)) = read_record(&self.path) {
+            if record.is_owned_by(self.handle) {
+                let _ = std::fs::remove_file(&self.path);
+            }
+        }
+    }
+
+}
+
+/// Make the record at `partial` the record of the writer at `path`, unless another writer's heartbeat is fresh.
+/// Creating the record with a hard link fails if one already exists, and a stale record is taken over by first moving it aside,
+/// which only one of several writers racing for it can do, so two writers never both believe they own the record.
+fn claim(path: &Path, partial: &Path, handle: u64) -> Result<(), Error> {
+    for _ in 0..CLAIM_ATTEMPTS {
+        match std::fs::hard_link(partial, path) {
+            Ok(()) => {
+                return match read_record(path)? {
+                    Some(record) if record.is_owned_by(handle) => Ok(()),
+                    Some(record) => Err(Error::WriterActive(record.status)),
+                    None => Err(Error::ConcurrentModification)
+                };
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {},
+            Err(err) => return Err(Error::IO(err))
+        }
+
+        let Some(existing) = read_record(path)? else {
+            continue;
+        };
+        if !existing.status.is_stale() {
+            return Err(Error::WriterActive(existing.status));
+        }
+        let displaced = scratch_path(path, handle, "stale");
+        match std::fs::rename(path, &displaced) {
+            Ok(()) => {},
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(Error::IO(err))
+        }
+        let moved = read_record(&displaced);
+        match moved {
+            Ok(Some(moved)) if moved != existing => {
+                // Another writer replaced the stale record in the meantime, so its record is put back
+                let _ = std::fs::hard_link(&displaced, path);
+                let _ = std::fs::remove_file(&displaced);
+                return Err(Error::WriterActive(moved.status));
+            },
+            _ => {
+                let _ = std::fs::remove_file(&displaced);
+            }
+        }
+    }
+    Err(Error::ConcurrentModification)
+}
+
+impl File {
+
+    /// The process recorded as writing to the file at `path`, if it was opened with `Config::writer_heartbeat`,
+    /// so that a second instance of an application can tell whether the file is in use or its writer crashed.
+    /// Returns `None` if no writer is recorded. Records left by crashed writers are returned too, see `WriterStatus::is_stale`.
+    pub fn writer_status<P: AsRef<Path>>(path: P) -> Result<Option<WriterStatus>, Error> {
+        read_status(&heartbeat_path(path.as_ref()))
+    }
+
+    /// Remove the record of the writer of the file at `path`, so that it can be opened with `Config::writer_heartbeat` even though
+    /// the writer's heartbeat is still fresh, such as after the user confirmed that the other instance is gone.
+    /// Records of stale writers don't need to be removed first.
+    pub fn break_writer_lock<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+        match std::fs::remove_file(heartbeat_path(path.as_ref())) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(Error::IO(err)),
+            _ => Ok(())
+        }
+    }
+
+}
+
+/// The writer of a file is recorded next to it, in `<path>.writer`
+fn heartbeat_path(path: &Path) -> PathBuf {
+    let mut heartbeat = path.as_os_str().to_owned();
+    heartbeat.push(".writer");
+    PathBuf::from(heartbeat)
+}
+
+/// A file next to the record used by one handle of this process, in `<path>.<pid>.<handle>.<kind>`
+fn scratch_path(path: &Path, handle: u64, kind: &str) -> PathBuf {
+    let mut scratch = path.as_os_str().to_owned();
+    scratch.push(format!(".{}.{handle}.{kind}", std::process::id()));
+    PathBuf::from(scratch)
+}
+
+fn read_status(path: &Path) -> Result<Option<WriterStatus>, Error> {
+    Ok(read_record(path)?.map(|record| record.status))
+}
+
+/// The handle is stored on a line after the status
+fn read_record(path: &Path) -> Result<Option<Record>, Error> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => {
+            let status = WriterStatus::decode(&text).ok_or(Error::CorruptedFile)?;
+            let handle = text.lines().nth(3).and_then(|handle| handle.parse().ok());
+            Ok(Some(Record { status, handle }))
+        },
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(Error::IO(err))
+    }
+}
+
+fn encode_record(interval: Duration, handle: u64) -> String {
+    let status = WriterStatus { pid: std::process::id(), last_active: SystemTime::now(), interval };
+    format!("{}{handle}\n", status.encode())
+}
+
+fn write_record(path: &Path, interval: Duration, handle: u64) -> Result<(), Error> {
+    // Written next to the record and moved over it, so readers never see half a record
+    let partial = scratch_path(path, handle, "partial");
+    std::fs::write(&partial, encode_record(interval, handle)).map_err(Error::IO)?;
+    std::fs::rename(&partial, path).map_err(Error::IO)
+}
+
+#[test]
+fn writer_heartbeat() {
+    use crate::Config;
+
+    let config = Config { writer_heartbeat: Some(Duration::from_millis(20)), ..Config::default() };
+    let file = File::open("writer_heartbeat.verter", config).unwrap();
+    let status = File::writer_status("writer_heartbeat.verter").unwrap().unwrap();
+    assert_eq!(status.pid, std::process::id());
+    std::thread::sleep(Duration::from_millis(100));
+    let status = File::writer_status("writer_heartbeat.verter").unwrap().unwrap();
+    assert!(!status.is_stale());
+    assert!(status.since_last_active() < Duration::from_millis(100));
+
+    // A second handle in this process is refused too, and doesn't withdraw the first handle's record
+    match File::open("writer_heartbeat.verter", config) {
+        Err(Error::WriterActive(status)) => assert_eq!(status.pid, std::process::id()),
+        Ok(_) | Err(_) => panic!("should error with writer active")
+    }
+    assert!(File::writer_status("writer_heartbeat.verter").unwrap().is_some());
+    drop(file);
+    assert_eq!(File::writer_status("writer_heartbeat.verter").unwrap(), None);
+
+    // Another process with a fresh heartbeat keeps the file, until its heartbeat goes stale or its lock is broken
+    let other = WriterStatus { pid: std::process::id() + 1, last_active: SystemTime::now(), interval: Duration::from_secs(60) };
+    std::fs::write("writer_heartbeat.verter.writer", other.encode()).unwrap();
+    match File::open("writer_heartbeat.verter", config) {
+        Err(Error::WriterActive(status)) => assert_eq!(status.pid, other.pid),
+        Ok(_) | Err(_) => panic!("should error with writer active")
+    }
+    File::break_writer_lock("writer_heartbeat.verter").unwrap();
+
+    // A writer whose lock was broken leaves the record of the writer that took over alone
+    let file = File::open("writer_heartbeat.verter", config).unwrap();
+    File::break_writer_lock("writer_heartbeat.verter").unwrap();
+    std::fs::write("writer_heartbeat.verter.writer", other.encode()).unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    drop(file);
+    assert_eq!(File::writer_status("writer_heartbeat.verter").unwrap().map(|status| status.pid), Some(other.pid));
+    File::break_writer_lock("writer_heartbeat.verter").unwrap();
+    let crashed = WriterStatus { last_active: SystemTime::now() - Duration::from_secs(600), ..other };
+    assert!(crashed.is_stale());
+    std::fs::write("writer_heartbeat.verter.writer", crashed.encode()).unwrap();
+    drop(File::open("writer_heartbeat.verter", config).unwrap());
+    assert!(!std::fs::exists("writer_heartbeat.verter.writer").unwrap());
+
+    std::fs::remove_file("writer_heartbeat.verter").unwrap();
+}
@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::backend::StaticBytes;
+use crate::{Backend, Error, File};
+
+/// How many times each page of a file was written to, returned by `File::heatmap`, for finding regions of the file that churn.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeatMap {
+    /// The number of writes to each page that was written to, keyed by the pointer to the page
+    pub pages: BTreeMap<u64, u64>,
+    /// The number of writes to the file's header
+    pub header: u64
+}
+
+impl HeatMap {
+
+    /// Add the counts from another heat map, such as one saved at the end of an earlier session.
+    pub fn merge(&mut self, other: &HeatMap) {
+        for (page, writes) in &other.pages {
+            *self.pages.entry(*page).or_default() += writes;
+        }
+        self.header += other.header;
+    }
+
+    /// Encode the heat map as bytes, for saving it across sessions.
+    /// The encoding is the number of header writes, followed by the pointer and count of every page, all as little-endian u64s.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header.to_le_bytes().to_vec();
+        for (page, writes) in &self.pages {
+            bytes.extend_from_slice(&page.to_le_bytes());
+            bytes.extend_from_slice(&writes.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decode a heat map encoded with `HeatMap::to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let vals = crate::decode_u64s(bytes)?;
+        let (header, pages) = vals.split_first().ok_or(Error::CorruptedFile)?;
+        if pages.len() % 2 != 0 {
+            return Err(Error::CorruptedFile);
+        }
+        Ok(Self {
+            pages: pages.chunks(2).map(|pair| (pair[0], pair[1])).collect(),
+            header: *header
+        })
+    }
+
+    /// Draw the heat map as text, one character per page in address order and `width` pages per line,
+    /// from ` ` for pages that were never written to through `.:-=+*#%` up to `@` for the most written page.
+    /// `first_page` and `page_size` are the pointer to the file's first page and the distance between pages, and `pages` is the number of pages.
+    pub fn render(&self, first_page: u64, page_size: u64, pages: u64, width: usize) -> String {
+        const SHADES: &[u8] = b" .:-=+*#%@";
+        let max = self.pages.values().copied().max().unwrap_or(0).max(1);
+        let mut text = String::new();
+        for i in 0..pages {
+            if i > 0 && i.is_multiple_of(width.max(1) as u64) {
+                text.push('\n');
+            }
+            let writes = self.pages.get(&(first_page + i * page_size)).copied().unwrap_or(0);
+            let shade = (writes * (SHADES.len() as u64 - 1)).div_ceil(max);
+            text.push(SHADES[shade as usize] as char);
+        }
+        text
+    }
+
+}
+
+/// Where the pages of the file start, so the writes can be attributed to pages
+pub(crate) struct HeatCounts {
+    header_size: u64,
+    total_page_size: u64,
+    map: HeatMap
+}
+
+impl HeatCounts {
+
+    fn record(&mut self, start: u64, len: u64) {
+        if len == 0 {
+            return;
+        }
+        let end = start + len;
+        if start < self.header_size {
+            self.map.header += 1;
+        }
+        let start = start.max(self.header_size);
+        if start >= end {
+            return;
+        }
+        let first = (start - self.header_size) / self.total_page_size;
+        let last = (end - 1 - self.header_size) / self.total_page_size;
+        for page in first..=last {
+            *self.map.pages.entry(self.header_size + page * self.total_page_size).or_default() += 1;
+        }
+    }
+
+}
+
+/// Wraps a backend, counting how many times each page is written to
+struct HeatTracking {
+    inner: Box<dyn Backend>,
+    pos: u64,
+    counts: Arc<Mutex<HeatCounts>>
+}
+
+impl Read for HeatTracking {
+
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+}
+
+impl Write for HeatTracking {
+
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.counts.lock().unwrap().record(self.pos, n as u64);
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+
+}
+
+impl Seek for HeatTracking {
+
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.pos = self.inner.seek(pos)?;
+        Ok(self.pos)
+    }
+
+}
+
+impl Backend for HeatTracking {
+
+    fn len(&self) -> std::io::Result<u64> {
+        self.inner.len()
+    }
+
+    fn sync_data(&self) -> std::io::Result<()> {
+        self.inner.sync_data()
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        self.inner.read_exact_at(buf, offset)
+    }
+
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        self.inner.set_len(len)
+    }
+
+    fn lock_shared(&self) -> std::io::Result<()> {
+        self.inner.lock_shared()
+    }
+
+    fn lock_exclusive(&self) -> std::io::Result<()> {
+        self.inner.lock_exclusive()
+    }
+
+    fn unlock(&self) -> std::io::Result<()> {
+        self.inner.unlock()
+    }
+
+    fn available_space(&self) -> std::io::Result<Option<u64>> {
+        self.inner.available_space()
+    }
+
+    fn clone_into(&self, dest: &std::fs::File, len: u64) -> std::io::Result<bool> {
+        self.inner.clone_into(dest, len)
+    }
+
+}
+
+impl File {
+
+    /// How many times each page was written to since the file was opened, if `Config::heatmap` is set.
+    /// Writes to the file are counted at the storage level, so every write verter makes, including page headers and its own tables, shows up.
+    pub fn heatmap(&self) -> Option<HeatMap> {
+        Some(self.heat.as_ref()?.lock().unwrap().map.clone())
+    }
+
+    /// Start counting writes to each page, once the file's header is known
+    pub(crate) fn track_heat(&mut self) -> Result<(), Error> {
+        let counts = Arc::new(Mutex::new(HeatCounts {
+            header_size: self.header_size(),
+            total_page_size: self.total_page_size(),
+            map: HeatMap::default()
+        }));
+        let mut inner = std::mem::replace(&mut self.file, Box::new(StaticBytes::new(&[])));
+        let pos = inner.stream_position().map_err(Error::IO)?;
+        self.file = Box::new(HeatTracking { inner, pos, counts: counts.clone() });
+        self.heat = Some(counts);
+        Ok(())
+    }
+
+}
+
+#[test]
+fn heatmap() {
+    use crate::Config;
+
+    let config = Config {
+        heatmap: true,
+        ..Config::default()
+    };
+    let mut file = File::open("heatmap.verter", config).unwrap();
+    let hot = file.alloc().unwrap();
+    let cold = file.alloc_with(b"written once").unwrap();
+    for i in 0..10u8 {
+        file.write(hot, &[i; 10]).unwrap();
+    }
+
+    let heatmap = file.heatmap().unwrap();
+    assert!(heatmap.pages[&hot] > heatmap.pages[&cold]);
+    assert!(heatmap.header > 0);
+    assert_eq!(HeatMap::from_bytes(&heatmap.to_bytes()).unwrap(), heatmap);
+    let pages = (file.file_size().unwrap() - file.header_size()) / file.total_page_size();
+    let text = heatmap.render(file.header_size(), file.total_page_size(), pages, 80);
+    assert_eq!(text.chars().nth(((hot - file.header_size()) / file.total_page_size()) as usize), Some('@'));
+
+    let mut total = heatmap.clone();
+    total.merge(&heatmap);
+    assert_eq!(total.pages[&hot], 2 * heatmap.pages[&hot]);
+
+    assert!(File::open("heatmap.verter", Config::default()).unwrap().heatmap().is_none());
+
+    std::fs::remove_file("heatmap.verter").unwrap();
+}
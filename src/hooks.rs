@@ -0,0 +1,105 @@
+use crate::audit::{Audit, AuditOp};
+use crate::subscribe::{ChangeEvent, Subscribers};
+use crate::File;
+
+/// Callbacks the embedding application registers to be told about changes to a file's chains,
+/// such as to keep a live display of memory usage or to track leaks. Get them with `File::hooks`.
+/// Callbacks run after the change succeeds, and are also called for the chains verter allocates for its own tables.
+#[derive(Default)]
+pub struct Hooks {
+    on_alloc: Vec<Box<dyn FnMut(u64) + Send + Sync>>,
+    on_delete: Vec<Box<dyn FnMut(u64) + Send + Sync>>,
+    on_write: Vec<Box<dyn FnMut(u64, usize) + Send + Sync>>,
+    /// Fired from the same places as the callbacks, but not removed by `Hooks::clear`
+    pub(crate) audit: Audit,
+    /// The subscriptions made with `File::subscribe`, which `Hooks::clear` doesn't remove either
+    pub(crate) subscribers: Subscribers
+}
+
+impl Hooks {
+
+    /// Register a callback called with the pointer of every newly allocated chain.
+    pub fn on_alloc<F: FnMut(u64) + Send + Sync + 'static>(&mut self, callback: F) {
+        self.on_alloc.push(Box::new(callback));
+    }
+
+    /// Register a callback called with the pointer of every deleted chain.
+    pub fn on_delete<F: FnMut(u64) + Send + Sync + 'static>(&mut self, callback: F) {
+        self.on_delete.push(Box::new(callback));
+    }
+
+    /// Register a callback called with the pointer of every chain written to, along with the size of the written data.
+    pub fn on_write<F: FnMut(u64, usize) + Send + Sync + 'static>(&mut self, callback: F) {
+        self.on_write.push(Box::new(callback));
+    }
+
+    /// Remove every registered callback. The audit sink stays in place.
+    pub fn clear(&mut self) {
+        self.on_alloc.clear();
+        self.on_delete.clear();
+        self.on_write.clear();
+    }
+
+    pub(crate) fn alloc(&mut self, ptr: u64) {
+        self.on_alloc.iter_mut().for_each(|callback| callback(ptr));
+        self.audit.record(AuditOp::Alloc, ptr, 0);
+    }
+
+    pub(crate) fn delete(&mut self, ptr: u64) {
+        self.on_delete.iter_mut().for_each(|callback| callback(ptr));
+        self.audit.record(AuditOp::Delete, ptr, 0);
+        self.subscribers.notify(ChangeEvent::Deleted(ptr));
+    }
+
+    pub(crate) fn write(&mut self, ptr: u64, size: usize) {
+        self.on_write.iter_mut().for_each(|callback| callback(ptr, size));
+        self.audit.record(AuditOp::Write, ptr, size);
+        self.subscribers.notify(ChangeEvent::Written(ptr));
+    }
+
+}
+
+impl File {
+
+    /// The callbacks run when chains are allocated, deleted or written to.
+    pub fn hooks(&mut self) -> &mut Hooks {
+        &mut self.hooks
+    }
+
+}
+
+#[test]
+fn hooks() {
+    use std::sync::{Arc, Mutex};
+    use crate::Config;
+
+    let mut file = File::open("hooks.verter", Config::default()).unwrap();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let alloc_events = events.clone();
+    file.hooks().on_alloc(move |ptr| alloc_events.lock().unwrap().push(format!("alloc {}", ptr)));
+    let delete_events = events.clone();
+    file.hooks().on_delete(move |ptr| delete_events.lock().unwrap().push(format!("delete {}", ptr)));
+    let write_events = events.clone();
+    file.hooks().on_write(move |ptr, size| write_events.lock().unwrap().push(format!("write {} {}", ptr, size)));
+
+    let a = file.alloc().unwrap();
+    file.write(a, &[0xAB; 300]).unwrap();
+    let b = file.alloc_with(b"frame").unwrap();
+    file.delete_many(&[a, b]).unwrap();
+    assert!(file.write(a, b"deleted").is_err());
+    assert_eq!(*events.lock().unwrap(), vec![
+        format!("alloc {}", a),
+        format!("write {} 300", a),
+        format!("alloc {}", b),
+        format!("write {} 5", b),
+        format!("delete {}", a),
+        format!("delete {}", b)
+    ]);
+
+    file.hooks().clear();
+    let c = file.alloc().unwrap();
+    file.delete(c).unwrap();
+    assert_eq!(events.lock().unwrap().len(), 6);
+
+    std::fs::remove_file("hooks.verter").unwrap();
+}
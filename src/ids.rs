@@ -0,0 +1,104 @@
+use crate::{decode_u64s, encode_u64s, Error, File};
+
+/// The number of durable ID counters every file has. See `File::next_id`.
+pub const ID_COUNTERS: usize = 16;
+
+impl File {
+
+    /// Take the next ID from one of the file's durable counters, for giving objects IDs that are never handed out twice, even across restarts.
+    /// Each of the `ID_COUNTERS` counters counts up from 1 on its own, so 0 can stand for no ID.
+    /// Take IDs with `Transaction::next_id` instead to have the counter turned back along with the writes if the transaction is rolled back.
+    /// Fails with `Error::UnsupportedVersion` for files created before format version 4, whose header has no room for the counters,
+    /// until `File::canonicalize` rewrites them in the current format.
+    /// Panics if `counter_slot` isn't below `ID_COUNTERS`.
+    pub fn next_id(&mut self, counter_slot: usize) -> Result<u64, Error> {
+        assert!(counter_slot < ID_COUNTERS, "counter slot must be below ID_COUNTERS");
+        self.check_for_external_changes()?;
+        let mut counters = self.read_id_counters()?;
+        counters[counter_slot] += 1;
+        self.write_id_counters(&counters)?;
+        Ok(counters[counter_slot])
+    }
+
+    /// The chain holding the ID counters, allocating it if no ID was taken yet
+    pub(crate) fn id_counter_chain(&mut self) -> Result<u64, Error> {
+        if self.format_version < 4 {
+            return Err(Error::UnsupportedVersion);
+        }
+        let mut chain = self.read_u64(self.id_counters_ptr())?;
+        if chain == 0 {
+            chain = self.alloc()?;
+            self.write_u64(self.id_counters_ptr(), chain)?;
+        }
+        Ok(chain)
+    }
+
+    /// The chain holding the ID counters, or 0 if there is none
+    pub(crate) fn id_counter_chain_if_any(&mut self) -> Result<u64, Error> {
+        if self.format_version < 4 {
+            return Ok(0);
+        }
+        self.read_u64(self.id_counters_ptr())
+    }
+
+    /// The last ID taken from every counter, or 0 for counters no ID was taken from
+    pub(crate) fn read_id_counters(&mut self) -> Result<[u64; ID_COUNTERS], Error> {
+        let mut counters = [0; ID_COUNTERS];
+        let chain = self.id_counter_chain_if_any()?;
+        if chain != 0 {
+            for (counter, stored) in counters.iter_mut().zip(decode_u64s(&self.read_chain(chain)?)?) {
+                *counter = stored;
+            }
+        }
+        Ok(counters)
+    }
+
+    pub(crate) fn write_id_counters(&mut self, counters: &[u64; ID_COUNTERS]) -> Result<(), Error> {
+        let chain = self.id_counter_chain()?;
+        self.write_chain(chain, &encode_u64s(counters))
+    }
+
+}
+
+#[test]
+fn next_id() {
+    use crate::Config;
+
+    let mut file = File::open("next_id.verter", Config::default()).unwrap();
+    assert_eq!(file.next_id(0).unwrap(), 1);
+    assert_eq!(file.next_id(0).unwrap(), 2);
+    assert_eq!(file.next_id(3).unwrap(), 1);
+    drop(file);
+
+    // The counters survive reopening the file, and rolled back transactions give their IDs back
+    let mut file = File::open("next_id.verter", Config::default()).unwrap();
+    let layer = file.alloc().unwrap();
+    let mut transaction = file.transaction().unwrap();
+    assert_eq!(transaction.next_id(0).unwrap(), 3);
+    transaction.write(layer, &3u64.to_le_bytes()).unwrap();
+    transaction.rollback().unwrap();
+    let mut transaction = file.transaction().unwrap();
+    assert_eq!(transaction.next_id(0).unwrap(), 3);
+    assert_eq!(transaction.next_id(0).unwrap(), 4);
+    transaction.commit().unwrap();
+    assert_eq!(file.next_id(0).unwrap(), 5);
+    assert_eq!(file.next_id(3).unwrap(), 2);
+
+    // Compacting the file keeps the counters
+    file.canonicalize(|_| Vec::new()).unwrap();
+    assert_eq!(file.next_id(0).unwrap(), 6);
+    file.validate().unwrap();
+
+    // Older files have no room for the counters until they are rewritten
+    let fixture = std::fs::read(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/v3.verter")).unwrap();
+    let mut old = File::open_backend(Box::new(std::io::Cursor::new(fixture)), Config::default()).unwrap();
+    match old.next_id(0) {
+        Err(Error::UnsupportedVersion) => {},
+        Ok(_) | Err(_) => panic!("should error with unsupported version")
+    }
+    old.canonicalize(|data| data.get(..8).map(|ptr| vec![u64::from_le_bytes(ptr.try_into().unwrap())]).unwrap_or_default()).unwrap();
+    assert_eq!(old.next_id(0).unwrap(), 1);
+    old.validate().unwrap();
+
+    std::fs::remove_file("next_id.verter").unwrap();
+}
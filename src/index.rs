@@ -0,0 +1,178 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{Error, File, PageHeader};
+
+/// What the index knows about a chain
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndexedChain {
+    /// The number of pages in the chain
+    pub pages: u64,
+    /// The number of bytes of data in the chain
+    pub len: u64
+}
+
+/// A map of every chain in the file, kept in memory and built a few pages at a time by `File::index_step`,
+/// so that opening a large file stays fast while tools still end up with metadata about all of it.
+#[derive(Clone, Debug, Default)]
+pub struct ChainIndex {
+    /// The value of the change counter when the index was started, to notice when the file changed under it
+    change_counter: u64,
+    /// Where the scan continues from, or `None` if it has not started yet
+    next_page: Option<u64>,
+    /// The header of every allocated page scanned so far
+    headers: BTreeMap<u64, PageHeader>,
+    free_pages: u64,
+    scanned: u64,
+    total: u64,
+    /// Every chain in the file, keyed by the pointer to its first page, filled in once the scan is complete
+    chains: Option<BTreeMap<u64, IndexedChain>>
+}
+
+impl ChainIndex {
+
+    /// Whether every page in the file has been scanned.
+    pub fn is_complete(&self) -> bool {
+        self.chains.is_some()
+    }
+
+    /// The number of pages scanned so far and the total number of pages in the file.
+    pub fn progress(&self) -> (u64, u64) {
+        (self.scanned, self.total)
+    }
+
+    /// Every chain in the file, or `None` if the scan is not complete yet.
+    /// The chains verter uses for its own tables are left out.
+    pub fn chains(&self) -> Option<&BTreeMap<u64, IndexedChain>> {
+        self.chains.as_ref()
+    }
+
+    /// What the index knows about a chain, once the scan is complete.
+    pub fn chain(&self, ptr: u64) -> Option<IndexedChain> {
+        self.chains.as_ref()?.get(&ptr).copied()
+    }
+
+    /// The number of free pages found so far.
+    pub fn free_pages(&self) -> u64 {
+        self.free_pages
+    }
+
+    /// The total number of bytes of data in every chain except verter's own, or `None` if the scan is not complete yet.
+    pub fn data_bytes(&self) -> Option<u64> {
+        Some(self.chains.as_ref()?.values().map(|chain| chain.len).sum())
+    }
+
+}
+
+impl File {
+
+    /// The index of the file's chains, as far as it has been built. See `File::index_step`.
+    /// Any change to the file discards the index, which then starts over.
+    pub fn index(&mut self) -> Result<&ChainIndex, Error> {
+        self.check_for_external_changes()?;
+        if self.index.change_counter != self.change_counter {
+            self.index = ChainIndex { change_counter: self.change_counter, ..ChainIndex::default() };
+        }
+        Ok(&self.index)
+    }
+
+    /// Scan up to `max_pages` more pages into the index, returning whether it is complete.
+    /// Call it whenever the application is idle, or let `background::Maintenance` do it, until the index is complete.
+    pub fn index_step(&mut self, max_pages: u64) -> Result<bool, Error> {
+        self.index()?;
+        if self.index.is_complete() {
+            return Ok(true);
+        }
+
+        let quarantined = self.quarantined_pages()?;
+        let file_size = self.file_size()?;
+        let page_size = self.total_page_size();
+        self.index.total = (file_size - self.header_size()) / page_size;
+        let mut ptr = self.index.next_page.unwrap_or(self.header_size());
+        let mut scanned = 0;
+        while scanned < max_pages && ptr + page_size <= file_size {
+            if !quarantined.contains(&ptr) {
+                match self.read_page_header(ptr)? {
+                    PageHeader::DeletedPage(_) => self.index.free_pages += 1,
+                    header => {
+                        self.index.headers.insert(ptr, header);
+                    }
+                }
+            }
+            self.index.scanned += 1;
+            scanned += 1;
+            ptr += page_size;
+        }
+        self.index.next_page = Some(ptr);
+
+        if ptr + page_size > file_size {
+            let mut chains = self.link_indexed_chains()?;
+            for chain in self.internal_chains()? {
+                chains.remove(&chain);
+            }
+            self.index.headers.clear();
+            self.index.chains = Some(chains);
+        }
+        Ok(self.index.is_complete())
+    }
+
+    /// Follow the scanned page headers to find every chain
+    fn link_indexed_chains(&self) -> Result<BTreeMap<u64, IndexedChain>, Error> {
+        let headers = &self.index.headers;
+        let continuations = headers.values().filter_map(|header| match header {
+            PageHeader::NextPage(next) => Some(*next),
+            _ => None
+        }).collect::<HashSet<_>>();
+
+        let mut chains = BTreeMap::new();
+        for head in headers.keys().filter(|ptr| !continuations.contains(ptr)) {
+            let mut ptr = *head;
+            let mut pages = 1;
+            let final_size = loop {
+                match headers.get(&ptr) {
+                    Some(PageHeader::NextPage(next)) if pages < headers.len() as u64 => {
+                        ptr = *next;
+                        pages += 1;
+                    },
+                    Some(PageHeader::FinalPage(size)) => break *size,
+                    _ => return Err(Error::CorruptedFile)
+                }
+            };
+            let len = ((pages - 1) * self.config.page_size as u64 + final_size).checked_sub(self.meta_size()).ok_or(Error::CorruptedFile)?;
+            chains.insert(*head, IndexedChain { pages, len });
+        }
+        Ok(chains)
+    }
+
+}
+
+#[test]
+fn index() {
+    use crate::Config;
+
+    let mut file = File::open("index.verter", Config::default()).unwrap();
+    let small = file.alloc_with(b"frame").unwrap();
+    let large = file.alloc_with(&[0xAB; 3000]).unwrap();
+    let deleted = file.alloc_with(b"deleted").unwrap();
+    file.delete(deleted).unwrap();
+
+    assert!(!file.index().unwrap().is_complete());
+    assert!(!file.index_step(1).unwrap());
+    assert_eq!(file.index().unwrap().progress().0, 1);
+    while !file.index_step(1).unwrap() {}
+
+    let index = file.index().unwrap();
+    assert_eq!(index.progress().0, index.progress().1);
+    assert_eq!(index.chain(small), Some(IndexedChain { pages: 1, len: 5 }));
+    assert_eq!(index.chain(large).unwrap().len, 3000);
+    assert_eq!(index.chain(deleted), None);
+    assert!(index.free_pages() >= 1);
+    assert_eq!(index.data_bytes(), Some(3005));
+
+    // Changing the file starts the index over
+    file.write(small, b"changed").unwrap();
+    assert!(!file.index().unwrap().is_complete());
+    assert!(file.index_step(u64::MAX).unwrap());
+    assert_eq!(file.index().unwrap().chain(small).unwrap().len, 7);
+
+    std::fs::remove_file("index.verter").unwrap();
+}
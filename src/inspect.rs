@@ -0,0 +1,377 @@
+//! Tools for looking inside verter files, such as comparing what two versions of a file contain.
+
+use std::collections::{BTreeMap, HashSet};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::objects::{ObjId, ObjectStore};
+use crate::dedup::content_hash;
+use crate::{Error, File, PageHeader, BYTES_IN_U64};
+
+/// Identifies a chain in a way that can be matched up between two files
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChainKey {
+    /// The file's root chain
+    Root,
+    /// The root chain of the partition with the given name
+    PartitionRoot(String),
+    /// Any other chain, identified by its pointer
+    Chain(u64)
+}
+
+/// What changed between two versions of a collection of chains or objects
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diff<K> {
+    /// Keys only present in the second version
+    pub added: Vec<K>,
+    /// Keys only present in the first version
+    pub removed: Vec<K>,
+    /// Keys present in both versions whose data differs
+    pub changed: Vec<K>
+}
+
+impl<K> Diff<K> {
+
+    /// Whether both versions hold the same data.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+}
+
+impl<K> Default for Diff<K> {
+
+    fn default() -> Self {
+        Self {
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new()
+        }
+    }
+
+}
+
+/// The length and content hash of a chain, as recorded in a `Manifest`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// The number of bytes of data in the chain
+    pub len: u64,
+    /// The 64-bit FNV-1a hash of the chain's data.
+    /// Catches accidental damage, but is not cryptographic, so it doesn't protect against deliberate tampering.
+    pub hash: u64
+}
+
+/// A listing of every chain holding user data in a file, along with its length and content hash.
+/// Created with `File::manifest`, and checked against a file with `File::verify_manifest`,
+/// such as after a project file was copied between machines.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// The entry of every chain, keyed like in `diff`
+    pub entries: BTreeMap<ChainKey, ManifestEntry>
+}
+
+impl Manifest {
+
+    const ROOT: u8 = 0;
+    const PARTITION_ROOT: u8 = 1;
+    const CHAIN: u8 = 2;
+
+    /// Encode the manifest as bytes, for storing or sending it alongside the file.
+    /// Every entry is a tag byte, followed by the partition name's length and bytes for partition roots or the pointer for other chains,
+    /// followed by the length and the hash. All numbers are little-endian u64s.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (key, entry) in &self.entries {
+            match key {
+                ChainKey::Root => bytes.push(Self::ROOT),
+                ChainKey::PartitionRoot(name) => {
+                    bytes.push(Self::PARTITION_ROOT);
+                    bytes.extend_from_slice(&(name.len() as u64).to_le_bytes());
+                    bytes.extend_from_slice(name.as_bytes());
+                },
+                ChainKey::Chain(ptr) => {
+                    bytes.push(Self::CHAIN);
+                    bytes.extend_from_slice(&ptr.to_le_bytes());
+                }
+            }
+            bytes.extend_from_slice(&entry.len.to_le_bytes());
+            bytes.extend_from_slice(&entry.hash.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decode a manifest encoded with `Manifest::to_bytes`, failing with `Error::CorruptedFile` if the bytes are malformed.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, Error> {
+        fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], Error> {
+            let (taken, rest) = bytes.split_at_checked(n).ok_or(Error::CorruptedFile)?;
+            *bytes = rest;
+            Ok(taken)
+        }
+        fn take_u64(bytes: &mut &[u8]) -> Result<u64, Error> {
+            Ok(u64::from_le_bytes(take(bytes, BYTES_IN_U64 as usize)?.try_into().unwrap()))
+        }
+
+        let mut manifest = Self::default();
+        while !bytes.is_empty() {
+            let key = match take(&mut bytes, 1)?[0] {
+                Self::ROOT => ChainKey::Root,
+                Self::PARTITION_ROOT => {
+                    let len = take_u64(&mut bytes)?;
+                    let name = take(&mut bytes, usize::try_from(len).map_err(|_| Error::CorruptedFile)?)?;
+                    ChainKey::PartitionRoot(String::from_utf8(name.to_vec()).map_err(|_| Error::CorruptedFile)?)
+                },
+                Self::CHAIN => ChainKey::Chain(take_u64(&mut bytes)?),
+                _ => return Err(Error::CorruptedFile)
+            };
+            let len = take_u64(&mut bytes)?;
+            let hash = take_u64(&mut bytes)?;
+            manifest.entries.insert(key, ManifestEntry { len, hash });
+        }
+        Ok(manifest)
+    }
+
+}
+
+/// Compare the chains of two files, matching up root chains by partition name and all other chains by pointer.
+/// The chains verter uses internally are not compared.
+pub fn diff(a: &mut File, b: &mut File) -> Result<Diff<ChainKey>, Error> {
+    let a_chains = user_chains(a)?;
+    let b_chains = user_chains(b)?;
+    compare(&a_chains, &b_chains, |_, ptr| Ok(Some(a.read(ptr)?)), |_, ptr| Ok(Some(b.read(ptr)?)))
+}
+
+/// Compare two versions of an object store, matching up objects by their ID.
+/// Each store is given by the pointer to its table chain in its file.
+pub fn diff_objects(a: &mut File, a_store: u64, b: &mut File, b_store: u64) -> Result<Diff<ObjId>, Error> {
+    let a_store = ObjectStore::open(a, a_store)?;
+    let b_store = ObjectStore::open(b, b_store)?;
+    let a_ids = a_store.iter().map(|id| (id, ())).collect();
+    let b_ids = b_store.iter().map(|id| (id, ())).collect();
+    compare(&a_ids, &b_ids, |id, ()| a_store.get(a, id), |id, ()| b_store.get(b, id))
+}
+
+impl File {
+
+    /// List every chain holding user data along with its length and content hash.
+    /// Chains are keyed like in `diff`, and the chains verter uses internally are left out.
+    pub fn manifest(&mut self) -> Result<Manifest, Error> {
+        let mut manifest = Manifest::default();
+        for (key, ptr) in user_chains(self)? {
+            let data = self.read(ptr)?;
+            manifest.entries.insert(key, ManifestEntry { len: data.len() as u64, hash: content_hash(&data) });
+        }
+        Ok(manifest)
+    }
+
+    /// Check the file against a manifest, returning the chains the file has that the manifest doesn't as `added`,
+    /// the chains the manifest lists that the file lacks as `removed`, and the chains whose length or hash differ as `changed`.
+    /// The file matches the manifest if the result is empty.
+    pub fn verify_manifest(&mut self, manifest: &Manifest) -> Result<Diff<ChainKey>, Error> {
+        let actual = self.manifest()?;
+        let mut diff = Diff::default();
+        for (key, expected) in &manifest.entries {
+            match actual.entries.get(key) {
+                Some(entry) if entry == expected => {},
+                Some(_) => diff.changed.push(key.clone()),
+                None => diff.removed.push(key.clone())
+            }
+        }
+        diff.added = actual.entries.keys().filter(|key| !manifest.entries.contains_key(key)).cloned().collect();
+        Ok(diff)
+    }
+
+    /// Write the pages of the chain starting at `ptr` to `out`, with each page header decoded and its payload in xxd's format.
+    /// Meant for debugging damaged chains, so `ptr` may point anywhere in a chain, and the dump stops with a note
+    /// at the first page that can't continue the chain instead of failing.
+    pub fn hexdump<W: Write>(&mut self, ptr: u64, out: &mut W) -> Result<(), Error> {
+        if !self.is_page_ptr(ptr) {
+            return Err(Error::InvalidPointer);
+        }
+        let mut visited = HashSet::new();
+        let mut page = ptr;
+        loop {
+            if !visited.insert(page) {
+                writeln!(out, "page {:#010x}: loops back into the chain", page).map_err(Error::IO)?;
+                return Ok(());
+            }
+            let raw_header = self.read_u64(page)?;
+            let head = if raw_header & PageHeader::HEAD_FLAG != 0 { " (head)" } else { "" };
+            let header = match PageHeader::from_u64(raw_header) {
+                Ok(header) => header,
+                Err(_) => {
+                    writeln!(out, "page {:#010x}{}: unknown header {:#018x}", page, head, raw_header).map_err(Error::IO)?;
+                    return Ok(());
+                }
+            };
+            let (description, len) = match header {
+                PageHeader::NextPage(next) => (format!("NextPage({:#010x})", next), self.config.page_size as u64),
+                PageHeader::FinalPage(size) => (format!("FinalPage({})", size), size.min(self.config.page_size as u64)),
+                PageHeader::DeletedPage(next) => (format!("DeletedPage({:#010x})", next), 0)
+            };
+            writeln!(out, "page {:#010x}{}: {}", page, head, description).map_err(Error::IO)?;
+
+            let mut payload = vec![0; len as usize];
+            self.file.seek(SeekFrom::Start(page + BYTES_IN_U64)).map_err(Error::IO)?;
+            self.file.read_exact(&mut payload).map_err(Error::IO)?;
+            for (i, line) in payload.chunks(16).enumerate() {
+                let mut hex = String::new();
+                for (j, byte) in line.iter().enumerate() {
+                    if j % 2 == 0 && j != 0 {
+                        hex.push(' ');
+                    }
+                    hex.push_str(&format!("{:02x}", byte));
+                }
+                let ascii = line.iter().map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' }).collect::<String>();
+                writeln!(out, "{:08x}: {:<39}  {}", page + BYTES_IN_U64 + i as u64 * 16, hex, ascii).map_err(Error::IO)?;
+            }
+
+            match header {
+                PageHeader::NextPage(next) if self.is_page_ptr(next) => page = next,
+                PageHeader::NextPage(next) => {
+                    writeln!(out, "page {:#010x}: not a page", next).map_err(Error::IO)?;
+                    return Ok(());
+                },
+                PageHeader::FinalPage(_) | PageHeader::DeletedPage(_) => return Ok(())
+            }
+        }
+    }
+
+}
+
+/// The chains of a file that hold user data, keyed by how they are matched up between files
+pub(crate) fn user_chains(file: &mut File) -> Result<BTreeMap<ChainKey, u64>, Error> {
+    file.check_for_external_changes()?;
+    let internal_chains = file.internal_chains()?.into_iter().collect::<HashSet<_>>();
+    let mut chains = file.chain_heads()?.into_iter()
+        .filter(|ptr| !internal_chains.contains(ptr))
+        .map(|ptr| (ChainKey::Chain(ptr), ptr))
+        .collect::<BTreeMap<_, _>>();
+
+    let root = file.root_page()?;
+    if root != 0 {
+        chains.remove(&ChainKey::Chain(root));
+        chains.insert(ChainKey::Root, root);
+    }
+    for entry in file.read_partition_table()? {
+        chains.remove(&ChainKey::Chain(entry.root));
+        chains.insert(ChainKey::PartitionRoot(entry.name), entry.root);
+    }
+    Ok(chains)
+}
+
+fn compare<K: Clone + Ord, V: Copy, A, B>(a_keys: &BTreeMap<K, V>, b_keys: &BTreeMap<K, V>, mut read_a: A, mut read_b: B) -> Result<Diff<K>, Error>
+where
+    A: FnMut(K, V) -> Result<Option<Vec<u8>>, Error>,
+    B: FnMut(K, V) -> Result<Option<Vec<u8>>, Error>
+{
+    let mut diff = Diff::default();
+    for (key, a_val) in a_keys {
+        match b_keys.get(key) {
+            Some(b_val) => {
+                if read_a(key.clone(), *a_val)? != read_b(key.clone(), *b_val)? {
+                    diff.changed.push(key.clone());
+                }
+            },
+            None => diff.removed.push(key.clone())
+        }
+    }
+    diff.added = b_keys.keys().filter(|key| !a_keys.contains_key(key)).cloned().collect();
+    Ok(diff)
+}
+
+#[test]
+fn inspect_diff() {
+    use crate::Config;
+
+    let mut a = File::open("inspect_diff_a.verter", Config::default()).unwrap();
+    a.write_root(b"project").unwrap();
+    let mut objects = ObjectStore::create(&mut a).unwrap();
+    let kept = objects.insert(&mut a, b"kept layer").unwrap();
+    let edited = objects.insert(&mut a, b"edited layer").unwrap();
+    let removed = objects.insert(&mut a, b"removed layer").unwrap();
+    a.partition("cache").unwrap().write_root(b"cache").unwrap();
+    drop(a);
+    std::fs::copy("inspect_diff_a.verter", "inspect_diff_b.verter").unwrap();
+
+    let mut a = File::open("inspect_diff_a.verter", Config::default()).unwrap();
+    let mut b = File::open("inspect_diff_b.verter", Config::default()).unwrap();
+    assert!(diff(&mut a, &mut b).unwrap().is_empty());
+
+    b.write_root(b"project v2").unwrap();
+    let mut objects = ObjectStore::open(&mut b, objects.ptr()).unwrap();
+    objects.update(&mut b, edited, b"edited layer v2").unwrap();
+    objects.remove(&mut b, removed).unwrap();
+    let added = objects.insert(&mut b, b"added layer").unwrap();
+
+    let chains = diff(&mut a, &mut b).unwrap();
+    assert!(chains.changed.contains(&ChainKey::Root));
+    assert!(!chains.changed.contains(&ChainKey::PartitionRoot("cache".to_owned())));
+
+    let object_diff = diff_objects(&mut a, objects.ptr(), &mut b, objects.ptr()).unwrap();
+    assert_eq!(object_diff.added, vec![added]);
+    assert_eq!(object_diff.removed, vec![removed]);
+    assert_eq!(object_diff.changed, vec![edited]);
+    assert!(!object_diff.changed.contains(&kept));
+
+    std::fs::remove_file("inspect_diff_a.verter").unwrap();
+    std::fs::remove_file("inspect_diff_b.verter").unwrap();
+}
+
+#[test]
+fn hexdump() {
+    use crate::Config;
+
+    let mut file = File::open("hexdump.verter", Config::default()).unwrap();
+    let mut data = b"Hello, hexdump!\n".to_vec();
+    data.resize(130, 0xAB);
+    let ptr = file.alloc_with(&data).unwrap();
+    let pages = file.chain_pages(ptr).unwrap();
+
+    let mut out = Vec::new();
+    file.hexdump(ptr, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines = out.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], format!("page {:#010x} (head): NextPage({:#010x})", ptr, pages[1]));
+    assert_eq!(lines[1], format!("{:08x}: 4865 6c6c 6f2c 2068 6578 6475 6d70 210a  Hello, hexdump!.", ptr + 8));
+    assert_eq!(lines[9], format!("page {:#010x}: FinalPage(10)", pages[1]));
+    assert_eq!(lines.len(), 11);
+
+    // Dumping from the middle of a damaged chain
+    file.write_u64(pages[1], 0x55 << PageHeader::TYPE_SHIFT).unwrap();
+    let mut out = Vec::new();
+    file.hexdump(pages[1], &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), format!("page {:#010x}: unknown header 0x5500000000000000\n", pages[1]));
+
+    std::fs::remove_file("hexdump.verter").unwrap();
+}
+
+#[test]
+fn manifest() {
+    use crate::Config;
+
+    let mut file = File::open("manifest.verter", Config::default()).unwrap();
+    file.write_root(b"project").unwrap();
+    let frame = file.alloc_with(&[0xAB; 500]).unwrap();
+    let removed = file.alloc_with(b"removed").unwrap();
+    file.partition("cache").unwrap().write_root(b"cache").unwrap();
+
+    let manifest = Manifest::from_bytes(&file.manifest().unwrap().to_bytes()).unwrap();
+    assert_eq!(manifest, file.manifest().unwrap());
+    assert_eq!(manifest.entries[&ChainKey::Chain(frame)], ManifestEntry { len: 500, hash: content_hash(&[0xAB; 500]) });
+    assert!(manifest.entries.contains_key(&ChainKey::PartitionRoot("cache".to_owned())));
+    assert!(file.verify_manifest(&manifest).unwrap().is_empty());
+
+    file.write(frame, &[0xCD; 500]).unwrap();
+    let added = file.alloc_with(b"added").unwrap();
+    file.delete(removed).unwrap();
+    let diff = file.verify_manifest(&manifest).unwrap();
+    assert_eq!(diff.changed, vec![ChainKey::Chain(frame)]);
+    assert_eq!(diff.removed, vec![ChainKey::Chain(removed)]);
+    assert_eq!(diff.added, vec![ChainKey::Chain(added)]);
+
+    match Manifest::from_bytes(&[Manifest::CHAIN, 1, 2]) {
+        Err(Error::CorruptedFile) => {},
+        Ok(_) | Err(_) => panic!("should error with corrupted file")
+    }
+
+    std::fs::remove_file("manifest.verter").unwrap();
+}
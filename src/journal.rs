@@ -0,0 +1,117 @@
+use crate::{Error, File, BYTES_IN_U64};
+
+/// The next sequence number, along with every retained op and its sequence number
+type Journal = (u64, Vec<(u64, Vec<u8>)>);
+
+/// The journal chain holds the next sequence number, followed by every retained op encoded as (sequence number, length, bytes)
+fn decode_journal(data: &[u8]) -> Result<Journal, Error> {
+    if data.is_empty() {
+        return Ok((1, Vec::new()));
+    }
+    let (next_seq, mut data) = data.split_at_checked(BYTES_IN_U64 as usize).ok_or(Error::CorruptedFile)?;
+    let next_seq = u64::from_le_bytes(next_seq.try_into().unwrap());
+    let mut ops = Vec::new();
+    while !data.is_empty() {
+        let (prefix, rest) = data.split_at_checked(2 * BYTES_IN_U64 as usize).ok_or(Error::CorruptedFile)?;
+        let seq = u64::from_le_bytes(prefix[..8].try_into().unwrap());
+        let len = u64::from_le_bytes(prefix[8..].try_into().unwrap()) as usize;
+        let (op, rest) = rest.split_at_checked(len).ok_or(Error::CorruptedFile)?;
+        ops.push((seq, op.to_vec()));
+        data = rest;
+    }
+    Ok((next_seq, ops))
+}
+
+fn encode_journal(next_seq: u64, ops: &[(u64, Vec<u8>)]) -> Vec<u8> {
+    let mut data = next_seq.to_le_bytes().to_vec();
+    for (seq, op) in ops {
+        data.extend_from_slice(&seq.to_le_bytes());
+        data.extend_from_slice(&(op.len() as u64).to_le_bytes());
+        data.extend_from_slice(op);
+    }
+    data
+}
+
+impl File {
+
+    /// Append an operation to the file's journal, returning its sequence number.
+    /// The operation is an arbitrary blob describing a change made by the application, for replaying the change elsewhere.
+    /// Sequence numbers start at 1 and keep increasing, even after old operations are dropped with `File::truncate_ops`.
+    pub fn append_op(&mut self, op: &[u8]) -> Result<u64, Error> {
+        self.check_for_external_changes()?;
+        let (next_seq, mut ops) = self.read_journal()?;
+        ops.push((next_seq, op.to_vec()));
+        self.write_journal(next_seq + 1, &ops)?;
+        Ok(next_seq)
+    }
+
+    /// Every journaled operation with a sequence number greater than `seq`, in order.
+    /// Pass 0 to get the whole journal.
+    pub fn ops_since(&mut self, seq: u64) -> Result<Vec<(u64, Vec<u8>)>, Error> {
+        self.check_for_external_changes()?;
+        let (_, mut ops) = self.read_journal()?;
+        ops.retain(|(op_seq, _)| *op_seq > seq);
+        Ok(ops)
+    }
+
+    /// The sequence number of the most recently journaled operation, or 0 if nothing was ever journaled.
+    pub fn journal_seq(&mut self) -> Result<u64, Error> {
+        self.check_for_external_changes()?;
+        Ok(self.read_journal()?.0 - 1)
+    }
+
+    /// Drop every journaled operation with a sequence number up to and including `seq`,
+    /// such as once every collaborator has received them.
+    pub fn truncate_ops(&mut self, seq: u64) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        let (next_seq, mut ops) = self.read_journal()?;
+        ops.retain(|(op_seq, _)| *op_seq > seq);
+        self.write_journal(next_seq, &ops)
+    }
+
+    pub(crate) fn read_journal(&mut self) -> Result<Journal, Error> {
+        let chain = self.read_u64(self.journal_ptr())?;
+        if chain == 0 {
+            return Ok((1, Vec::new()));
+        }
+        decode_journal(&self.read_chain(chain)?)
+    }
+
+    pub(crate) fn write_journal(&mut self, next_seq: u64, ops: &[(u64, Vec<u8>)]) -> Result<(), Error> {
+        let mut chain = self.read_u64(self.journal_ptr())?;
+        if chain == 0 {
+            chain = self.alloc()?;
+            self.write_u64(self.journal_ptr(), chain)?;
+        }
+        self.write_chain(chain, &encode_journal(next_seq, ops))
+    }
+
+}
+
+#[test]
+fn journal() {
+    use crate::Config;
+
+    let mut file = File::open("journal.verter", Config::default()).unwrap();
+    assert_eq!(file.journal_seq().unwrap(), 0);
+    assert_eq!(file.append_op(b"add stroke").unwrap(), 1);
+    assert_eq!(file.append_op(b"move layer").unwrap(), 2);
+    assert_eq!(file.append_op(&[0xAB; 300]).unwrap(), 3);
+    drop(file);
+
+    let mut file = File::open("journal.verter", Config::default()).unwrap();
+    assert_eq!(file.journal_seq().unwrap(), 3);
+    assert_eq!(file.ops_since(1).unwrap(), vec![(2, b"move layer".to_vec()), (3, vec![0xAB; 300])]);
+
+    // Sequence numbers keep increasing after the journal is truncated
+    file.truncate_ops(3).unwrap();
+    assert_eq!(file.ops_since(0).unwrap(), Vec::new());
+    assert_eq!(file.append_op(b"delete frame").unwrap(), 4);
+    assert_eq!(file.ops_since(0).unwrap(), vec![(4, b"delete frame".to_vec())]);
+
+    file.gc(&[], |_| Vec::new()).unwrap();
+    assert_eq!(file.ops_since(3).unwrap(), vec![(4, b"delete frame".to_vec())]);
+    file.validate().unwrap();
+
+    std::fs::remove_file("journal.verter").unwrap();
+}
@@ -0,0 +1,79 @@
+//! Checks that chains and files crossing 4 GiB work, for applications storing video-scale data.
+//! These tests write several gigabytes to disk, so they only run with the `expensive-tests` feature.
+
+use crate::{decode_u64s, encode_u64s, Config, File};
+
+const FOUR_GIB: u64 = 4 << 30;
+/// The data is written a chunk at a time, and every chunk is filled with its index,
+/// so data read from the wrong side of a 4 GiB boundary has the wrong value
+const CHUNK_SIZE: usize = 64 << 20;
+const CHUNKS_BELOW_FOUR_GIB: u8 = (FOUR_GIB / CHUNK_SIZE as u64) as u8;
+
+#[test]
+fn chain_past_4_gib() {
+    // Large pages keep the number of pages walked on every append small
+    let config = Config { page_size: 1 << 20, ..Config::default() };
+    let mut file = File::open("chain_past_4_gib.verter", config).unwrap();
+    let video = file.alloc().unwrap();
+    for i in 0..=CHUNKS_BELOW_FOUR_GIB {
+        file.append(video, &vec![i; CHUNK_SIZE]).unwrap();
+    }
+    file.append(video, &[0xEE; 1000]).unwrap();
+    let len = (CHUNKS_BELOW_FOUR_GIB as u64 + 1) * CHUNK_SIZE as u64 + 1000;
+    assert!(len > FOUR_GIB);
+
+    let chunks = file.read_chunked(video, 16).unwrap();
+    assert_eq!(chunks.len(), len);
+    let (offset, straddling, _) = chunks.resume_at(FOUR_GIB - 8).next().unwrap().unwrap();
+    assert_eq!(offset, FOUR_GIB - 8);
+    assert_eq!(straddling[..8], [CHUNKS_BELOW_FOUR_GIB - 1; 8]);
+    assert_eq!(straddling[8..], [CHUNKS_BELOW_FOUR_GIB; 8]);
+    let (_, tail, _) = file.read_chunked(video, 1000).unwrap().resume_at(len - 1000).next().unwrap().unwrap();
+    assert_eq!(tail, [0xEE; 1000]);
+
+    // Edits across the boundary only touch the pages they cover
+    file.patch(video, &[(FOUR_GIB - 4, b"boundary")]).unwrap();
+    let (_, straddling, _) = file.read_chunked(video, 16).unwrap().resume_at(FOUR_GIB - 8).next().unwrap().unwrap();
+    assert_eq!(straddling[4..12], *b"boundary");
+
+    // Chains past the end of the first 4 GiB of the file survive reopening it
+    let thumbnail = file.alloc_with(b"thumbnail").unwrap();
+    assert!(thumbnail > FOUR_GIB);
+    file.write_root(&encode_u64s(&[video, thumbnail])).unwrap();
+    drop(file);
+    let mut file = File::open("chain_past_4_gib.verter", config).unwrap();
+    assert_eq!(decode_u64s(&file.read_root().unwrap()).unwrap(), [video, thumbnail]);
+    assert_eq!(file.read(thumbnail).unwrap(), b"thumbnail");
+    let mut reader = crate::parse::Reader::with_page_size(std::fs::File::open("chain_past_4_gib.verter").unwrap(), config.page_size).unwrap();
+    assert_eq!(reader.read(thumbnail).unwrap(), b"thumbnail");
+
+    // Pages freed past 4 GiB are reused
+    file.delete(video).unwrap();
+    let size = std::fs::metadata("chain_past_4_gib.verter").unwrap().len();
+    let reused = file.alloc_with(&vec![0xAB; 2 * CHUNK_SIZE]).unwrap();
+    assert_eq!(std::fs::metadata("chain_past_4_gib.verter").unwrap().len(), size);
+    assert_eq!(file.read(reused).unwrap(), vec![0xAB; 2 * CHUNK_SIZE]);
+    file.validate().unwrap();
+
+    std::fs::remove_file("chain_past_4_gib.verter").unwrap();
+}
+
+#[test]
+fn file_past_4_gib() {
+    let mut file = File::open("file_past_4_gib.verter", Config::default()).unwrap();
+    let frames = (0..=CHUNKS_BELOW_FOUR_GIB).map(|i| file.alloc_with(&vec![i; CHUNK_SIZE]).unwrap()).collect::<Vec<_>>();
+    file.write_root(&encode_u64s(&frames)).unwrap();
+    assert!(std::fs::metadata("file_past_4_gib.verter").unwrap().len() > FOUR_GIB);
+    let last = *frames.last().unwrap();
+    assert!(last + CHUNK_SIZE as u64 > FOUR_GIB);
+    assert_eq!(file.read(last).unwrap(), vec![CHUNKS_BELOW_FOUR_GIB; CHUNK_SIZE]);
+
+    // Rewriting the file carries the chains across the boundary over intact
+    file.delete(frames[0]).unwrap();
+    file.write_root(&encode_u64s(&frames[1..])).unwrap();
+    let remap = file.canonicalize(|data| decode_u64s(data).unwrap_or_default()).unwrap();
+    assert_eq!(file.read(remap[&last]).unwrap(), vec![CHUNKS_BELOW_FOUR_GIB; CHUNK_SIZE]);
+    file.validate().unwrap();
+
+    std::fs::remove_file("file_past_4_gib.verter").unwrap();
+}
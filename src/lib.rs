@@ -1,4 +1,215 @@
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{IoSlice, Read, Seek, SeekFrom, Write};
+
+mod backend;
+pub use backend::Backend;
+use backend::StaticBytes;
+
+mod retry;
+pub use retry::RetryPolicy;
+use retry::Retrying;
+
+mod segments;
+use segments::Segments;
+
+mod page_map;
+pub use page_map::PageLayout;
+
+mod shared;
+pub use shared::SharedReader;
+
+mod heartbeat;
+pub use heartbeat::WriterStatus;
+
+mod snapshot;
+pub use snapshot::SnapshotView;
+
+mod ring;
+pub use ring::RingChain;
+
+mod slab;
+pub use slab::{Slab, SlabKey};
+
+mod cancel;
+pub use cancel::CancellationToken;
+
+mod hooks;
+pub use hooks::Hooks;
+
+mod subscribe;
+pub use subscribe::ChangeEvent;
+
+mod audit;
+pub use audit::{AuditOp, AuditRecord, AuditSink};
+
+mod undo;
+
+mod gc;
+pub use gc::UnreachableReport;
+
+mod refcount;
+
+mod dedup;
+
+mod patch;
+
+mod log;
+
+mod meta;
+pub use meta::ChainMeta;
+
+mod summary;
+pub use summary::{ChainFormat, ChainSummary};
+
+mod evict;
+
+mod tier;
+
+mod weak;
+pub use weak::WeakPtr;
+
+mod alloc_policy;
+pub use alloc_policy::AllocPolicy;
+
+mod space;
+
+mod freelist;
+pub use freelist::{FreeListAnomaly, FreeListStats};
+
+mod maintenance;
+
+mod partition;
+pub use partition::Partition;
+
+mod canonical;
+
+mod compact;
+pub use compact::CompactionReport;
+
+mod tags;
+
+mod journal;
+
+mod pin;
+
+mod ids;
+pub use ids::ID_COUNTERS;
+
+mod named_roots;
+
+mod schema;
+pub use schema::SchemaMigration;
+
+mod transaction;
+pub use transaction::{Savepoint, Transaction};
+
+mod scrub;
+pub use scrub::ScrubReport;
+
+mod search;
+pub use search::{ScanLimits, ScanReport};
+
+mod quarantine;
+pub use quarantine::PageLoss;
+
+mod chain_ref;
+pub use chain_ref::ChainRef;
+
+mod vfs;
+pub use vfs::ChainFile;
+
+mod chunked;
+pub use chunked::{ChainChunks, ChainReader};
+
+mod index;
+pub use index::{ChainIndex, IndexedChain};
+
+mod heat;
+pub use heat::HeatMap;
+use heat::HeatCounts;
+
+mod write_opts;
+pub use write_opts::{WriteOpts, WriteOutcome};
+
+mod durability;
+pub use durability::Durability;
+use durability::SyncState;
+
+mod typed;
+pub use typed::Element;
+
+mod scatter;
+
+mod memory;
+
+mod read_only;
+
+mod freeze;
+
+mod adopt;
+
+mod growth;
+
+mod fork;
+
+mod sniff;
+pub use sniff::{sniff, sniff_path, SniffInfo};
+
+mod export;
+
+#[cfg(feature = "compression")]
+mod compression;
+
+#[cfg(feature = "parallel")]
+mod parallel;
+
+mod salvage;
+pub use salvage::SalvageReport;
+
+mod validate;
+pub use validate::{Recovery, Validation, ValidationStatus};
+
+pub mod objects;
+
+pub mod fs;
+
+pub mod diff;
+
+#[cfg(feature = "remote")]
+pub mod remote;
+
+pub mod migrate;
+
+pub mod inspect;
+
+pub mod exchange;
+
+pub mod format;
+use format::{FORMAT_VERSION, HEADER_CHAIN_FLAG, MAGIC_LEN_SHIFT, MAX_MAGIC_LEN, PAGE_MAP_FLAG, VERSION_MASK};
+
+pub mod parse;
+
+pub mod raw;
+
+pub mod recover;
+
+pub mod autosave;
+
+pub mod cache;
+
+#[cfg(feature = "background")]
+pub mod background;
+
+#[cfg(feature = "async")]
+pub mod async_file;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "python")]
+mod python;
+
+#[cfg(all(test, feature = "expensive-tests"))]
+mod large_files;
 
 #[derive(Debug)]
 pub enum Error {
@@ -6,17 +217,187 @@ pub enum Error {
     InvalidFile,
     InvalidPointer,
     DeletedPointer,
-    CorruptedFile
+    CorruptedFile,
+    /// A record is too large to ever fit in its container
+    RecordTooLarge,
+    /// An operation would exceed one of the resource limits in the `Config`
+    LimitExceeded,
+    /// The file was modified by another handle or process since this handle last touched it.
+    /// Call `File::refresh` to acknowledge the changes and continue using the handle.
+    ConcurrentModification,
+    /// A path does not name the kind of entry the operation expects
+    InvalidPath,
+    /// Chain metadata was requested, but `Config::chain_meta` is disabled
+    MetadataDisabled,
+    /// A chain's data is in the cold tier, but no cold tier was set with `File::set_cold_tier`
+    NoColdTier,
+    /// A remote server rejected the client's authentication token
+    AuthenticationFailed,
+    /// The file uses a format version or page type that this version of verter does not understand
+    UnsupportedVersion,
+    /// The operation was stopped early through its `CancellationToken`
+    Cancelled,
+    /// A transaction was prepared but not yet committed or aborted. See `Transaction::prepare`.
+    PreparedTransaction,
+    /// The disk doesn't have enough free space for a write. See `Config::check_free_space`.
+    InsufficientSpace,
+    /// The file's data follows an older schema than `Config::schema_version`, and there is no `Config::schema_migration`
+    OutdatedSchema,
+    /// There isn't enough memory for a buffer the operation needs, such as one holding a huge chain
+    OutOfMemory,
+    /// The `Config` can't be used, such as one with empty magic bytes
+    InvalidConfig,
+    /// The handle was made read-only with `File::set_read_only`
+    ReadOnly,
+    /// The chain was frozen with `File::freeze`, so it can't be changed or deleted
+    FrozenChain,
+    /// The chain holds one of verter's own data structures, such as the journal or a tag, or the path is reserved for an `Archive`'s own files,
+    /// so it can't be changed or deleted directly
+    ReservedChain,
+    /// The file's pages aren't `Config::page_size` bytes long. `page_size` is the page size found in the file, if one could be found.
+    /// Open the file with `File::open_with_detected_config`, and convert it to the expected page size with `migrate::repage`.
+    GeometryMismatch {
+        page_size: Option<usize>
+    },
+    /// The file ends before pages it links to, such as a file whose tail a cloud sync client hasn't downloaded yet.
+    /// `missing_bytes` is how much longer the file needs to be, at least. See `File::open_when_synced`.
+    TruncatedFile {
+        missing_bytes: u64
+    },
+    /// The chain is the root chain, a partition's root chain or a named root, which `File::delete` refuses to delete.
+    /// Use `File::delete_root` or `File::remove_partition` to delete them on purpose, or unregister a named root with `File::remove_named_root` first.
+    ProtectedChain,
+    /// Another process or handle opened the file with `Config::writer_heartbeat` and its heartbeat is still fresh.
+    /// Once it is stale, or after `File::break_writer_lock`, the file can be opened.
+    WriterActive(WriterStatus)
 }
 
 const BYTES_IN_U64: u64 = 8;
 
+fn encode_u64s(vals: &[u64]) -> Vec<u8> {
+    vals.iter().flat_map(|val| val.to_le_bytes()).collect()
+}
+
+fn decode_u64s(bytes: &[u8]) -> Result<Vec<u64>, Error> {
+    if !bytes.len().is_multiple_of(BYTES_IN_U64 as usize) {
+        return Err(Error::CorruptedFile);
+    }
+    Ok(bytes.chunks_exact(BYTES_IN_U64 as usize).map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap())).collect())
+}
+
+/// Allocate a zeroed buffer of `len` bytes, failing with `Error::OutOfMemory` instead of aborting the process if the allocation fails
+fn try_zeroed(len: usize) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(len).map_err(|_| Error::OutOfMemory)?;
+    buf.resize(len, 0);
+    Ok(buf)
+}
+
+/// Append bytes to a buffer, failing with `Error::OutOfMemory` instead of aborting the process if it can't grow
+fn try_extend(buf: &mut Vec<u8>, bytes: &[u8]) -> Result<(), Error> {
+    buf.try_reserve(bytes.len()).map_err(|_| Error::OutOfMemory)?;
+    buf.extend_from_slice(bytes);
+    Ok(())
+}
+
+fn write_all_vectored<W: Write>(writer: &mut W, mut slices: &mut [IoSlice]) -> std::io::Result<()> {
+    IoSlice::advance_slices(&mut slices, 0);
+    while !slices.is_empty() {
+        match writer.write_vectored(slices) {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(n) => IoSlice::advance_slices(&mut slices, n),
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(err)
+        }
+    }
+    Ok(())
+}
+
 #[derive(Clone, Copy)]
 pub struct Config {
-    /// The magic bytes at the start of the file
+    /// The magic bytes at the start of the file.
+    /// Must be between 1 and `format::MAX_MAGIC_LEN` bytes long, like every one of `legacy_magic_bytes`, or opening fails with `Error::InvalidConfig`.
     pub magic_bytes: &'static [u8],
+    /// Older magic bytes that are also accepted when opening a file, such as ones used before an app was renamed
+    pub legacy_magic_bytes: &'static [&'static [u8]],
+    /// Whether to replace legacy magic bytes with `magic_bytes` the first time a file is modified.
+    /// Only possible when the legacy magic bytes have the same length, since the rest of the file would otherwise need to move.
+    pub rewrite_legacy_magic: bool,
     /// The number of bytes per page, excluding the page header
-    pub page_size: usize
+    pub page_size: usize,
+    /// The maximum number of operations kept in the undo history
+    pub undo_depth: usize,
+    /// Whether to overwrite freed bytes with garbage, so that deleted data doesn't linger in the file.
+    /// Disabling this makes deleting and shrinking chains cheaper.
+    pub wipe_freed_bytes: bool,
+    /// The maximum number of bytes a single chain may hold, if any.
+    /// Protects against hostile or corrupted files making `read` allocate huge buffers.
+    pub max_chain_size: Option<u64>,
+    /// The maximum size of the file in bytes, if any.
+    /// Checked when opening the file and when allocating new pages.
+    pub max_file_size: Option<u64>,
+    /// How thoroughly an existing file is checked when it is opened
+    pub validation: Validation,
+    /// What opening an existing file does if it was shut down in the middle of a two-phase commit
+    pub recovery: Recovery,
+    /// Whether to keep the old versions of chains around when they are written to or deleted, until the next `File::checkpoint`.
+    pub log_structured: bool,
+    /// Whether to prefix every chain with a `ChainMeta`, storing its timestamps and user flags.
+    /// Must be the same every time the file is opened.
+    pub chain_meta: bool,
+    /// How chains are laid out in their pages.
+    /// Must be the same every time the file is opened.
+    pub chain_format: ChainFormat,
+    /// How the pages of a new file are laid out. Existing files keep the layout they were created with,
+    /// which `migrate::repage` can change by copying a file into a new one.
+    pub page_layout: PageLayout,
+    /// If set, the file is split into segment files of at most this many bytes, named `<path>.000`, `<path>.001`, and so on.
+    /// Pointers still form a single address space spanning every segment.
+    pub segment_size: Option<u64>,
+    /// How free pages are chosen when a chain needs new pages
+    pub alloc_policy: AllocPolicy,
+    /// Whether to check the free space on the disk before a write that needs new pages,
+    /// failing with `Error::InsufficientSpace` before anything is written instead of running out partway through.
+    /// Assumes every new page grows the file, even if it could be reused from the free list.
+    pub check_free_space: bool,
+    /// If set, IO operations that fail with a transient error are retried instead of failing the whole operation
+    pub retry: Option<RetryPolicy>,
+    /// The version of the application's data schema, if it keeps track of one.
+    /// New files are stamped with it, and opening a file stamped with an older version runs `schema_migration`.
+    pub schema_version: Option<u32>,
+    /// Called with the schema version a file is stamped with when it is older than `schema_version`,
+    /// to bring the file's data up to date. The file is then stamped with `schema_version`.
+    /// Without a migration, opening such a file fails with `Error::OutdatedSchema`.
+    pub schema_migration: Option<SchemaMigration>,
+    /// Whether to count how many times each page is written to while the file is open. See `File::heatmap`.
+    pub heatmap: bool,
+    /// How often changes are synced to durable storage
+    pub durability: Durability,
+    /// Whether a write that shrinks a chain also cuts the free pages at the end of the file off with `File::trim_tail`,
+    /// so that the size of the file keeps tracking the data in it as chains grow and shrink.
+    pub trim_tail: bool,
+    /// Whether the root chain is only allocated the first time it is written to, instead of when the file is created,
+    /// so that files that never use it don't pay for an empty chain. Until then, `File::read_root` returns no data.
+    pub lazy_root: bool,
+    /// The most bytes bulk operations buffer at a time, if any, for machines low on memory.
+    /// `File::canonicalize` builds the new file in a temporary file instead of in memory, and chains are copied a piece at a time
+    /// by `File::canonicalize`, `File::export_chain`, `File::import_file` and the exchange format.
+    /// Tracers still see whole chains, and the root chains and cold chains in an exchange stream are still read in one go.
+    pub max_working_memory: Option<usize>,
+    /// Whether pages taken from the free list are zeroed before a chain uses them, and the unused end of a chain's final page is kept zeroed,
+    /// so that raw pages never hold stale or garbage bytes, even after a crash partway through a write.
+    /// For applications that memory-map or checksum whole pages. Pages added to the end of the file are always zeroed.
+    pub zero_new_pages: bool,
+    /// The fraction of the file's pages that may be free, if any limit, such as to keep the file from staying huge after mass deletions.
+    /// Once deleting chains goes over it, the free list is sorted by address and the free pages at the end of the file are cut off.
+    /// If that isn't enough, `File::compaction_due` tells the application to compact the file.
+    /// Must be between 0 and 1, or opening fails with `Error::InvalidConfig`.
+    pub max_free_fraction: Option<f64>,
+    /// If set, `File::open` records this process as the file's writer next to the file, refreshing a heartbeat at this interval
+    /// on a worker thread until the handle is dropped, so other processes can tell with `File::writer_status` whether the writer is still alive.
+    /// Opening fails with `Error::WriterActive` while another writer's heartbeat is fresh, including that of another handle in this process.
+    /// Other ways of opening a file don't record a writer.
+    pub writer_heartbeat: Option<std::time::Duration>
 }
 
 impl Default for Config {
@@ -24,13 +405,40 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             magic_bytes: b"VERTER__",
-            page_size: 120
+            legacy_magic_bytes: &[],
+            rewrite_legacy_magic: false,
+            page_size: 120,
+            undo_depth: 100,
+            wipe_freed_bytes: true,
+            max_chain_size: None,
+            max_file_size: None,
+            validation: Validation::Fast,
+            recovery: Recovery::Manual,
+            log_structured: false,
+            chain_meta: false,
+            chain_format: ChainFormat::V1,
+            page_layout: PageLayout::Interleaved,
+            segment_size: None,
+            alloc_policy: AllocPolicy::FirstFree,
+            check_free_space: false,
+            retry: None,
+            schema_version: None,
+            schema_migration: None,
+            heatmap: false,
+            durability: Durability::OnFlush,
+            trim_tail: false,
+            lazy_root: false,
+            max_working_memory: None,
+            zero_new_pages: false,
+            max_free_fraction: None,
+            writer_heartbeat: None
         }
     }
 
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
+#[allow(clippy::enum_variant_names)]
 enum PageHeader {
     /// There is a next page.
     /// u64 -> The pointer of the next page
@@ -45,33 +453,96 @@ enum PageHeader {
 
 impl PageHeader {
 
-    const FLAG_MASK: u64 = 3u64 << 62;
-    const NEXT_PAGE_FLAG: u64 = 0u64 << 62;
-    const FINAL_PAGE_FLAG: u64 = 1u64 << 62;
-    const DELETED_PAGE_FLAG: u64 = 2u64 << 62; 
+    /// The page type is stored in the top 8 bits of the header, leaving room for new kinds of pages in future format versions.
+    /// Page types this version of verter does not know are rejected instead of being misinterpreted.
+    const TYPE_SHIFT: u32 = format::PAGE_TYPE_SHIFT;
+    const VALUE_MASK: u64 = (1u64 << Self::TYPE_SHIFT) - 1;
+    const TYPE_MASK: u64 = 0x3F;
+    /// The top bit of the type marks the first page of a chain, so pointers into the middle of a chain can be rejected
+    const HEAD_FLAG: u64 = format::HEAD_FLAG;
+    /// The next bit marks the first page of a chain that must not change. See `File::freeze`.
+    const FROZEN_FLAG: u64 = format::FROZEN_FLAG;
+    const NEXT_PAGE_TYPE: u64 = format::NEXT_PAGE;
+    const FINAL_PAGE_TYPE: u64 = format::FINAL_PAGE;
+    const DELETED_PAGE_TYPE: u64 = format::DELETED_PAGE;
 
     fn to_u64(self) -> u64 {
-        match self {
-            PageHeader::NextPage(next) => Self::NEXT_PAGE_FLAG | next,
-            PageHeader::FinalPage(size) => Self::FINAL_PAGE_FLAG | size,
-            PageHeader::DeletedPage(next) => Self::DELETED_PAGE_FLAG | next
-        }
+        let (page_type, val) = match self {
+            PageHeader::NextPage(next) => (Self::NEXT_PAGE_TYPE, next),
+            PageHeader::FinalPage(size) => (Self::FINAL_PAGE_TYPE, size),
+            PageHeader::DeletedPage(next) => (Self::DELETED_PAGE_TYPE, next)
+        };
+        (page_type << Self::TYPE_SHIFT) | val
     }
 
-    fn from_u64(val: u64) -> Self {
-        let subval = val & !Self::FLAG_MASK; 
-        match val & Self::FLAG_MASK {
-            Self::NEXT_PAGE_FLAG => Self::NextPage(subval),
-            Self::FINAL_PAGE_FLAG => Self::FinalPage(subval),
-            Self::DELETED_PAGE_FLAG | _ => Self::DeletedPage(subval),
+    /// Encode the header of the first page of a chain
+    fn to_head_u64(self) -> u64 {
+        self.to_u64() | Self::HEAD_FLAG
+    }
+
+    fn from_u64(val: u64) -> Result<Self, Error> {
+        let subval = val & Self::VALUE_MASK;
+        match (val >> Self::TYPE_SHIFT) & Self::TYPE_MASK {
+            Self::NEXT_PAGE_TYPE => Ok(Self::NextPage(subval)),
+            Self::FINAL_PAGE_TYPE => Ok(Self::FinalPage(subval)),
+            Self::DELETED_PAGE_TYPE => Ok(Self::DeletedPage(subval)),
+            _ => Err(Error::UnsupportedVersion)
         }
     }
 
 }
 
 pub struct File {
-    file: std::fs::File,
-    config: Config
+    file: Box<dyn Backend>,
+    config: Config,
+    /// The value of the change counter in the header the last time this handle touched the file
+    change_counter: u64,
+    /// The file demoted chains are moved to. See `File::set_cold_tier`.
+    cold: Option<Box<File>>,
+    /// The magic bytes actually at the start of the file, which may be one of `Config::legacy_magic_bytes`
+    magic_bytes: &'static [u8],
+    /// The format version the file's header is laid out for, which stays at 3 for files created before the header grew in version 4
+    format_version: u64,
+    /// The pages of the chain holding the header fields after the format version, in files upgraded from format version 0,
+    /// or empty if the fields follow the format version
+    header_pages: Vec<u64>,
+    /// The header size the backend lays pages out in page maps after, if the file has page maps. See `PageLayout::PageMaps`.
+    page_maps: Option<std::sync::Arc<std::sync::atomic::AtomicU64>>,
+    /// The buffer `File::read_ref` reads chains into, kept around so its allocation can be reused
+    read_buffer: Vec<u8>,
+    /// The number of adjacent pages new pages are allocated in runs of, set by `File::write_large`
+    alloc_group: usize,
+    /// The growth factors of chains that over-allocate when appended to. See `File::set_growth_hint`.
+    growth_hints: std::collections::HashMap<u64, u32>,
+    /// The pointer to the root chain, which only changes when the root chain is allocated, replaced by `File::reset_root` or deleted with `File::delete_root`.
+    /// Cached the first time it is read, and cleared by `File::refresh` in case the file was replaced.
+    root_page_cache: Option<u64>,
+    /// The chains `File::internal_chains` returns, so that writes and deletes don't read every table to check they aren't given one.
+    /// Built the first time it is needed, and cleared whenever a header field or table listing the chains changes.
+    reserved_chains: Option<std::collections::HashSet<u64>>,
+    /// Callbacks registered by the application. See `File::hooks`.
+    hooks: Hooks,
+    /// The in-memory map of chains built by `File::index_step`
+    index: ChainIndex,
+    /// The write counts of every page, shared with the backend counting them, if `Config::heatmap` is set
+    heat: Option<std::sync::Arc<std::sync::Mutex<HeatCounts>>>,
+    /// What has been written since the file was last synced, for `Config::durability`
+    sync_state: SyncState,
+    /// Whether the operation in progress changed the file, which is counted as one change once it ends, or `None` outside of operations.
+    /// See `File::as_one_change`.
+    pending_change: Option<bool>,
+    /// Whether changes are refused with `Error::ReadOnly`. See `File::set_read_only`.
+    read_only: bool,
+    /// The worker checking the file if it was opened with `Validation::Background`
+    background_validation: Option<std::sync::Arc<validate::BackgroundValidation>>,
+    /// Whether opening the file rolled back a transaction left prepared by an unclean shutdown
+    recovered: bool,
+    /// Keeps this process recorded as the file's writer if it was opened with `Config::writer_heartbeat`
+    heartbeat: Option<heartbeat::Heartbeat>,
+    /// The number of pages freed since free pages were last counted for `Config::max_free_fraction`
+    freed_since_maintenance: u64,
+    /// See `File::compaction_due`
+    compaction_due: bool
 }
 
 impl File {
@@ -80,121 +551,479 @@ impl File {
     /// Creates and initiates it if it currently does not exist.
     /// Will return an error if the file is invalid(ie has incorrect magic bytes).
     pub fn open<P: AsRef<std::path::Path>>(path: P, config: Config) -> Result<File, Error> {
-        let create = !std::fs::exists(&path).map_err(Error::IO)?;
-        
-        let file = std::fs::OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .open(path)
-            .map_err(Error::IO)?;
+        let path = path.as_ref();
+        let heartbeat = config.writer_heartbeat.map(|interval| heartbeat::Heartbeat::start(path, interval)).transpose()?;
+        let (create, file): (bool, Box<dyn Backend>) = match config.segment_size {
+            Some(segment_size) => {
+                let create = !Segments::exist(path).map_err(Error::IO)?;
+                (create, Box::new(Segments::open(path, segment_size).map_err(Error::IO)?))
+            },
+            None => {
+                let create = !std::fs::exists(path).map_err(Error::IO)?;
+                let file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .truncate(false)
+                    .read(true)
+                    .write(true)
+                    .open(path)
+                    .map_err(Error::IO)?;
+                (create, Box::new(file))
+            }
+        };
+
+        let mut file = Self::from_backend(file, config, create)?;
+        file.heartbeat = heartbeat;
+        if config.validation == Validation::Background || (config.recovery == Recovery::VerifyInBackground && file.recovered) {
+            file.background_validation = Some(validate::BackgroundValidation::spawn(path.to_path_buf(), config));
+        }
+        Ok(file)
+    }
+
+    /// Open a file like `File::open`, but if its pages turn out to be a different size than `Config::page_size`,
+    /// open it with the page size it was created with instead.
+    /// `File::config` tells which page size was used, and `migrate::repage` converts the file to the page size the application expects.
+    pub fn open_with_detected_config<P: AsRef<std::path::Path>>(path: P, config: Config) -> Result<File, Error> {
+        let path = path.as_ref();
+        match Self::open(path, config) {
+            Err(Error::GeometryMismatch { page_size: Some(page_size) }) => Self::open(path, Config { page_size, ..config }),
+            result => result
+        }
+    }
+
+    /// Open a file like `File::open`, but if it fails with `Error::TruncatedFile`, such as while a cloud sync client is still downloading it,
+    /// wait for the rest of the file to arrive and try again, giving up once `timeout` has passed.
+    pub fn open_when_synced<P: AsRef<std::path::Path>>(path: P, config: Config, timeout: std::time::Duration) -> Result<File, Error> {
+        let path = path.as_ref();
+        let start = std::time::Instant::now();
+        let mut backoff = std::time::Duration::from_millis(10);
+        loop {
+            match Self::open(path, config) {
+                Err(Error::TruncatedFile { .. }) if start.elapsed() < timeout => {
+                    std::thread::sleep(backoff.min(timeout.saturating_sub(start.elapsed())));
+                    backoff = (backoff * 2).min(std::time::Duration::from_secs(1));
+                },
+                result => return result
+            }
+        }
+    }
+
+    /// The configuration the file was opened with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Open a file from an already open `std::fs::File`. It must be open for reading, and for writing unless it is only read.
+    /// An empty file is initiated like a newly created one. `Config::segment_size` is ignored.
+    pub fn from_file(file: std::fs::File, config: Config) -> Result<File, Error> {
+        let create = file.metadata().map_err(Error::IO)?.len() == 0;
+        Self::from_backend(Box::new(file), config, create)
+    }
+
+    /// Open a file stored in a custom backend, such as `testing::CrashSim`.
+    /// An empty backend is initiated like a newly created file. `Config::segment_size` is ignored.
+    pub fn open_backend(backend: Box<dyn Backend>, config: Config) -> Result<File, Error> {
+        let create = backend.is_empty().map_err(Error::IO)?;
+        Self::from_backend(backend, config, create)
+    }
+
+    /// Open a file from an already open file descriptor, such as one handed over by a sandbox or file picker.
+    /// See `File::from_file`.
+    #[cfg(unix)]
+    pub fn from_owned_fd(fd: std::os::fd::OwnedFd, config: Config) -> Result<File, Error> {
+        Self::from_file(std::fs::File::from(fd), config)
+    }
+
+    /// Open a read-only file from bytes embedded in the program, such as a template document included with `include_bytes!`.
+    /// Anything that would modify the file fails with an IO error. Use `migrate::repage` to copy it into a real file.
+    pub fn open_bytes(bytes: &'static [u8], config: Config) -> Result<File, Error> {
+        Self::from_backend(Box::new(StaticBytes::new(bytes)), config, false)
+    }
 
+    /// Open a file stored in a backend, creating and initiating it first if `create` is set
+    fn from_backend(file: Box<dyn Backend>, config: Config, create: bool) -> Result<File, Error> {
+        let mut magic_lens = std::iter::once(config.magic_bytes).chain(config.legacy_magic_bytes.iter().copied()).map(<[u8]>::len);
+        if magic_lens.any(|len| len == 0 || len > MAX_MAGIC_LEN) {
+            return Err(Error::InvalidConfig);
+        }
+        if config.max_free_fraction.is_some_and(|fraction| !(0.0..=1.0).contains(&fraction)) {
+            return Err(Error::InvalidConfig);
+        }
+        let file: Box<dyn Backend> = match config.retry {
+            Some(policy) => Box::new(Retrying::new(file, policy)),
+            None => file
+        };
         let mut file = Self {
             file,
-            config
+            config,
+            change_counter: 0,
+            cold: None,
+            magic_bytes: config.magic_bytes,
+            format_version: FORMAT_VERSION,
+            header_pages: Vec::new(),
+            page_maps: None,
+            read_buffer: Vec::new(),
+            alloc_group: 1,
+            growth_hints: Default::default(),
+            root_page_cache: None,
+            reserved_chains: None,
+            hooks: Hooks::default(),
+            index: ChainIndex::default(),
+            heat: None,
+            sync_state: SyncState::default(),
+            pending_change: None,
+            read_only: false,
+            background_validation: None,
+            recovered: false,
+            heartbeat: None,
+            freed_since_maintenance: 0,
+            compaction_due: false
         };
 
         if create {
+            if config.page_layout == PageLayout::PageMaps {
+                file.map_pages();
+            }
             file.create_header()?;
         } else {
             file.check_if_file_valid()?;
+            file.check_file_size_limit(file.file_size()?)?;
+            file.refresh()?;
+            file.recover()?;
+            if file.config.validation == Validation::Full || (file.config.recovery == Recovery::Verify && file.recovered) {
+                file.validate()?;
+            }
+            file.check_schema_version()?;
+        }
+        if file.config.heatmap {
+            file.track_heat()?;
         }
 
         Ok(file)
     }
 
     /// Read the data from a page chain. 
-    pub fn read(&mut self, mut ptr: u64) -> Result<Vec<u8>, Error> {
+    pub fn read(&mut self, ptr: u64) -> Result<Vec<u8>, Error> {
+        self.check_for_external_changes()?;
         self.check_if_pointer_valid(ptr)?;
+        if let Some(data) = self.read_cold(ptr)? {
+            return Ok(data);
+        }
+        self.read_chain(ptr)
+    }
 
+    /// Read the data stored in a chain's own pages
+    fn read_chain(&mut self, ptr: u64) -> Result<Vec<u8>, Error> {
         let mut data = Vec::new();
+        self.read_chain_into(ptr, &mut data)?;
 
-        loop {
-            let header = self.read_page_header(ptr)?; 
-            match header {
-                PageHeader::NextPage(next) => {
-                    data.extend(std::iter::repeat(0).take(self.config.page_size));
-                    self.file.seek(SeekFrom::Start(ptr + BYTES_IN_U64)).map_err(Error::IO)?;
-                    let read_to = data.len() - self.config.page_size;
-                    self.file.read(&mut data[read_to..]).map_err(Error::IO)?;
-                    ptr = next;
-                },
-                PageHeader::FinalPage(size) => {
-                    let size = size as usize;
-                    data.extend(std::iter::repeat(0).take(size));
-                    self.file.seek(SeekFrom::Start(ptr + BYTES_IN_U64)).map_err(Error::IO)?;
-                    let read_to = data.len() - size; 
-                    self.file.read(&mut data[read_to..]).map_err(Error::IO)?;
-                    break;
-                },
-                PageHeader::DeletedPage(_) => {
-                    return Err(Error::CorruptedFile);
+        // Skip over the chain's metadata
+        data.drain(..self.meta_size() as usize);
+
+        Ok(data)
+    }
+
+    /// Read the contents of a chain's own pages into a buffer, including the chain's metadata.
+    /// The buffer is resized to fit, reusing its allocation.
+    /// Pages are read together with their headers, several physically adjacent pages at a time, so walking the chain
+    /// takes a single read per run of contiguous pages instead of a header read per page.
+    fn read_chain_into(&mut self, ptr: u64, data: &mut Vec<u8>) -> Result<(), Error> {
+        /// The most pages read at once while the chain stays contiguous
+        const MAX_READ_AHEAD: u64 = 64;
+
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+
+        let file_size = self.file_size()?;
+        let total_page_size = self.total_page_size();
+        let max_pages = file_size / total_page_size;
+        data.clear();
+
+        let mut window = Vec::new();
+        let mut read_ahead = 1;
+        let mut page = ptr;
+        let mut pages_read = 0;
+        'walk: loop {
+            let count = read_ahead.min(file_size.saturating_sub(page) / total_page_size);
+            if count == 0 {
+                return Err(self.past_end(page, file_size));
+            }
+            window.resize((count * total_page_size) as usize, 0);
+            self.file.read_exact_at(&mut window, page).map_err(Error::IO)?;
+
+            for (i, bytes) in window.chunks_exact(total_page_size as usize).enumerate() {
+                pages_read += 1;
+                let (header, payload) = bytes.split_at(BYTES_IN_U64 as usize);
+                match PageHeader::from_u64(u64::from_le_bytes(header.try_into().unwrap()))? {
+                    PageHeader::NextPage(next) => {
+                        if pages_read >= max_pages {
+                            return Err(Error::CorruptedFile);
+                        }
+                        if let Some(max_chain_size) = self.config.max_chain_size {
+                            if pages_read * self.config.page_size as u64 >= max_chain_size {
+                                return Err(Error::LimitExceeded);
+                            }
+                        }
+                        try_extend(data, payload)?;
+
+                        // If the chain jumps elsewhere, start reading again from its next page
+                        let this_page = page + i as u64 * total_page_size;
+                        if next != this_page + total_page_size {
+                            page = next;
+                            read_ahead = 1;
+                            continue 'walk;
+                        }
+                    },
+                    PageHeader::FinalPage(size) => {
+                        if size > self.config.page_size as u64 {
+                            return Err(Error::CorruptedFile);
+                        }
+                        try_extend(data, &payload[..size as usize])?;
+                        break 'walk;
+                    },
+                    PageHeader::DeletedPage(_) => return Err(Error::CorruptedFile)
                 }
             }
+
+            // The chain continued through the whole window, so read further ahead next time
+            page += count * total_page_size;
+            read_ahead = (read_ahead * 2).min(MAX_READ_AHEAD);
         }
 
-        Ok(data)
+        if data.len() < self.meta_size() as usize {
+            return Err(Error::CorruptedFile);
+        }
+
+        Ok(())
     }
 
     /// Read the root page chain.
     pub fn read_root(&mut self) -> Result<Vec<u8>, Error> {
-        let root_page = self.root_page()?;
-        self.read(root_page)
+        self.check_for_external_changes()?;
+        match self.root_page()? {
+            0 => Ok(Vec::new()),
+            root_page => self.read(root_page)
+        }
+    }
+
+    /// Read the root page chain, first initializing it with the given data if it is empty, such as in a freshly created file.
+    pub fn read_root_or_init<F: FnOnce() -> Vec<u8>>(&mut self, init: F) -> Result<Vec<u8>, Error> {
+        if !self.root_is_empty()? {
+            return self.read_root();
+        }
+        let data = init();
+        self.write_root(&data)?;
+        Ok(data)
+    }
+
+    /// Check whether the root page chain holds no data.
+    pub fn root_is_empty(&mut self) -> Result<bool, Error> {
+        self.check_for_external_changes()?;
+        match self.root_page()? {
+            0 => Ok(true),
+            root_page => Ok(self.chain_len(root_page)? == 0)
+        }
     }
 
     /// Write data to a page chain.
-    pub fn write(&mut self, mut ptr: u64, mut data: &[u8]) -> Result<(), Error> {
-        self.check_if_pointer_valid(ptr)?;
-        
-        while data.len() > self.config.page_size {
-            self.file.seek(SeekFrom::Start(ptr + BYTES_IN_U64)).map_err(Error::IO)?;
-            self.file.write(&data[..self.config.page_size]).map_err(Error::IO)?;
-            data = &data[self.config.page_size..];
-            ptr = match self.read_page_header(ptr)? {
-                PageHeader::NextPage(next) => next,
-                PageHeader::FinalPage(_) => {
-                    let new_page = self.alloc()?;
-                    self.write_page_header(ptr, PageHeader::NextPage(new_page))?;
-                    new_page
-                },
-                PageHeader::DeletedPage(_) => {
-                    return Err(Error::CorruptedFile);
-                }
+    /// In log-structured mode, the previous contents of the chain are kept as an old version. See `File::versions`.
+    /// If the process stops partway through a write, such as by panicking, every chain stays readable, though the chain written to
+    /// may hold a mix of old and new data and some pages may leak. This relies on the writes reaching the OS in order, so it doesn't cover
+    /// power loss or an OS crash, after which any writes made since the file was last synced may have reached the disk in any order.
+    pub fn write(&mut self, ptr: u64, data: &[u8]) -> Result<(), Error> {
+        self.write_reporting(ptr, data).map(drop)
+    }
+
+    /// Write data to a page chain, reporting how its pages changed. See `File::write_with_outcome`.
+    pub(crate) fn write_reporting(&mut self, ptr: u64, data: &[u8]) -> Result<WriteOutcome, Error> {
+        self.as_one_change(|file| file.write_reporting_unsynced(ptr, data))
+    }
+
+    fn write_reporting_unsynced(&mut self, ptr: u64, data: &[u8]) -> Result<WriteOutcome, Error> {
+        self.check_writable()?;
+        if self.write_cold(ptr, data)? {
+            self.hooks.write(ptr, data.len());
+            return Ok(WriteOutcome { pages_allocated: 0, pages_freed: 0, final_len: data.len() as u64 });
+        }
+        self.check_not_reserved(ptr)?;
+        if self.config.log_structured {
+            self.check_for_external_changes()?;
+            self.check_if_pointer_valid(ptr)?;
+            self.check_not_frozen(ptr)?;
+            if self.config.max_chain_size.is_some_and(|max_chain_size| data.len() as u64 > max_chain_size) {
+                return Err(Error::LimitExceeded);
             }
+            self.preserve_version(ptr)?;
+        }
+        let outcome = self.write_chain_reporting(ptr, data)?;
+        self.hooks.write(ptr, data.len());
+        Ok(outcome)
+    }
+
+    /// Write data to a page chain, reusing the chain's existing pages.
+    /// Used directly for verter's internal chains, which are never versioned.
+    fn write_chain(&mut self, ptr: u64, data: &[u8]) -> Result<(), Error> {
+        self.write_chain_reporting(ptr, data).map(drop)
+    }
+
+    fn write_chain_reporting(&mut self, ptr: u64, data: &[u8]) -> Result<WriteOutcome, Error> {
+        self.as_one_change(|file| file.write_chain_unsynced(ptr, data))
+    }
+
+    fn write_chain_unsynced(&mut self, ptr: u64, data: &[u8]) -> Result<WriteOutcome, Error> {
+        self.check_writable()?;
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        self.check_not_frozen(ptr)?;
+        if self.config.max_chain_size.is_some_and(|max_chain_size| data.len() as u64 > max_chain_size) {
+            return Err(Error::LimitExceeded);
         }
 
-        let final_page_header = self.read_page_header(ptr)?;
-        if let PageHeader::NextPage(truncated_pages) = final_page_header {
-            // If there are more pages in this chain we no longer need, delete them
-            self.delete(truncated_pages)?;
+        self.note_reserved_write(ptr)?;
+
+        let final_len = data.len() as u64;
+        let data = &self.prefix_meta(ptr, data)?;
+        self.note_unsynced(data.len() as u64);
+        let pages_needed = self.pages_needed(data.len() as u64) as usize;
+        let mut pages = self.chain_pages(ptr)?;
+        let outcome = WriteOutcome {
+            pages_allocated: pages_needed.saturating_sub(pages.len()) as u64,
+            pages_freed: pages.len().saturating_sub(pages_needed) as u64,
+            final_len
+        };
+        self.check_free_space(pages_needed.saturating_sub(pages.len()))?;
+        // Pages the chain no longer needs are only freed once it stops linking to them
+        let surplus = pages.get(pages_needed).copied();
+        pages.truncate(pages_needed);
+        if pages.len() < pages_needed {
+            let new_pages = self.alloc_pages(pages_needed - pages.len())?;
+            pages.extend(new_pages);
         }
 
-        self.file.seek(SeekFrom::Start(ptr + BYTES_IN_U64)).map_err(Error::IO)?;
-        self.file.write(data).map_err(Error::IO)?;
-        self.file.write(&vec![0xFF; self.config.page_size - data.len()]).map_err(Error::IO)?; // Clear remainder of the page 
-        self.write_page_header(ptr, PageHeader::FinalPage(data.len() as u64))?;
+        let final_size = data.len() - (pages.len() - 1) * self.config.page_size;
+        let headers = (0..pages.len()).map(|i| {
+            let header = match pages.get(i + 1) {
+                Some(next) => PageHeader::NextPage(*next),
+                None => PageHeader::FinalPage(final_size as u64)
+            };
+            if i == 0 {
+                header.to_head_u64().to_le_bytes()
+            } else {
+                header.to_u64().to_le_bytes()
+            }
+        }).collect::<Vec<_>>();
+        let slack = vec![if self.config.zero_new_pages { 0 } else { 0xFF }; self.config.page_size - final_size];
+
+        let payloads = data.chunks(self.config.page_size).collect::<Vec<_>>();
+        // The run holding the head page is written last, so that if the process stops partway through the write,
+        // the chain only ever links to pages that are allocated to it. Nothing is synced in between, so this ordering only holds
+        // as long as the OS doesn't crash before the writes reach the disk
+        for run in self.page_runs(&pages).into_iter().rev() {
+            // Write the headers and data of the whole run with a single syscall
+            let mut slices = Vec::new();
+            for i in run.clone() {
+                slices.push(IoSlice::new(&headers[i]));
+                slices.push(IoSlice::new(payloads.get(i).copied().unwrap_or_default()));
+            }
+            if run.end == pages.len() && (self.config.wipe_freed_bytes || self.config.zero_new_pages) {
+                slices.push(IoSlice::new(&slack)); // Clear remainder of the page
+            }
+            self.file.seek(SeekFrom::Start(pages[run.start])).map_err(Error::IO)?;
+            write_all_vectored(&mut self.file, &mut slices).map_err(Error::IO)?;
+        }
+        if let Some(surplus) = surplus {
+            self.free_pages(surplus)?;
+            if self.config.trim_tail {
+                self.trim_tail()?;
+            }
+        }
 
-        Ok(())
+        self.bump_change_counter()?;
+        self.maintain_if_due()?;
+        Ok(outcome)
     }
 
     /// Write to the root page chain
     pub fn write_root(&mut self, data: &[u8]) -> Result<(), Error> {
-        let root_page = self.root_page()?;
+        let root_page = self.root_page_or_alloc()?;
         self.write(root_page, data)
     }
 
+    /// Write data to several page chains, then flush everything to disk with a single sync.
+    /// The writes are performed in address order to improve locality, and count as a single change to the file.
+    /// All pointers are validated before anything is written.
+    pub fn write_batch(&mut self, writes: &[(u64, &[u8])]) -> Result<(), Error> {
+        for (ptr, _) in writes {
+            self.check_if_pointer_valid(*ptr)?;
+        }
+
+        let mut writes = writes.to_vec();
+        writes.sort_by_key(|(ptr, _)| *ptr);
+        self.as_one_change_then(|file| {
+            for (ptr, data) in writes {
+                file.write(ptr, data)?;
+            }
+            Ok(())
+        }, Self::write_change_counter)?;
+
+        self.flush()
+    }
+
     /// Allocate a new page.
     /// Either takes the first page in the free list or creates a new page at the end of the file.
-    /// Initializes page with a header of PageHeader::FinalPage(0). 
+    /// Initializes page with a header of PageHeader::FinalPage(0), followed by the chain's metadata if `Config::chain_meta` is enabled
+    /// and its summary in `ChainFormat::V2`.
     pub fn alloc(&mut self) -> Result<u64, Error> {
+        self.as_one_change(Self::alloc_unsynced)
+    }
+
+    fn alloc_unsynced(&mut self) -> Result<u64, Error> {
+        let header = PageHeader::FinalPage(self.meta_size()).to_head_u64();
+        let page = self.alloc_page_as(header)?;
+        if self.meta_size() > 0 {
+            self.init_meta(page)?;
+        }
+        self.hooks.alloc(page);
+        Ok(page)
+    }
+
+    /// Allocate a new chain and fill it with data, returning the pointer to the chain.
+    /// If writing the data fails, the chain is freed again instead of being left behind empty.
+    pub fn alloc_with(&mut self, data: &[u8]) -> Result<u64, Error> {
+        if self.config.max_chain_size.is_some_and(|max_chain_size| data.len() as u64 > max_chain_size) {
+            return Err(Error::LimitExceeded);
+        }
+        self.as_one_change(|file| {
+            let ptr = file.alloc()?;
+            if let Err(err) = file.write_chain(ptr, data) {
+                file.free_pages(ptr)?;
+                file.hooks.delete(ptr);
+                return Err(err);
+            }
+            file.hooks.write(ptr, data.len());
+            Ok(ptr)
+        })
+    }
+
+    /// Allocate a page to extend a chain with
+    fn alloc_page(&mut self) -> Result<u64, Error> {
+        self.alloc_page_as(PageHeader::FinalPage(0).to_u64())
+    }
+
+    /// Allocate a page, writing `header` as its raw header.
+    /// The header is the only part of the page written, so the common case of writing to a freshly allocated chain
+    /// doesn't write its pages twice.
+    fn alloc_page_as(&mut self, header: u64) -> Result<u64, Error> {
+        if self.config.alloc_policy != AllocPolicy::FirstFree {
+            let page = self.alloc_pages(1)?[0];
+            self.write_u64(page, header)?;
+            return Ok(page);
+        }
+
+        self.check_for_external_changes()?;
         let free_page = self.first_free_page()?;
 
         let page = if free_page == 0 {
-            // Create new page at the end of the file
-            let new_page_ptr = self.file.seek(SeekFrom::End(0)).map_err(Error::IO)?;
-            self.file.write(&vec![0xFF; self.total_page_size() as usize]).map_err(Error::IO)?;
-
-            new_page_ptr
+            self.append_page()?
         } else {
             // Remove free page from chain
             let new_free_page = self.read_page_header(free_page)?;
@@ -205,98 +1034,656 @@ impl File {
                 _ => return Err(Error::CorruptedFile)
             }
 
+            self.zero_if_configured(free_page)?;
             free_page
         };
 
-        self.write_page_header(page, PageHeader::FinalPage(0))?;
+        self.write_u64(page, header)?;
+        self.bump_change_counter()?;
 
         Ok(page)
     }
 
-    /// Delete a page chain.
-    /// Note that this simply adds the page to the free list, without actually ever shrinking the file.
-    pub fn delete(&mut self, mut ptr: u64) -> Result<(), Error> {
-        self.check_if_pointer_valid(ptr)?;
-
-        loop {
-            let header = self.read_page_header(ptr)?;
-            let free_pages = self.first_free_page()?;
-            self.write_page_header(ptr, PageHeader::DeletedPage(free_pages))?;
-            self.write_u64(self.first_free_page_ptr(), ptr)?;
-
-            // Write garbage to the deleted page
-            self.file.seek(SeekFrom::Start(ptr + BYTES_IN_U64)).map_err(Error::IO)?;
-            self.file.write(&vec![0xFF; self.config.page_size]).map_err(Error::IO)?;
-
-            match header {
-                PageHeader::NextPage(next) => ptr = next,
-                PageHeader::FinalPage(_) => break,
-                PageHeader::DeletedPage(_) => {
-                    return Err(Error::CorruptedFile);
-                }
-            } 
+    /// Zero the bytes of a page taken from the free list if `Config::zero_new_pages` is set
+    fn zero_if_configured(&mut self, page: u64) -> Result<(), Error> {
+        if self.config.zero_new_pages {
+            self.file.seek(SeekFrom::Start(page + BYTES_IN_U64)).map_err(Error::IO)?;
+            self.file.write_all(&vec![0; self.config.page_size]).map_err(Error::IO)?;
         }
-
         Ok(())
     }
 
-    fn read_u64(&mut self, ptr: u64) -> Result<u64, Error> {
-        self.file.seek(SeekFrom::Start(ptr as u64)).map_err(Error::IO)?;
-        let mut bytes = [0; BYTES_IN_U64 as usize];
-        self.file.read(&mut bytes).map_err(Error::IO)?;
-        Ok(u64::from_le_bytes(bytes))
+    /// Create a new page at the end of the file, without initializing its header.
+    /// The file is only extended, leaving the new bytes zeroed, so the page isn't written until it is used.
+    fn append_page(&mut self) -> Result<u64, Error> {
+        self.check_writable()?;
+        let new_page_ptr = self.file_size()?;
+        self.check_file_size_limit(new_page_ptr + self.total_page_size())?;
+        self.file.set_len(new_page_ptr + self.total_page_size()).map_err(Error::IO)?;
+        Ok(new_page_ptr)
     }
 
-    fn read_page_header(&mut self, ptr: u64) -> Result<PageHeader, Error> {
-        self.read_u64(ptr).map(PageHeader::from_u64)
+    /// Delete a page chain.
+    /// Note that this simply adds the page to the free list, without shrinking the file. See `File::trim_tail`.
+    /// In log-structured mode, the chain stays readable until the next checkpoint.
+    /// Chains allocated in a partition are removed from the partition too.
+    /// Fails with `Error::ProtectedChain` for the root chain, the root chains of partitions and named roots, since the file would be left pointing at a deleted chain.
+    pub fn delete(&mut self, ptr: u64) -> Result<(), Error> {
+        self.check_not_reserved(ptr)?;
+        self.check_not_protected(ptr)?;
+        self.delete_chain(ptr)
     }
 
-    fn write_u64(&mut self, ptr: u64, val: u64) -> Result<(), Error> {
-        self.file.seek(SeekFrom::Start(ptr)).map_err(Error::IO)?;
-        self.file.write(&val.to_le_bytes()).map_err(Error::IO)?;
-        Ok(())
+    /// Delete the root chain on purpose, leaving the file without one until the root is next written to,
+    /// as if it had been created with `Config::lazy_root`. Returns `false` if there is no root chain.
+    pub fn delete_root(&mut self) -> Result<bool, Error> {
+        self.as_one_change(|file| file.delete_root_unsynced())
     }
 
-    fn write_page_header(&mut self, ptr: u64, header: PageHeader) -> Result<(), Error> {
-        self.write_u64(ptr, header.to_u64())
+    fn delete_root_unsynced(&mut self) -> Result<bool, Error> {
+        self.check_for_external_changes()?;
+        let root_page = self.root_page()?;
+        if root_page == 0 {
+            return Ok(false);
+        }
+        self.delete_chain(root_page)?;
+        self.write_u64(self.root_page_ptr(), 0)?;
+        self.root_page_cache = Some(0);
+        self.bump_change_counter()?;
+        Ok(true)
     }
 
-    fn magic_bytes_ptr(&self) -> u64 {
-        0
+    /// Replace the root chain with a new empty one, such as to clear the document without losing tags, partitions and other chains.
+    /// The file switches to the new root chain with a single write, so a crash leaves either the old root or the new one, never a partly cleared one.
+    /// The old root chain is deleted afterwards, or left as an unreachable chain for `File::gc` if the process crashes in between.
+    /// Chains the old root pointed to stay allocated, so free them with `File::gc` too.
+    pub fn reset_root(&mut self) -> Result<(), Error> {
+        self.as_one_change(|file| file.reset_root_unsynced())
     }
 
-    fn first_free_page_ptr(&self) -> u64 {
-        self.magic_bytes_ptr() + self.config.magic_bytes.len() as u64
+    fn reset_root_unsynced(&mut self) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        let old_root = self.root_page()?;
+        if old_root != 0 {
+            self.check_not_frozen(old_root)?;
+        }
+        let new_root = self.alloc()?;
+        self.write_u64(self.root_page_ptr(), new_root)?;
+        self.root_page_cache = Some(new_root);
+        self.bump_change_counter()?;
+        if old_root != 0 {
+            self.delete_chain(old_root)?;
+        }
+        Ok(())
     }
 
-    fn header_size(&self) -> u64 {
-        self.config.magic_bytes.len() as u64 + 2 * BYTES_IN_U64
+    /// Delete a page chain, even if it is one of verter's internal chains.
+    /// Used directly for verter's internal chains once the structure holding them no longer needs them.
+    /// Reserved and protected chains aren't refused, so anything deleting chains on behalf of the application, such as `File::decref`,
+    /// goes through `File::delete` instead.
+    fn delete_chain(&mut self, ptr: u64) -> Result<(), Error> {
+        self.as_one_change(|file| file.delete_chain_unsynced(ptr))
     }
 
-    fn total_page_size(&self) -> u64 {
-        BYTES_IN_U64 + self.config.page_size as u64
+    fn delete_chain_unsynced(&mut self, ptr: u64) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        self.check_not_frozen(ptr)?;
+        self.forget_refcount(ptr)?;
+        self.forget_cold(ptr)?;
+        self.forget_pin(ptr)?;
+        self.forget_partition_members(&[ptr])?;
+        self.growth_hints.remove(&ptr);
+        self.retire_chain(ptr)?;
+        self.hooks.delete(ptr);
+        self.maintain_if_due()
     }
 
-    fn root_page_ptr(&self) -> u64 {
-        self.first_free_page_ptr() + BYTES_IN_U64
+    /// Delete a page chain if it has not already been deleted.
+    /// Returns whether the chain was deleted, making cleanup code that may run twice safe.
+    pub fn delete_if_allocated(&mut self, ptr: u64) -> Result<bool, Error> {
+        match self.delete(ptr) {
+            Ok(()) => Ok(true),
+            Err(Error::DeletedPointer) => Ok(false),
+            Err(err) => Err(err)
+        }
     }
 
-    fn first_free_page(&mut self) -> Result<u64, Error> {
-        self.read_u64(self.first_free_page_ptr())
+    /// Delete several page chains at once.
+    /// Every pointer is validated before anything is deleted. The freed pages are linked to each other first
+    /// and then spliced into the free list as a whole, instead of updating the head of the free list once per page.
+    pub fn delete_many(&mut self, ptrs: &[u64]) -> Result<(), Error> {
+        self.as_one_change(|file| file.delete_many_unsynced(ptrs))
+    }
+
+    fn delete_many_unsynced(&mut self, ptrs: &[u64]) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        for (i, ptr) in ptrs.iter().enumerate() {
+            self.check_if_pointer_valid(*ptr)?;
+            self.check_not_frozen(*ptr)?;
+            self.check_not_reserved(*ptr)?;
+            self.check_not_protected(*ptr)?;
+            if ptrs[..i].contains(ptr) {
+                return Err(Error::DeletedPointer);
+            }
+        }
+
+        self.forget_partition_members(ptrs)?;
+        let mut pages = Vec::new();
+        for ptr in ptrs {
+            self.forget_refcount(*ptr)?;
+            self.forget_cold(*ptr)?;
+            self.forget_pin(*ptr)?;
+            self.growth_hints.remove(ptr);
+            self.bump_generation(*ptr)?;
+            if self.config.log_structured {
+                self.defer_delete(*ptr)?;
+            } else {
+                pages.extend(self.chain_pages(*ptr)?);
+            }
+        }
+        if !pages.is_empty() {
+            let mut next = self.first_free_page()?;
+            for page in pages.iter().rev() {
+                self.write_page_header(*page, PageHeader::DeletedPage(next))?;
+                if self.config.wipe_freed_bytes {
+                    self.file.seek(SeekFrom::Start(page + BYTES_IN_U64)).map_err(Error::IO)?;
+                    self.file.write_all(&vec![0xFF; self.config.page_size]).map_err(Error::IO)?;
+                }
+                next = *page;
+            }
+            self.write_u64(self.first_free_page_ptr(), next)?;
+            self.note_freed(pages.len() as u64);
+            self.bump_change_counter()?;
+        }
+
+        for ptr in ptrs {
+            self.hooks.delete(*ptr);
+        }
+        self.maintain_if_due()
+    }
+
+    /// Free a chain that is no longer in use, or defer freeing it until the next checkpoint in log-structured mode.
+    fn retire_chain(&mut self, ptr: u64) -> Result<(), Error> {
+        self.bump_generation(ptr)?;
+        if self.config.log_structured {
+            self.defer_delete(ptr)
+        } else {
+            self.free_pages(ptr)
+        }
+    }
+
+    /// Add every page in the chain starting at `ptr` to the free list.
+    fn free_pages(&mut self, mut ptr: u64) -> Result<(), Error> {
+        loop {
+            let header = self.read_page_header(ptr)?;
+            let free_pages = self.first_free_page()?;
+            self.write_page_header(ptr, PageHeader::DeletedPage(free_pages))?;
+            self.write_u64(self.first_free_page_ptr(), ptr)?;
+            self.note_freed(1);
+
+            // Write garbage to the deleted page
+            if self.config.wipe_freed_bytes {
+                self.file.seek(SeekFrom::Start(ptr + BYTES_IN_U64)).map_err(Error::IO)?;
+                self.file.write_all(&vec![0xFF; self.config.page_size]).map_err(Error::IO)?;
+            }
+
+            match header {
+                PageHeader::NextPage(next) => ptr = next,
+                PageHeader::FinalPage(_) => break,
+                PageHeader::DeletedPage(_) => {
+                    return Err(Error::CorruptedFile);
+                }
+            } 
+        }
+
+        self.bump_change_counter()
+    }
+
+    /// Acknowledge changes made to the file by other handles or processes, so that this handle can keep using the file.
+    pub fn refresh(&mut self) -> Result<(), Error> {
+        self.change_counter = self.read_u64(self.change_counter_ptr())?;
+        self.root_page_cache = None;
+        self.reserved_chains = None;
+        Ok(())
+    }
+
+    /// Return `Error::ConcurrentModification` if someone else changed the file since this handle last touched it.
+    fn check_for_external_changes(&mut self) -> Result<(), Error> {
+        if self.read_u64(self.change_counter_ptr())? != self.change_counter {
+            return Err(Error::ConcurrentModification);
+        }
+        Ok(())
+    }
+
+    fn bump_change_counter(&mut self) -> Result<(), Error> {
+        self.write_change_counter()?;
+        if self.pending_change.is_none() {
+            self.sync_if_due()?;
+        }
+        Ok(())
+    }
+
+    /// Bump the change counter without syncing, or note the change if an operation is in progress. See `File::as_one_change`.
+    fn write_change_counter(&mut self) -> Result<(), Error> {
+        self.check_writable()?;
+        if let Some(changed) = &mut self.pending_change {
+            *changed = true;
+            return Ok(());
+        }
+        if self.config.rewrite_legacy_magic && self.magic_bytes != self.config.magic_bytes && self.magic_bytes.len() == self.config.magic_bytes.len() {
+            self.file.seek(SeekFrom::Start(self.magic_bytes_ptr())).map_err(Error::IO)?;
+            self.file.write_all(self.config.magic_bytes).map_err(Error::IO)?;
+            self.magic_bytes = self.config.magic_bytes;
+        }
+        self.change_counter = self.change_counter.wrapping_add(1);
+        self.write_u64(self.change_counter_ptr(), self.change_counter)
+    }
+
+    /// Get the pointers of all the pages in a chain, in order.
+    fn chain_pages(&mut self, ptr: u64) -> Result<Vec<u64>, Error> {
+        self.chain_layout(ptr).map(|(pages, _)| pages)
+    }
+
+    /// Get the pointers of all the pages in a chain, along with the number of bytes in the final page.
+    /// Fails if the chain is longer than `Config::max_chain_size` or loops back on itself.
+    fn chain_layout(&mut self, mut ptr: u64) -> Result<(Vec<u64>, u64), Error> {
+        let file_size = self.file_size()?;
+        let max_pages = file_size / self.total_page_size();
+        let mut pages = vec![ptr];
+        loop {
+            match self.read_page_header(ptr)? {
+                PageHeader::NextPage(next) => {
+                    if next + self.total_page_size() > file_size {
+                        return Err(self.past_end(next, file_size));
+                    }
+                    if pages.len() as u64 >= max_pages {
+                        return Err(Error::CorruptedFile);
+                    }
+                    if let Some(max_chain_size) = self.config.max_chain_size {
+                        if pages.len() as u64 * self.config.page_size as u64 >= max_chain_size {
+                            return Err(Error::LimitExceeded);
+                        }
+                    }
+                    pages.push(next);
+                    ptr = next;
+                },
+                PageHeader::FinalPage(size) => {
+                    if size > self.config.page_size as u64 {
+                        return Err(Error::CorruptedFile);
+                    }
+                    return Ok((pages, size));
+                },
+                PageHeader::DeletedPage(_) => return Err(Error::CorruptedFile)
+            }
+        }
+    }
+
+    /// Split a list of pages into runs of physically adjacent pages.
+    fn page_runs(&self, pages: &[u64]) -> Vec<std::ops::Range<usize>> {
+        let mut runs = Vec::new();
+        let mut start = 0;
+        for i in 1..=pages.len() {
+            if i == pages.len() || pages[i] != pages[i - 1] + self.total_page_size() {
+                runs.push(start..i);
+                start = i;
+            }
+        }
+        runs
+    }
+
+    /// Split a chain's data buffer into the payloads of its pages.
+    #[cfg(feature = "parallel")]
+    fn split_payloads<'a>(&self, mut data: &'a mut [u8], pages: usize) -> Vec<&'a mut [u8]> {
+        let mut payloads = Vec::with_capacity(pages);
+        for _ in 1..pages {
+            let (payload, rest) = std::mem::take(&mut data).split_at_mut(self.config.page_size);
+            payloads.push(payload);
+            data = rest;
+        }
+        payloads.push(data);
+        payloads
+    }
+
+    /// Get the pointers of every allocated page in the file, along with its header.
+    fn allocated_pages(&mut self) -> Result<Vec<(u64, PageHeader)>, Error> {
+        let quarantined = self.quarantined_pages()?;
+        let file_size = self.file_size()?;
+        let mut pages = Vec::new();
+        let mut ptr = self.header_size();
+        while ptr + self.total_page_size() <= file_size {
+            if quarantined.contains(&ptr) {
+                ptr += self.total_page_size();
+                continue;
+            }
+            let header = self.read_page_header(ptr)?;
+            if !matches!(header, PageHeader::DeletedPage(_)) {
+                pages.push((ptr, header));
+            }
+            ptr += self.total_page_size();
+        }
+        Ok(pages)
+    }
+
+    /// Get the pointers to the first page of every chain in the file, in address order.
+    fn chain_heads(&mut self) -> Result<Vec<u64>, Error> {
+        let pages = self.allocated_pages()?;
+        let continuations = pages.iter().filter_map(|(_, header)| match header {
+            PageHeader::NextPage(next) => Some(*next),
+            _ => None
+        }).collect::<std::collections::HashSet<_>>();
+        Ok(pages.into_iter().map(|(ptr, _)| ptr).filter(|ptr| !continuations.contains(ptr)).collect())
+    }
+
+    /// The number of pages a chain needs to hold `len` bytes.
+    /// Takes the length as a `u64` so that chains longer than 4 GiB are counted correctly where `usize` is 32 bits.
+    fn pages_needed(&self, len: u64) -> u64 {
+        len.div_ceil(self.config.page_size as u64).max(1)
+    }
+
+    fn read_u64(&mut self, ptr: u64) -> Result<u64, Error> {
+        self.file.seek(SeekFrom::Start(ptr)).map_err(Error::IO)?;
+        let mut bytes = [0; BYTES_IN_U64 as usize];
+        // The bytes may be split across segments, so keep reading until the end of the file
+        let mut filled = 0;
+        while filled < bytes.len() {
+            match self.file.read(&mut bytes[filled..]).map_err(Error::IO)? {
+                0 => break,
+                n => filled += n
+            }
+        }
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn read_page_header(&mut self, ptr: u64) -> Result<PageHeader, Error> {
+        PageHeader::from_u64(self.read_u64(ptr)?)
+    }
+
+    fn write_u64(&mut self, ptr: u64, val: u64) -> Result<(), Error> {
+        self.check_writable()?;
+        if self.reserved_chains.is_some() && (self.internal_chain_fields().contains(&ptr) || self.table_fields().contains(&ptr) || [self.version_table_ptr(), self.id_counters_ptr(), self.named_roots_ptr()].contains(&ptr)) {
+            self.reserved_chains = None;
+        }
+        self.file.seek(SeekFrom::Start(ptr)).map_err(Error::IO)?;
+        self.file.write_all(&val.to_le_bytes()).map_err(Error::IO)?;
+        Ok(())
+    }
+
+    fn write_page_header(&mut self, ptr: u64, header: PageHeader) -> Result<(), Error> {
+        self.write_u64(ptr, header.to_u64())
+    }
+
+    /// Write the header of the first page of a chain
+    fn write_head_page_header(&mut self, ptr: u64, header: PageHeader) -> Result<(), Error> {
+        self.write_u64(ptr, header.to_head_u64())
+    }
+
+    fn is_head_page(&mut self, ptr: u64) -> Result<bool, Error> {
+        Ok(self.read_u64(ptr)? & PageHeader::HEAD_FLAG != 0)
+    }
+
+    fn magic_bytes_ptr(&self) -> u64 {
+        0
+    }
+
+    fn format_version_ptr(&self) -> u64 {
+        self.header_field_ptr(0)
+    }
+
+    fn first_free_page_ptr(&self) -> u64 {
+        self.header_field_ptr(1)
+    }
+
+    /// The pointer to the pointer to the chain holding the header fields of files upgraded from format version 0
+    fn header_chain_ptr(&self) -> u64 {
+        self.format_version_ptr() + BYTES_IN_U64
+    }
+
+    /// The pointer to the header field at `idx` in `format::HEADER_FIELDS`.
+    /// Files upgraded from format version 0 keep the fields after the format version in the pages of a chain instead,
+    /// with as many fields in each page as fit. See `format::HEADER_CHAIN_FLAG`.
+    fn header_field_ptr(&self, idx: usize) -> u64 {
+        if idx == 0 || self.header_pages.is_empty() {
+            return self.magic_bytes_ptr() + self.magic_bytes.len() as u64 + idx as u64 * BYTES_IN_U64;
+        }
+        let fields_per_page = self.config.page_size / BYTES_IN_U64 as usize;
+        let (page, slot) = ((idx - 1) / fields_per_page, (idx - 1) % fields_per_page);
+        self.header_pages[page] + BYTES_IN_U64 + slot as u64 * BYTES_IN_U64
+    }
+
+    /// Whether `ptr` is a field of the file header, which is in the header chain for files upgraded from format version 0
+    fn is_header_field(&self, ptr: u64) -> bool {
+        ptr < self.header_size() || self.header_pages.iter().any(|page| ptr > *page && ptr < page + self.total_page_size())
+    }
+
+    fn header_size(&self) -> u64 {
+        let fields = if self.header_pages.is_empty() {
+            format::header_fields(self.format_version)
+        } else {
+            format::HEADER_CHAIN_FIELDS
+        };
+        self.magic_bytes_ptr() + self.magic_bytes.len() as u64 + fields.len() as u64 * BYTES_IN_U64
+    }
+
+    fn total_page_size(&self) -> u64 {
+        BYTES_IN_U64 + self.config.page_size as u64
+    }
+
+    fn root_page_ptr(&self) -> u64 {
+        self.header_field_ptr(2)
+    }
+
+    fn undo_chain_ptr(&self) -> u64 {
+        self.header_field_ptr(3)
+    }
+
+    fn redo_chain_ptr(&self) -> u64 {
+        self.header_field_ptr(4)
+    }
+
+    fn refcount_table_ptr(&self) -> u64 {
+        self.header_field_ptr(5)
+    }
+
+    fn change_counter_ptr(&self) -> u64 {
+        self.header_field_ptr(6)
+    }
+
+    fn dedup_table_ptr(&self) -> u64 {
+        self.header_field_ptr(7)
+    }
+
+    fn dictionaries_ptr(&self) -> u64 {
+        self.header_field_ptr(8)
+    }
+
+    fn version_table_ptr(&self) -> u64 {
+        self.header_field_ptr(9)
+    }
+
+    fn cold_table_ptr(&self) -> u64 {
+        self.header_field_ptr(10)
+    }
+
+    fn generation_table_ptr(&self) -> u64 {
+        self.header_field_ptr(11)
+    }
+
+    fn partition_table_ptr(&self) -> u64 {
+        self.header_field_ptr(12)
+    }
+
+    fn quarantine_table_ptr(&self) -> u64 {
+        self.header_field_ptr(13)
+    }
+
+    fn tag_table_ptr(&self) -> u64 {
+        self.header_field_ptr(14)
+    }
+
+    fn journal_ptr(&self) -> u64 {
+        self.header_field_ptr(15)
+    }
+
+    fn prepared_transaction_ptr(&self) -> u64 {
+        self.header_field_ptr(16)
+    }
+
+    fn schema_version_ptr(&self) -> u64 {
+        self.header_field_ptr(17)
+    }
+
+    fn pin_table_ptr(&self) -> u64 {
+        self.header_field_ptr(18)
+    }
+
+    /// Only part of the header from format version 4 on
+    fn id_counters_ptr(&self) -> u64 {
+        self.header_field_ptr(19)
+    }
+
+    /// Only part of the header from format version 5 on
+    fn named_roots_ptr(&self) -> u64 {
+        self.header_field_ptr(20)
+    }
+
+    fn first_free_page(&mut self) -> Result<u64, Error> {
+        self.read_u64(self.first_free_page_ptr())
     }
 
     fn root_page(&mut self) -> Result<u64, Error> {
-        self.read_u64(self.root_page_ptr())
+        if let Some(root_page) = self.root_page_cache {
+            return Ok(root_page);
+        }
+        let root_page = self.read_u64(self.root_page_ptr())?;
+        self.root_page_cache = Some(root_page);
+        Ok(root_page)
+    }
+
+    /// The root chain, allocating it first if `Config::lazy_root` kept it from being created so far
+    fn root_page_or_alloc(&mut self) -> Result<u64, Error> {
+        self.check_for_external_changes()?;
+        let root_page = self.root_page()?;
+        if root_page != 0 {
+            return Ok(root_page);
+        }
+        let root_page = self.alloc()?;
+        self.write_u64(self.root_page_ptr(), root_page)?;
+        self.root_page_cache = Some(root_page);
+        Ok(root_page)
+    }
+
+    /// The root chain along with every chain belonging to a partition, which are only freed through their partition,
+    /// the named roots, and the chains allocated or deleted by a prepared transaction, which are kept until it is resolved
+    fn root_chains(&mut self) -> Result<Vec<u64>, Error> {
+        let mut roots = match self.root_page()? {
+            0 => Vec::new(),
+            root_page => vec![root_page]
+        };
+        roots.extend(self.partition_user_chains()?);
+        roots.extend(self.named_root_chains()?);
+        roots.extend(self.prepared_chains()?);
+        Ok(roots)
+    }
+
+    /// The chains verter uses internally to store its own data structures.
+    /// These are always considered reachable, and never contain user pointers.
+    fn internal_chains(&mut self) -> Result<Vec<u64>, Error> {
+        let mut chains = Vec::new();
+        for field in self.internal_chain_fields() {
+            let chain = self.read_u64(field)?;
+            if chain != 0 {
+                chains.push(chain);
+            }
+        }
+
+        if let Some(header_chain) = self.header_pages.first() {
+            chains.push(*header_chain);
+        }
+
+        let id_counters = self.id_counter_chain_if_any()?;
+        if id_counters != 0 {
+            chains.push(id_counters);
+        }
+
+        let named_roots = self.named_root_table_if_any()?;
+        if named_roots != 0 {
+            chains.push(named_roots);
+        }
+
+        chains.extend(self.partition_chains()?);
+        chains.extend(self.tag_chains()?);
+
+        // Old versions of chains are kept alive until the next checkpoint
+        chains.extend(self.retained_versions()?);
+
+        // Compression dictionaries are kept alive as long as the file exists, since old chains may still need them
+        let dictionaries = self.read_u64(self.dictionaries_ptr())?;
+        if dictionaries != 0 {
+            chains.push(dictionaries);
+            chains.extend(decode_u64s(&self.read(dictionaries)?)?);
+        }
+
+        Ok(chains)
+    }
+
+    /// The header fields pointing straight at one of verter's internal chains
+    fn internal_chain_fields(&self) -> [u64; 10] {
+        [self.undo_chain_ptr(), self.redo_chain_ptr(), self.refcount_table_ptr(), self.dedup_table_ptr(), self.cold_table_ptr(), self.generation_table_ptr(), self.quarantine_table_ptr(), self.journal_ptr(), self.prepared_transaction_ptr(), self.pin_table_ptr()]
+    }
+
+    /// The header fields pointing at the tables that list more of verter's internal chains,
+    /// other than the version table, which keeps `File::reserved_chains` up to date itself as it grows
+    fn table_fields(&self) -> [u64; 3] {
+        [self.partition_table_ptr(), self.tag_table_ptr(), self.dictionaries_ptr()]
+    }
+
+    /// Return `Error::ReservedChain` if a chain is one of verter's internal chains,
+    /// so that a stale or mistaken pointer can't overwrite the file's own data structures
+    fn check_not_reserved(&mut self, ptr: u64) -> Result<(), Error> {
+        let reserved = match self.reserved_chains.take() {
+            Some(reserved) => reserved,
+            None => self.internal_chains()?.into_iter().collect()
+        };
+        let is_reserved = reserved.contains(&ptr);
+        self.reserved_chains = Some(reserved);
+        if is_reserved {
+            return Err(Error::ReservedChain);
+        }
+        Ok(())
+    }
+
+    /// Forget the reserved chains if a write to `ptr` may change them, which is the case for the tables listing internal chains
+    fn note_reserved_write(&mut self, ptr: u64) -> Result<(), Error> {
+        if self.reserved_chains.as_ref().is_some_and(|reserved| reserved.contains(&ptr)) {
+            for field in self.table_fields() {
+                if self.read_u64(field)? == ptr {
+                    self.reserved_chains = None;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Return `Error::ProtectedChain` if a chain is the root chain, a partition's root chain or a named root
+    fn check_not_protected(&mut self, ptr: u64) -> Result<(), Error> {
+        if ptr == self.root_page()? || self.partition_roots()?.contains(&ptr) || self.named_root_chains()?.contains(&ptr) {
+            return Err(Error::ProtectedChain);
+        }
+        Ok(())
     }
 
     fn file_size(&self) -> Result<u64, Error> {
-        self.file.metadata().map(|metadata| metadata.len()).map_err(Error::IO)
+        self.file.len().map_err(Error::IO)
     }
 
     fn create_header(&mut self) -> Result<(), Error> {
         // Magic Bytes
         self.file.seek(SeekFrom::Start(self.magic_bytes_ptr())).map_err(Error::IO)?;
-        self.file.write(&self.config.magic_bytes).map_err(Error::IO)?;
+        self.file.write_all(self.config.magic_bytes).map_err(Error::IO)?;
+
+        // Format Version
+        self.write_u64(self.format_version_ptr(), self.format_version_field())?;
 
         // First Free Page
         self.write_u64(self.first_free_page_ptr(), 0)?;
@@ -304,25 +1691,338 @@ impl File {
         // Root Page
         self.write_u64(self.root_page_ptr(), 0)?;
 
-        // Initialize Root Page Chain
-        let first_root_page = self.alloc()?;
-        self.write_u64(self.root_page_ptr(), first_root_page)?;
+        // Undo/Redo Chains, created lazily
+        self.write_u64(self.undo_chain_ptr(), 0)?;
+        self.write_u64(self.redo_chain_ptr(), 0)?;
+
+        // Refcount Table, created lazily
+        self.write_u64(self.refcount_table_ptr(), 0)?;
+
+        // Change Counter
+        self.write_u64(self.change_counter_ptr(), 0)?;
+
+        // Dedup Table, created lazily
+        self.write_u64(self.dedup_table_ptr(), 0)?;
+
+        // Compression Dictionary List, created lazily
+        self.write_u64(self.dictionaries_ptr(), 0)?;
+
+        // Version Table, created lazily
+        self.write_u64(self.version_table_ptr(), 0)?;
+
+        // Cold Tier Table, created lazily
+        self.write_u64(self.cold_table_ptr(), 0)?;
+
+        // Generation Table, created lazily
+        self.write_u64(self.generation_table_ptr(), 0)?;
+
+        // Partition Table, created lazily
+        self.write_u64(self.partition_table_ptr(), 0)?;
+
+        // Quarantine Table, created lazily
+        self.write_u64(self.quarantine_table_ptr(), 0)?;
+
+        // Tag Table, created lazily
+        self.write_u64(self.tag_table_ptr(), 0)?;
+
+        // Journal, created lazily
+        self.write_u64(self.journal_ptr(), 0)?;
+
+        // Prepared Transaction, only present between Transaction::prepare and resolving it
+        self.write_u64(self.prepared_transaction_ptr(), 0)?;
+
+        // Schema Version
+        self.write_u64(self.schema_version_ptr(), self.config.schema_version.unwrap_or(0) as u64)?;
+
+        // Pin Table, created lazily
+        self.write_u64(self.pin_table_ptr(), 0)?;
+
+        // ID Counters, created lazily
+        self.write_u64(self.id_counters_ptr(), 0)?;
+
+        // Named Roots, created lazily
+        self.write_u64(self.named_roots_ptr(), 0)?;
+
+        // Initialize Root Page Chain, unless it is created lazily
+        if !self.config.lazy_root {
+            let first_root_page = self.alloc()?;
+            self.write_u64(self.root_page_ptr(), first_root_page)?;
+            self.root_page_cache = Some(first_root_page);
+        }
 
         Ok(())
     }
 
     fn check_if_file_valid(&mut self) -> Result<(), Error> {
         self.file.seek(SeekFrom::Start(0)).map_err(Error::IO)?;
-        let mut magic_bytes = vec![0; self.config.magic_bytes.len()];
-        let bytes_read = self.file.read(&mut magic_bytes).map_err(Error::IO)?;
-        if bytes_read < self.config.magic_bytes.len() || self.config.magic_bytes != magic_bytes {
-            return Err(Error::InvalidFile)
+        let recognized = std::iter::once(self.config.magic_bytes).chain(self.config.legacy_magic_bytes.iter().copied()).collect::<Vec<_>>();
+        let longest = recognized.iter().map(|magic_bytes| magic_bytes.len()).max().unwrap_or(0);
+        let mut file_start = Vec::new();
+        (&mut self.file).take(longest as u64).read_to_end(&mut file_start).map_err(Error::IO)?;
+        let matching = recognized.into_iter()
+            .filter(|magic_bytes| file_start.starts_with(magic_bytes))
+            .collect::<Vec<_>>();
+
+        // When several magic bytes match, such as one being the start of another, the length stored in the file decides
+        let mut stored_magic_len = None;
+        for magic_bytes in &matching {
+            let field = self.read_u64(magic_bytes.len() as u64)?;
+            if field & VERSION_MASK >= 3 && format::stored_magic_len(field) == magic_bytes.len() as u64 {
+                stored_magic_len = Some(magic_bytes.len());
+            }
+        }
+        self.magic_bytes = match stored_magic_len {
+            Some(len) => matching.into_iter().find(|magic_bytes| magic_bytes.len() == len).unwrap(),
+            None => matching.into_iter().next().ok_or(Error::InvalidFile)?
+        };
+
+        let field = self.read_u64(self.format_version_ptr())?;
+        let stores_magic_len = format::stored_magic_len(field) == self.magic_bytes.len() as u64;
+        if !(stores_magic_len && (3..=FORMAT_VERSION).contains(&(field & VERSION_MASK))) && self.is_unversioned(field)? {
+            return self.upgrade_from_v0();
+        }
+        // Older files keep the header they were written with, since their pages can't be moved to make room for new fields
+        self.format_version = (field & VERSION_MASK).clamp(3, FORMAT_VERSION);
+        match field & VERSION_MASK {
+            3..=FORMAT_VERSION if stores_magic_len => {
+                if field & HEADER_CHAIN_FLAG != 0 {
+                    self.load_header_chain()?;
+                }
+                if field & PAGE_MAP_FLAG != 0 {
+                    self.map_pages();
+                }
+                self.check_geometry()
+            },
+            3..=FORMAT_VERSION => Err(Error::InvalidFile),
+            1 => {
+                self.check_geometry()?;
+                self.upgrade_from_v1()
+            },
+            2 => {
+                self.check_geometry()?;
+                self.upgrade_from_v2()
+            },
+            _ => Err(Error::UnsupportedVersion)
+        }
+    }
+
+    /// How many pages at the start of the file are checked to tell a truncated file from one with another page size
+    const GEOMETRY_PAGES: u64 = 64;
+
+    /// The page size isn't stored in the file, so check that the file's layout fits `Config::page_size`,
+    /// failing with `Error::GeometryMismatch` if the file is laid out for another page size
+    fn check_geometry(&mut self) -> Result<(), Error> {
+        let header_size = self.header_size();
+        let file_size = self.file_size()?;
+        if file_size < header_size {
+            return Ok(());
+        }
+        let total_page_size = self.total_page_size();
+        let on_grid = |ptr: u64| ptr >= header_size && (ptr - header_size).is_multiple_of(total_page_size);
+        let is_page = |ptr: u64| on_grid(ptr) && ptr < file_size;
+
+        let aligned = (file_size - header_size).is_multiple_of(total_page_size);
+        let mut valid = aligned;
+        let mut links = Vec::new();
+        for field in [self.first_free_page_ptr(), self.root_page_ptr()] {
+            let ptr = self.read_u64(field)?;
+            valid &= ptr == 0 || is_page(ptr);
+            links.push(ptr);
+        }
+        let root_page = self.read_u64(self.root_page_ptr())?;
+        if valid && root_page != 0 {
+            valid = match self.read_page_header(root_page) {
+                Ok(PageHeader::NextPage(next)) => {
+                    links.push(next);
+                    is_page(next)
+                },
+                Ok(PageHeader::FinalPage(size)) => size <= self.config.page_size as u64,
+                Ok(PageHeader::DeletedPage(_)) | Err(_) => false
+            };
+        }
+        if valid {
+            return Ok(());
+        }
+
+        // If the pages at the start of the file are laid out for this page size, links to pages past the end of the file,
+        // or a page cut off partway, mean the rest of the file is missing
+        let mut fits = links.iter().all(|link| *link == 0 || on_grid(*link));
+        let mut checked = 0;
+        let mut ptr = header_size;
+        while fits && ptr + BYTES_IN_U64 <= file_size && checked < Self::GEOMETRY_PAGES {
+            fits = match self.read_page_header(ptr) {
+                Ok(PageHeader::NextPage(next)) => on_grid(next),
+                Ok(PageHeader::FinalPage(size)) => size <= self.config.page_size as u64,
+                Ok(PageHeader::DeletedPage(next)) => next == 0 || on_grid(next),
+                Err(_) => false
+            };
+            checked += 1;
+            ptr += total_page_size;
+        }
+        let mut end = header_size + (file_size - header_size).div_ceil(total_page_size) * total_page_size;
+        for link in links.into_iter().filter(|link| on_grid(*link)) {
+            end = end.max(link + total_page_size);
+        }
+
+        // A short file can fit another page size too, so trust whichever page size explains more of the page headers
+        let page_size = self.with_stored_bytes(|file| Ok(sniff(&mut file.file)))?.and_then(|info| info.page_size).filter(|page_size| *page_size != self.config.page_size);
+        let sniffed_pages = page_size.map_or(0, |page_size| ((file_size - header_size) / (page_size as u64 + BYTES_IN_U64)).min(Self::GEOMETRY_PAGES));
+        if fits && end > file_size && checked > sniffed_pages {
+            return Err(Error::TruncatedFile { missing_bytes: end - file_size });
+        }
+        // A file that fits no other page size either is left for the checks that find corruption
+        if page_size.is_some() || !aligned {
+            return Err(Error::GeometryMismatch { page_size });
         }
         Ok(())
     }
 
+    /// The error for a link to a page that doesn't fit in the file: `Error::TruncatedFile` if the page would fit in a longer file,
+    /// such as one whose tail hasn't been synced yet, or `Error::CorruptedFile` if the link doesn't point at a page at all
+    fn past_end(&self, ptr: u64, file_size: u64) -> Error {
+        if ptr < self.header_size() || !(ptr - self.header_size()).is_multiple_of(self.total_page_size()) {
+            return Error::CorruptedFile;
+        }
+        Error::TruncatedFile { missing_bytes: ptr + self.total_page_size() - file_size }
+    }
+
+    /// The value of the format version field, which also holds the length of the magic bytes,
+    /// whether the header fields are stored in a chain and whether the page headers are stored in page maps
+    fn format_version_field(&self) -> u64 {
+        let header_chain = if self.header_pages.is_empty() { 0 } else { HEADER_CHAIN_FLAG };
+        let page_maps = if self.has_page_maps() { PAGE_MAP_FLAG } else { 0 };
+        self.format_version | (self.magic_bytes.len() as u64) << MAGIC_LEN_SHIFT | header_chain | page_maps
+    }
+
+    /// Whether the file is from before the format was versioned, when the magic bytes were directly followed by the pointers
+    /// to the first free page and the root page, given the first of them.
+    /// The root chain of those files was always allocated along with the file, so it starts at the first page.
+    fn is_unversioned(&mut self, first_free_page: u64) -> Result<bool, Error> {
+        let header_size = self.format_version_ptr() + format::UNVERSIONED_FIELDS.len() as u64 * BYTES_IN_U64;
+        let root_page = self.read_u64(self.header_chain_ptr())?;
+        Ok(root_page == header_size && (first_free_page == 0 || first_free_page >= header_size))
+    }
+
+    /// Files from before the format was versioned have a header of only the pointers to the first free page and the root page,
+    /// and keep the page type in the top two bits of page headers.
+    /// Their page headers are rewritten with the current page types and chain head flags. Their pages can't be moved to make room
+    /// for the current header without breaking the pointers stored in the application's data, so the header fields are moved
+    /// into a chain at the end of the file instead. See `format::HEADER_CHAIN_FLAG`.
+    fn upgrade_from_v0(&mut self) -> Result<(), Error> {
+        let header_size = self.header_chain_ptr() + BYTES_IN_U64;
+        let total_page_size = self.total_page_size();
+        let file_size = self.file_size()?;
+        let fields_per_page = self.config.page_size / BYTES_IN_U64 as usize;
+        if fields_per_page == 0 || file_size < header_size || !(file_size - header_size).is_multiple_of(total_page_size) {
+            return Err(Error::GeometryMismatch { page_size: None });
+        }
+        let first_free_page = self.read_u64(self.format_version_ptr())?;
+        let root_page = self.read_u64(self.header_chain_ptr())?;
+
+        // Every page header is checked before anything is written, so a file with another page size is left as it is
+        let is_page = |ptr: u64| ptr >= header_size && (ptr - header_size).is_multiple_of(total_page_size) && ptr < file_size;
+        let mut pages = Vec::new();
+        for ptr in (header_size..file_size).step_by(total_page_size as usize) {
+            let header = self.read_u64(ptr)?;
+            let value = header & ((1 << format::UNVERSIONED_PAGE_TYPE_SHIFT) - 1);
+            pages.push((ptr, match header >> format::UNVERSIONED_PAGE_TYPE_SHIFT {
+                0 if is_page(value) => PageHeader::NextPage(value),
+                1 if value <= self.config.page_size as u64 => PageHeader::FinalPage(value),
+                2 if value == 0 || is_page(value) => PageHeader::DeletedPage(value),
+                _ => return Err(Error::GeometryMismatch { page_size: None })
+            }));
+        }
+        let continuations = pages.iter().filter_map(|(_, header)| match header {
+            PageHeader::NextPage(next) => Some(*next),
+            _ => None
+        }).collect::<std::collections::HashSet<_>>();
+
+        let chain_len = (format::HEADER_FIELDS.len() - 1) as u64 * BYTES_IN_U64;
+        let bytes_per_page = fields_per_page as u64 * BYTES_IN_U64;
+        let header_pages = (0..chain_len.div_ceil(bytes_per_page)).map(|idx| file_size + idx * total_page_size).collect::<Vec<_>>();
+        self.check_file_size_limit(file_size + header_pages.len() as u64 * total_page_size)?;
+        self.file.set_len(file_size + header_pages.len() as u64 * total_page_size).map_err(Error::IO)?;
+        for (idx, page) in header_pages.iter().enumerate() {
+            let header = match header_pages.get(idx + 1) {
+                Some(next) => PageHeader::NextPage(*next),
+                None => PageHeader::FinalPage(chain_len - idx as u64 * bytes_per_page)
+            };
+            if idx == 0 {
+                self.write_head_page_header(*page, header)?;
+            } else {
+                self.write_page_header(*page, header)?;
+            }
+        }
+        self.header_pages = header_pages;
+        self.format_version = FORMAT_VERSION;
+        self.write_u64(self.first_free_page_ptr(), first_free_page)?;
+        self.write_u64(self.root_page_ptr(), root_page)?;
+        self.file.sync_data().map_err(Error::IO)?;
+
+        for (ptr, header) in pages {
+            if matches!(header, PageHeader::DeletedPage(_)) || continuations.contains(&ptr) {
+                self.write_page_header(ptr, header)?;
+            } else {
+                self.write_head_page_header(ptr, header)?;
+            }
+        }
+        self.file.sync_data().map_err(Error::IO)?;
+
+        // The file only reads as upgraded once everything else is in place
+        self.write_u64(self.header_chain_ptr(), self.header_pages[0])?;
+        self.write_u64(self.format_version_ptr(), self.format_version_field())
+    }
+
+    /// Find the pages of the chain holding the header fields of a file upgraded from format version 0
+    fn load_header_chain(&mut self) -> Result<(), Error> {
+        let header_size = self.header_chain_ptr() + BYTES_IN_U64;
+        let total_page_size = self.total_page_size();
+        let file_size = self.file_size()?;
+        let fields_per_page = self.config.page_size / BYTES_IN_U64 as usize;
+        if fields_per_page == 0 {
+            return Err(Error::GeometryMismatch { page_size: None });
+        }
+        let page_count = (format::header_fields(self.format_version).len() - 1).div_ceil(fields_per_page);
+        let mut pages = Vec::with_capacity(page_count);
+        let mut page = self.read_u64(self.header_chain_ptr())?;
+        for idx in 0..page_count {
+            if page < header_size || !(page - header_size).is_multiple_of(total_page_size) || page + total_page_size > file_size {
+                return Err(Error::GeometryMismatch { page_size: None });
+            }
+            pages.push(page);
+            page = match self.read_page_header(page)? {
+                PageHeader::NextPage(next) if idx + 1 < page_count => next,
+                PageHeader::FinalPage(_) if idx + 1 == page_count => 0,
+                _ => return Err(Error::CorruptedFile)
+            };
+        }
+        self.header_pages = pages;
+        Ok(())
+    }
+
+    /// Version 1 files have no chain head flags, so mark the first page of every chain
+    fn upgrade_from_v1(&mut self) -> Result<(), Error> {
+        for head in self.chain_heads()? {
+            let header = self.read_page_header(head)?;
+            self.write_head_page_header(head, header)?;
+        }
+        self.upgrade_from_v2()
+    }
+
+    /// Version 2 files don't store the length of their magic bytes
+    fn upgrade_from_v2(&mut self) -> Result<(), Error> {
+        self.write_u64(self.format_version_ptr(), self.format_version_field())
+    }
+
+    fn check_file_size_limit(&self, size: u64) -> Result<(), Error> {
+        match self.config.max_file_size {
+            Some(max_file_size) if size > max_file_size => Err(Error::LimitExceeded),
+            _ => Ok(())
+        }
+    }
+
     fn check_if_pointer_valid(&mut self, ptr: u64) -> Result<(), Error> {
-        if ptr < self.header_size() || (ptr - self.header_size()) % self.total_page_size() != 0 {
+        if ptr < self.header_size() || !(ptr - self.header_size()).is_multiple_of(self.total_page_size()) {
             return Err(Error::InvalidPointer);
         }
         if ptr >= self.file_size()? {
@@ -332,6 +2032,10 @@ impl File {
         if matches!(self.read_page_header(ptr)?, PageHeader::DeletedPage(_)) {
             return Err(Error::DeletedPointer);
         }
+        if !self.is_head_page(ptr)? {
+            // Points into the middle of a chain
+            return Err(Error::InvalidPointer);
+        }
 
         Ok(())
     }
@@ -366,7 +2070,7 @@ fn deletion() {
 fn truncation() {
     let mut file = File::open("truncation.verter", Config::default()).unwrap();
     file.write_root(&vec![0xAE; 2000]).unwrap();
-    file.write_root(&vec![0xBA; 200]).unwrap();
+    file.write_root(&[0xBA; 200]).unwrap();
     drop(file);
 
     let file_size = std::fs::metadata("truncation.verter").unwrap().len();
@@ -401,6 +2105,59 @@ fn magic_bytes() {
     std::fs::remove_file("magic_bytes.verter").unwrap();
 }
 
+#[test]
+fn legacy_magic_bytes() {
+    let legacy = Config {
+        magic_bytes: b"OLDAPP",
+        ..Config::default()
+    };
+    let mut file = File::open("legacy_magic_bytes.verter", legacy).unwrap();
+    file.write_root(b"old data").unwrap();
+    drop(file);
+
+    let config = Config {
+        magic_bytes: b"NEWAPP",
+        legacy_magic_bytes: &[b"OLDAPP"],
+        rewrite_legacy_magic: true,
+        ..Config::default()
+    };
+    let mut file = File::open("legacy_magic_bytes.verter", config).unwrap();
+    assert_eq!(file.read_root().unwrap(), b"old data");
+    assert!(std::fs::read("legacy_magic_bytes.verter").unwrap().starts_with(b"OLDAPP"));
+    file.write_root(b"new data").unwrap();
+    drop(file);
+
+    assert!(std::fs::read("legacy_magic_bytes.verter").unwrap().starts_with(b"NEWAPP"));
+    let mut file = File::open("legacy_magic_bytes.verter", Config { legacy_magic_bytes: &[], ..config }).unwrap();
+    assert_eq!(file.read_root().unwrap(), b"new data");
+
+    std::fs::remove_file("legacy_magic_bytes.verter").unwrap();
+}
+
+#[test]
+fn magic_lengths() {
+    for magic_bytes in [&b""[..], &[b'M'; format::MAX_MAGIC_LEN + 1]] {
+        match File::open_backend(Box::new(std::io::Cursor::new(Vec::new())), Config { magic_bytes, ..Config::default() }) {
+            Err(Error::InvalidConfig) => {},
+            Ok(_) | Err(_) => panic!("should error with invalid config")
+        }
+    }
+
+    // The old magic bytes followed by the format version look like the new magic bytes, but the stored length tells them apart
+    let mut file = File::open("magic_lengths.verter", Config { magic_bytes: b"AB", ..Config::default() }).unwrap();
+    file.write_root(b"old data").unwrap();
+    drop(file);
+    let bytes = std::fs::read("magic_lengths.verter").unwrap();
+    assert_eq!(bytes[2..10], (FORMAT_VERSION | 2 << format::MAGIC_LEN_SHIFT).to_le_bytes());
+    assert!(bytes.starts_with(b"AB\x06"));
+    let config = Config { magic_bytes: b"AB\x06", legacy_magic_bytes: &[b"AB"], ..Config::default() };
+    let mut file = File::open("magic_lengths.verter", config).unwrap();
+    assert_eq!(file.read_root().unwrap(), b"old data");
+    assert_eq!(sniff_path("magic_lengths.verter").unwrap().magic, b"AB");
+
+    std::fs::remove_file("magic_lengths.verter").unwrap();
+}
+
 #[test]
 fn invalid_pointer() {
     let mut file = File::open("invalid_pointer.verter", Config::default()).unwrap();
@@ -425,6 +2182,116 @@ fn invalid_pointer() {
     std::fs::remove_file("invalid_pointer.verter").unwrap();
 }
 
+#[test]
+fn no_wipe() {
+    let config = Config {
+        wipe_freed_bytes: false,
+        ..Config::default()
+    };
+    let mut file = File::open("no_wipe.verter", config).unwrap();
+    let alloc = file.alloc().unwrap();
+    file.write(alloc, &[0xAB; 300]).unwrap();
+    file.write(alloc, &[0xCD; 50]).unwrap();
+    assert_eq!(file.read(alloc).unwrap(), vec![0xCD; 50]);
+
+    // The stale bytes are left behind in the file
+    let bytes = std::fs::read("no_wipe.verter").unwrap();
+    let payload = (alloc + BYTES_IN_U64) as usize;
+    assert_eq!(bytes[payload + 50..payload + config.page_size], vec![0xAB; config.page_size - 50]);
+
+    std::fs::remove_file("no_wipe.verter").unwrap();
+}
+
+#[test]
+fn zero_new_pages() {
+    let config = Config {
+        zero_new_pages: true,
+        ..Config::default()
+    };
+    let mut file = File::open("zero_new_pages.verter", config).unwrap();
+    let old = file.alloc_with(&[0xAB; 300]).unwrap();
+    file.delete(old).unwrap();
+
+    // The freed pages are wiped with garbage, but zeroed again when a chain reuses them
+    let alloc = file.alloc().unwrap();
+    file.patch(alloc, &[(0, &[0xCD; 10]), (200, &[0xCD; 10])]).unwrap();
+    let bytes = std::fs::read("zero_new_pages.verter").unwrap();
+    for page in file.chain_pages(alloc).unwrap() {
+        let payload = &bytes[(page + BYTES_IN_U64) as usize..][..config.page_size];
+        assert!(payload.iter().all(|byte| *byte == 0 || *byte == 0xCD));
+    }
+
+    // Shrinking a chain zeroes the rest of its final page
+    file.write(alloc, &[0xEF; 50]).unwrap();
+    let bytes = std::fs::read("zero_new_pages.verter").unwrap();
+    let payload = (alloc + BYTES_IN_U64) as usize;
+    assert_eq!(bytes[payload + 50..payload + config.page_size], vec![0; config.page_size - 50]);
+
+    std::fs::remove_file("zero_new_pages.verter").unwrap();
+}
+
+#[test]
+fn batch_write() {
+    let mut file = File::open("batch_write.verter", Config::default()).unwrap();
+    let ptrs = (0..10).map(|_| file.alloc().unwrap()).collect::<Vec<_>>();
+    let data = (0..10).map(|i| vec![i as u8; i * 50]).collect::<Vec<_>>();
+    let writes = ptrs.iter().rev().copied().zip(data.iter().rev().map(Vec::as_slice)).collect::<Vec<_>>();
+    file.write_batch(&writes).unwrap();
+    drop(file);
+
+    let mut file = File::open("batch_write.verter", Config::default()).unwrap();
+    for (ptr, data) in ptrs.iter().zip(data.iter()) {
+        assert_eq!(&file.read(*ptr).unwrap(), data);
+    }
+
+    std::fs::remove_file("batch_write.verter").unwrap();
+}
+
+#[test]
+fn resource_limits() {
+    let mut file = File::open("resource_limits.verter", Config::default()).unwrap();
+    let alloc = file.alloc().unwrap();
+    file.write(alloc, &[0xAB; 1000]).unwrap();
+    drop(file);
+
+    let mut file = File::open("resource_limits.verter", Config {
+        max_chain_size: Some(500),
+        ..Config::default()
+    }).unwrap();
+    match file.read(alloc) {
+        Err(Error::LimitExceeded) => {},
+        Ok(_) | Err(_) => panic!("should error with limit exceeded")
+    }
+    drop(file);
+
+    match File::open("resource_limits.verter", Config {
+        max_file_size: Some(500),
+        ..Config::default()
+    }) {
+        Err(Error::LimitExceeded) => {},
+        Ok(_) | Err(_) => panic!("should error with limit exceeded")
+    }
+
+    std::fs::remove_file("resource_limits.verter").unwrap();
+}
+
+#[test]
+fn concurrent_modification() {
+    let mut file1 = File::open("concurrent_modification.verter", Config::default()).unwrap();
+    let mut file2 = File::open("concurrent_modification.verter", Config::default()).unwrap();
+
+    file2.write_root(b"changed behind file1's back").unwrap();
+    match file1.read_root() {
+        Err(Error::ConcurrentModification) => {},
+        Ok(_) | Err(_) => panic!("should error with concurrent modification")
+    }
+
+    file1.refresh().unwrap();
+    assert_eq!(file1.read_root().unwrap(), b"changed behind file1's back");
+
+    std::fs::remove_file("concurrent_modification.verter").unwrap();
+}
+
 #[test]
 fn extension() {
     let mut file = File::open("extension.verter", Config::default()).unwrap();
@@ -443,3 +2310,560 @@ fn extension() {
     
     std::fs::remove_file("extension.verter").unwrap();
 }
+
+#[test]
+fn unsupported_version() {
+    let mut file = File::open("unsupported_version.verter", Config::default()).unwrap();
+    let alloc = file.alloc().unwrap();
+    // A page type from some future format version
+    file.write_u64(alloc, (7 << PageHeader::TYPE_SHIFT) | 16).unwrap();
+    match file.read(alloc) {
+        Err(Error::UnsupportedVersion) => {},
+        Ok(_) | Err(_) => panic!("should error with unsupported version")
+    }
+
+    // Pointers into the middle of a chain are rejected
+    let chain = file.alloc_with(&[0xAB; 300]).unwrap();
+    let second_page = file.chain_pages(chain).unwrap()[1];
+    match file.read(second_page) {
+        Err(Error::InvalidPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with invalid pointer")
+    }
+
+    file.write_u64(file.format_version_ptr(), FORMAT_VERSION + 1).unwrap();
+    drop(file);
+    match File::open("unsupported_version.verter", Config::default()) {
+        Err(Error::UnsupportedVersion) => {},
+        Ok(_) | Err(_) => panic!("should error with unsupported version")
+    }
+
+    std::fs::remove_file("unsupported_version.verter").unwrap();
+}
+
+#[test]
+fn unversioned_file() {
+    // Laid out the way files were before the format was versioned: the magic bytes, the pointers to the first free page and the root page,
+    // and pages with the page type in the top two bits of their headers
+    let page = |idx: u64| 24 + idx * 128;
+    let mut bytes = b"VERTER__".to_vec();
+    bytes.extend(encode_u64s(&[page(5), page(0)]));
+    for (header, data) in [
+        (1 << 62 | 13, &b"Hello, World!"[..]),
+        (1 << 62 | 32, b"What an unexpectedly lovely day!"),
+        (page(3), &[1; 120]),
+        (page(4), &[2; 120]),
+        (1 << 62 | 60, &[3; 60]),
+        (2 << 62, &[])
+    ] {
+        bytes.extend(u64::to_le_bytes(header));
+        bytes.extend(data);
+        bytes.extend(vec![0xFF; 120 - data.len()]);
+    }
+    std::fs::write("unversioned_file.verter", &bytes).unwrap();
+
+    // Nothing is changed if the pages don't fit the page size
+    match File::open("unversioned_file.verter", Config { page_size: 100, ..Config::default() }) {
+        Err(Error::GeometryMismatch { .. }) => {},
+        Ok(_) | Err(_) => panic!("should error with geometry mismatch")
+    }
+    assert_eq!(std::fs::read("unversioned_file.verter").unwrap(), bytes);
+
+    // No page moves, so the pointers the application stored stay valid
+    let mut file = File::open("unversioned_file.verter", Config::default()).unwrap();
+    let long = [vec![1; 120], vec![2; 120], vec![3; 60]].concat();
+    assert_eq!(file.read_root().unwrap(), b"Hello, World!");
+    assert_eq!(file.read(page(1)).unwrap(), b"What an unexpectedly lovely day!");
+    assert_eq!(file.read(page(2)).unwrap(), long);
+    match file.read(page(3)) {
+        Err(Error::InvalidPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with invalid pointer")
+    }
+    file.validate().unwrap();
+    // The free list is kept too
+    assert_eq!(file.alloc_with(b"new").unwrap(), page(5));
+    assert_eq!(file.next_id(0).unwrap(), 1);
+    file.write(page(1), b"edited").unwrap();
+    assert_eq!(file.gc(&[page(1), page(2), page(5)], |_| Vec::new()).unwrap(), 0);
+    drop(file);
+
+    let mut file = File::open("unversioned_file.verter", Config::default()).unwrap();
+    assert_eq!(file.read(page(1)).unwrap(), b"edited");
+    assert_eq!(file.read(page(5)).unwrap(), b"new");
+    assert_eq!(file.next_id(0).unwrap(), 2);
+    file.validate().unwrap();
+    // Header fields in the header chain are restored when a transaction is rolled back
+    let mut transaction = file.transaction().unwrap();
+    transaction.set_schema_version(7).unwrap();
+    transaction.rollback().unwrap();
+    assert_eq!(file.schema_version().unwrap(), 0);
+    file.validate().unwrap();
+
+    // Canonicalizing moves the file to the current header
+    let remap = file.canonicalize(|_| Vec::new()).unwrap();
+    assert_eq!(file.header_size(), 8 + format::HEADER_FIELDS.len() as u64 * BYTES_IN_U64);
+    drop(file);
+    let mut file = File::open("unversioned_file.verter", Config::default()).unwrap();
+    assert_eq!(file.read(remap[&page(2)]).unwrap(), long);
+    assert_eq!(file.next_id(0).unwrap(), 3);
+    file.validate().unwrap();
+
+    std::fs::remove_file("unversioned_file.verter").unwrap();
+}
+
+#[test]
+fn delete_if_allocated() {
+    let mut file = File::open("delete_if_allocated.verter", Config::default()).unwrap();
+    let alloc = file.alloc().unwrap();
+    assert!(file.delete_if_allocated(alloc).unwrap());
+    assert!(!file.delete_if_allocated(alloc).unwrap());
+    match file.delete_if_allocated(alloc + 1) {
+        Err(Error::InvalidPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with invalid pointer")
+    }
+
+    std::fs::remove_file("delete_if_allocated.verter").unwrap();
+}
+
+#[test]
+fn read_fragmented_chain() {
+    let mut file = File::open("read_fragmented_chain.verter", Config::default()).unwrap();
+    let chains = (0..3).map(|_| file.alloc_with(&[0xAB; 600]).unwrap()).collect::<Vec<_>>();
+    file.delete(chains[1]).unwrap();
+
+    // The chain grows into the freed pages in the middle of the file, then continues at its end
+    let data = (0..3000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    file.write(chains[0], &data).unwrap();
+    let pages = file.chain_pages(chains[0]).unwrap();
+    assert!(file.page_runs(&pages).len() > 2);
+    assert_eq!(file.read(chains[0]).unwrap(), data);
+    assert_eq!(file.read(chains[2]).unwrap(), vec![0xAB; 600]);
+
+    std::fs::remove_file("read_fragmented_chain.verter").unwrap();
+}
+
+#[test]
+fn delete_many() {
+    let mut file = File::open("delete_many.verter", Config::default()).unwrap();
+    let chains = (0..4).map(|_| file.alloc_with(&[0xAB; 300]).unwrap()).collect::<Vec<_>>();
+
+    // Nothing is deleted if any pointer is invalid
+    match file.delete_many(&[chains[0], chains[1], chains[1]]) {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+    assert_eq!(file.read(chains[0]).unwrap(), vec![0xAB; 300]);
+
+    file.delete_many(&[chains[0], chains[2], chains[3]]).unwrap();
+    let stats = file.free_list_stats().unwrap();
+    assert_eq!(stats.len, 9);
+    assert!(stats.anomalies.is_empty());
+    assert_eq!(file.read(chains[1]).unwrap(), vec![0xAB; 300]);
+    match file.read(chains[2]) {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+    assert_eq!(file.alloc().unwrap(), chains[0]);
+    file.validate().unwrap();
+
+    std::fs::remove_file("delete_many.verter").unwrap();
+}
+
+#[test]
+fn alloc_with() {
+    let config = Config {
+        max_file_size: Some(4096),
+        ..Config::default()
+    };
+    let mut file = File::open("alloc_with.verter", config).unwrap();
+    let ptr = file.alloc_with(b"brush stroke").unwrap();
+    assert_eq!(file.read(ptr).unwrap(), b"brush stroke");
+
+    // A failed allocation doesn't leave an empty chain behind
+    let chains = file.chain_heads().unwrap().len();
+    match file.alloc_with(&[0; 10000]) {
+        Err(Error::LimitExceeded) => {},
+        Ok(_) | Err(_) => panic!("should error with limit exceeded")
+    }
+    assert_eq!(file.chain_heads().unwrap().len(), chains);
+
+    std::fs::remove_file("alloc_with.verter").unwrap();
+}
+
+#[test]
+fn read_root_or_init() {
+    let mut file = File::open("read_root_or_init.verter", Config::default()).unwrap();
+    assert!(file.root_is_empty().unwrap());
+    assert_eq!(file.read_root_or_init(|| b"new project".to_vec()).unwrap(), b"new project");
+    assert!(!file.root_is_empty().unwrap());
+    drop(file);
+
+    let mut file = File::open("read_root_or_init.verter", Config::default()).unwrap();
+    assert_eq!(file.read_root_or_init(|| panic!("root is already initialized")).unwrap(), b"new project");
+
+    std::fs::remove_file("read_root_or_init.verter").unwrap();
+}
+
+#[test]
+fn lazy_root() {
+    let config = Config { lazy_root: true, ..Config::default() };
+    let mut file = File::open("lazy_root.verter", config).unwrap();
+    assert_eq!(file.file_size().unwrap(), file.header_size());
+    assert_eq!(file.read_root().unwrap(), b"");
+    assert!(file.root_is_empty().unwrap());
+    let frame = file.alloc_with(b"frame").unwrap();
+    file.validate().unwrap();
+    assert_eq!(file.gc(&[frame], |_| Vec::new()).unwrap(), 0);
+    let remap = file.canonicalize(|_| Vec::new()).unwrap();
+    assert_eq!(file.read(remap[&frame]).unwrap(), b"frame");
+    drop(file);
+
+    let mut file = File::open("lazy_root.verter", config).unwrap();
+    assert_eq!(file.read_root().unwrap(), b"");
+    file.write_root(b"project").unwrap();
+    drop(file);
+
+    let mut file = File::open("lazy_root.verter", config).unwrap();
+    assert_eq!(file.read_root().unwrap(), b"project");
+    file.validate().unwrap();
+
+    std::fs::remove_file("lazy_root.verter").unwrap();
+}
+
+#[test]
+fn open_bytes() {
+    let mut file = File::open("open_bytes.verter", Config::default()).unwrap();
+    file.write_root(b"template").unwrap();
+    let scene = file.alloc_with(&[0xAB; 300]).unwrap();
+    drop(file);
+    let bytes: &'static [u8] = Vec::leak(std::fs::read("open_bytes.verter").unwrap());
+    std::fs::remove_file("open_bytes.verter").unwrap();
+
+    let mut template = File::open_bytes(bytes, Config::default()).unwrap();
+    assert_eq!(template.read_root().unwrap(), b"template");
+    assert_eq!(template.read(scene).unwrap(), vec![0xAB; 300]);
+    match template.write(scene, b"edited") {
+        Err(Error::IO(_)) => {},
+        Ok(_) | Err(_) => panic!("should error with IO")
+    }
+
+    let remap = migrate::repage(&mut template, "open_bytes_copy.verter", Config::default()).unwrap();
+    let mut copy = File::open("open_bytes_copy.verter", Config::default()).unwrap();
+    copy.write(remap[&scene], b"edited").unwrap();
+    assert_eq!(copy.read_root().unwrap(), b"template");
+
+    std::fs::remove_file("open_bytes_copy.verter").unwrap();
+}
+
+#[test]
+fn from_file() {
+    let open = || std::fs::OpenOptions::new().create(true).truncate(false).read(true).write(true).open("from_file.verter").unwrap();
+
+    let mut file = File::from_file(open(), Config::default()).unwrap();
+    file.write_root(b"picked file").unwrap();
+    drop(file);
+
+    #[cfg(unix)]
+    let mut file = File::from_owned_fd(open().into(), Config::default()).unwrap();
+    #[cfg(not(unix))]
+    let mut file = File::from_file(open(), Config::default()).unwrap();
+    assert_eq!(file.read_root().unwrap(), b"picked file");
+
+    std::fs::remove_file("from_file.verter").unwrap();
+}
+
+#[test]
+fn write_interrupted_by_panic() {
+    use std::io::Cursor;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    /// An in-memory backend that panics partway through an operation, as if the application crashed in the middle of it
+    struct Faulty {
+        bytes: Cursor<Vec<u8>>,
+        writes_left: Arc<AtomicU64>
+    }
+
+    impl Read for Faulty {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.bytes.read(buf)
+        }
+    }
+
+    impl Write for Faulty {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.writes_left.load(Ordering::Relaxed) == 0 {
+                panic!("injected fault");
+            }
+            self.writes_left.fetch_sub(1, Ordering::Relaxed);
+            self.bytes.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.bytes.flush()
+        }
+    }
+
+    impl Seek for Faulty {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.bytes.seek(pos)
+        }
+    }
+
+    impl Backend for Faulty {
+        fn len(&self) -> std::io::Result<u64> {
+            self.bytes.len()
+        }
+
+        fn sync_data(&self) -> std::io::Result<()> {
+            Ok(())
+        }
+
+        fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+            self.bytes.read_exact_at(buf, offset)
+        }
+
+        fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+            self.bytes.set_len(len)
+        }
+    }
+
+    // Growing and shrinking a chain, interrupted after every possible number of writes
+    for (old_len, new_len) in [(300, 900), (900, 300)] {
+        for fault_at in 0.. {
+            let writes_left = Arc::new(AtomicU64::new(u64::MAX));
+            let backend = Faulty { bytes: Cursor::new(Vec::new()), writes_left: writes_left.clone() };
+            let mut file = File::from_backend(Box::new(backend), Config::default(), true).unwrap();
+            let ptr = file.alloc_with(&vec![1; old_len]).unwrap();
+            let other = file.alloc_with(&[2; 500]).unwrap();
+            writes_left.store(fault_at, Ordering::Relaxed);
+            let result = catch_unwind(AssertUnwindSafe(|| file.write(ptr, &vec![3; new_len])));
+
+            // Whatever reached the disk still holds well-formed chains
+            let mut bytes = vec![0; file.file_size().unwrap() as usize];
+            file.file.read_exact_at(&mut bytes, 0).unwrap();
+            let mut reopened = File::from_backend(Box::new(Cursor::new(bytes)), Config::default(), false).unwrap();
+            let data = reopened.read(ptr).unwrap();
+            assert_eq!(reopened.read(other).unwrap(), vec![2; 500]);
+            if result.is_ok() {
+                assert_eq!(data, vec![3; new_len]);
+                reopened.validate().unwrap();
+                break;
+            }
+        }
+    }
+}
+
+#[test]
+fn out_of_memory() {
+    match try_zeroed(usize::MAX) {
+        Err(Error::OutOfMemory) => {},
+        Ok(_) | Err(_) => panic!("should error with out of memory")
+    }
+    let mut buf = vec![0; 16];
+    match try_extend(&mut buf, &[0; 16]).and_then(|()| try_zeroed(isize::MAX as usize)) {
+        Err(Error::OutOfMemory) => {},
+        Ok(_) | Err(_) => panic!("should error with out of memory")
+    }
+    assert_eq!(buf.len(), 32);
+}
+
+#[test]
+fn reserved_chains() {
+    let mut file = File::open("reserved_chains.verter", Config::default()).unwrap();
+    file.append_op(b"add stroke").unwrap();
+    file.write_root(b"scene").unwrap();
+    file.tag("draft").unwrap();
+    let journal = file.read_u64(file.journal_ptr()).unwrap();
+    let tag = file.tag_chains().unwrap()[1];
+
+    for chain in [journal, tag] {
+        let results = [
+            file.write(chain, b"oops"),
+            file.write_with_outcome(chain, b"oops").map(drop),
+            file.write_with(chain, b"oops", WriteOpts::default()),
+            file.write_large(chain, b"oops", 4),
+            file.patch(chain, &[(0, b"oops")]),
+            file.append(chain, b"oops"),
+            file.chain_file(chain).and_then(|mut chain_file| chain_file.set_len(0)),
+            file.delete(chain),
+            file.delete_if_allocated(chain).map(drop),
+            file.delete_many(&[chain]),
+            file.freeze(chain),
+            file.unfreeze(chain).map(drop),
+            file.incref(chain).map(drop),
+            file.decref(chain).map(drop),
+            file.set_chain_flags(chain, 1),
+            file.pin(chain),
+            file.unpin(chain).map(drop),
+            file.set_named_root("oops", chain).map(drop)
+        ];
+        for result in results {
+            match result {
+                Err(Error::ReservedChain) => {},
+                Ok(_) | Err(_) => panic!("should error with reserved chain")
+            }
+        }
+    }
+    assert_eq!(file.ops_since(0).unwrap(), vec![(1, b"add stroke".to_vec())]);
+    assert_eq!(file.read_root_at_tag("draft").unwrap().unwrap(), b"scene");
+
+    // verter still removes its own chains
+    assert!(file.remove_tag("draft").unwrap());
+    file.validate().unwrap();
+
+    // The reserved chains are remembered between writes, and updated as verter's tables change
+    file.tag("final").unwrap();
+    let tag = file.tag_chains().unwrap()[1];
+    match file.write(tag, b"oops") {
+        Err(Error::ReservedChain) => {},
+        Ok(_) | Err(_) => panic!("should error with reserved chain")
+    }
+    assert!(file.remove_tag("final").unwrap());
+    match file.write(tag, b"oops") {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+    let frame = file.alloc_with(b"frame").unwrap();
+    file.write(frame, b"frame 2").unwrap();
+    file.validate().unwrap();
+
+    std::fs::remove_file("reserved_chains.verter").unwrap();
+}
+
+#[test]
+fn geometry_mismatch() {
+    let config = Config { page_size: 256, ..Config::default() };
+    let mut file = File::open("geometry_mismatch.verter", config).unwrap();
+    let frame = file.alloc_with(&[0xAB; 1000]).unwrap();
+    file.write_root(&frame.to_le_bytes()).unwrap();
+    drop(file);
+
+    match File::open("geometry_mismatch.verter", Config::default()) {
+        Err(Error::GeometryMismatch { page_size: Some(256) }) => {},
+        Ok(_) | Err(_) => panic!("should error with geometry mismatch")
+    }
+
+    // The guided fallback opens the file with the page size it was created with, ready to be repaged
+    let mut file = File::open_with_detected_config("geometry_mismatch.verter", Config::default()).unwrap();
+    assert_eq!(file.config().page_size, 256);
+    assert_eq!(file.read(frame).unwrap(), vec![0xAB; 1000]);
+    let remap = migrate::repage(&mut file, "geometry_mismatch_repaged.verter", Config::default()).unwrap();
+    drop(file);
+    let mut file = File::open("geometry_mismatch_repaged.verter", Config::default()).unwrap();
+    assert_eq!(file.read(remap[&frame]).unwrap(), vec![0xAB; 1000]);
+
+    std::fs::remove_file("geometry_mismatch.verter").unwrap();
+    std::fs::remove_file("geometry_mismatch_repaged.verter").unwrap();
+}
+
+#[test]
+fn truncated_file() {
+    let mut file = File::open("truncated_file.verter", Config::default()).unwrap();
+    file.write_root(b"scene").unwrap();
+    let frame = file.alloc_with(&[0xAB; 1000]).unwrap();
+    drop(file);
+    let synced = std::fs::read("truncated_file.verter").unwrap();
+    let total_page_size = Config::default().page_size as u64 + BYTES_IN_U64;
+
+    // The frame's last pages haven't arrived yet
+    let partial = std::fs::OpenOptions::new().write(true).open("truncated_file.verter").unwrap();
+    partial.set_len(synced.len() as u64 - 3 * total_page_size).unwrap();
+    let mut file = File::open("truncated_file.verter", Config::default()).unwrap();
+    assert_eq!(file.read_root().unwrap(), b"scene");
+    match file.read(frame) {
+        // Only the first missing page is known from the chain's links
+        Err(Error::TruncatedFile { missing_bytes }) => assert_eq!(missing_bytes, total_page_size),
+        Ok(_) | Err(_) => panic!("should error with truncated file")
+    }
+    drop(file);
+
+    // A page cut off partway is found when the file is opened
+    std::fs::write("truncated_file.verter", &synced[..synced.len() - 10]).unwrap();
+    match File::open("truncated_file.verter", Config::default()) {
+        Err(Error::TruncatedFile { missing_bytes: 10 }) => {},
+        Ok(_) | Err(_) => panic!("should error with truncated file")
+    }
+
+    let sync = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        std::fs::write("truncated_file.verter", synced).unwrap();
+    });
+    let mut file = File::open_when_synced("truncated_file.verter", Config::default(), std::time::Duration::from_secs(10)).unwrap();
+    sync.join().unwrap();
+    assert_eq!(file.read(frame).unwrap(), vec![0xAB; 1000]);
+
+    std::fs::remove_file("truncated_file.verter").unwrap();
+}
+
+#[test]
+fn protected_chains() {
+    let mut file = File::open("protected_chains.verter", Config::default()).unwrap();
+    file.write_root(b"scene").unwrap();
+    let root = file.root_page().unwrap();
+    file.partition("brushes").unwrap();
+    let partition_root = file.partition_roots().unwrap()[0];
+    let frame = file.alloc_with(b"frame").unwrap();
+
+    for chain in [root, partition_root] {
+        let results = [file.delete(chain), file.delete_many(&[frame, chain]), file.decref(chain).map(drop)];
+        for result in results {
+            match result {
+                Err(Error::ProtectedChain) => {},
+                Ok(_) | Err(_) => panic!("should error with protected chain")
+            }
+        }
+        let mut transaction = file.transaction().unwrap();
+        match transaction.delete(chain) {
+            Err(Error::ProtectedChain) => {},
+            Ok(_) | Err(_) => panic!("should error with protected chain")
+        }
+    }
+    assert_eq!(file.read(frame).unwrap(), b"frame");
+    assert_eq!(file.read_root().unwrap(), b"scene");
+
+    // Deleting them on purpose
+    assert!(file.delete_root().unwrap());
+    assert!(!file.delete_root().unwrap());
+    assert!(file.read_root().unwrap().is_empty());
+    assert!(file.remove_partition("brushes").unwrap());
+    file.validate().unwrap();
+    file.write_root(b"new scene").unwrap();
+    assert_eq!(file.read_root().unwrap(), b"new scene");
+    drop(file);
+    let mut file = File::open("protected_chains.verter", Config::default()).unwrap();
+    assert_eq!(file.read_root().unwrap(), b"new scene");
+
+    std::fs::remove_file("protected_chains.verter").unwrap();
+}
+
+#[test]
+fn reset_root() {
+    let mut file = File::open("reset_root.verter", Config::default()).unwrap();
+    let frame = file.alloc_with(b"frame").unwrap();
+    file.write_root(&[0xAB; 1000]).unwrap();
+    let old_root = file.root_page().unwrap();
+    file.tag("before clearing").unwrap();
+    file.partition("brushes").unwrap().write_root(b"round brush").unwrap();
+
+    file.reset_root().unwrap();
+    assert!(file.read_root().unwrap().is_empty());
+    match file.read(old_root) {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+    // Everything else is kept
+    assert_eq!(file.read(frame).unwrap(), b"frame");
+    assert_eq!(file.read_root_at_tag("before clearing").unwrap().unwrap(), vec![0xAB; 1000]);
+    assert_eq!(file.partition("brushes").unwrap().read_root().unwrap(), b"round brush");
+    file.validate().unwrap();
+
+    // A file without a root chain gets an empty one
+    file.delete_root().unwrap();
+    file.reset_root().unwrap();
+    assert!(file.read_root().unwrap().is_empty());
+    assert_ne!(file.root_page().unwrap(), 0);
+    file.validate().unwrap();
+
+    std::fs::remove_file("reset_root.verter").unwrap();
+}
@@ -0,0 +1,149 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::{decode_u64s, encode_u64s, Error, File, PageHeader};
+
+impl File {
+
+    /// The old versions of a chain kept by log-structured mode since the last checkpoint, oldest first.
+    /// Each version is a pointer to a chain that can be read with `File::read`, but should not be modified.
+    pub fn versions(&mut self, ptr: u64) -> Result<Vec<u64>, Error> {
+        self.check_if_pointer_valid(ptr)?;
+        Ok(self.read_version_table()?.into_iter()
+            .filter(|(chain, version)| *chain == ptr && *version != ptr)
+            .map(|(_, version)| version)
+            .collect())
+    }
+
+    /// Free every old version kept by log-structured mode, along with every chain whose deletion was deferred.
+    pub fn checkpoint(&mut self) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        let table_chain = self.read_u64(self.version_table_ptr())?;
+        if table_chain == 0 {
+            return Ok(());
+        }
+        for (_, version) in self.read_version_table()? {
+            self.free_pages(version)?;
+        }
+        self.write_u64(self.version_table_ptr(), 0)?;
+        self.free_pages(table_chain)
+    }
+
+    /// Keep the current contents of a chain as an old version, leaving the chain itself empty except for its metadata.
+    /// The head page is copied to a new page that takes over the rest of the old chain,
+    /// since the head page is what identifies the chain and must stay in place.
+    pub(crate) fn preserve_version(&mut self, ptr: u64) -> Result<(), Error> {
+        let meta_size = self.meta_size();
+        if matches!(self.read_page_header(ptr)?, PageHeader::FinalPage(size) if size == meta_size) {
+            return Ok(());
+        }
+
+        let mut page = vec![0; self.total_page_size() as usize];
+        self.file.seek(SeekFrom::Start(ptr)).map_err(Error::IO)?;
+        self.file.read_exact(&mut page).map_err(Error::IO)?;
+        let version = self.alloc_page()?;
+        self.file.seek(SeekFrom::Start(version)).map_err(Error::IO)?;
+        self.file.write_all(&page).map_err(Error::IO)?;
+        // The chain's metadata stays at the start of the head page
+        self.write_head_page_header(ptr, PageHeader::FinalPage(meta_size))?;
+        self.write_summary(&[ptr], 0)?;
+
+        let mut table = self.read_version_table()?;
+        table.push((ptr, version));
+        self.write_version_table(&table)
+    }
+
+    /// Keep a deleted chain readable until the next checkpoint.
+    pub(crate) fn defer_delete(&mut self, ptr: u64) -> Result<(), Error> {
+        let mut table = self.read_version_table()?;
+        if table.contains(&(ptr, ptr)) {
+            return Err(Error::DeletedPointer);
+        }
+        table.push((ptr, ptr));
+        self.write_version_table(&table)
+    }
+
+    /// The version table chain, every old version, and every chain awaiting deletion
+    pub(crate) fn retained_versions(&mut self) -> Result<Vec<u64>, Error> {
+        let table_chain = self.read_u64(self.version_table_ptr())?;
+        if table_chain == 0 {
+            return Ok(Vec::new());
+        }
+        let mut chains = vec![table_chain];
+        chains.extend(self.read_version_table()?.into_iter().map(|(_, version)| version));
+        Ok(chains)
+    }
+
+    /// The version table lists (chain, version) pairs in the order the versions were made.
+    /// Chains awaiting deletion are listed as their own version.
+    fn read_version_table(&mut self) -> Result<Vec<(u64, u64)>, Error> {
+        let chain = self.read_u64(self.version_table_ptr())?;
+        if chain == 0 {
+            return Ok(Vec::new());
+        }
+        let entries = decode_u64s(&self.read(chain)?)?;
+        if !entries.len().is_multiple_of(2) {
+            return Err(Error::CorruptedFile);
+        }
+        Ok(entries.chunks_exact(2).map(|entry| (entry[0], entry[1])).collect())
+    }
+
+    fn write_version_table(&mut self, table: &[(u64, u64)]) -> Result<(), Error> {
+        let mut chain = self.read_u64(self.version_table_ptr())?;
+        if chain == 0 {
+            chain = self.alloc()?;
+            self.write_u64(self.version_table_ptr(), chain)?;
+        }
+        let entries = table.iter().flat_map(|(chain, version)| [*chain, *version]).collect::<Vec<_>>();
+        self.write_chain(chain, &encode_u64s(&entries))?;
+        // The table only grows until the next checkpoint, so the reserved chains are extended instead of being read again
+        if let Some(reserved) = &mut self.reserved_chains {
+            reserved.insert(chain);
+            reserved.extend(table.iter().map(|(_, version)| *version));
+        }
+        Ok(())
+    }
+
+}
+
+#[test]
+fn log_structured() {
+    use crate::Config;
+
+    let config = Config {
+        log_structured: true,
+        ..Config::default()
+    };
+
+    let mut file = File::open("log_structured.verter", config).unwrap();
+    let ledger = file.alloc().unwrap();
+    file.write(ledger, &[1; 300]).unwrap();
+    file.write(ledger, &[2; 50]).unwrap();
+    file.patch(ledger, &[(0, b"three")]).unwrap();
+    let entry = file.alloc().unwrap();
+    file.write(entry, b"entry").unwrap();
+    file.delete(entry).unwrap();
+    drop(file);
+
+    let mut file = File::open("log_structured.verter", config).unwrap();
+    let versions = file.versions(ledger).unwrap();
+    assert_eq!(versions.len(), 2);
+    assert_eq!(file.read(versions[0]).unwrap(), vec![1; 300]);
+    assert_eq!(file.read(versions[1]).unwrap(), vec![2; 50]);
+    assert_eq!(&file.read(ledger).unwrap()[..5], b"three");
+    assert_eq!(file.read(entry).unwrap(), b"entry");
+    file.validate().unwrap();
+
+    file.checkpoint().unwrap();
+    assert!(file.versions(ledger).unwrap().is_empty());
+    match file.read(versions[0]) {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+    match file.read(entry) {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+    file.validate().unwrap();
+
+    std::fs::remove_file("log_structured.verter").unwrap();
+}
@@ -0,0 +1,74 @@
+use crate::{Error, File};
+
+impl File {
+
+    /// Whether `Config::max_free_fraction` is still exceeded after the last automatic maintenance,
+    /// because the free pages lie between chains in use instead of at the end of the file.
+    /// Reclaiming them moves chains, which changes their pointers, so it is left to the application to call `File::compact`.
+    pub fn compaction_due(&self) -> bool {
+        self.compaction_due
+    }
+
+    /// Count pages added to the free list towards the next automatic maintenance
+    pub(crate) fn note_freed(&mut self, pages: u64) {
+        self.freed_since_maintenance += pages;
+    }
+
+    /// Sort the free list and trim the end of the file if more than `Config::max_free_fraction` of its pages are free.
+    /// Counting the free pages walks the free list, so it is only done once enough pages were freed since the last time to go over the limit.
+    pub(crate) fn maintain_if_due(&mut self) -> Result<(), Error> {
+        let Some(max_free_fraction) = self.config.max_free_fraction else {
+            return Ok(());
+        };
+        let pages = (self.file_size()? - self.header_size()) / self.total_page_size();
+        let max_free = (max_free_fraction * pages as f64) as u64;
+        if self.freed_since_maintenance <= max_free {
+            return Ok(());
+        }
+        self.freed_since_maintenance = 0;
+        if self.free_list()?.len() as u64 <= max_free {
+            return Ok(());
+        }
+
+        // Sorting the free list makes new chains fill the start of the file, leaving its end free to be trimmed later
+        self.sort_free_list()?;
+        self.trim_tail()?;
+        let pages = (self.file_size()? - self.header_size()) / self.total_page_size();
+        self.compaction_due = self.free_list()?.len() as f64 > max_free_fraction * pages as f64;
+        Ok(())
+    }
+
+}
+
+#[test]
+fn max_free_fraction() {
+    use crate::Config;
+
+    let config = Config { max_free_fraction: Some(0.25), ..Config::default() };
+    let mut file = File::open("max_free_fraction.verter", config).unwrap();
+    let frames = (0..20).map(|_| file.alloc_with(&[0xAB; 500]).unwrap()).collect::<Vec<_>>();
+    let full_size = std::fs::metadata("max_free_fraction.verter").unwrap().len();
+
+    // Deleting the frames at the end of the file shrinks it once a quarter of it is free
+    for frame in frames[10..].iter().rev() {
+        file.delete(*frame).unwrap();
+    }
+    assert!(std::fs::metadata("max_free_fraction.verter").unwrap().len() < full_size / 2 + 1000);
+    assert!(!file.compaction_due());
+
+    // Free pages between chains in use can only be reclaimed by compacting
+    for frame in frames[..9].iter().step_by(2) {
+        file.delete(*frame).unwrap();
+    }
+    assert!(file.compaction_due());
+    file.compact(|_| Vec::new()).unwrap();
+    assert!(!file.compaction_due());
+    file.validate().unwrap();
+
+    match File::open("max_free_fraction.verter", Config { max_free_fraction: Some(1.5), ..Config::default() }) {
+        Err(Error::InvalidConfig) => {},
+        Ok(_) | Err(_) => panic!("should error with invalid config")
+    }
+
+    std::fs::remove_file("max_free_fraction.verter").unwrap();
+}
@@ -0,0 +1,115 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{Error, File};
+
+/// The number of bytes bulk operations move at a time when `Config::max_working_memory` allows it
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A scratch file in the system's temporary directory, deleted when dropped.
+/// Bulk operations build their intermediate results in one when `Config::max_working_memory` is set, instead of in memory.
+pub(crate) struct ScratchFile {
+    path: PathBuf
+}
+
+impl ScratchFile {
+
+    pub(crate) fn create() -> Result<(Self, std::fs::File), Error> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let name = format!("verter-scratch-{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+        let path = std::env::temp_dir().join(name);
+        let file = std::fs::File::options().read(true).write(true).create_new(true).open(&path).map_err(Error::IO)?;
+        Ok((Self { path }, file))
+    }
+
+}
+
+impl Drop for ScratchFile {
+
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+
+}
+
+impl File {
+
+    /// The number of bytes a bulk operation may buffer at a time, staying within `Config::max_working_memory`
+    pub(crate) fn chunk_size(&self) -> usize {
+        match self.config.max_working_memory {
+            Some(max) => max.clamp(1, CHUNK_SIZE),
+            None => CHUNK_SIZE
+        }
+    }
+
+    /// Pass the data stored in a chain's own pages to `f`, a chunk at a time, returning the data's length.
+    /// Set `with_meta` to include the chain's metadata.
+    pub(crate) fn stream_chain<F: FnMut(&[u8]) -> Result<(), Error>>(&mut self, ptr: u64, with_meta: bool, mut f: F) -> Result<u64, Error> {
+        let (pages, final_size) = self.chain_layout(ptr)?;
+        let total = (pages.len() as u64 - 1) * self.config.page_size as u64 + final_size;
+        let start = if with_meta { 0 } else { self.meta_size() };
+        let len = total.checked_sub(start).ok_or(Error::CorruptedFile)?;
+
+        let mut chunk = vec![0; self.chunk_size()];
+        let mut offset = 0;
+        while offset < len {
+            let n = chunk.len().min((len - offset) as usize);
+            self.read_at(&pages, start + offset, &mut chunk[..n])?;
+            f(&chunk[..n])?;
+            offset += n as u64;
+        }
+        Ok(len)
+    }
+
+}
+
+#[test]
+fn working_memory() {
+    use crate::{decode_u64s, encode_u64s, ChainFormat, Config};
+
+    let config = Config {
+        max_working_memory: Some(100),
+        chain_meta: true,
+        chain_format: ChainFormat::V2 { head_checksum: true },
+        ..Config::default()
+    };
+    let mut file = File::open("working_memory.verter", config).unwrap();
+    let texture = (0..5000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let junk = file.alloc_with(&[0; 1000]).unwrap();
+    let ptr = file.alloc_with(&texture).unwrap();
+    file.delete(junk).unwrap();
+    file.write_root(&encode_u64s(&[ptr])).unwrap();
+
+    let remap = file.canonicalize(|data| decode_u64s(data).unwrap_or_default()).unwrap();
+    let ptr = remap[&ptr];
+    file.write_root(&encode_u64s(&[ptr])).unwrap();
+    assert_eq!(file.read(ptr).unwrap(), texture);
+    file.validate().unwrap();
+    let scratch = format!("verter-scratch-{}-", std::process::id());
+    assert!(!std::fs::read_dir(std::env::temp_dir()).unwrap().any(|entry| entry.unwrap().file_name().to_string_lossy().starts_with(&scratch)));
+
+    file.export_chain(ptr, "working_memory.bin").unwrap();
+    let imported = file.import_file("working_memory.bin").unwrap();
+    assert_eq!(file.read(imported).unwrap(), texture);
+
+    let mut stream = Vec::new();
+    file.export_exchange(&mut stream).unwrap();
+    let mut copy = File::open("working_memory_copy.verter", config).unwrap();
+    let imported = copy.import_exchange(&mut stream.as_slice()).unwrap();
+    assert_eq!(copy.read(imported[&crate::inspect::ChainKey::Chain(ptr)]).unwrap(), texture);
+
+    // A stream cut off partway through a chain doesn't leak the chain's pages
+    let used = |file: &mut File| file.file_size().unwrap() - file.free_list_stats().unwrap().free_bytes;
+    let used_before = used(&mut copy);
+    let cut = stream.windows(64).position(|window| window == &texture[..64]).unwrap() + 1000;
+    match copy.import_exchange(&mut &stream[..cut]) {
+        Err(Error::IO(_)) => {},
+        Ok(_) | Err(_) => panic!("should error with io error")
+    }
+    assert_eq!(used(&mut copy), used_before);
+    copy.validate().unwrap();
+
+    std::fs::remove_file("working_memory.verter").unwrap();
+    std::fs::remove_file("working_memory.bin").unwrap();
+    std::fs::remove_file("working_memory_copy.verter").unwrap();
+}
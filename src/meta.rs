@@ -0,0 +1,168 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{Error, File, PageHeader, BYTES_IN_U64};
+
+/// The metadata stored at the start of every chain when `Config::chain_meta` is enabled.
+/// Stored as the created and modified timestamps in milliseconds since the Unix epoch, followed by the flags.
+const META_SIZE: u64 = 2 * BYTES_IN_U64 + 4;
+
+/// The timestamps and user flags of a chain
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChainMeta {
+    /// When the chain was allocated
+    pub created: SystemTime,
+    /// When the chain's data was last written to or patched
+    pub modified: SystemTime,
+    /// Flags for the application's own use, such as marking a chain dirty. Set with `File::set_chain_flags`.
+    pub flags: u32
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as u64).unwrap_or(0)
+}
+
+fn from_millis(millis: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+fn encode_meta(created: u64, modified: u64, flags: u32) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(META_SIZE as usize);
+    bytes.extend_from_slice(&created.to_le_bytes());
+    bytes.extend_from_slice(&modified.to_le_bytes());
+    bytes.extend_from_slice(&flags.to_le_bytes());
+    bytes
+}
+
+impl File {
+
+    /// Get the timestamps and user flags of a chain.
+    /// Requires `Config::chain_meta` to be enabled.
+    pub fn chain_meta(&mut self, ptr: u64) -> Result<ChainMeta, Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        let (created, modified, flags) = self.read_meta(ptr)?.ok_or(Error::CorruptedFile)?;
+        Ok(ChainMeta {
+            created: from_millis(created),
+            modified: from_millis(modified),
+            flags
+        })
+    }
+
+    /// Set the user flags of a chain. Does not change the chain's modified timestamp.
+    /// Requires `Config::chain_meta` to be enabled.
+    pub fn set_chain_flags(&mut self, ptr: u64, flags: u32) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        self.check_not_reserved(ptr)?;
+        self.read_meta(ptr)?.ok_or(Error::CorruptedFile)?;
+        self.check_writable()?;
+        self.file.seek(SeekFrom::Start(ptr + BYTES_IN_U64 + 2 * BYTES_IN_U64)).map_err(Error::IO)?;
+        self.file.write_all(&flags.to_le_bytes()).map_err(Error::IO)?;
+        self.bump_change_counter()
+    }
+
+    /// The number of bytes at the start of every chain taken up by its metadata and its `ChainSummary`
+    pub(crate) fn meta_size(&self) -> u64 {
+        let meta_size = if self.config.chain_meta {
+            META_SIZE
+        } else {
+            0
+        };
+        meta_size + self.summary_size()
+    }
+
+    /// Write the metadata and summary of a newly allocated chain, whose header already accounts for them
+    pub(crate) fn init_meta(&mut self, ptr: u64) -> Result<(), Error> {
+        let mut prefix = Vec::new();
+        if self.config.chain_meta {
+            let now = now_millis();
+            prefix.extend_from_slice(&encode_meta(now, now, 0));
+        }
+        prefix.extend_from_slice(&self.encode_summary(&[]));
+        self.check_writable()?;
+        self.file.seek(SeekFrom::Start(ptr + BYTES_IN_U64)).map_err(Error::IO)?;
+        self.file.write_all(&prefix).map_err(Error::IO)
+    }
+
+    /// Update the modified timestamp of a chain
+    pub(crate) fn touch_meta(&mut self, ptr: u64) -> Result<(), Error> {
+        self.check_writable()?;
+        self.file.seek(SeekFrom::Start(ptr + BYTES_IN_U64 + BYTES_IN_U64)).map_err(Error::IO)?;
+        self.file.write_all(&now_millis().to_le_bytes()).map_err(Error::IO)
+    }
+
+    /// Prepend the chain's metadata and summary to data about to be written to it, updating the modified timestamp.
+    /// Chains without metadata, such as ones written before `Config::chain_meta` was enabled, get fresh metadata.
+    pub(crate) fn prefix_meta(&mut self, ptr: u64, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut prefixed = Vec::with_capacity(self.meta_size() as usize + data.len());
+        if self.config.chain_meta {
+            let now = now_millis();
+            let (created, _, flags) = self.read_meta(ptr)?.unwrap_or((now, now, 0));
+            prefixed.extend_from_slice(&encode_meta(created, now, flags));
+        }
+        prefixed.extend_from_slice(&self.encode_summary(data));
+        prefixed.extend_from_slice(data);
+        Ok(prefixed)
+    }
+
+    /// Read the (created, modified, flags) metadata of a chain, or `None` if the chain is too short to have any
+    fn read_meta(&mut self, ptr: u64) -> Result<Option<(u64, u64, u32)>, Error> {
+        if !self.config.chain_meta {
+            return Err(Error::MetadataDisabled);
+        }
+        if let PageHeader::FinalPage(size) = self.read_page_header(ptr)? {
+            if size < META_SIZE {
+                return Ok(None);
+            }
+        }
+        let mut bytes = [0; META_SIZE as usize];
+        self.file.seek(SeekFrom::Start(ptr + BYTES_IN_U64)).map_err(Error::IO)?;
+        self.file.read_exact(&mut bytes).map_err(Error::IO)?;
+        let created = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        let modified = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        let flags = u32::from_le_bytes(bytes[16..20].try_into().unwrap());
+        Ok(Some((created, modified, flags)))
+    }
+
+}
+
+#[test]
+fn chain_meta() {
+    use crate::Config;
+
+    let config = Config {
+        chain_meta: true,
+        ..Config::default()
+    };
+
+    let before = SystemTime::now() - Duration::from_millis(1);
+    let mut file = File::open("chain_meta.verter", config).unwrap();
+    let thumbnail = file.alloc().unwrap();
+    assert_eq!(file.read(thumbnail).unwrap(), b"");
+    file.write(thumbnail, &[0xAB; 300]).unwrap();
+    file.patch(thumbnail, &[(0, b"png")]).unwrap();
+    file.set_chain_flags(thumbnail, 0b101).unwrap();
+    drop(file);
+
+    let mut file = File::open("chain_meta.verter", config).unwrap();
+    let mut expected = vec![0xAB; 300];
+    expected[..3].copy_from_slice(b"png");
+    assert_eq!(file.read(thumbnail).unwrap(), expected);
+    let meta = file.chain_meta(thumbnail).unwrap();
+    assert_eq!(meta.flags, 0b101);
+    assert!(meta.created >= before);
+    assert!(meta.modified >= meta.created);
+
+    file.write(thumbnail, b"small").unwrap();
+    assert_eq!(file.chain_meta(thumbnail).unwrap().flags, 0b101);
+    drop(file);
+
+    let mut file = File::open("chain_meta.verter", Config::default()).unwrap();
+    match file.chain_meta(thumbnail) {
+        Err(Error::MetadataDisabled) => {},
+        Ok(_) | Err(_) => panic!("should error with metadata disabled")
+    }
+
+    std::fs::remove_file("chain_meta.verter").unwrap();
+}
@@ -0,0 +1,122 @@
+//! Tools for moving data between files with different configurations.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use crate::{decode_u64s, encode_u64s, CancellationToken, Config, Error, File};
+
+/// Copy every chain of a file into a freshly formatted file at `dest`, which can use a different page size or other configuration.
+/// The root chain, undo history, reference counts, dedup table, ID counters, named roots and compression dictionaries are carried over.
+/// Old versions kept by log-structured mode are not, and chains in the cold tier are copied into the new file itself.
+/// Pointers stored inside the copied data are not rewritten, so use the returned map from old to new pointers to remap them.
+/// Pinned chains are moved like any other chain, since pages of a different size don't line up with the old ones.
+pub fn repage<P: AsRef<Path>>(src: &mut File, dest: P, config: Config) -> Result<BTreeMap<u64, u64>, Error> {
+    repage_cancellable(src, dest, config, &CancellationToken::new())
+}
+
+/// Like `repage`, but stops with `Error::Cancelled` once `cancel` is cancelled.
+/// The source file is never modified, so a cancelled copy can simply be discarded.
+pub fn repage_cancellable<P: AsRef<Path>>(src: &mut File, dest: P, config: Config, cancel: &CancellationToken) -> Result<BTreeMap<u64, u64>, Error> {
+    let mut dest = File::open(dest, config)?;
+
+    let root = src.root_page()?;
+    dest.write_root(&src.read_root()?)?;
+
+    let internal_chains = src.internal_chains()?.into_iter().collect::<HashSet<_>>();
+    let mut remap = BTreeMap::new();
+    for ptr in src.chain_heads()? {
+        if ptr == root || internal_chains.contains(&ptr) {
+            continue;
+        }
+        cancel.check()?;
+        let new_ptr = dest.alloc()?;
+        dest.write(new_ptr, &src.read(ptr)?)?;
+        if src.is_frozen(ptr)? {
+            dest.freeze(new_ptr)?;
+        }
+        remap.insert(ptr, new_ptr);
+    }
+    if root != 0 {
+        remap.insert(root, dest.root_page()?);
+    }
+
+    for (src_field, dest_field) in [(src.undo_chain_ptr(), dest.undo_chain_ptr()), (src.redo_chain_ptr(), dest.redo_chain_ptr())] {
+        let ops = src.read_op_stack(src_field)?;
+        dest.write_op_stack(dest_field, &ops)?;
+    }
+
+    let refcounts = src.read_refcount_table()?.into_iter()
+        .filter_map(|(ptr, count)| Some((*remap.get(&ptr)?, count)))
+        .collect();
+    dest.write_refcount_table(&refcounts)?;
+
+    let dedup = src.read_dedup_table()?.into_iter()
+        .filter_map(|(hash, ptr)| Some((hash, *remap.get(&ptr)?)))
+        .collect::<Vec<_>>();
+    if !dedup.is_empty() {
+        dest.write_dedup_table(&dedup)?;
+    }
+
+    dest.write_u64(dest.schema_version_ptr(), src.read_u64(src.schema_version_ptr())?)?;
+
+    if src.id_counter_chain_if_any()? != 0 {
+        dest.write_id_counters(&src.read_id_counters()?)?;
+    }
+
+    let named_roots = src.read_named_roots()?;
+    if !named_roots.is_empty() {
+        dest.write_named_roots(&named_roots.into_iter().map(|(name, ptr)| (name, remap[&ptr])).collect())?;
+    }
+
+    // Pinned chains can't keep their pointers when the pages don't line up, but stay pinned in the new file
+    let pins = src.read_pin_table()?.into_iter()
+        .filter_map(|ptr| remap.get(&ptr).copied())
+        .collect();
+    dest.write_pin_table(&pins)?;
+
+    // Dictionaries are referred to by their index in the list, so keep them in the same order
+    let dictionaries = src.read_u64(src.dictionaries_ptr())?;
+    if dictionaries != 0 {
+        let mut new_dictionaries = Vec::new();
+        for dictionary in decode_u64s(&src.read(dictionaries)?)? {
+            let new_dictionary = dest.alloc()?;
+            dest.write_chain(new_dictionary, &src.read(dictionary)?)?;
+            new_dictionaries.push(new_dictionary);
+        }
+        let list_chain = dest.alloc()?;
+        dest.write_u64(dest.dictionaries_ptr(), list_chain)?;
+        dest.write_chain(list_chain, &encode_u64s(&new_dictionaries))?;
+    }
+
+    Ok(remap)
+}
+
+#[test]
+fn repage_to_larger_pages() {
+    let mut file = File::open("repage_src.verter", Config::default()).unwrap();
+    let texture = file.alloc().unwrap();
+    file.write(texture, &[0xAB; 5000]).unwrap();
+    file.incref(texture).unwrap();
+    let scene = file.alloc().unwrap();
+    file.write(scene, b"scene").unwrap();
+    file.write_root(&texture.to_le_bytes()).unwrap();
+    file.record_undo(b"op").unwrap();
+
+    let config = Config {
+        page_size: 4096,
+        ..Config::default()
+    };
+    let remap = repage(&mut file, "repage_dest.verter", config).unwrap();
+    drop(file);
+
+    let mut file = File::open("repage_dest.verter", config).unwrap();
+    assert_eq!(file.read(remap[&texture]).unwrap(), vec![0xAB; 5000]);
+    assert_eq!(file.read(remap[&scene]).unwrap(), b"scene");
+    assert_eq!(file.read_root().unwrap(), texture.to_le_bytes());
+    assert_eq!(file.refcount(remap[&texture]).unwrap(), 2);
+    assert_eq!(file.undo().unwrap().unwrap(), b"op");
+    file.validate().unwrap();
+
+    std::fs::remove_file("repage_src.verter").unwrap();
+    std::fs::remove_file("repage_dest.verter").unwrap();
+}
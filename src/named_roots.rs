@@ -0,0 +1,225 @@
+use std::collections::BTreeMap;
+
+use crate::{decode_u64s, Error, File, BYTES_IN_U64};
+
+impl File {
+
+    /// Register a chain under a name, such as `"scenes/intro"`, so it can be found again with `File::named_root`.
+    /// Named roots are kept by `File::gc` and `File::canonicalize` like the root chain, and `File::delete` refuses to delete them.
+    /// Replaces the chain registered under the name before, which is returned but not deleted.
+    /// Fails with `Error::UnsupportedVersion` for files created before format version 5, whose header has no room for the registry,
+    /// until `File::canonicalize` rewrites them in the current format.
+    pub fn set_named_root(&mut self, name: &str, ptr: u64) -> Result<Option<u64>, Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        self.check_not_reserved(ptr)?;
+        let mut table = self.read_named_roots()?;
+        let old = table.insert(name.to_owned(), ptr);
+        self.write_named_roots(&table)?;
+        Ok(old)
+    }
+
+    /// The chain registered under a name, or `None` if there is none.
+    pub fn named_root(&mut self, name: &str) -> Result<Option<u64>, Error> {
+        self.check_for_external_changes()?;
+        Ok(self.read_named_roots()?.remove(name))
+    }
+
+    /// Every name and the chain registered under it, sorted by name.
+    pub fn named_roots(&mut self) -> Result<Vec<(String, u64)>, Error> {
+        self.check_for_external_changes()?;
+        Ok(self.read_named_roots()?.into_iter().collect())
+    }
+
+    /// Unregister a name, returning the chain that was registered under it so it can be deleted, or `None` if there was none.
+    pub fn remove_named_root(&mut self, name: &str) -> Result<Option<u64>, Error> {
+        self.check_for_external_changes()?;
+        let mut table = self.read_named_roots()?;
+        let Some(ptr) = table.remove(name) else {
+            return Ok(None);
+        };
+        self.write_named_roots(&table)?;
+        Ok(Some(ptr))
+    }
+
+    /// Move the chain registered under `old` to the name `new`, without copying it.
+    /// Returns `false` if nothing is registered under `old`. Fails with `Error::InvalidPath` if something else is registered under `new`.
+    pub fn rename_root(&mut self, old: &str, new: &str) -> Result<bool, Error> {
+        self.check_for_external_changes()?;
+        if !self.read_named_roots()?.contains_key(old) {
+            return Ok(false);
+        }
+        self.rename_roots(&[(old, new)])?;
+        Ok(true)
+    }
+
+    /// Rename several named roots at once, with a single write of the registry, so either every rename happens or none do.
+    /// All names are renamed together, so names can be swapped.
+    /// Fails with `Error::InvalidPath` if nothing is registered under one of the old names,
+    /// or if two chains would end up under the same name.
+    pub fn rename_roots(&mut self, renames: &[(&str, &str)]) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        let mut table = self.read_named_roots()?;
+        let mut moved = Vec::new();
+        for (old, new) in renames {
+            let ptr = table.remove(*old).ok_or(Error::InvalidPath)?;
+            moved.push((new.to_string(), ptr));
+        }
+        insert_renamed(&mut table, moved)?;
+        self.write_named_roots(&table)
+    }
+
+    /// Rename every named root starting with `old_prefix` to start with `new_prefix` instead, such as `"scenes/"` to `"acts/"`,
+    /// with a single write of the registry. Returns how many were renamed.
+    /// Fails with `Error::InvalidPath` if two chains would end up under the same name, in which case nothing is renamed.
+    pub fn rename_prefix(&mut self, old_prefix: &str, new_prefix: &str) -> Result<usize, Error> {
+        self.rename_prefixes(&[(old_prefix, new_prefix)])
+    }
+
+    /// Apply several prefix renames at once like `File::rename_prefix`, so either all of them happen or none do.
+    /// Each name is renamed by the first of the prefixes it starts with. Returns how many names were renamed.
+    pub fn rename_prefixes(&mut self, renames: &[(&str, &str)]) -> Result<usize, Error> {
+        self.check_for_external_changes()?;
+        let mut table = self.read_named_roots()?;
+        let mut moved = Vec::new();
+        for name in table.keys().cloned().collect::<Vec<_>>() {
+            let Some(new) = renames.iter().find_map(|(old, new)| Some(format!("{new}{}", name.strip_prefix(*old)?))) else {
+                continue;
+            };
+            moved.push((new, table.remove(&name).unwrap()));
+        }
+        let count = moved.len();
+        if count > 0 {
+            insert_renamed(&mut table, moved)?;
+            self.write_named_roots(&table)?;
+        }
+        Ok(count)
+    }
+
+    /// The chains of every named root
+    pub(crate) fn named_root_chains(&mut self) -> Result<Vec<u64>, Error> {
+        Ok(self.read_named_roots()?.into_values().collect())
+    }
+
+    /// The chain holding the named roots, or 0 if there is none
+    pub(crate) fn named_root_table_if_any(&mut self) -> Result<u64, Error> {
+        if self.format_version < 5 {
+            return Ok(0);
+        }
+        self.read_u64(self.named_roots_ptr())
+    }
+
+    /// The registry is a list of entries sorted by name, each encoded as (name length, name bytes, chain)
+    pub(crate) fn read_named_roots(&mut self) -> Result<BTreeMap<String, u64>, Error> {
+        let chain = self.named_root_table_if_any()?;
+        if chain == 0 {
+            return Ok(BTreeMap::new());
+        }
+        let data = self.read_chain(chain)?;
+        let mut data = data.as_slice();
+        let mut table = BTreeMap::new();
+        while !data.is_empty() {
+            let (name_len, rest) = data.split_at_checked(BYTES_IN_U64 as usize).ok_or(Error::CorruptedFile)?;
+            let name_len = u64::from_le_bytes(name_len.try_into().unwrap()) as usize;
+            let (name, rest) = rest.split_at_checked(name_len).ok_or(Error::CorruptedFile)?;
+            let name = String::from_utf8(name.to_vec()).map_err(|_| Error::CorruptedFile)?;
+            let (chain, rest) = rest.split_at_checked(BYTES_IN_U64 as usize).ok_or(Error::CorruptedFile)?;
+            table.insert(name, decode_u64s(chain)?[0]);
+            data = rest;
+        }
+        Ok(table)
+    }
+
+    pub(crate) fn write_named_roots(&mut self, table: &BTreeMap<String, u64>) -> Result<(), Error> {
+        if self.format_version < 5 {
+            return Err(Error::UnsupportedVersion);
+        }
+        let mut data = Vec::new();
+        for (name, root) in table {
+            data.extend_from_slice(&(name.len() as u64).to_le_bytes());
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(&root.to_le_bytes());
+        }
+        self.as_one_change(|file| {
+            let mut chain = file.read_u64(file.named_roots_ptr())?;
+            if chain == 0 {
+                chain = file.alloc()?;
+                file.write_u64(file.named_roots_ptr(), chain)?;
+            }
+            file.write_chain(chain, &data)
+        })
+    }
+
+}
+
+/// Register renamed chains under their new names, failing if two chains would share a name
+fn insert_renamed(table: &mut BTreeMap<String, u64>, moved: Vec<(String, u64)>) -> Result<(), Error> {
+    for (name, ptr) in moved {
+        if table.insert(name, ptr).is_some() {
+            return Err(Error::InvalidPath);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn named_roots() {
+    use crate::Config;
+
+    let mut file = File::open("named_roots.verter", Config::default()).unwrap();
+    let intro = file.alloc_with(b"intro").unwrap();
+    let outro = file.alloc_with(b"outro").unwrap();
+    let notes = file.alloc_with(b"notes").unwrap();
+    assert_eq!(file.set_named_root("scenes/intro", intro).unwrap(), None);
+    file.set_named_root("scenes/outro", outro).unwrap();
+    file.set_named_root("notes", notes).unwrap();
+    assert_eq!(file.named_root("scenes/intro").unwrap(), Some(intro));
+
+    // Renaming moves names without copying their chains
+    assert!(file.rename_root("notes", "docs/notes").unwrap());
+    assert!(!file.rename_root("notes", "docs/notes").unwrap());
+    assert_eq!(file.rename_prefix("scenes/", "acts/one/").unwrap(), 2);
+    assert_eq!(file.named_roots().unwrap(), vec![
+        ("acts/one/intro".to_owned(), intro),
+        ("acts/one/outro".to_owned(), outro),
+        ("docs/notes".to_owned(), notes)
+    ]);
+    file.rename_roots(&[("acts/one/intro", "acts/one/outro"), ("acts/one/outro", "acts/one/intro")]).unwrap();
+    assert_eq!(file.named_root("acts/one/intro").unwrap(), Some(outro));
+
+    // A batch that fails part way renames nothing
+    for result in [
+        file.rename_roots(&[("docs/notes", "notes"), ("missing", "found")]),
+        file.rename_roots(&[("docs/notes", "acts/one/intro")]),
+        file.rename_prefix("docs/notes", "acts/one/intro").map(drop),
+        file.rename_prefixes(&[("acts/one/intro", "x"), ("acts/one/outro", "x")]).map(drop)
+    ] {
+        match result {
+            Err(Error::InvalidPath) => {},
+            Ok(_) | Err(_) => panic!("should error with invalid path")
+        }
+    }
+    assert_eq!(file.named_root("docs/notes").unwrap(), Some(notes));
+    // Both prefixes are applied together, so names moving between them don't collide
+    assert_eq!(file.rename_prefixes(&[("acts/one/", "acts/two/"), ("docs/", "acts/one/")]).unwrap(), 3);
+    assert_eq!(file.named_root("acts/one/notes").unwrap(), Some(notes));
+
+    // Named roots are kept by gc, and can't be deleted while registered
+    file.gc(&[], |_| Vec::new()).unwrap();
+    assert_eq!(file.read(intro).unwrap(), b"intro");
+    match file.delete(intro) {
+        Err(Error::ProtectedChain) => {},
+        Ok(_) | Err(_) => panic!("should error with protected chain")
+    }
+    assert_eq!(file.remove_named_root("acts/two/outro").unwrap(), Some(intro));
+    file.delete(intro).unwrap();
+    file.validate().unwrap();
+
+    // Canonicalizing keeps every name pointing at its chain
+    let remap = file.canonicalize(|_| Vec::new()).unwrap();
+    assert_eq!(file.named_root("acts/one/notes").unwrap(), Some(remap[&notes]));
+    assert_eq!(file.read(remap[&notes]).unwrap(), b"notes");
+    file.validate().unwrap();
+
+    std::fs::remove_file("named_roots.verter").unwrap();
+}
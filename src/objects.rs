@@ -0,0 +1,248 @@
+//! A table of objects identified by numeric IDs, stored in their own chains or inline in the table.
+
+use std::collections::BTreeMap;
+
+use crate::{decode_u64s, encode_u64s, Error, File, BYTES_IN_U64};
+
+/// The ID of an object in an `ObjectStore`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjId(pub u64);
+
+/// Where an object's data lives
+enum Entry {
+    /// The object is stored in its own chain
+    Chain(u64),
+    /// The object is small enough to be stored directly in the table
+    Inline(Vec<u8>)
+}
+
+impl Entry {
+
+    const CHAIN: u64 = 0;
+    const INLINE: u64 = 1;
+
+    /// Encode the entry as a (kind, value) pair of words.
+    /// For inline entries, the kind word also holds the length of the data in its second byte.
+    fn encode(&self) -> [u64; 2] {
+        match self {
+            Entry::Chain(chain) => [Self::CHAIN, *chain],
+            Entry::Inline(data) => {
+                let mut value = [0; BYTES_IN_U64 as usize];
+                value[..data.len()].copy_from_slice(data);
+                [Self::INLINE | ((data.len() as u64) << 8), u64::from_le_bytes(value)]
+            }
+        }
+    }
+
+    fn decode(kind: u64, value: u64) -> Result<Self, Error> {
+        match kind & 0xFF {
+            Self::CHAIN => Ok(Entry::Chain(value)),
+            Self::INLINE => {
+                let len = (kind >> 8) as usize;
+                if len > ObjectStore::MAX_INLINE_SIZE {
+                    return Err(Error::CorruptedFile);
+                }
+                Ok(Entry::Inline(value.to_le_bytes()[..len].to_vec()))
+            },
+            _ => Err(Error::CorruptedFile)
+        }
+    }
+
+}
+
+/// Maps object IDs to the chains storing the objects.
+/// Objects of up to 8 bytes are stored inline in the table instead of getting a chain of their own.
+/// The table is persisted in its own chain, so the store can be reopened later using `ObjectStore::open`.
+/// IDs are never reused, even after the object is removed.
+pub struct ObjectStore {
+    /// Pointer to the table chain
+    ptr: u64,
+    /// The ID the next inserted object will get
+    next_id: u64,
+    /// Object ID -> Object entry
+    objects: BTreeMap<u64, Entry>
+}
+
+impl ObjectStore {
+
+    /// The largest object that gets stored inline in the table
+    const MAX_INLINE_SIZE: usize = BYTES_IN_U64 as usize;
+
+    /// Create a new, empty object store in the file.
+    pub fn create(file: &mut File) -> Result<Self, Error> {
+        let ptr = file.alloc()?;
+        let store = Self {
+            ptr,
+            next_id: 1,
+            objects: BTreeMap::new()
+        };
+        store.save(file)?;
+        Ok(store)
+    }
+
+    /// Open an object store previously created with `ObjectStore::create`.
+    pub fn open(file: &mut File, ptr: u64) -> Result<Self, Error> {
+        let table = decode_u64s(&file.read(ptr)?)?;
+        let Some((next_id, entries)) = table.split_first() else {
+            return Err(Error::CorruptedFile);
+        };
+        if !entries.len().is_multiple_of(3) {
+            return Err(Error::CorruptedFile);
+        }
+        let objects = entries.chunks_exact(3)
+            .map(|entry| Ok((entry[0], Entry::decode(entry[1], entry[2])?)))
+            .collect::<Result<_, Error>>()?;
+        Ok(Self {
+            ptr,
+            next_id: *next_id,
+            objects
+        })
+    }
+
+    /// The pointer to the store's table chain, used to reopen the store.
+    pub fn ptr(&self) -> u64 {
+        self.ptr
+    }
+
+    /// The number of objects in the store.
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn contains(&self, id: ObjId) -> bool {
+        self.objects.contains_key(&id.0)
+    }
+
+    /// Iterate over the IDs of all objects in the store, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = ObjId> + '_ {
+        self.objects.keys().map(|id| ObjId(*id))
+    }
+
+    /// Store a new object, returning its ID.
+    pub fn insert(&mut self, file: &mut File, data: &[u8]) -> Result<ObjId, Error> {
+        let entry = Self::store(file, data)?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.objects.insert(id, entry);
+        self.save(file)?;
+
+        Ok(ObjId(id))
+    }
+
+    /// Read an object. Returns `None` if there is no object with the given ID.
+    pub fn get(&self, file: &mut File, id: ObjId) -> Result<Option<Vec<u8>>, Error> {
+        match self.objects.get(&id.0) {
+            Some(Entry::Chain(chain)) => file.read(*chain).map(Some),
+            Some(Entry::Inline(data)) => Ok(Some(data.clone())),
+            None => Ok(None)
+        }
+    }
+
+    /// Replace the data of an existing object. Returns `false` if there is no object with the given ID.
+    pub fn update(&mut self, file: &mut File, id: ObjId, data: &[u8]) -> Result<bool, Error> {
+        let Some(entry) = self.objects.get(&id.0) else {
+            return Ok(false);
+        };
+        if let Entry::Chain(chain) = entry {
+            if data.len() > Self::MAX_INLINE_SIZE {
+                file.write(*chain, data)?;
+                return Ok(true);
+            }
+        }
+
+        let old_entry = std::mem::replace(self.objects.get_mut(&id.0).unwrap(), Self::store(file, data)?);
+        self.save(file)?;
+        Self::free(file, old_entry)?;
+        Ok(true)
+    }
+
+    /// Remove an object, freeing its chain. Returns `false` if there is no object with the given ID.
+    pub fn remove(&mut self, file: &mut File, id: ObjId) -> Result<bool, Error> {
+        let Some(entry) = self.objects.remove(&id.0) else {
+            return Ok(false);
+        };
+        self.save(file)?;
+        Self::free(file, entry)?;
+        Ok(true)
+    }
+
+    /// Remove all objects and free the store's table chain.
+    pub fn delete(self, file: &mut File) -> Result<(), Error> {
+        file.delete(self.ptr)?;
+        for entry in self.objects.into_values() {
+            Self::free(file, entry)?;
+        }
+        Ok(())
+    }
+
+    /// Store an object's data, either inline or in a new chain
+    fn store(file: &mut File, data: &[u8]) -> Result<Entry, Error> {
+        if data.len() <= Self::MAX_INLINE_SIZE {
+            return Ok(Entry::Inline(data.to_vec()));
+        }
+        let chain = file.alloc()?;
+        file.write(chain, data)?;
+        Ok(Entry::Chain(chain))
+    }
+
+    fn free(file: &mut File, entry: Entry) -> Result<(), Error> {
+        match entry {
+            Entry::Chain(chain) => file.delete(chain),
+            Entry::Inline(_) => Ok(())
+        }
+    }
+
+    fn save(&self, file: &mut File) -> Result<(), Error> {
+        let mut table = vec![self.next_id];
+        for (id, entry) in &self.objects {
+            table.push(*id);
+            table.extend(entry.encode());
+        }
+        file.write(self.ptr, &encode_u64s(&table))
+    }
+
+}
+
+#[test]
+fn object_store() {
+    use crate::Config;
+
+    let mut file = File::open("object_store.verter", Config::default()).unwrap();
+    let mut store = ObjectStore::create(&mut file).unwrap();
+    let a = store.insert(&mut file, b"keyframe").unwrap();
+    let b = store.insert(&mut file, &[0xAB; 500]).unwrap();
+    let c = store.insert(&mut file, b"stroke").unwrap();
+    assert!(store.update(&mut file, a, b"moved keyframe").unwrap());
+    assert!(store.remove(&mut file, c).unwrap());
+    let ptr = store.ptr();
+    drop(file);
+
+    let mut file = File::open("object_store.verter", Config::default()).unwrap();
+    let mut store = ObjectStore::open(&mut file, ptr).unwrap();
+    assert_eq!(store.iter().collect::<Vec<_>>(), vec![a, b]);
+    assert_eq!(store.get(&mut file, a).unwrap().unwrap(), b"moved keyframe");
+    assert_eq!(store.get(&mut file, b).unwrap().unwrap(), vec![0xAB; 500]);
+    assert_eq!(store.get(&mut file, c).unwrap(), None);
+
+    // IDs are not reused
+    let d = store.insert(&mut file, b"new").unwrap();
+    assert!(d > c);
+
+    // Small objects don't take up any pages
+    let file_size = std::fs::metadata("object_store.verter").unwrap().len();
+    let tiny = store.insert(&mut file, &[1, 2, 3]).unwrap();
+    assert!(store.update(&mut file, b, &[4, 5]).unwrap());
+    drop(file);
+    let mut file = File::open("object_store.verter", Config::default()).unwrap();
+    let store = ObjectStore::open(&mut file, ptr).unwrap();
+    assert_eq!(store.get(&mut file, tiny).unwrap().unwrap(), vec![1, 2, 3]);
+    assert_eq!(store.get(&mut file, b).unwrap().unwrap(), vec![4, 5]);
+    assert_eq!(std::fs::metadata("object_store.verter").unwrap().len(), file_size);
+
+    std::fs::remove_file("object_store.verter").unwrap();
+}
@@ -0,0 +1,301 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::backend::StaticBytes;
+use crate::format::{page_map_offsets, page_map_pointer_len, page_map_stored_len, FIELD_SIZE, PAGE_GROUP_LEN};
+use crate::{Backend, Error, File};
+
+/// How the pages of a new file are laid out. See `Config::page_layout`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PageLayout {
+    /// Every page's header is stored right before its data
+    #[default]
+    Interleaved,
+    /// Page headers are stored apart from the data, in a page map at the start of every group of `format::PAGE_GROUP_LEN` pages.
+    /// The data of adjacent pages is then stored contiguously, so reading a long chain laid out in order reads one range of the file
+    /// per group, and walking a chain only reads the page maps. See `format::PAGE_MAP_FLAG`.
+    /// Versions of verter from before format version 6 can't open such files.
+    PageMaps
+}
+
+/// A backend storing a file with page maps, so the rest of verter can keep using the pointers of the interleaved layout as offsets.
+/// Ranges spanning several pages are split into the parts stored in page maps and the parts stored as data,
+/// and the parts stored next to each other are read or written together.
+pub(crate) struct PageMaps {
+    inner: Box<dyn Backend>,
+    /// The size of the file header the pages follow, or 0 while offsets are passed through as they are stored. See `File::with_stored_bytes`.
+    header_size: Arc<AtomicU64>,
+    page_size: u64,
+    pos: u64
+}
+
+impl PageMaps {
+
+    /// The ranges `len` bytes starting at the pointer `offset` are stored in, as (offset into the bytes, stored offset, length),
+    /// sorted by where they are stored and with the ranges stored right after each other merged into runs
+    fn runs(&self, offset: u64, len: u64) -> Vec<Vec<(usize, u64, u64)>> {
+        let header_size = self.header_size.load(Ordering::Relaxed);
+        if header_size == 0 {
+            return vec![vec![(0, offset, len)]];
+        }
+        let total_page_size = FIELD_SIZE + self.page_size;
+        let end = offset + len;
+        let mut pieces = Vec::new();
+        let mut pos = offset;
+        while pos < end {
+            let (stored, piece_end) = if pos < header_size {
+                (pos, header_size)
+            } else {
+                let in_page = (pos - header_size) % total_page_size;
+                let page = pos - in_page;
+                let (header, data) = page_map_offsets(header_size, self.page_size, page);
+                if in_page < FIELD_SIZE {
+                    (header + in_page, page + FIELD_SIZE)
+                } else {
+                    (data + in_page - FIELD_SIZE, page + total_page_size)
+                }
+            };
+            let piece_len = piece_end.min(end) - pos;
+            pieces.push(((pos - offset) as usize, stored, piece_len));
+            pos += piece_len;
+        }
+        pieces.sort_by_key(|(_, stored, _)| *stored);
+
+        let mut runs: Vec<Vec<(usize, u64, u64)>> = Vec::new();
+        for piece in pieces {
+            match runs.last_mut() {
+                Some(run) if run.last().is_some_and(|(_, stored, len)| stored + len == piece.1) => run.push(piece),
+                _ => runs.push(vec![piece])
+            }
+        }
+        runs
+    }
+
+    fn stored_len(&self, len: u64) -> u64 {
+        match self.header_size.load(Ordering::Relaxed) {
+            0 => len,
+            header_size => page_map_stored_len(header_size, self.page_size, len)
+        }
+    }
+
+}
+
+impl Read for PageMaps {
+
+    /// Past the end of the file, only the zeroed headers in the page map of the last group are stored,
+    /// so the length only needs to be looked up when a read runs out of stored bytes
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = match self.read_exact_at(buf, self.pos) {
+            Ok(()) => buf.len(),
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                let n = buf.len().min(self.len()?.saturating_sub(self.pos) as usize);
+                self.read_exact_at(&mut buf[..n], self.pos)?;
+                n
+            },
+            Err(err) => return Err(err)
+        };
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+}
+
+impl Write for PageMaps {
+
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for run in self.runs(self.pos, buf.len() as u64) {
+            let mut bytes = Vec::new();
+            for (offset, _, len) in &run {
+                bytes.extend_from_slice(&buf[*offset..*offset + *len as usize]);
+            }
+            self.inner.seek(SeekFrom::Start(run[0].1))?;
+            self.inner.write_all(&bytes)?;
+        }
+        self.pos += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+
+}
+
+impl Seek for PageMaps {
+
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len()?.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset)
+        };
+        self.pos = new_pos.ok_or(std::io::ErrorKind::InvalidInput)?;
+        Ok(self.pos)
+    }
+
+}
+
+impl Backend for PageMaps {
+
+    fn len(&self) -> std::io::Result<u64> {
+        let stored_len = self.inner.len()?;
+        Ok(match self.header_size.load(Ordering::Relaxed) {
+            0 => stored_len,
+            header_size => page_map_pointer_len(header_size, self.page_size, stored_len)
+        })
+    }
+
+    fn sync_data(&self) -> std::io::Result<()> {
+        self.inner.sync_data()
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        for run in self.runs(offset, buf.len() as u64) {
+            if let [(offset, stored, len)] = run[..] {
+                self.inner.read_exact_at(&mut buf[offset..offset + len as usize], stored)?;
+                continue;
+            }
+            let mut bytes = vec![0; run.iter().map(|(_, _, len)| *len as usize).sum()];
+            self.inner.read_exact_at(&mut bytes, run[0].1)?;
+            let mut rest = bytes.as_slice();
+            for (offset, _, len) in run {
+                let (piece, next) = rest.split_at(len as usize);
+                buf[offset..offset + len as usize].copy_from_slice(piece);
+                rest = next;
+            }
+        }
+        Ok(())
+    }
+
+    /// Cutting pages off the end of the file leaves their headers in the page map of the last group,
+    /// so they are zeroed in case the file grows again, like the bytes of pages added to the end of any other file
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        let header_size = self.header_size.load(Ordering::Relaxed);
+        let old_len = self.len()?;
+        self.inner.set_len(self.stored_len(len))?;
+        if header_size == 0 || len >= old_len || len < header_size {
+            return Ok(());
+        }
+        let total_page_size = FIELD_SIZE + self.page_size;
+        let pages = (len - header_size).div_ceil(total_page_size);
+        let old_pages = (old_len - header_size).div_ceil(total_page_size);
+        if pages.is_multiple_of(PAGE_GROUP_LEN) {
+            return Ok(());
+        }
+        let cut = old_pages.min(pages.next_multiple_of(PAGE_GROUP_LEN)) - pages;
+        let (header, _) = page_map_offsets(header_size, self.page_size, header_size + pages * total_page_size);
+        self.inner.seek(SeekFrom::Start(header))?;
+        self.inner.write_all(&vec![0; (cut * FIELD_SIZE) as usize])
+    }
+
+    fn lock_shared(&self) -> std::io::Result<()> {
+        self.inner.lock_shared()
+    }
+
+    fn lock_exclusive(&self) -> std::io::Result<()> {
+        self.inner.lock_exclusive()
+    }
+
+    fn unlock(&self) -> std::io::Result<()> {
+        self.inner.unlock()
+    }
+
+    fn available_space(&self) -> std::io::Result<Option<u64>> {
+        self.inner.available_space()
+    }
+
+    fn clone_into(&self, dest: &std::fs::File, len: u64) -> std::io::Result<bool> {
+        self.inner.clone_into(dest, self.stored_len(len))
+    }
+
+}
+
+impl File {
+
+    /// Whether the file keeps its page headers in page maps. See `PageLayout::PageMaps`.
+    pub(crate) fn has_page_maps(&self) -> bool {
+        self.page_maps.as_ref().is_some_and(|header_size| header_size.load(Ordering::Relaxed) != 0)
+    }
+
+    /// Read and write the file's pages through page maps from now on, once the size of its header is known,
+    /// or pick up a new header size if it already does
+    pub(crate) fn map_pages(&mut self) {
+        let header_size = self.header_size();
+        if let Some(state) = &self.page_maps {
+            state.store(header_size, Ordering::Relaxed);
+            return;
+        }
+        let state = Arc::new(AtomicU64::new(header_size));
+        let inner = std::mem::replace(&mut self.file, Box::new(StaticBytes::new(&[])));
+        self.file = Box::new(PageMaps { inner, header_size: state.clone(), page_size: self.config.page_size as u64, pos: 0 });
+        self.page_maps = Some(state);
+    }
+
+    /// Run `f` with the file's bytes as they are stored instead of as pointers see them, such as to copy the whole file.
+    /// The two only differ in files with page maps.
+    pub(crate) fn with_stored_bytes<T>(&mut self, f: impl FnOnce(&mut File) -> Result<T, Error>) -> Result<T, Error> {
+        let header_size = self.page_maps.as_ref().map(|state| state.swap(0, Ordering::Relaxed));
+        let result = f(self);
+        if let (Some(state), Some(header_size)) = (&self.page_maps, header_size) {
+            state.store(header_size, Ordering::Relaxed);
+        }
+        result
+    }
+
+}
+
+#[test]
+fn page_maps() {
+    use crate::format::{HeaderLayout, FORMAT_VERSION};
+    use crate::Config;
+
+    let config = Config { page_layout: PageLayout::PageMaps, page_size: 100, ..Config::default() };
+    let mut file = File::open("page_maps.verter", config).unwrap();
+    assert!(file.has_page_maps());
+    let frames = (0..3u8).map(|i| file.alloc_with(&vec![i; 40_000]).unwrap()).collect::<Vec<_>>();
+    let small = file.alloc_with(b"small").unwrap();
+    file.write_root(&crate::encode_u64s(&frames)).unwrap();
+    file.delete(frames[1]).unwrap();
+    file.write(frames[2], &[7; 100]).unwrap();
+    file.validate().unwrap();
+    let len = file.file_size().unwrap();
+    drop(file);
+
+    // The data of a chain written in one go is stored contiguously
+    let bytes = std::fs::read("page_maps.verter").unwrap();
+    let layout = HeaderLayout { magic_len: 8, format_version: FORMAT_VERSION, header_chain: false, page_maps: true };
+    assert_eq!(layout.stored_len(100, len), bytes.len() as u64);
+    assert_eq!(layout.pointer_len(100, bytes.len() as u64), len);
+    let data = layout.page_data_offset(100, frames[0]) as usize;
+    assert_eq!(&bytes[data..data + 40_000], &[0; 40_000][..]);
+    assert_eq!(crate::sniff_path("page_maps.verter").unwrap().page_size, Some(100));
+    let header = layout.page_header_offset(100, small) as usize;
+    assert_eq!(u64::from_le_bytes(bytes[header..header + 8].try_into().unwrap()) & !crate::format::HEAD_FLAG, (crate::format::FINAL_PAGE << crate::format::PAGE_TYPE_SHIFT) | 5);
+
+    let mut file = File::open("page_maps.verter", Config { page_layout: PageLayout::Interleaved, ..config }).unwrap();
+    assert!(file.has_page_maps());
+    assert_eq!(file.read(frames[0]).unwrap(), vec![0; 40_000]);
+    assert_eq!(file.read(frames[2]).unwrap(), [7; 100]);
+    assert_eq!(file.read(small).unwrap(), b"small");
+
+    // Pages cut off the end leave no headers behind for the pages that take their place
+    file.trim_tail().unwrap();
+    let grown = file.alloc_with(&[9; 1000]).unwrap();
+    file.validate().unwrap();
+    assert_eq!(file.read(grown).unwrap(), [9; 1000]);
+
+    // Copies of the file are copies of its stored bytes
+    let remap = file.canonicalize(|data| crate::decode_u64s(data).unwrap_or_default()).unwrap();
+    assert!(file.has_page_maps());
+    assert_eq!(file.read(remap[&frames[0]]).unwrap(), vec![0; 40_000]);
+    file.validate().unwrap();
+    let mut fork = file.fork_to("page_maps_fork.verter").unwrap();
+    assert_eq!(fork.read(remap[&small]).unwrap(), b"small");
+    fork.validate().unwrap();
+    drop(fork);
+    assert_eq!(crate::parse::Reader::from_bytes(&std::fs::read("page_maps_fork.verter").unwrap()).unwrap().read(remap[&small]).unwrap(), b"small");
+
+    std::fs::remove_file("page_maps.verter").unwrap();
+    std::fs::remove_file("page_maps_fork.verter").unwrap();
+}
@@ -0,0 +1,69 @@
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{try_zeroed, Error, File, BYTES_IN_U64};
+
+/// Where to find the data of a chain passed to `File::read_many`
+enum Source {
+    /// The pages of the chain, along with the number of bytes in the final page
+    Pages(Vec<u64>, u64),
+    /// The chain is in the cold tier, and was already read from there
+    Cold(Vec<u8>)
+}
+
+impl File {
+
+    /// Read the data from many page chains at once, reading their pages in parallel across rayon's thread pool.
+    /// Returns the result of reading each chain, in the same order as `ptrs`.
+    pub fn read_many(&mut self, ptrs: &[u64]) -> Vec<Result<Vec<u8>, Error>> {
+        // Walking a chain reads page headers through the file's cursor, so find every chain's pages up front
+        let sources = ptrs.iter().map(|ptr| self.read_source(*ptr)).collect::<Vec<_>>();
+
+        let this = &*self;
+        let meta_size = this.meta_size() as usize;
+        sources.into_par_iter().map(|source| match source? {
+            Source::Cold(data) => Ok(data),
+            Source::Pages(pages, final_size) => {
+                let mut data = try_zeroed((pages.len() - 1) * this.config.page_size + final_size as usize)?;
+                for (page, payload) in pages.iter().zip(this.split_payloads(&mut data, pages.len())) {
+                    this.file.read_exact_at(payload, page + BYTES_IN_U64).map_err(Error::IO)?;
+                }
+                if data.len() < meta_size {
+                    return Err(Error::CorruptedFile);
+                }
+                data.drain(..meta_size);
+                Ok(data)
+            }
+        }).collect()
+    }
+
+    fn read_source(&mut self, ptr: u64) -> Result<Source, Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        if let Some(data) = self.read_cold(ptr)? {
+            return Ok(Source::Cold(data));
+        }
+        let (pages, final_size) = self.chain_layout(ptr)?;
+        Ok(Source::Pages(pages, final_size))
+    }
+
+}
+
+#[test]
+fn read_many() {
+    use crate::Config;
+
+    let mut file = File::open("read_many.verter", Config::default()).unwrap();
+    let chains = (0..50u8).map(|i| file.alloc_with(&vec![i; i as usize * 37]).unwrap()).collect::<Vec<_>>();
+    file.delete(chains[10]).unwrap();
+
+    let results = file.read_many(&chains);
+    for (i, result) in results.into_iter().enumerate() {
+        match result {
+            Err(Error::DeletedPointer) if i == 10 => {},
+            Ok(data) if i != 10 => assert_eq!(data, vec![i as u8; i * 37]),
+            Ok(_) | Err(_) => panic!("chain {} was read incorrectly", i)
+        }
+    }
+
+    std::fs::remove_file("read_many.verter").unwrap();
+}
@@ -0,0 +1,224 @@
+//! Reading verter files straight from their bytes, following only the on-disk format described in `format`.
+//! A `Reader` has no way to write, lock or open anything, so it suits inspecting untrusted uploads on a server or viewing files in WASM.
+
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use crate::format::{HeaderLayout, DELETED_PAGE, FIELD_SIZE, FINAL_PAGE, FORMAT_VERSION, FROZEN_FLAG, HEADER_CHAIN_FLAG, HEAD_FLAG, NEXT_PAGE, PAGE_MAP_FLAG, PAGE_TYPE_SHIFT};
+use crate::{sniff, Error};
+
+/// A read-only view of a verter file in any seekable reader, without a `File` handle.
+/// Chains are read as they are stored, including the chain metadata of `Config::chain_meta` and `ChainFormat::V2`,
+/// and without decompressing compressed chains.
+pub struct Reader<R> {
+    reader: R,
+    layout: HeaderLayout,
+    page_size: u64,
+    /// The number of bytes the file's pointers span, which is less than the stored length in files with page maps
+    len: u64
+}
+
+impl<'a> Reader<Cursor<&'a [u8]>> {
+
+    /// Read a file held in memory, such as an uploaded file. See `Reader::new`.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, Error> {
+        Self::new(Cursor::new(bytes))
+    }
+
+}
+
+impl<R: Read + Seek> Reader<R> {
+
+    /// Read a file, telling its magic bytes, format version and page size from its contents with `sniff`.
+    /// Fails with `Error::InvalidFile` if it isn't a verter file, and with `Error::GeometryMismatch` if the page size can't be told,
+    /// in which case use `Reader::with_page_size`.
+    /// Files from before the format was versioned fail with `Error::UnsupportedVersion` until they are upgraded by opening them as a `File`.
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let info = sniff(&mut reader).ok_or(Error::InvalidFile)?;
+        let page_size = info.page_size.ok_or(Error::GeometryMismatch { page_size: None })?;
+        Self::with_layout(reader, info.magic.len() as u64, info.format_version, page_size)
+    }
+
+    /// Read a file whose page size is known, still telling its magic bytes and format version from its contents.
+    pub fn with_page_size(mut reader: R, page_size: usize) -> Result<Self, Error> {
+        let info = sniff(&mut reader).ok_or(Error::InvalidFile)?;
+        Self::with_layout(reader, info.magic.len() as u64, info.format_version, page_size)
+    }
+
+    fn with_layout(mut reader: R, magic_len: u64, format_version: u64, page_size: usize) -> Result<Self, Error> {
+        if format_version == 0 || format_version > FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion);
+        }
+        let len = reader.seek(SeekFrom::End(0)).map_err(Error::IO)?;
+        let layout = HeaderLayout { magic_len, format_version, header_chain: false, page_maps: false };
+        let mut reader = Self { reader, layout, page_size: page_size as u64, len };
+        if len < reader.layout.size() {
+            return Err(Error::InvalidFile);
+        }
+        let field = reader.read_u64(magic_len)?;
+        reader.layout.header_chain = field & HEADER_CHAIN_FLAG != 0;
+        reader.layout.page_maps = field & PAGE_MAP_FLAG != 0;
+        reader.len = reader.layout.pointer_len(reader.page_size, len);
+        Ok(reader)
+    }
+
+    /// The layout of the file's header.
+    pub fn layout(&self) -> HeaderLayout {
+        self.layout
+    }
+
+    /// The file's page size.
+    pub fn page_size(&self) -> usize {
+        self.page_size as usize
+    }
+
+    /// The pointer to the root chain, or `None` if it was never allocated.
+    pub fn root(&mut self) -> Result<Option<u64>, Error> {
+        let root = self.read_header_field("root_page")?;
+        Ok((root != 0).then_some(root))
+    }
+
+    /// The data stored in the root chain, which is empty if it was never allocated.
+    pub fn read_root(&mut self) -> Result<Vec<u8>, Error> {
+        match self.root()? {
+            Some(root) => self.read(root),
+            None => Ok(Vec::new())
+        }
+    }
+
+    /// The pointer to every chain in the file, in address order, including the chains verter uses for its own tables.
+    pub fn chains(&mut self) -> Result<Vec<u64>, Error> {
+        let mut chains = Vec::new();
+        let mut ptr = self.layout.size();
+        while ptr + FIELD_SIZE + self.page_size <= self.len {
+            let header = self.read_page_header(ptr)?;
+            if header & HEAD_FLAG != 0 && page_type(header) != DELETED_PAGE {
+                chains.push(ptr);
+            }
+            ptr += FIELD_SIZE + self.page_size;
+        }
+        Ok(chains)
+    }
+
+    /// The data stored in a chain.
+    /// Fails with `Error::InvalidPointer` if `ptr` isn't the start of a chain, and with `Error::CorruptedFile` if the chain is broken.
+    pub fn read(&mut self, ptr: u64) -> Result<Vec<u8>, Error> {
+        if !self.is_page(ptr) || self.read_page_header(ptr)? & HEAD_FLAG == 0 {
+            return Err(Error::InvalidPointer);
+        }
+        let mut data = Vec::new();
+        let mut page = ptr;
+        let max_pages = (self.len - self.layout.size()) / (FIELD_SIZE + self.page_size);
+        for _ in 0..max_pages {
+            let header = self.read_page_header(page)?;
+            let value = header & ((1 << PAGE_TYPE_SHIFT) - 1);
+            let (size, next) = match page_type(header) {
+                NEXT_PAGE if self.is_page(value) => (self.page_size, value),
+                FINAL_PAGE if value <= self.page_size => (value, 0),
+                DELETED_PAGE if page == ptr => return Err(Error::DeletedPointer),
+                _ => return Err(Error::CorruptedFile)
+            };
+            let start = data.len();
+            data.resize(start + size as usize, 0);
+            self.reader.seek(SeekFrom::Start(self.layout.page_data_offset(self.page_size, page))).map_err(Error::IO)?;
+            self.reader.read_exact(&mut data[start..]).map_err(Error::IO)?;
+            if next == 0 {
+                return Ok(data);
+            }
+            page = next;
+        }
+        // The chain has more pages than the file, so it loops
+        Err(Error::CorruptedFile)
+    }
+
+    fn is_page(&self, ptr: u64) -> bool {
+        ptr >= self.layout.size() && (ptr - self.layout.size()).is_multiple_of(FIELD_SIZE + self.page_size) && ptr + FIELD_SIZE + self.page_size <= self.len
+    }
+
+    /// Read a field of the file header, following the header chain of files upgraded from format version 0
+    fn read_header_field(&mut self, field: &str) -> Result<u64, Error> {
+        if !self.layout.header_chain {
+            return self.read_u64(self.layout.field_offset(field).unwrap());
+        }
+        let header_chain = self.read_u64(self.layout.field_offset("header_chain").unwrap())?;
+        let offset = self.layout.header_chain_offset(field, self.page_size).ok_or(Error::CorruptedFile)? as usize;
+        let data = self.read(header_chain)?;
+        let bytes = data.get(offset..offset + FIELD_SIZE as usize).ok_or(Error::CorruptedFile)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_page_header(&mut self, ptr: u64) -> Result<u64, Error> {
+        self.read_u64(self.layout.page_header_offset(self.page_size, ptr))
+    }
+
+    fn read_u64(&mut self, offset: u64) -> Result<u64, Error> {
+        let mut bytes = [0; FIELD_SIZE as usize];
+        self.reader.seek(SeekFrom::Start(offset)).map_err(Error::IO)?;
+        self.reader.read_exact(&mut bytes).map_err(Error::IO)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+}
+
+/// The page type of a page header, without the flags set on the first page of a chain
+fn page_type(header: u64) -> u64 {
+    (header & !(HEAD_FLAG | FROZEN_FLAG)) >> PAGE_TYPE_SHIFT
+}
+
+#[test]
+fn reader() {
+    use crate::{Config, File};
+
+    let mut file = File::open("parse_reader.verter", Config::default()).unwrap();
+    let frames = (0..5u8).map(|i| file.alloc_with(&vec![i; 100 + 150 * i as usize]).unwrap()).collect::<Vec<_>>();
+    let deleted = file.alloc_with(b"deleted").unwrap();
+    file.delete(deleted).unwrap();
+    file.write_root(&crate::encode_u64s(&frames)).unwrap();
+    drop(file);
+
+    let bytes = std::fs::read("parse_reader.verter").unwrap();
+    let mut reader = Reader::from_bytes(&bytes).unwrap();
+    assert_eq!(reader.page_size(), Config::default().page_size);
+    assert_eq!(reader.layout().format_version, FORMAT_VERSION);
+    assert_eq!(crate::decode_u64s(&reader.read_root().unwrap()).unwrap(), frames);
+    for (i, frame) in frames.iter().enumerate() {
+        assert_eq!(reader.read(*frame).unwrap(), vec![i as u8; 100 + 150 * i]);
+    }
+    let chains = reader.chains().unwrap();
+    assert!(frames.iter().all(|frame| chains.contains(frame)));
+    assert!(!chains.contains(&deleted));
+    match reader.read(frames[4] + 8) {
+        Err(Error::InvalidPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with invalid pointer")
+    }
+
+    // A chain looping back on itself is reported instead of being followed forever
+    let mut looped = bytes.clone();
+    let head = frames[4] as usize;
+    looped[head..head + 8].copy_from_slice(&(HEAD_FLAG | frames[4]).to_le_bytes());
+    match Reader::with_page_size(Cursor::new(looped.as_slice()), Config::default().page_size).unwrap().read(frames[4]) {
+        Err(Error::CorruptedFile) => {},
+        Ok(_) | Err(_) => panic!("should error with corrupted file")
+    }
+
+    // Files from before the format was versioned are only read once upgraded
+    let unversioned = std::fs::read("fixtures/v0.verter").unwrap();
+    match Reader::from_bytes(&unversioned) {
+        Err(Error::UnsupportedVersion) => {},
+        Ok(_) | Err(_) => panic!("should error with unsupported version")
+    }
+    std::fs::write("parse_reader_v0.verter", &unversioned).unwrap();
+    drop(File::open("parse_reader_v0.verter", Config::default()).unwrap());
+    let upgraded = std::fs::read("parse_reader_v0.verter").unwrap();
+    let mut reader = Reader::from_bytes(&upgraded).unwrap();
+    assert!(reader.layout().header_chain);
+    assert_eq!(reader.root().unwrap(), Some(reader.layout().size()));
+    assert_eq!(reader.read_root().unwrap(), File::open("parse_reader_v0.verter", Config::default()).unwrap().read_root().unwrap());
+
+    match Reader::from_bytes(b"not a verter file") {
+        Err(Error::InvalidFile) => {},
+        Ok(_) | Err(_) => panic!("should error with invalid file")
+    }
+
+    std::fs::remove_file("parse_reader.verter").unwrap();
+    std::fs::remove_file("parse_reader_v0.verter").unwrap();
+}
@@ -0,0 +1,319 @@
+use crate::{decode_u64s, encode_u64s, Error, File, BYTES_IN_U64};
+
+/// A partition's entry in the partition table
+pub(crate) struct PartitionEntry {
+    pub(crate) name: String,
+    /// The partition's root chain
+    pub(crate) root: u64,
+    /// The chain listing the chains allocated in the partition
+    pub(crate) members: u64
+}
+
+/// A logically separate store inside a file, with its own root chain and space accounting.
+/// Chains allocated through a partition can only be accessed through that partition.
+pub struct Partition<'a> {
+    file: &'a mut File,
+    name: String,
+    root: u64,
+    members: u64
+}
+
+impl File {
+
+    /// Get a handle to a partition of the file, creating the partition if it does not exist.
+    pub fn partition(&mut self, name: &str) -> Result<Partition<'_>, Error> {
+        self.check_for_external_changes()?;
+        let mut table = self.read_partition_table()?;
+        let (root, members) = match table.iter().find(|entry| entry.name == name) {
+            Some(entry) => (entry.root, entry.members),
+            None => {
+                let root = self.alloc()?;
+                let members = self.alloc()?;
+                table.push(PartitionEntry { name: name.to_owned(), root, members });
+                self.write_partition_table(&table)?;
+                (root, members)
+            }
+        };
+        Ok(Partition { file: self, name: name.to_owned(), root, members })
+    }
+
+    /// Get a handle for untrusted code, such as a third-party plugin, that can only reach the chains of the partition named `namespace`,
+    /// creating the partition if it does not exist.
+    /// The handle checks every pointer it is given against the partition's own list of chains, so a plugin can't read or change
+    /// the application's chains or another plugin's, even by guessing pointers, and can't delete the partition's root chain.
+    pub fn restricted(&mut self, namespace: &str) -> Result<Partition<'_>, Error> {
+        self.partition(namespace)
+    }
+
+    /// The names of every partition in the file.
+    pub fn partitions(&mut self) -> Result<Vec<String>, Error> {
+        Ok(self.read_partition_table()?.into_iter().map(|entry| entry.name).collect())
+    }
+
+    /// Delete a partition along with every chain in it.
+    /// Returns `false` if there is no partition with the given name.
+    /// The partition is only removed from the partition table once its chains are deleted, so if deleting one fails, such as a frozen chain,
+    /// the partition is kept along with the chains that couldn't be deleted.
+    pub fn remove_partition(&mut self, name: &str) -> Result<bool, Error> {
+        self.check_for_external_changes()?;
+        let mut table = self.read_partition_table()?;
+        let Some(idx) = table.iter().position(|entry| entry.name == name) else {
+            return Ok(false);
+        };
+
+        // Members deleted some other way are already gone
+        let mut members = decode_u64s(&self.read_chain(table[idx].members)?)?;
+        members.retain(|member| self.check_if_pointer_valid(*member).is_ok());
+        self.delete_many(&members)?;
+        self.delete_chain(table[idx].root)?;
+        let entry = table.remove(idx);
+        self.write_partition_table(&table)?;
+        self.delete_chain(entry.members)?;
+        Ok(true)
+    }
+
+    /// Remove chains that are about to be deleted from the member lists of the partitions they were allocated in
+    pub(crate) fn forget_partition_members(&mut self, ptrs: &[u64]) -> Result<(), Error> {
+        if self.read_u64(self.partition_table_ptr())? == 0 {
+            return Ok(());
+        }
+        for entry in self.read_partition_table()? {
+            let mut members = decode_u64s(&self.read_chain(entry.members)?)?;
+            let len = members.len();
+            members.retain(|member| !ptrs.contains(member));
+            if members.len() != len {
+                self.write_chain(entry.members, &encode_u64s(&members))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The root chains of every partition
+    pub(crate) fn partition_roots(&mut self) -> Result<Vec<u64>, Error> {
+        Ok(self.read_partition_table()?.into_iter().map(|entry| entry.root).collect())
+    }
+
+    /// The root chains of every partition, along with every chain allocated in a partition
+    pub(crate) fn partition_user_chains(&mut self) -> Result<Vec<u64>, Error> {
+        let mut chains = Vec::new();
+        for entry in self.read_partition_table()? {
+            chains.push(entry.root);
+            chains.extend(decode_u64s(&self.read_chain(entry.members)?)?);
+        }
+        Ok(chains)
+    }
+
+    /// The partition table chain and the member lists of every partition
+    pub(crate) fn partition_chains(&mut self) -> Result<Vec<u64>, Error> {
+        let table_chain = self.read_u64(self.partition_table_ptr())?;
+        if table_chain == 0 {
+            return Ok(Vec::new());
+        }
+        let mut chains = vec![table_chain];
+        chains.extend(self.read_partition_table()?.into_iter().map(|entry| entry.members));
+        Ok(chains)
+    }
+
+    /// The partition table is a list of entries, each encoded as (name length, name bytes, root, members)
+    pub(crate) fn read_partition_table(&mut self) -> Result<Vec<PartitionEntry>, Error> {
+        let chain = self.read_u64(self.partition_table_ptr())?;
+        if chain == 0 {
+            return Ok(Vec::new());
+        }
+        let data = self.read_chain(chain)?;
+        let mut data = data.as_slice();
+        let mut table = Vec::new();
+        while !data.is_empty() {
+            let (name_len, rest) = data.split_at_checked(BYTES_IN_U64 as usize).ok_or(Error::CorruptedFile)?;
+            let name_len = u64::from_le_bytes(name_len.try_into().unwrap()) as usize;
+            let (name, rest) = rest.split_at_checked(name_len).ok_or(Error::CorruptedFile)?;
+            let name = String::from_utf8(name.to_vec()).map_err(|_| Error::CorruptedFile)?;
+            let (ptrs, rest) = rest.split_at_checked(2 * BYTES_IN_U64 as usize).ok_or(Error::CorruptedFile)?;
+            let ptrs = decode_u64s(ptrs)?;
+            table.push(PartitionEntry { name, root: ptrs[0], members: ptrs[1] });
+            data = rest;
+        }
+        Ok(table)
+    }
+
+    pub(crate) fn write_partition_table(&mut self, table: &[PartitionEntry]) -> Result<(), Error> {
+        let mut chain = self.read_u64(self.partition_table_ptr())?;
+        if chain == 0 {
+            chain = self.alloc()?;
+            self.write_u64(self.partition_table_ptr(), chain)?;
+        }
+        let mut data = Vec::new();
+        for entry in table {
+            data.extend_from_slice(&(entry.name.len() as u64).to_le_bytes());
+            data.extend_from_slice(entry.name.as_bytes());
+            data.extend_from_slice(&encode_u64s(&[entry.root, entry.members]));
+        }
+        self.write_chain(chain, &data)
+    }
+
+}
+
+impl Partition<'_> {
+
+    /// The name of the partition.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Allocate a new chain in the partition.
+    pub fn alloc(&mut self) -> Result<u64, Error> {
+        let ptr = self.file.alloc()?;
+        let mut members = self.members()?;
+        members.push(ptr);
+        self.file.write_chain(self.members, &encode_u64s(&members))?;
+        Ok(ptr)
+    }
+
+    /// Allocate a new chain in the partition holding `data`.
+    pub fn alloc_with(&mut self, data: &[u8]) -> Result<u64, Error> {
+        let ptr = self.alloc()?;
+        self.file.write(ptr, data)?;
+        Ok(ptr)
+    }
+
+    /// Read the data from a chain in the partition.
+    pub fn read(&mut self, ptr: u64) -> Result<Vec<u8>, Error> {
+        self.check_member(ptr)?;
+        self.file.read(ptr)
+    }
+
+    /// Write data to a chain in the partition.
+    pub fn write(&mut self, ptr: u64, data: &[u8]) -> Result<(), Error> {
+        self.check_member(ptr)?;
+        self.file.write(ptr, data)
+    }
+
+    /// Apply byte-range edits to a chain in the partition, like `File::patch`.
+    pub fn patch(&mut self, ptr: u64, edits: &[(u64, &[u8])]) -> Result<(), Error> {
+        self.check_member(ptr)?;
+        self.file.patch(ptr, edits)
+    }
+
+    /// Delete a chain in the partition.
+    pub fn delete(&mut self, ptr: u64) -> Result<(), Error> {
+        self.check_member(ptr)?;
+        self.file.delete(ptr)
+    }
+
+    /// Read the partition's root chain.
+    pub fn read_root(&mut self) -> Result<Vec<u8>, Error> {
+        self.file.read(self.root)
+    }
+
+    /// Write to the partition's root chain.
+    pub fn write_root(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.file.write(self.root, data)
+    }
+
+    /// The chains allocated in the partition, not including its root chain.
+    pub fn chains(&mut self) -> Result<Vec<u64>, Error> {
+        self.members()
+    }
+
+    /// The number of bytes taken up by the pages of the partition's chains, including its root chain.
+    pub fn used_bytes(&mut self) -> Result<u64, Error> {
+        let mut pages = self.file.chain_pages(self.root)?.len() as u64;
+        for chain in self.members()? {
+            pages += self.file.chain_pages(chain)?.len() as u64;
+        }
+        Ok(pages * self.file.total_page_size())
+    }
+
+    fn members(&mut self) -> Result<Vec<u64>, Error> {
+        decode_u64s(&self.file.read_chain(self.members)?)
+    }
+
+    fn check_member(&mut self, ptr: u64) -> Result<(), Error> {
+        if !self.members()?.contains(&ptr) {
+            return Err(Error::InvalidPointer);
+        }
+        Ok(())
+    }
+
+}
+
+#[test]
+fn partitions() {
+    use crate::Config;
+
+    let mut file = File::open("partitions.verter", Config::default()).unwrap();
+    let mut scenes = file.partition("scenes").unwrap();
+    let scene = scenes.alloc().unwrap();
+    scenes.write(scene, &[0xAB; 500]).unwrap();
+    scenes.write_root(&scene.to_le_bytes()).unwrap();
+    assert_eq!(scenes.used_bytes().unwrap(), 6 * 128);
+
+    let mut assets = file.partition("assets").unwrap();
+    assets.write_root(b"asset index").unwrap();
+    match assets.read(scene) {
+        Err(Error::InvalidPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with invalid pointer")
+    }
+    drop(file);
+
+    let mut file = File::open("partitions.verter", Config::default()).unwrap();
+    assert_eq!(file.partitions().unwrap(), vec!["scenes".to_owned(), "assets".to_owned()]);
+    let mut scenes = file.partition("scenes").unwrap();
+    assert_eq!(scenes.read_root().unwrap(), scene.to_le_bytes());
+    assert_eq!(scenes.read(scene).unwrap(), vec![0xAB; 500]);
+    assert_eq!(scenes.chains().unwrap(), vec![scene]);
+
+    // Partition chains are only freed through their partition
+    assert_eq!(file.gc(&[], |_| Vec::new()).unwrap(), 0);
+
+    // Chains deleted through the file leave their partition too
+    let mut scenes = file.partition("scenes").unwrap();
+    let draft = scenes.alloc_with(b"draft").unwrap();
+    file.delete(draft).unwrap();
+    let mut scenes = file.partition("scenes").unwrap();
+    assert_eq!(scenes.chains().unwrap(), vec![scene]);
+    assert_eq!(scenes.used_bytes().unwrap(), 6 * 128);
+
+    assert!(file.remove_partition("scenes").unwrap());
+    assert!(file.find_unreachable(&[], |_| Vec::new()).unwrap().chains.is_empty());
+    assert_eq!(file.partitions().unwrap(), vec!["assets".to_owned()]);
+    assert_eq!(file.partition("assets").unwrap().read_root().unwrap(), b"asset index");
+    file.validate().unwrap();
+
+    std::fs::remove_file("partitions.verter").unwrap();
+}
+
+#[test]
+fn restricted() {
+    use crate::Config;
+
+    let mut file = File::open("restricted.verter", Config::default()).unwrap();
+    let project = file.alloc_with(b"project").unwrap();
+    let mut other_plugin = file.restricted("other_plugin").unwrap();
+    let other_settings = other_plugin.alloc_with(b"other settings").unwrap();
+
+    let mut plugin = file.restricted("plugin").unwrap();
+    let settings = plugin.alloc_with(b"settings").unwrap();
+    plugin.patch(settings, &[(0, b"S")]).unwrap();
+    assert_eq!(plugin.read(settings).unwrap(), b"Settings");
+    // Chains outside the plugin's namespace can't be reached through its handle
+    for ptr in [project, other_settings] {
+        match plugin.read(ptr) {
+            Err(Error::InvalidPointer) => {},
+            Ok(_) | Err(_) => panic!("should error with invalid pointer")
+        }
+        match plugin.patch(ptr, &[(0, b"X")]) {
+            Err(Error::InvalidPointer) => {},
+            Ok(_) | Err(_) => panic!("should error with invalid pointer")
+        }
+        match plugin.delete(ptr) {
+            Err(Error::InvalidPointer) => {},
+            Ok(_) | Err(_) => panic!("should error with invalid pointer")
+        }
+    }
+    assert_eq!(file.read(project).unwrap(), b"project");
+    assert_eq!(file.restricted("other_plugin").unwrap().read(other_settings).unwrap(), b"other settings");
+    file.validate().unwrap();
+
+    std::fs::remove_file("restricted.verter").unwrap();
+}
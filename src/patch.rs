@@ -0,0 +1,123 @@
+use std::io::{Seek, SeekFrom, Write};
+
+use crate::{try_zeroed, Error, File, PageHeader, BYTES_IN_U64};
+
+impl File {
+
+    /// Apply a set of byte-range edits to a chain, only touching the pages the edits cover.
+    /// Each edit is an offset into the chain's data along with the bytes to write there.
+    /// If an edit goes past the end of the chain, the chain is extended, with any gap filled with zeros.
+    pub fn patch(&mut self, ptr: u64, edits: &[(u64, &[u8])]) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        self.check_not_frozen(ptr)?;
+        self.check_not_reserved(ptr)?;
+
+        if self.config.log_structured || self.is_cold(ptr)? {
+            // Editing pages in place would overwrite the old version, and cold chains are not stored in this file's pages,
+            // so rewrite the whole chain instead
+            let mut data = self.read(ptr)?;
+            for (offset, bytes) in edits {
+                let end = *offset as usize + bytes.len();
+                if data.len() < end {
+                    data.resize(end, 0);
+                }
+                data[*offset as usize..end].copy_from_slice(bytes);
+            }
+            return self.write(ptr, &data);
+        }
+
+        // Edit offsets are relative to the data after the chain's metadata
+        let meta_size = self.meta_size();
+        let (mut pages, final_size) = self.chain_layout(ptr)?;
+        let page_size = self.config.page_size as u64;
+        let old_len = (pages.len() as u64 - 1) * page_size + final_size;
+        let new_len = edits.iter().map(|(offset, bytes)| meta_size + offset + bytes.len() as u64).fold(old_len, u64::max);
+        if self.config.max_chain_size.is_some_and(|max_chain_size| new_len - meta_size > max_chain_size) {
+            return Err(Error::LimitExceeded);
+        }
+
+        let old_page_count = pages.len();
+        self.reserve_growth(ptr, pages.len(), self.pages_needed(new_len) as usize)?;
+        while (pages.len() as u64) < self.pages_needed(new_len) {
+            pages.push(self.alloc_page()?);
+        }
+
+        if new_len > old_len {
+            self.write_at(&pages, old_len, &try_zeroed((new_len - old_len) as usize)?)?;
+        }
+        for (offset, bytes) in edits {
+            self.write_at(&pages, meta_size + *offset, bytes)?;
+        }
+        if self.config.chain_meta {
+            self.touch_meta(ptr)?;
+        }
+
+        if new_len > old_len {
+            // Link the new pages into the chain only once their contents are written
+            for i in (old_page_count - 1)..pages.len() {
+                let header = match pages.get(i + 1) {
+                    Some(next) => PageHeader::NextPage(*next),
+                    None => PageHeader::FinalPage(new_len - (pages.len() as u64 - 1) * page_size)
+                };
+                if i == 0 {
+                    self.write_head_page_header(pages[i], header)?;
+                } else {
+                    self.write_page_header(pages[i], header)?;
+                }
+            }
+        }
+        self.write_summary(&pages, new_len - meta_size)?;
+
+        self.bump_change_counter()?;
+        self.hooks.write(ptr, edits.iter().map(|(_, bytes)| bytes.len()).sum());
+        Ok(())
+    }
+
+    /// Write bytes at an offset into the data of a chain made of the given pages
+    pub(crate) fn write_at(&mut self, pages: &[u64], offset: u64, mut bytes: &[u8]) -> Result<(), Error> {
+        self.check_writable()?;
+        let page_size = self.config.page_size as u64;
+        let mut page_idx = (offset / page_size) as usize;
+        let mut page_offset = offset % page_size;
+        while !bytes.is_empty() {
+            let page = *pages.get(page_idx).ok_or(Error::CorruptedFile)?;
+            let n = bytes.len().min((page_size - page_offset) as usize);
+            self.file.seek(SeekFrom::Start(page + BYTES_IN_U64 + page_offset)).map_err(Error::IO)?;
+            self.file.write_all(&bytes[..n]).map_err(Error::IO)?;
+            bytes = &bytes[n..];
+            page_idx += 1;
+            page_offset = 0;
+        }
+        Ok(())
+    }
+
+}
+
+#[test]
+fn patch() {
+    use crate::Config;
+
+    let mut file = File::open("patch.verter", Config::default()).unwrap();
+    let alloc = file.alloc().unwrap();
+    let mut expected = (0..1000).map(|i| i as u8).collect::<Vec<_>>();
+    file.write(alloc, &expected).unwrap();
+
+    file.patch(alloc, &[(5, b"hello"), (115, &[0xAB; 10]), (990, &[0xCD; 20])]).unwrap();
+    expected[5..10].copy_from_slice(b"hello");
+    expected[115..125].fill(0xAB);
+    expected.truncate(990);
+    expected.extend_from_slice(&[0xCD; 20]);
+    assert_eq!(file.read(alloc).unwrap(), expected);
+
+    // Edits past the end leave a zero-filled gap
+    file.patch(alloc, &[(1200, b"end")]).unwrap();
+    expected.resize(1200, 0);
+    expected.extend_from_slice(b"end");
+    drop(file);
+
+    let mut file = File::open("patch.verter", Config::default()).unwrap();
+    assert_eq!(file.read(alloc).unwrap(), expected);
+
+    std::fs::remove_file("patch.verter").unwrap();
+}
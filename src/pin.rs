@@ -0,0 +1,142 @@
+use std::collections::BTreeSet;
+
+use crate::{decode_u64s, encode_u64s, Error, File, PageHeader};
+
+impl File {
+
+    /// Pin a chain, so that `File::canonicalize` keeps it at the same pointer instead of moving it.
+    /// For chains whose pointers are stored outside the file, such as in sidecar files, and so could never be remapped.
+    /// Pins are stored in the file, and are dropped when the chain is deleted.
+    pub fn pin(&mut self, ptr: u64) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        self.check_not_reserved(ptr)?;
+        let mut pins = self.read_pin_table()?;
+        if pins.insert(ptr) {
+            self.write_pin_table(&pins)?;
+        }
+        Ok(())
+    }
+
+    /// Allow a pinned chain to be moved again. Returns whether the chain was pinned.
+    pub fn unpin(&mut self, ptr: u64) -> Result<bool, Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        self.check_not_reserved(ptr)?;
+        let mut pins = self.read_pin_table()?;
+        let pinned = pins.remove(&ptr);
+        if pinned {
+            self.write_pin_table(&pins)?;
+        }
+        Ok(pinned)
+    }
+
+    /// Whether a chain is pinned.
+    pub fn is_pinned(&mut self, ptr: u64) -> Result<bool, Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        Ok(self.read_pin_table()?.contains(&ptr))
+    }
+
+    /// Every pinned chain, in address order.
+    pub fn pinned(&mut self) -> Result<Vec<u64>, Error> {
+        self.check_for_external_changes()?;
+        Ok(self.read_pin_table()?.into_iter().collect())
+    }
+
+    /// Unpin a chain that is about to be deleted
+    pub(crate) fn forget_pin(&mut self, ptr: u64) -> Result<(), Error> {
+        if self.read_u64(self.pin_table_ptr())? == 0 {
+            return Ok(());
+        }
+        let mut pins = self.read_pin_table()?;
+        if pins.remove(&ptr) {
+            self.write_pin_table(&pins)?;
+        }
+        Ok(())
+    }
+
+    /// Allocate empty chains at exactly the given pointers of a freshly created file, for copying pinned chains into.
+    /// The file is grown to reach every pointer, and the pages in between are freed in address order,
+    /// so they are handed out to other chains from the start of the file.
+    pub(crate) fn alloc_pinned(&mut self, pins: &BTreeSet<u64>) -> Result<(), Error> {
+        let Some(last) = pins.last() else {
+            return Ok(());
+        };
+        let mut filler = Vec::new();
+        while self.file_size()? <= *last {
+            let page = self.append_page()?;
+            if pins.contains(&page) {
+                self.write_head_page_header(page, PageHeader::FinalPage(self.meta_size()))?;
+                if self.meta_size() > 0 {
+                    self.init_meta(page)?;
+                }
+            } else {
+                self.write_page_header(page, PageHeader::FinalPage(0))?;
+                filler.push(page);
+            }
+        }
+        // Freed pages go to the front of the free list
+        for page in filler.into_iter().rev() {
+            self.free_pages(page)?;
+        }
+        self.bump_change_counter()
+    }
+
+    pub(crate) fn read_pin_table(&mut self) -> Result<BTreeSet<u64>, Error> {
+        let chain = self.read_u64(self.pin_table_ptr())?;
+        if chain == 0 {
+            return Ok(BTreeSet::new());
+        }
+        Ok(decode_u64s(&self.read_chain(chain)?)?.into_iter().collect())
+    }
+
+    pub(crate) fn write_pin_table(&mut self, pins: &BTreeSet<u64>) -> Result<(), Error> {
+        let mut chain = self.read_u64(self.pin_table_ptr())?;
+        if chain == 0 {
+            if pins.is_empty() {
+                return Ok(());
+            }
+            chain = self.alloc()?;
+            self.write_u64(self.pin_table_ptr(), chain)?;
+        }
+        self.write_chain(chain, &encode_u64s(&pins.iter().copied().collect::<Vec<_>>()))
+    }
+
+}
+
+#[test]
+fn pin() {
+    use crate::Config;
+
+    let mut file = File::open("pin.verter", Config::default()).unwrap();
+    let garbage = file.alloc_with(&[0xAB; 1000]).unwrap();
+    let sidecar = file.alloc_with(b"referenced by a sidecar file").unwrap();
+    let other = file.alloc_with(b"free to move").unwrap();
+    file.pin(sidecar).unwrap();
+    assert!(file.is_pinned(sidecar).unwrap());
+    assert!(!file.is_pinned(other).unwrap());
+    file.delete(garbage).unwrap();
+    drop(file);
+
+    let mut file = File::open("pin.verter", Config::default()).unwrap();
+    assert_eq!(file.pinned().unwrap(), vec![sidecar]);
+    let remap = file.canonicalize(|_| Vec::new()).unwrap();
+    assert_eq!(remap[&sidecar], sidecar);
+    assert_ne!(remap[&other], other);
+    assert_eq!(file.read(sidecar).unwrap(), b"referenced by a sidecar file");
+    assert_eq!(file.read(remap[&other]).unwrap(), b"free to move");
+    assert_eq!(file.pinned().unwrap(), vec![sidecar]);
+    file.validate().unwrap();
+
+    // Moved chains fill the space before the pinned chain
+    assert!(remap[&other] < sidecar);
+
+    assert!(file.unpin(sidecar).unwrap());
+    assert!(!file.unpin(sidecar).unwrap());
+    file.pin(sidecar).unwrap();
+    file.delete(sidecar).unwrap();
+    assert!(file.pinned().unwrap().is_empty());
+
+    std::fs::remove_file("pin.verter").unwrap();
+}
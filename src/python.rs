@@ -0,0 +1,71 @@
+use pyo3::{create_exception, exceptions::{PyException, PyIOError}, prelude::*, types::PyBytes};
+
+use crate::{Config, Error};
+
+create_exception!(verter, VerterError, PyException);
+
+fn to_py_err(err: Error) -> PyErr {
+    match err {
+        Error::IO(err) => PyIOError::new_err(err.to_string()),
+        err => VerterError::new_err(format!("{:?}", err))
+    }
+}
+
+/// A verter file, opened from Python.
+#[pyclass(name = "File")]
+struct PyFile {
+    file: crate::File
+}
+
+#[pymethods]
+impl PyFile {
+
+    #[new]
+    #[pyo3(signature = (path, page_size = None, magic_bytes = None))]
+    fn open(path: std::path::PathBuf, page_size: Option<usize>, magic_bytes: Option<Vec<u8>>) -> PyResult<Self> {
+        let mut config = Config::default();
+        if let Some(page_size) = page_size {
+            config.page_size = page_size;
+        }
+        if let Some(magic_bytes) = magic_bytes {
+            // The config needs the magic bytes for the whole lifetime of the program
+            config.magic_bytes = Box::leak(magic_bytes.into_boxed_slice());
+        }
+        let file = crate::File::open(path, config).map_err(to_py_err)?;
+        Ok(Self { file })
+    }
+
+    fn read<'py>(&mut self, py: Python<'py>, ptr: u64) -> PyResult<Bound<'py, PyBytes>> {
+        let data = self.file.read(ptr).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &data))
+    }
+
+    fn write(&mut self, ptr: u64, data: &[u8]) -> PyResult<()> {
+        self.file.write(ptr, data).map_err(to_py_err)
+    }
+
+    fn alloc(&mut self) -> PyResult<u64> {
+        self.file.alloc().map_err(to_py_err)
+    }
+
+    fn delete(&mut self, ptr: u64) -> PyResult<()> {
+        self.file.delete(ptr).map_err(to_py_err)
+    }
+
+    fn read_root<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let data = self.file.read_root().map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &data))
+    }
+
+    fn write_root(&mut self, data: &[u8]) -> PyResult<()> {
+        self.file.write_root(data).map_err(to_py_err)
+    }
+
+}
+
+#[pymodule]
+fn verter(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyFile>()?;
+    m.add("VerterError", m.py().get_type::<VerterError>())?;
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use std::collections::HashSet;
+use std::io::{Seek, SeekFrom, Write};
+
+use crate::{decode_u64s, encode_u64s, Error, File, PageHeader, BYTES_IN_U64};
+
+/// A damaged page that was cut out of its chain by `File::remap_bad_page`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PageLoss {
+    /// The damaged page
+    pub page: u64,
+    /// The zeroed page that took its place in the chain.
+    /// Equal to `page` if the damaged page was the first page of a chain, which was repaired in place since its pointer is in use.
+    pub replacement: u64,
+    /// Whether the header of the damaged page could not be trusted, so every page after it in the chain was lost too
+    pub lost_tail: bool
+}
+
+impl File {
+
+    /// Cut a damaged page out of its chain, losing the data in that page rather than the whole file.
+    /// The page is replaced with a newly allocated, zeroed page and added to the quarantine list, so it is never reused.
+    /// If the damaged page's link to the rest of the chain is broken, the chain ends at the replacement page.
+    /// Deleted pages can't be remapped, but a damaged free list can be rebuilt with `File::sort_free_list`.
+    pub fn remap_bad_page(&mut self, ptr: u64) -> Result<PageLoss, Error> {
+        self.check_for_external_changes()?;
+        if !self.is_page_ptr(ptr) || self.quarantined_pages()?.contains(&ptr) {
+            return Err(Error::InvalidPointer);
+        }
+
+        let next = match self.read_page_header(ptr) {
+            Ok(PageHeader::DeletedPage(_)) => return Err(Error::DeletedPointer),
+            Ok(PageHeader::NextPage(next)) if self.link_is_sound(next) => Some(PageHeader::NextPage(next)),
+            Ok(PageHeader::FinalPage(size)) if size <= self.config.page_size as u64 => Some(PageHeader::FinalPage(size)),
+            _ => None
+        };
+        let lost_tail = next.is_none();
+        // Keep the rest of the chain readable by filling the replacement page
+        let header = next.unwrap_or(PageHeader::FinalPage(self.config.page_size as u64));
+
+        let replacement = match self.page_linking_to(ptr)? {
+            Some(prev) => {
+                let replacement = self.alloc_page()?;
+                self.write_page_header(replacement, header)?;
+                self.zero_payload(replacement)?;
+                let prev_header = PageHeader::NextPage(replacement);
+                if self.is_head_page(prev)? {
+                    self.write_head_page_header(prev, prev_header)?;
+                } else {
+                    self.write_page_header(prev, prev_header)?;
+                }
+                replacement
+            },
+            None => {
+                self.write_head_page_header(ptr, header)?;
+                self.zero_payload(ptr)?;
+                ptr
+            }
+        };
+
+        let loss = PageLoss { page: ptr, replacement, lost_tail };
+        let mut quarantine = self.quarantine()?;
+        quarantine.push(loss);
+        self.write_quarantine(&quarantine)?;
+        self.bump_change_counter()?;
+        Ok(loss)
+    }
+
+    /// Every page remapped by `File::remap_bad_page`, in the order they were remapped.
+    pub fn quarantine(&mut self) -> Result<Vec<PageLoss>, Error> {
+        let chain = self.read_u64(self.quarantine_table_ptr())?;
+        if chain == 0 {
+            return Ok(Vec::new());
+        }
+        let entries = decode_u64s(&self.read_chain(chain)?)?;
+        if !entries.len().is_multiple_of(3) {
+            return Err(Error::CorruptedFile);
+        }
+        Ok(entries.chunks_exact(3).map(|entry| PageLoss {
+            page: entry[0],
+            replacement: entry[1],
+            lost_tail: entry[2] != 0
+        }).collect())
+    }
+
+    /// The pages taken out of use by `File::remap_bad_page`, which are neither part of a chain nor in the free list
+    pub(crate) fn quarantined_pages(&mut self) -> Result<HashSet<u64>, Error> {
+        if self.read_u64(self.quarantine_table_ptr())? == 0 {
+            return Ok(HashSet::new());
+        }
+        Ok(self.quarantine()?.into_iter()
+            .filter(|loss| loss.replacement != loss.page)
+            .map(|loss| loss.page)
+            .collect())
+    }
+
+    fn write_quarantine(&mut self, quarantine: &[PageLoss]) -> Result<(), Error> {
+        let mut chain = self.read_u64(self.quarantine_table_ptr())?;
+        if chain == 0 {
+            chain = self.alloc()?;
+            self.write_u64(self.quarantine_table_ptr(), chain)?;
+        }
+        let entries = quarantine.iter().flat_map(|loss| [loss.page, loss.replacement, loss.lost_tail as u64]).collect::<Vec<_>>();
+        self.write_chain(chain, &encode_u64s(&entries))
+    }
+
+    /// Find the page whose header links to `ptr`, skipping pages whose headers can't be read
+    fn page_linking_to(&mut self, ptr: u64) -> Result<Option<u64>, Error> {
+        let quarantined = self.quarantined_pages()?;
+        let file_size = self.file_size()?;
+        let mut page = self.header_size();
+        while page + self.total_page_size() <= file_size {
+            if !quarantined.contains(&page) && matches!(self.read_page_header(page), Ok(PageHeader::NextPage(next)) if next == ptr) {
+                return Ok(Some(page));
+            }
+            page += self.total_page_size();
+        }
+        Ok(None)
+    }
+
+    fn zero_payload(&mut self, ptr: u64) -> Result<(), Error> {
+        self.check_writable()?;
+        self.file.seek(SeekFrom::Start(ptr + BYTES_IN_U64)).map_err(Error::IO)?;
+        self.file.write_all(&vec![0; self.config.page_size]).map_err(Error::IO)
+    }
+
+}
+
+#[test]
+fn remap_bad_page() {
+    use crate::Config;
+
+    let mut file = File::open("remap_bad_page.verter", Config::default()).unwrap();
+    let data = (0..600).map(|i| i as u8).collect::<Vec<_>>();
+    let chain = file.alloc_with(&data).unwrap();
+    let pages = file.chain_pages(chain).unwrap();
+    let page_size = Config::default().page_size;
+
+    // A page whose header is intact keeps the rest of the chain
+    let loss = file.remap_bad_page(pages[2]).unwrap();
+    assert!(!loss.lost_tail);
+    assert_ne!(loss.replacement, pages[2]);
+    let mut expected = data.clone();
+    expected[2 * page_size..3 * page_size].fill(0);
+    assert_eq!(file.read(chain).unwrap(), expected);
+
+    // A page with a damaged header takes the rest of the chain with it
+    file.write_u64(pages[1], (0x55 << PageHeader::TYPE_SHIFT) | 3).unwrap();
+    assert_eq!(file.scrub(|_, _| true).unwrap().bad_pages, vec![pages[1]]);
+    let loss = file.remap_bad_page(pages[1]).unwrap();
+    assert!(loss.lost_tail);
+    let mut expected = data[..2 * page_size].to_vec();
+    expected[page_size..].fill(0);
+    assert_eq!(file.read(chain).unwrap(), expected);
+
+    assert_eq!(file.quarantine().unwrap().len(), 2);
+    assert!(file.scrub(|_, _| true).unwrap().bad_pages.is_empty());
+    // The quarantined pages are never handed out again
+    for _ in 0..10 {
+        let ptr = file.alloc().unwrap();
+        assert!(ptr != pages[1] && ptr != pages[2]);
+    }
+
+    std::fs::remove_file("remap_bad_page.verter").unwrap();
+}
@@ -0,0 +1,189 @@
+//! Direct access to a file's pages, for building custom structures such as B-trees on verter pages instead of on chains.
+//!
+//! Everything in this module is covered by semver like the rest of the crate: the functions and the meaning of the values they return
+//! only change in a new major version. The layout of the pages themselves is described in `format`.
+//!
+//! Raw pages are chains of exactly one full page, so the rest of verter keeps treating them like any other chain:
+//! they are kept by `File::gc` and `File::canonicalize` when a tracer returns them, and are checked by `File::validate`.
+//! Pages can only be used raw in files without chain metadata, since it would take up the start of every page.
+
+use std::io::{Seek, SeekFrom, Write};
+
+use crate::{Error, File, BYTES_IN_U64};
+
+/// The header at the start of every page, which links the pages of chains and of the free list together
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageHeader {
+    /// The page is followed by the page at this pointer in its chain
+    Next(u64),
+    /// The page is the last page of its chain, holding this many bytes. Raw pages are always full.
+    Final(u64),
+    /// The page is free, and the next free page is at this pointer, or 0 if it is the last one
+    Free(u64)
+}
+
+/// The number of bytes of data a page holds, which is `Config::page_size`.
+pub fn page_size(file: &File) -> usize {
+    file.config.page_size
+}
+
+/// The number of pages in the file, both free and in use.
+pub fn page_count(file: &mut File) -> Result<u64, Error> {
+    Ok((file.file_size()? - file.header_size()) / file.total_page_size())
+}
+
+/// The pointer to the page with the given index, counting from the start of the file, for scanning every page.
+pub fn page_ptr(file: &File, idx: u64) -> u64 {
+    file.header_size() + idx * file.total_page_size()
+}
+
+/// Read the header of any page.
+/// Fails with `Error::InvalidPointer` if `page` isn't the start of a page.
+pub fn page_header(file: &mut File, page: u64) -> Result<PageHeader, Error> {
+    check_page(file, page)?;
+    Ok(match file.read_page_header(page)? {
+        crate::PageHeader::NextPage(next) => PageHeader::Next(next),
+        crate::PageHeader::FinalPage(len) => PageHeader::Final(len),
+        crate::PageHeader::DeletedPage(next) => PageHeader::Free(next)
+    })
+}
+
+/// Whether a page is the first page of a chain, which raw pages always are.
+/// Fails with `Error::InvalidPointer` if `page` isn't the start of a page.
+pub fn is_chain_start(file: &mut File, page: u64) -> Result<bool, Error> {
+    check_page(file, page)?;
+    file.is_head_page(page)
+}
+
+/// Read all `page_size` bytes of a page in use, whether it is a raw page or a page of a chain.
+/// Fails with `Error::DeletedPointer` if the page is free.
+pub fn read_page(file: &mut File, page: u64) -> Result<Vec<u8>, Error> {
+    file.check_for_external_changes()?;
+    if let PageHeader::Free(_) = page_header(file, page)? {
+        return Err(Error::DeletedPointer);
+    }
+    let mut data = vec![0; file.config.page_size];
+    file.file.seek(SeekFrom::Start(page + BYTES_IN_U64)).map_err(Error::IO)?;
+    std::io::Read::read_exact(&mut file.file, &mut data).map_err(Error::IO)?;
+    Ok(data)
+}
+
+/// Overwrite the start of a raw page with `data`, leaving the rest of the page as it was.
+/// The page is written in place, even in a `Config::log_structured` file.
+/// Fails with `Error::InvalidPointer` if the page isn't a raw page, so the pages of ordinary chains can't be corrupted through it,
+/// with `Error::ReservedChain` if it holds one of verter's own tables, and with `Error::RecordTooLarge` if `data` is longer than a page.
+pub fn write_page(file: &mut File, page: u64, data: &[u8]) -> Result<(), Error> {
+    file.check_writable()?;
+    file.check_for_external_changes()?;
+    check_raw_page(file, page)?;
+    file.check_not_reserved(page)?;
+    file.check_not_frozen(page)?;
+    if data.len() > file.config.page_size {
+        return Err(Error::RecordTooLarge);
+    }
+    file.file.seek(SeekFrom::Start(page + BYTES_IN_U64)).map_err(Error::IO)?;
+    file.file.write_all(data).map_err(Error::IO)?;
+    file.bump_change_counter()?;
+    file.hooks.write(page, data.len());
+    Ok(())
+}
+
+/// Allocate a raw page. Its contents are left over from whatever used the page before, unless `Config::zero_new_pages` is enabled.
+/// Fails with `Error::InvalidConfig` if the file has chain metadata.
+pub fn alloc_page(file: &mut File) -> Result<u64, Error> {
+    Ok(alloc_pages(file, 1)?[0])
+}
+
+/// Allocate several raw pages at once, chosen by `Config::alloc_policy` like the pages of a chain,
+/// so that pages allocated together are adjacent where possible. See `alloc_page`.
+pub fn alloc_pages(file: &mut File, count: usize) -> Result<Vec<u64>, Error> {
+    if file.meta_size() > 0 {
+        return Err(Error::InvalidConfig);
+    }
+    file.check_writable()?;
+    file.check_for_external_changes()?;
+    let pages = file.alloc_pages(count)?;
+    for page in &pages {
+        file.write_head_page_header(*page, crate::PageHeader::FinalPage(file.config.page_size as u64))?;
+        file.hooks.alloc(*page);
+    }
+    file.bump_change_counter()?;
+    Ok(pages)
+}
+
+/// Free a raw page, like `File::delete`.
+/// Fails with `Error::InvalidPointer` if the page isn't a raw page.
+pub fn free_page(file: &mut File, page: u64) -> Result<(), Error> {
+    file.check_for_external_changes()?;
+    check_raw_page(file, page)?;
+    file.delete(page)
+}
+
+fn check_page(file: &mut File, page: u64) -> Result<(), Error> {
+    if page < file.header_size() || !(page - file.header_size()).is_multiple_of(file.total_page_size()) || page + file.total_page_size() > file.file_size()? {
+        return Err(Error::InvalidPointer);
+    }
+    Ok(())
+}
+
+/// Check that a page is a chain of one full page
+fn check_raw_page(file: &mut File, page: u64) -> Result<(), Error> {
+    match page_header(file, page)? {
+        PageHeader::Free(_) => Err(Error::DeletedPointer),
+        PageHeader::Final(len) if len == file.config.page_size as u64 && file.is_head_page(page)? => Ok(()),
+        _ => Err(Error::InvalidPointer)
+    }
+}
+
+#[test]
+fn raw_pages() {
+    use crate::{decode_u64s, encode_u64s, Config};
+
+    let mut file = File::open("raw_pages.verter", Config::default()).unwrap();
+    let nodes = alloc_pages(&mut file, 3).unwrap();
+    write_page(&mut file, nodes[0], &encode_u64s(&nodes[1..])).unwrap();
+    write_page(&mut file, nodes[1], &[0xAB; 120]).unwrap();
+    assert_eq!(read_page(&mut file, nodes[0]).unwrap()[..16], encode_u64s(&nodes[1..]));
+    assert_eq!(read_page(&mut file, nodes[1]).unwrap(), [0xAB; 120]);
+    assert_eq!(page_header(&mut file, nodes[1]).unwrap(), PageHeader::Final(page_size(&file) as u64));
+    assert!(is_chain_start(&mut file, nodes[2]).unwrap());
+    // Raw pages are chains like any other
+    assert_eq!(file.read(nodes[1]).unwrap(), [0xAB; 120]);
+    file.validate().unwrap();
+
+    // The pages of ordinary chains can be read but not written
+    let chain = file.alloc_with(&[1; 300]).unwrap();
+    let second = match page_header(&mut file, chain).unwrap() {
+        PageHeader::Next(next) => next,
+        header => panic!("unexpected header {header:?}")
+    };
+    assert!(!is_chain_start(&mut file, second).unwrap());
+    assert_eq!(read_page(&mut file, second).unwrap(), [1; 120]);
+    for page in [chain, second] {
+        match write_page(&mut file, page, b"corrupt") {
+            Err(Error::InvalidPointer) => {},
+            Ok(_) | Err(_) => panic!("should error with invalid pointer")
+        }
+    }
+    match write_page(&mut file, nodes[2], &[0; 121]) {
+        Err(Error::RecordTooLarge) => {},
+        Ok(_) | Err(_) => panic!("should error with record too large")
+    }
+    match page_header(&mut file, nodes[2] + 8) {
+        Err(Error::InvalidPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with invalid pointer")
+    }
+
+    free_page(&mut file, nodes[2]).unwrap();
+    assert!(matches!(page_header(&mut file, nodes[2]).unwrap(), PageHeader::Free(_)));
+    match read_page(&mut file, nodes[2]) {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+    let pages = (0..page_count(&mut file).unwrap()).map(|idx| page_ptr(&file, idx)).collect::<Vec<_>>();
+    assert!(nodes.iter().all(|node| pages.contains(node)));
+    assert_eq!(decode_u64s(&read_page(&mut file, nodes[0]).unwrap()[..16]).unwrap(), nodes[1..]);
+    file.validate().unwrap();
+
+    std::fs::remove_file("raw_pages.verter").unwrap();
+}
@@ -0,0 +1,65 @@
+use crate::{Error, File};
+
+impl File {
+
+    /// Refuse or allow changes through this handle, such as to keep the file unchanged while a backup of it runs.
+    /// While the handle is read-only, anything that would modify the file fails with `Error::ReadOnly` before writing anything.
+    /// Other handles and processes can still change the file.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Whether changes through this handle are refused. See `File::set_read_only`.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Return `Error::ReadOnly` if the handle was made read-only with `File::set_read_only`
+    pub(crate) fn check_writable(&self) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        Ok(())
+    }
+
+}
+
+#[test]
+fn read_only() {
+    use crate::Config;
+
+    let config = Config { chain_meta: true, ..Config::default() };
+    let mut file = File::open("read_only.verter", config).unwrap();
+    file.write_root(b"project").unwrap();
+    let frame = file.alloc_with(&[0xAB; 300]).unwrap();
+    let before = std::fs::read("read_only.verter").unwrap();
+
+    file.set_read_only(true);
+    assert!(file.is_read_only());
+    assert_eq!(file.read_root().unwrap(), b"project");
+    assert_eq!(file.read(frame).unwrap(), vec![0xAB; 300]);
+    file.validate().unwrap();
+    let results = [
+        file.write_root(b"changed"),
+        file.write(frame, b"changed"),
+        file.alloc().map(drop),
+        file.delete(frame),
+        file.patch(frame, &[(0, b"changed")]),
+        file.set_chain_flags(frame, 1),
+        file.append_op(b"op").map(drop),
+        file.canonicalize(|_| Vec::new()).map(drop)
+    ];
+    for result in results {
+        match result {
+            Err(Error::ReadOnly) => {},
+            Ok(_) | Err(_) => panic!("should error with read only")
+        }
+    }
+    assert_eq!(std::fs::read("read_only.verter").unwrap(), before);
+
+    file.set_read_only(false);
+    file.write(frame, b"changed").unwrap();
+    assert_eq!(file.read(frame).unwrap(), b"changed");
+
+    std::fs::remove_file("read_only.verter").unwrap();
+}
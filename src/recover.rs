@@ -0,0 +1,108 @@
+//! Tools for getting data back out of files too damaged to open.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::{Config, Error, File, PageHeader, SalvageReport};
+
+/// How many pages at the start of the file are used to guess where pages start
+const ALIGNMENT_SAMPLE_PAGES: u64 = 4096;
+
+/// Extract every plausible chain from a file whose header was destroyed into a fresh file at `dest`.
+/// Unlike `File::salvage`, nothing in the header is trusted, including the magic bytes and the root pointer,
+/// so the root chain is recovered like any other chain and `SalvageReport::root_recovered` is always false.
+/// The file is scanned at page granularity, using whichever page alignment makes the most page headers look valid,
+/// and pages that would overlap the header of a file with this configuration are skipped.
+/// The recovered chains are only candidates: internal chains such as the undo history are extracted too,
+/// and pointers stored inside the recovered data are not rewritten, so use `SalvageReport::recovered` to remap them.
+/// Files laid out with `PageLayout::PageMaps` keep their page headers apart from their data and can't be carved without their header.
+pub fn carve<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dest: Q, config: Config) -> Result<SalvageReport, Error> {
+    let src = std::fs::File::open(src).map_err(Error::IO)?;
+    let mut src = File::unchecked(Box::new(src), config);
+    let mut dest = File::open(dest, config)?;
+
+    let file_size = src.file_size()?;
+    let page_size = src.total_page_size();
+    let alignment = guess_alignment(&src, file_size)?;
+
+    let mut headers = HashMap::new();
+    let header_size = src.header_size();
+    let mut ptr = header_size + (alignment + page_size - header_size % page_size) % page_size;
+    while ptr + page_size <= file_size {
+        let header = src.read_u64(ptr)?;
+        if let Some(header) = plausible_header(&src, header, alignment, file_size) {
+            headers.insert(ptr, header);
+        }
+        ptr += page_size;
+    }
+
+    src.extract_chains(&headers, None, &mut dest)
+}
+
+/// The offset of the first page within every run of `total_page_size` bytes that makes the most page headers look valid
+fn guess_alignment(src: &File, file_size: u64) -> Result<u64, Error> {
+    let page_size = src.total_page_size();
+    let sample_size = file_size.min(ALIGNMENT_SAMPLE_PAGES * page_size);
+    let mut sample = vec![0; sample_size as usize];
+    src.file.read_exact_at(&mut sample, 0).map_err(Error::IO)?;
+
+    let mut best = (0, 0);
+    for alignment in 0..page_size {
+        let plausible = (alignment..sample_size.saturating_sub(7))
+            .step_by(page_size as usize)
+            .filter(|ptr| {
+                let header = u64::from_le_bytes(sample[*ptr as usize..*ptr as usize + 8].try_into().unwrap());
+                plausible_header(src, header, alignment, file_size).is_some()
+            })
+            .count();
+        if plausible > best.1 {
+            best = (alignment, plausible);
+        }
+    }
+    Ok(best.0)
+}
+
+/// Parse a page header, rejecting ones that point to somewhere no page could start or hold more bytes than fit in a page
+fn plausible_header(src: &File, header: u64, alignment: u64, file_size: u64) -> Option<PageHeader> {
+    let page_size = src.total_page_size();
+    let plausible_ptr = |ptr: u64| ptr % page_size == alignment && ptr + page_size <= file_size;
+    match PageHeader::from_u64(header).ok()? {
+        PageHeader::NextPage(next) if plausible_ptr(next) => Some(PageHeader::NextPage(next)),
+        PageHeader::FinalPage(size) if size <= src.config.page_size as u64 => Some(PageHeader::FinalPage(size)),
+        PageHeader::DeletedPage(next) if next == 0 || plausible_ptr(next) => Some(PageHeader::DeletedPage(next)),
+        PageHeader::NextPage(_) | PageHeader::FinalPage(_) | PageHeader::DeletedPage(_) => None
+    }
+}
+
+#[test]
+fn carve_without_header() {
+    use std::io::{Seek, SeekFrom, Write};
+
+    let mut file = File::open("carve_src.verter", Config::default()).unwrap();
+    file.write_root(b"root data").unwrap();
+    let small = file.alloc_with(b"small chain").unwrap();
+    let large = file.alloc_with(&[0xAB; 1000]).unwrap();
+    let header_size = file.header_size();
+    drop(file);
+
+    // Wipe out the whole header
+    let mut raw = std::fs::OpenOptions::new().write(true).open("carve_src.verter").unwrap();
+    raw.seek(SeekFrom::Start(0)).unwrap();
+    raw.write_all(&vec![0xFF; header_size as usize]).unwrap();
+    drop(raw);
+    assert!(File::open("carve_src.verter", Config::default()).is_err());
+
+    let report = carve("carve_src.verter", "carve_dest.verter", Config::default()).unwrap();
+    assert!(!report.root_recovered);
+    let mut file = File::open("carve_dest.verter", Config::default()).unwrap();
+    let mut recovered = HashMap::new();
+    for (old, new) in &report.recovered {
+        recovered.insert(*old, file.read(*new).unwrap());
+    }
+    assert_eq!(recovered[&small], b"small chain");
+    assert_eq!(recovered[&large], vec![0xAB; 1000]);
+    assert!(recovered.values().any(|data| data == b"root data"));
+
+    std::fs::remove_file("carve_src.verter").unwrap();
+    std::fs::remove_file("carve_dest.verter").unwrap();
+}
@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+
+use crate::{decode_u64s, encode_u64s, Error, File};
+
+impl File {
+
+    /// Get the reference count of a chain.
+    /// Chains that were never increfed have a reference count of 1.
+    pub fn refcount(&mut self, ptr: u64) -> Result<u64, Error> {
+        self.check_if_pointer_valid(ptr)?;
+        Ok(self.read_refcount_table()?.get(&ptr).copied().unwrap_or(1))
+    }
+
+    /// Increment the reference count of a chain, returning the new count.
+    pub fn incref(&mut self, ptr: u64) -> Result<u64, Error> {
+        self.check_if_pointer_valid(ptr)?;
+        self.check_not_reserved(ptr)?;
+        let mut table = self.read_refcount_table()?;
+        let count = table.get(&ptr).copied().unwrap_or(1) + 1;
+        table.insert(ptr, count);
+        self.write_refcount_table(&table)?;
+        Ok(count)
+    }
+
+    /// Decrement the reference count of a chain, returning the new count.
+    /// When the count reaches 0, the chain is deleted with `File::delete`, so the last decref fails like `File::delete` would
+    /// for chains that can't be deleted, such as the root chain, and leaves the count at 1.
+    pub fn decref(&mut self, ptr: u64) -> Result<u64, Error> {
+        self.check_if_pointer_valid(ptr)?;
+        self.check_not_reserved(ptr)?;
+        let mut table = self.read_refcount_table()?;
+        let count = table.remove(&ptr).unwrap_or(1) - 1;
+        if count == 0 {
+            // Deleting the chain forgets its reference count
+            self.delete(ptr)?;
+            return Ok(0);
+        }
+        if count > 1 {
+            table.insert(ptr, count);
+        }
+        self.write_refcount_table(&table)?;
+        Ok(count)
+    }
+
+    /// Remove the reference count of a chain that is about to be deleted
+    pub(crate) fn forget_refcount(&mut self, ptr: u64) -> Result<(), Error> {
+        if self.read_u64(self.refcount_table_ptr())? == 0 {
+            return Ok(());
+        }
+        let mut table = self.read_refcount_table()?;
+        if table.remove(&ptr).is_some() {
+            self.write_refcount_table(&table)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read_refcount_table(&mut self) -> Result<BTreeMap<u64, u64>, Error> {
+        let chain = self.read_u64(self.refcount_table_ptr())?;
+        if chain == 0 {
+            return Ok(BTreeMap::new());
+        }
+        let entries = decode_u64s(&self.read(chain)?)?;
+        if !entries.len().is_multiple_of(2) {
+            return Err(Error::CorruptedFile);
+        }
+        Ok(entries.chunks_exact(2).map(|entry| (entry[0], entry[1])).collect())
+    }
+
+    pub(crate) fn write_refcount_table(&mut self, table: &BTreeMap<u64, u64>) -> Result<(), Error> {
+        let mut chain = self.read_u64(self.refcount_table_ptr())?;
+        if chain == 0 {
+            if table.is_empty() {
+                return Ok(());
+            }
+            chain = self.alloc()?;
+            self.write_u64(self.refcount_table_ptr(), chain)?;
+        }
+        let entries = table.iter().flat_map(|(ptr, count)| [*ptr, *count]).collect::<Vec<_>>();
+        self.write_chain(chain, &encode_u64s(&entries))
+    }
+
+}
+
+#[test]
+fn refcount() {
+    use crate::Config;
+
+    let mut file = File::open("refcount.verter", Config::default()).unwrap();
+    let texture = file.alloc().unwrap();
+    file.write(texture, b"pixels").unwrap();
+    assert_eq!(file.incref(texture).unwrap(), 2);
+    assert_eq!(file.incref(texture).unwrap(), 3);
+    drop(file);
+
+    let mut file = File::open("refcount.verter", Config::default()).unwrap();
+    assert_eq!(file.decref(texture).unwrap(), 2);
+    assert_eq!(file.decref(texture).unwrap(), 1);
+    assert_eq!(file.read(texture).unwrap(), b"pixels");
+    assert_eq!(file.decref(texture).unwrap(), 0);
+    match file.read(texture) {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+
+    // The last decref refuses to delete chains that `File::delete` refuses to delete
+    file.write_root(b"root").unwrap();
+    let root = file.root_page().unwrap();
+    let named = file.alloc_with(b"named").unwrap();
+    file.set_named_root("named", named).unwrap();
+    for ptr in [root, named] {
+        match file.decref(ptr) {
+            Err(Error::ProtectedChain) => {},
+            Ok(_) | Err(_) => panic!("should error with protected chain")
+        }
+        assert_eq!(file.refcount(ptr).unwrap(), 1);
+    }
+    let frozen = file.alloc_with(b"frozen").unwrap();
+    file.freeze(frozen).unwrap();
+    match file.decref(frozen) {
+        Err(Error::FrozenChain) => {},
+        Ok(_) | Err(_) => panic!("should error with frozen chain")
+    }
+    assert_eq!(file.read(frozen).unwrap(), b"frozen");
+    assert_eq!(file.read_root().unwrap(), b"root");
+    file.validate().unwrap();
+
+    std::fs::remove_file("refcount.verter").unwrap();
+}
@@ -0,0 +1,337 @@
+//! Serve a verter file over TCP, and access it from other machines with `RemoteFile`.
+//! Every message is a frame made of its length as a u64 followed by its bytes.
+//! The first frame a client sends is its authentication token.
+//! Frames longer than the server's limit end the connection before anything is allocated for them.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::{try_zeroed, Error, File, WriterStatus, BYTES_IN_U64};
+
+const READ: u8 = 0;
+const WRITE: u8 = 1;
+const ALLOC: u8 = 2;
+const DELETE: u8 = 3;
+const READ_ROOT: u8 = 4;
+const WRITE_ROOT: u8 = 5;
+
+const OK: u8 = 0;
+
+/// The longest authentication token a server reads
+const MAX_TOKEN_LEN: usize = 4096;
+/// The longest request a server reads by default
+const DEFAULT_MAX_FRAME_LEN: usize = 256 * 1024 * 1024;
+/// How long a server waits on a client by default
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn error_code(err: &Error) -> u8 {
+    match err {
+        Error::IO(_) => 1,
+        Error::InvalidFile => 2,
+        Error::InvalidPointer => 3,
+        Error::DeletedPointer => 4,
+        Error::CorruptedFile => 5,
+        Error::RecordTooLarge => 6,
+        Error::LimitExceeded => 7,
+        Error::ConcurrentModification => 8,
+        Error::InvalidPath => 9,
+        Error::MetadataDisabled => 10,
+        Error::NoColdTier => 11,
+        Error::AuthenticationFailed => 12,
+        Error::UnsupportedVersion => 13,
+        Error::Cancelled => 14,
+        Error::PreparedTransaction => 15,
+        Error::InsufficientSpace => 16,
+        Error::OutdatedSchema => 17,
+        Error::OutOfMemory => 18,
+        Error::InvalidConfig => 19,
+        Error::ReadOnly => 20,
+        Error::FrozenChain => 21,
+        Error::ReservedChain => 22,
+        Error::GeometryMismatch { .. } => 23,
+        Error::TruncatedFile { .. } => 24,
+        Error::ProtectedChain => 25,
+        Error::WriterActive(_) => 26
+    }
+}
+
+fn error_from_code(code: u8, message: &[u8]) -> Error {
+    match code {
+        2 => Error::InvalidFile,
+        3 => Error::InvalidPointer,
+        4 => Error::DeletedPointer,
+        5 => Error::CorruptedFile,
+        6 => Error::RecordTooLarge,
+        7 => Error::LimitExceeded,
+        8 => Error::ConcurrentModification,
+        9 => Error::InvalidPath,
+        10 => Error::MetadataDisabled,
+        11 => Error::NoColdTier,
+        12 => Error::AuthenticationFailed,
+        13 => Error::UnsupportedVersion,
+        14 => Error::Cancelled,
+        15 => Error::PreparedTransaction,
+        16 => Error::InsufficientSpace,
+        17 => Error::OutdatedSchema,
+        18 => Error::OutOfMemory,
+        19 => Error::InvalidConfig,
+        20 => Error::ReadOnly,
+        21 => Error::FrozenChain,
+        22 => Error::ReservedChain,
+        23 => Error::GeometryMismatch { page_size: std::str::from_utf8(message).ok().and_then(|page_size| page_size.parse().ok()) },
+        24 => Error::TruncatedFile { missing_bytes: std::str::from_utf8(message).ok().and_then(|missing_bytes| missing_bytes.parse().ok()).unwrap_or(0) },
+        25 => Error::ProtectedChain,
+        26 => match std::str::from_utf8(message).ok().and_then(WriterStatus::decode) {
+            Some(status) => Error::WriterActive(status),
+            None => Error::IO(std::io::Error::other("another writer is active"))
+        },
+        _ => Error::IO(std::io::Error::other(String::from_utf8_lossy(message).into_owned()))
+    }
+}
+
+fn write_frame(stream: &mut TcpStream, frame: &[u8]) -> Result<(), Error> {
+    stream.write_all(&(frame.len() as u64).to_le_bytes()).map_err(Error::IO)?;
+    stream.write_all(frame).map_err(Error::IO)
+}
+
+/// Read a frame, failing with `Error::LimitExceeded` if it is longer than `max_len`
+fn read_frame(stream: &mut TcpStream, max_len: usize) -> Result<Vec<u8>, Error> {
+    let mut len = [0; BYTES_IN_U64 as usize];
+    stream.read_exact(&mut len).map_err(Error::IO)?;
+    let len = u64::from_le_bytes(len);
+    if len > max_len as u64 {
+        return Err(Error::LimitExceeded);
+    }
+    let mut frame = try_zeroed(len as usize)?;
+    stream.read_exact(&mut frame).map_err(Error::IO)?;
+    Ok(frame)
+}
+
+/// Split a request into its operation, pointer and data
+fn parse_request(frame: &[u8]) -> Result<(u8, u64, &[u8]), Error> {
+    let (&op, rest) = frame.split_first().ok_or(Error::CorruptedFile)?;
+    let (ptr, data) = rest.split_at_checked(BYTES_IN_U64 as usize).ok_or(Error::CorruptedFile)?;
+    Ok((op, u64::from_le_bytes(ptr.try_into().unwrap()), data))
+}
+
+/// Checks a client's authentication token
+type AuthHook = Box<dyn Fn(&[u8]) -> bool + Send>;
+
+/// Serves a single verter file to `RemoteFile` clients.
+pub struct Server {
+    file: File,
+    auth: AuthHook,
+    max_frame_len: usize,
+    timeout: Option<Duration>
+}
+
+impl Server {
+
+    /// Serve a file to the clients whose token passes the given check.
+    pub fn new<F: Fn(&[u8]) -> bool + Send + 'static>(file: File, auth: F) -> Self {
+        Self {
+            file,
+            auth: Box::new(auth),
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            timeout: Some(DEFAULT_TIMEOUT)
+        }
+    }
+
+    /// Serve a file to every client, whatever token it sends.
+    /// Anyone who can reach the listener can then read, overwrite and delete anything in the file,
+    /// so only use this on a listener bound to a trusted network, such as `127.0.0.1`.
+    pub fn unauthenticated(file: File) -> Self {
+        Self::new(file, |_| true)
+    }
+
+    /// Set the longest request the server reads, 256 MiB by default.
+    /// Longer requests end the connection, so this also bounds the largest record clients can write.
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len;
+        self
+    }
+
+    /// Set how long the server waits for a client to send or receive data before dropping the connection, 30 seconds by default.
+    /// `None` waits forever, which lets an idle or stalled client hold the server indefinitely.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Accept and serve clients one at a time, forever.
+    /// The server owns the file, so clients are served in turn: a connected client holds the server until it disconnects
+    /// or stays idle for longer than the timeout, and other clients wait in the listener's backlog meanwhile.
+    /// Errors on a connection only end that connection.
+    pub fn serve(&mut self, listener: &TcpListener) -> Result<(), Error> {
+        loop {
+            let (stream, _) = listener.accept().map_err(Error::IO)?;
+            let _ = self.handle_connection(stream);
+        }
+    }
+
+    /// Serve a single client until it disconnects.
+    pub fn handle_connection(&mut self, mut stream: TcpStream) -> Result<(), Error> {
+        stream.set_nodelay(true).map_err(Error::IO)?;
+        stream.set_read_timeout(self.timeout).map_err(Error::IO)?;
+        stream.set_write_timeout(self.timeout).map_err(Error::IO)?;
+        let token = read_frame(&mut stream, MAX_TOKEN_LEN)?;
+        if !(self.auth)(&token) {
+            return write_frame(&mut stream, &[error_code(&Error::AuthenticationFailed)]);
+        }
+        write_frame(&mut stream, &[OK])?;
+
+        loop {
+            let request = match read_frame(&mut stream, self.max_frame_len) {
+                Ok(request) => request,
+                Err(Error::IO(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err)
+            };
+            let response = match self.handle_request(&request) {
+                Ok(data) => [&[OK], data.as_slice()].concat(),
+                Err(err) => {
+                    // IO errors can't be sent over the network, so send their message instead, and the details of errors that carry any
+                    let message = match &err {
+                        Error::IO(err) => err.to_string(),
+                        Error::GeometryMismatch { page_size: Some(page_size) } => page_size.to_string(),
+                        Error::TruncatedFile { missing_bytes } => missing_bytes.to_string(),
+                        Error::WriterActive(status) => status.encode(),
+                        _ => String::new()
+                    };
+                    [&[error_code(&err)], message.as_bytes()].concat()
+                }
+            };
+            write_frame(&mut stream, &response)?;
+        }
+    }
+
+    fn handle_request(&mut self, request: &[u8]) -> Result<Vec<u8>, Error> {
+        let (op, ptr, data) = parse_request(request)?;
+        match op {
+            READ => self.file.read(ptr),
+            WRITE => self.file.write(ptr, data).map(|_| Vec::new()),
+            ALLOC => self.file.alloc().map(|ptr| ptr.to_le_bytes().to_vec()),
+            DELETE => self.file.delete(ptr).map(|_| Vec::new()),
+            READ_ROOT => self.file.read_root(),
+            WRITE_ROOT => self.file.write_root(data).map(|_| Vec::new()),
+            _ => Err(Error::CorruptedFile)
+        }
+    }
+
+}
+
+/// A verter file served by a `Server` on another machine.
+pub struct RemoteFile {
+    stream: TcpStream
+}
+
+impl RemoteFile {
+
+    /// Connect to a server, authenticating with the given token.
+    pub fn connect<A: ToSocketAddrs>(addr: A, token: &[u8]) -> Result<Self, Error> {
+        let stream = TcpStream::connect(addr).map_err(Error::IO)?;
+        stream.set_nodelay(true).map_err(Error::IO)?;
+        let mut file = Self { stream };
+        write_frame(&mut file.stream, token)?;
+        file.response()?;
+        Ok(file)
+    }
+
+    /// Read the data from a page chain.
+    pub fn read(&mut self, ptr: u64) -> Result<Vec<u8>, Error> {
+        self.request(READ, ptr, &[])
+    }
+
+    /// Write data to a page chain.
+    pub fn write(&mut self, ptr: u64, data: &[u8]) -> Result<(), Error> {
+        self.request(WRITE, ptr, data).map(|_| ())
+    }
+
+    /// Allocate a new page.
+    pub fn alloc(&mut self) -> Result<u64, Error> {
+        let ptr = self.request(ALLOC, 0, &[])?;
+        Ok(u64::from_le_bytes(ptr.try_into().map_err(|_| Error::CorruptedFile)?))
+    }
+
+    /// Delete a page chain.
+    pub fn delete(&mut self, ptr: u64) -> Result<(), Error> {
+        self.request(DELETE, ptr, &[]).map(|_| ())
+    }
+
+    /// Read the root page chain.
+    pub fn read_root(&mut self) -> Result<Vec<u8>, Error> {
+        self.request(READ_ROOT, 0, &[])
+    }
+
+    /// Write to the root page chain.
+    pub fn write_root(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.request(WRITE_ROOT, 0, data).map(|_| ())
+    }
+
+    fn request(&mut self, op: u8, ptr: u64, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let request = [&[op], ptr.to_le_bytes().as_slice(), data].concat();
+        write_frame(&mut self.stream, &request)?;
+        self.response()
+    }
+
+    fn response(&mut self) -> Result<Vec<u8>, Error> {
+        let response = read_frame(&mut self.stream, usize::MAX)?;
+        let (&status, data) = response.split_first().ok_or(Error::CorruptedFile)?;
+        if status != OK {
+            return Err(error_from_code(status, data));
+        }
+        Ok(data.to_vec())
+    }
+
+}
+
+#[test]
+fn remote() {
+    use crate::Config;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let file = File::open("remote.verter", Config::default()).unwrap();
+        let mut server = Server::new(file, |token| token == b"studio").with_max_frame_len(1000);
+        (0..3).map(|_| server.handle_connection(listener.accept().unwrap().0)).collect::<Vec<_>>()
+    });
+
+    match RemoteFile::connect(addr, b"intruder") {
+        Err(Error::AuthenticationFailed) => {},
+        Ok(_) | Err(_) => panic!("should error with authentication failed")
+    }
+
+    let mut file = RemoteFile::connect(addr, b"studio").unwrap();
+    let ptr = file.alloc().unwrap();
+    file.write(ptr, &[0xAB; 500]).unwrap();
+    file.write_root(&ptr.to_le_bytes()).unwrap();
+    assert_eq!(file.read(ptr).unwrap(), vec![0xAB; 500]);
+    assert_eq!(file.read_root().unwrap(), ptr.to_le_bytes());
+    file.delete(ptr).unwrap();
+    match file.read(ptr) {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+    // Requests over the limit end the connection
+    match file.write(ptr, &[0xCD; 2000]) {
+        Err(Error::IO(_)) => {},
+        Ok(_) | Err(_) => panic!("should error with IO error")
+    }
+    drop(file);
+
+    // Oversized tokens are rejected before anything is allocated for them
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(&u64::MAX.to_le_bytes()).unwrap();
+    drop(stream);
+
+    let results = server.join().unwrap();
+    assert!(results[0].is_ok());
+    for result in &results[1..] {
+        match result {
+            Err(Error::LimitExceeded) => {},
+            Ok(_) | Err(_) => panic!("should error with limit exceeded")
+        }
+    }
+    std::fs::remove_file("remote.verter").unwrap();
+}
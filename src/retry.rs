@@ -0,0 +1,221 @@
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::time::Duration;
+
+use crate::Backend;
+
+/// How IO operations that fail with a transient error are retried, such as on network filesystems
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times a failed operation is retried before the error is returned
+    pub retries: u32,
+    /// How long to wait before the first retry. Doubles after every retry.
+    pub backoff: Duration,
+    /// Which kinds of error are retried. Any other error is returned straight away.
+    pub kinds: &'static [ErrorKind]
+}
+
+impl RetryPolicy {
+
+    /// The kinds of error that usually mean the operation might succeed if tried again
+    pub const TRANSIENT: &'static [ErrorKind] = &[ErrorKind::Interrupted, ErrorKind::WouldBlock, ErrorKind::TimedOut];
+
+}
+
+impl Default for RetryPolicy {
+
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            backoff: Duration::from_millis(10),
+            kinds: Self::TRANSIENT
+        }
+    }
+
+}
+
+/// Wraps a backend, retrying its operations according to a `RetryPolicy`
+pub(crate) struct Retrying {
+    inner: Box<dyn Backend>,
+    policy: RetryPolicy
+}
+
+impl Retrying {
+
+    pub(crate) fn new(inner: Box<dyn Backend>, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+
+    fn retry<T>(policy: RetryPolicy, mut op: impl FnMut() -> std::io::Result<T>) -> std::io::Result<T> {
+        let mut backoff = policy.backoff;
+        let mut retries = 0;
+        loop {
+            match op() {
+                Err(error) if retries < policy.retries && policy.kinds.contains(&error.kind()) => {
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                    retries += 1;
+                },
+                result => return result
+            }
+        }
+    }
+
+}
+
+impl Read for Retrying {
+
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Self::retry(self.policy, || self.inner.read(buf))
+    }
+
+}
+
+impl Write for Retrying {
+
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Self::retry(self.policy, || self.inner.write(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Self::retry(self.policy, || self.inner.flush())
+    }
+
+}
+
+impl Seek for Retrying {
+
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        Self::retry(self.policy, || self.inner.seek(pos))
+    }
+
+}
+
+impl Backend for Retrying {
+
+    fn len(&self) -> std::io::Result<u64> {
+        Self::retry(self.policy, || self.inner.len())
+    }
+
+    fn sync_data(&self) -> std::io::Result<()> {
+        Self::retry(self.policy, || self.inner.sync_data())
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        Self::retry(self.policy, || self.inner.read_exact_at(buf, offset))
+    }
+
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        Self::retry(self.policy, || self.inner.set_len(len))
+    }
+
+    fn lock_shared(&self) -> std::io::Result<()> {
+        Self::retry(self.policy, || self.inner.lock_shared())
+    }
+
+    fn lock_exclusive(&self) -> std::io::Result<()> {
+        Self::retry(self.policy, || self.inner.lock_exclusive())
+    }
+
+    fn unlock(&self) -> std::io::Result<()> {
+        Self::retry(self.policy, || self.inner.unlock())
+    }
+
+    fn available_space(&self) -> std::io::Result<Option<u64>> {
+        Self::retry(self.policy, || self.inner.available_space())
+    }
+
+    fn clone_into(&self, dest: &std::fs::File, len: u64) -> std::io::Result<bool> {
+        self.inner.clone_into(dest, len)
+    }
+
+}
+
+#[test]
+fn retry_transient_errors() {
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use crate::{Config, Error, File};
+
+    /// An in-memory backend where every third operation times out
+    struct Flaky {
+        bytes: Cursor<Vec<u8>>,
+        calls: AtomicU32
+    }
+
+    impl Flaky {
+
+        fn hiccup(&self) -> std::io::Result<()> {
+            if self.calls.fetch_add(1, Ordering::Relaxed) % 3 == 2 {
+                return Err(ErrorKind::TimedOut.into());
+            }
+            Ok(())
+        }
+
+    }
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.hiccup()?;
+            self.bytes.read(buf)
+        }
+    }
+
+    impl Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.hiccup()?;
+            self.bytes.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.bytes.flush()
+        }
+    }
+
+    impl Seek for Flaky {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.hiccup()?;
+            self.bytes.seek(pos)
+        }
+    }
+
+    impl Backend for Flaky {
+        fn len(&self) -> std::io::Result<u64> {
+            self.hiccup()?;
+            self.bytes.len()
+        }
+
+        fn sync_data(&self) -> std::io::Result<()> {
+            Ok(())
+        }
+
+        fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+            self.hiccup()?;
+            self.bytes.read_exact_at(buf, offset)
+        }
+
+        fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+            self.hiccup()?;
+            self.bytes.set_len(len)
+        }
+    }
+
+    let flaky = || Box::new(Flaky { bytes: Cursor::new(Vec::new()), calls: AtomicU32::new(0) });
+
+    match File::from_backend(flaky(), Config::default(), true).and_then(|mut file| file.alloc_with(&[0xAB; 1000])) {
+        Err(Error::IO(error)) if error.kind() == ErrorKind::TimedOut => {},
+        Ok(_) | Err(_) => panic!("should error with timed out")
+    }
+
+    let config = Config {
+        retry: Some(RetryPolicy {
+            backoff: Duration::ZERO,
+            ..RetryPolicy::default()
+        }),
+        ..Config::default()
+    };
+    let mut file = File::from_backend(flaky(), config, true).unwrap();
+    let ptr = file.alloc_with(&[0xAB; 1000]).unwrap();
+    file.write(ptr, &[0xCD; 500]).unwrap();
+    assert_eq!(file.read(ptr).unwrap(), vec![0xCD; 500]);
+    file.validate().unwrap();
+}
@@ -0,0 +1,197 @@
+use crate::{decode_u64s, encode_u64s, Error, File};
+
+/// A bounded log of records stored inside a verter file.
+/// Every record is stored in its own page chain, and once the records would take up more than the page budget, the oldest ones are evicted.
+/// The ring's cursors and record pointers are persisted in an index chain, so the ring can be reopened later using `RingChain::open`.
+pub struct RingChain {
+    /// Pointer to the index chain
+    ptr: u64,
+    /// The maximum number of pages the records may occupy
+    page_budget: u64,
+    /// The slot of the oldest record
+    head: u64,
+    /// The number of records currently in the ring
+    len: u64,
+    /// The number of pages currently occupied by the records
+    pages_used: u64,
+    /// Pointers to the record chains. Every record takes up at least one page, so there are never more than `page_budget` records.
+    slots: Vec<u64>
+}
+
+impl RingChain {
+
+    const CURSORS: usize = 4;
+
+    /// Create a new, empty ring in the file.
+    pub fn create(file: &mut File, page_budget: u64) -> Result<Self, Error> {
+        let ptr = file.alloc()?;
+        let ring = Self {
+            ptr,
+            page_budget,
+            head: 0,
+            len: 0,
+            pages_used: 0,
+            slots: vec![0; page_budget as usize]
+        };
+        ring.save(file)?;
+        Ok(ring)
+    }
+
+    /// Open a ring previously created with `RingChain::create`.
+    pub fn open(file: &mut File, ptr: u64) -> Result<Self, Error> {
+        let index = decode_u64s(&file.read(ptr)?)?;
+        if index.len() < Self::CURSORS {
+            return Err(Error::CorruptedFile);
+        }
+        let page_budget = index[0];
+        let slots = index[Self::CURSORS..].to_vec();
+        if slots.len() as u64 != page_budget || index[1] >= page_budget.max(1) || index[2] > page_budget {
+            return Err(Error::CorruptedFile);
+        }
+        Ok(Self {
+            ptr,
+            page_budget,
+            head: index[1],
+            len: index[2],
+            pages_used: index[3],
+            slots
+        })
+    }
+
+    /// The pointer to the ring's index chain, used to reopen the ring.
+    pub fn ptr(&self) -> u64 {
+        self.ptr
+    }
+
+    /// The number of records in the ring.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The maximum number of pages the records may occupy.
+    pub fn page_budget(&self) -> u64 {
+        self.page_budget
+    }
+
+    /// Append a record, evicting the oldest records if the page budget would be exceeded.
+    pub fn append(&mut self, file: &mut File, record: &[u8]) -> Result<(), Error> {
+        let pages = file.pages_needed(file.meta_size() + record.len() as u64);
+        if pages > self.page_budget {
+            return Err(Error::RecordTooLarge);
+        }
+
+        while self.pages_used + pages > self.page_budget {
+            self.evict_oldest(file)?;
+        }
+
+        let record_ptr = file.alloc()?;
+        file.write(record_ptr, record)?;
+
+        let tail = self.slot(self.len);
+        self.slots[tail] = record_ptr;
+        self.len += 1;
+        self.pages_used += pages;
+
+        self.save(file)
+    }
+
+    /// Read a record. Index 0 is the oldest record in the ring.
+    pub fn get(&self, file: &mut File, idx: usize) -> Result<Option<Vec<u8>>, Error> {
+        if idx >= self.len() {
+            return Ok(None);
+        }
+        file.read(self.slots[self.slot(idx as u64)]).map(Some)
+    }
+
+    /// Read all the records, from oldest to newest.
+    pub fn records(&self, file: &mut File) -> Result<Vec<Vec<u8>>, Error> {
+        (0..self.len).map(|i| file.read(self.slots[self.slot(i)])).collect()
+    }
+
+    /// Remove the oldest record from the ring.
+    pub fn evict_oldest(&mut self, file: &mut File) -> Result<(), Error> {
+        if self.len == 0 {
+            return Ok(());
+        }
+        let head = self.slot(0);
+        self.release(file, head)?;
+        self.head = (self.head + 1) % self.page_budget;
+        self.len -= 1;
+        self.save(file)
+    }
+
+    /// Remove all records and free the ring's index chain.
+    pub fn delete(mut self, file: &mut File) -> Result<(), Error> {
+        for i in 0..self.len {
+            let slot = self.slot(i);
+            self.release(file, slot)?;
+        }
+        file.delete(self.ptr)
+    }
+
+    fn slot(&self, idx: u64) -> usize {
+        ((self.head + idx) % self.page_budget) as usize
+    }
+
+    /// Free the record in a slot
+    fn release(&mut self, file: &mut File, slot: usize) -> Result<(), Error> {
+        let record_ptr = self.slots[slot];
+        let pages = file.chain_pages(record_ptr)?.len() as u64;
+        file.delete(record_ptr)?;
+        self.slots[slot] = 0;
+        self.pages_used = self.pages_used.saturating_sub(pages);
+        Ok(())
+    }
+
+    fn save(&self, file: &mut File) -> Result<(), Error> {
+        let mut index = vec![self.page_budget, self.head, self.len, self.pages_used];
+        index.extend_from_slice(&self.slots);
+        file.write(self.ptr, &encode_u64s(&index))
+    }
+
+}
+
+#[test]
+fn ring_eviction() {
+    use crate::Config;
+
+    let mut file = File::open("ring_eviction.verter", Config::default()).unwrap();
+    let mut ring = RingChain::create(&mut file, 4).unwrap();
+    for i in 0..6u8 {
+        ring.append(&mut file, &[i; 10]).unwrap();
+    }
+    assert_eq!(ring.len(), 4);
+
+    // A record spanning two pages should evict two single-page records
+    ring.append(&mut file, &[6; 200]).unwrap();
+    let ptr = ring.ptr();
+    drop(file);
+
+    let mut file = File::open("ring_eviction.verter", Config::default()).unwrap();
+    let mut ring = RingChain::open(&mut file, ptr).unwrap();
+    assert_eq!(ring.records(&mut file).unwrap(), vec![vec![4; 10], vec![5; 10], vec![6; 200]]);
+
+    match ring.append(&mut file, &[7; 1000]) {
+        Err(Error::RecordTooLarge) => {},
+        Ok(_) | Err(_) => panic!("should error with record too large")
+    }
+
+    std::fs::remove_file("ring_eviction.verter").unwrap();
+
+    // A record filling a page spills into a second page once the chain's metadata is stored in front of it
+    let config = Config { chain_meta: true, ..Config::default() };
+    let mut file = File::open("ring_eviction_meta.verter", config).unwrap();
+    let mut ring = RingChain::create(&mut file, 3).unwrap();
+    ring.append(&mut file, &[0; 120]).unwrap();
+    ring.append(&mut file, &[1; 120]).unwrap();
+    assert_eq!(ring.records(&mut file).unwrap(), vec![vec![1; 120]]);
+    match ring.append(&mut file, &[2; 360]) {
+        Err(Error::RecordTooLarge) => {},
+        Ok(_) | Err(_) => panic!("should error with record too large")
+    }
+    std::fs::remove_file("ring_eviction_meta.verter").unwrap();
+}
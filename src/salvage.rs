@@ -0,0 +1,269 @@
+use std::{collections::{HashMap, HashSet}, ops::Range, path::Path};
+
+use crate::dedup::content_hash;
+use crate::format::{FORMAT_VERSION, HEADER_CHAIN_FLAG, PAGE_MAP_FLAG, VERSION_MASK};
+use crate::{try_zeroed, Backend, ChainFormat, ChainIndex, Config, Error, File, Hooks, PageHeader, SyncState};
+
+/// The pages of a recovered chain, along with its data
+type RecoveredChain = (Vec<u64>, Vec<u8>);
+
+/// The result of `File::salvage`
+#[derive(Debug, Default)]
+pub struct SalvageReport {
+    /// Whether the root chain could be recovered
+    pub root_recovered: bool,
+    /// For every recovered chain, its pointer in the damaged file and its pointer in the salvaged file
+    pub recovered: Vec<(u64, u64)>,
+    /// Byte ranges of the damaged file containing pages that could not be recovered
+    pub unrecoverable: Vec<Range<u64>>,
+    /// Chains in the damaged file whose tail was lost, but whose beginning was recovered using their `ChainSummary`
+    pub truncated: Vec<u64>
+}
+
+impl File {
+
+    /// Recover as much data as possible from a damaged file.
+    /// Scans `src` page by page for intact chains, ignoring the free list, and copies them into a fresh file at `dest`.
+    /// Pointers stored inside the recovered data are not rewritten, so use `SalvageReport::recovered` to remap them.
+    pub fn salvage<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dest: Q, config: Config) -> Result<SalvageReport, Error> {
+        let src = std::fs::File::open(src).map_err(Error::IO)?;
+        let mut src = File::unchecked(Box::new(src), config);
+        // Files from before format version 4 have a shorter header, so their pages start earlier
+        let field = src.read_u64(src.format_version_ptr())?;
+        src.format_version = (field & VERSION_MASK).clamp(3, FORMAT_VERSION);
+        // Files upgraded from format version 0 keep their header fields in a chain, with their pages starting right after its pointer
+        if field & HEADER_CHAIN_FLAG != 0 {
+            src.load_header_chain()?;
+        }
+        if field & PAGE_MAP_FLAG != 0 {
+            src.map_pages();
+        }
+        src.refresh()?;
+        let mut dest = File::open(dest, config)?;
+
+        let file_size = src.file_size()?;
+        let mut headers = HashMap::new();
+        let mut ptr = src.header_size();
+        while ptr + src.total_page_size() <= file_size {
+            // Pages with unknown types can't be part of an intact chain
+            if let Ok(header) = PageHeader::from_u64(src.read_u64(ptr)?) {
+                headers.insert(ptr, header);
+            }
+            ptr += src.total_page_size();
+        }
+
+        let root = src.root_page().ok();
+        src.extract_chains(&headers, root, &mut dest)
+    }
+
+    /// Wrap a backend without reading or checking its header, for recovering data from damaged files
+    pub(crate) fn unchecked(file: Box<dyn Backend>, config: Config) -> File {
+        File {
+            file,
+            config,
+            change_counter: 0,
+            cold: None,
+            magic_bytes: config.magic_bytes,
+            format_version: FORMAT_VERSION,
+            header_pages: Vec::new(),
+            page_maps: None,
+            read_buffer: Vec::new(),
+            alloc_group: 1,
+            growth_hints: Default::default(),
+            root_page_cache: None,
+            reserved_chains: None,
+            hooks: Hooks::default(),
+            index: ChainIndex::default(),
+            heat: None,
+            sync_state: SyncState::default(),
+            pending_change: None,
+            read_only: false,
+            background_validation: None,
+            recovered: false,
+            heartbeat: None,
+            freed_since_maintenance: 0,
+            compaction_due: false
+        }
+    }
+
+    /// Copy every intact chain among the scanned page headers into `dest`, along with the beginning of every chain whose tail was damaged.
+    /// The chain starting at `root` is written as the root chain.
+    pub(crate) fn extract_chains(&mut self, headers: &HashMap<u64, PageHeader>, root: Option<u64>, dest: &mut File) -> Result<SalvageReport, Error> {
+        let mut report = SalvageReport::default();
+        let mut claimed = HashSet::new();
+
+        // Follow every chain from pages that no other page points to
+        let continuations = headers.values().filter_map(|header| match header {
+            PageHeader::NextPage(next) => Some(*next),
+            _ => None
+        }).collect::<HashSet<_>>();
+        let mut heads = headers.iter()
+            .filter(|(ptr, header)| !matches!(header, PageHeader::DeletedPage(_)) && !continuations.contains(ptr))
+            .map(|(ptr, _)| *ptr)
+            .collect::<Vec<_>>();
+        heads.sort();
+
+        for head in heads {
+            let (pages, data, truncated) = match self.intact_chain(head, headers, &claimed) {
+                Some(pages) => match self.read_intact_chain(&pages, headers) {
+                    Some(data) => (pages, data, false),
+                    None => continue
+                },
+                None => match self.read_damaged_chain(head, headers, &claimed)? {
+                    Some((pages, data)) => (pages, data, true),
+                    None => continue
+                }
+            };
+            claimed.extend(pages);
+            if truncated {
+                report.truncated.push(head);
+            }
+
+            if root == Some(head) {
+                dest.write_root(&data)?;
+                report.root_recovered = true;
+            } else {
+                let new_ptr = dest.alloc()?;
+                dest.write(new_ptr, &data)?;
+                report.recovered.push((head, new_ptr));
+            }
+        }
+
+        let mut lost = headers.iter()
+            .filter(|(ptr, header)| !matches!(header, PageHeader::DeletedPage(_)) && !claimed.contains(*ptr))
+            .map(|(ptr, _)| *ptr)
+            .collect::<Vec<_>>();
+        lost.sort();
+        for page in lost {
+            let page_end = page + self.total_page_size();
+            match report.unrecoverable.last_mut() {
+                Some(range) if range.end == page => range.end = page_end,
+                _ => report.unrecoverable.push(page..page_end)
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Read the data of an intact chain, without its metadata
+    fn read_intact_chain(&mut self, pages: &[u64], headers: &HashMap<u64, PageHeader>) -> Option<Vec<u8>> {
+        // Fragments of broken chains are not marked as chain heads, so read the pages directly
+        let Some(PageHeader::FinalPage(final_size)) = headers.get(pages.last().unwrap()) else {
+            return None;
+        };
+        let mut data = try_zeroed((pages.len() - 1) * self.config.page_size + *final_size as usize).ok()?;
+        if self.read_at(pages, 0, &mut data).is_err() || data.len() < self.meta_size() as usize {
+            return None;
+        }
+        data.drain(..self.meta_size() as usize);
+        Some(data)
+    }
+
+    /// Read as much of a chain whose tail was damaged as its `ChainSummary` vouches for, returning its readable pages and its data.
+    /// Only possible in `ChainFormat::V2`, where the head page records how long the chain should be.
+    fn read_damaged_chain(&mut self, head: u64, headers: &HashMap<u64, PageHeader>, claimed: &HashSet<u64>) -> Result<Option<RecoveredChain>, Error> {
+        let ChainFormat::V2 { head_checksum } = self.config.chain_format else {
+            return Ok(None);
+        };
+        // Fragments of broken chains don't start with a summary
+        if self.read_u64(head)? & PageHeader::HEAD_FLAG == 0 {
+            return Ok(None);
+        }
+
+        let mut pages = vec![head];
+        while let Some(PageHeader::NextPage(next)) = headers.get(pages.last().unwrap()) {
+            if !headers.contains_key(next) || claimed.contains(next) || pages.contains(next) {
+                break;
+            }
+            pages.push(*next);
+        }
+
+        let summary = self.read_summary(head)?;
+        let page_size = self.config.page_size as u64;
+        if summary.pages <= pages.len() as u64 || summary.len > summary.pages * page_size {
+            return Ok(None);
+        }
+        let mut data = try_zeroed(summary.len.min(pages.len() as u64 * page_size - self.meta_size()) as usize)?;
+        if self.read_at(&pages, self.meta_size(), &mut data).is_err() {
+            return Ok(None);
+        }
+        let head_len = data.len().min(self.config.page_size - self.meta_size() as usize);
+        if head_checksum && summary.head_checksum.is_some_and(|checksum| checksum != content_hash(&data[..head_len])) {
+            return Ok(None);
+        }
+        Ok(Some((pages, data)))
+    }
+
+    /// Walk a chain using already-parsed headers, returning its pages only if every link is intact
+    fn intact_chain(&self, mut ptr: u64, headers: &HashMap<u64, PageHeader>, claimed: &HashSet<u64>) -> Option<Vec<u64>> {
+        let mut pages = Vec::new();
+        let mut visited = HashSet::new();
+        loop {
+            if claimed.contains(&ptr) || !visited.insert(ptr) {
+                return None;
+            }
+            pages.push(ptr);
+            match headers.get(&ptr)? {
+                PageHeader::NextPage(next) => ptr = *next,
+                PageHeader::FinalPage(size) if *size <= self.config.page_size as u64 => return Some(pages),
+                PageHeader::FinalPage(_) | PageHeader::DeletedPage(_) => return None
+            }
+        }
+    }
+
+}
+
+#[test]
+fn salvage() {
+    let mut file = File::open("salvage_src.verter", Config::default()).unwrap();
+    file.write_root(b"root data").unwrap();
+    let intact = file.alloc().unwrap();
+    file.write(intact, &[0xAB; 300]).unwrap();
+    let broken = file.alloc().unwrap();
+    file.write(broken, &[0xCD; 300]).unwrap();
+
+    // Point the second page of the broken chain outside of the file
+    let second_page = file.chain_pages(broken).unwrap()[1];
+    file.write_page_header(second_page, PageHeader::NextPage(1 << 40)).unwrap();
+    drop(file);
+
+    let report = File::salvage("salvage_src.verter", "salvage_dest.verter", Config::default()).unwrap();
+    assert!(report.root_recovered);
+    assert!(report.recovered.iter().all(|(old, _)| *old != broken));
+    assert_eq!(report.unrecoverable, vec![broken..second_page + Config::default().page_size as u64 + 8]);
+
+    let (_, new_ptr) = report.recovered.iter().find(|(old, _)| *old == intact).unwrap();
+    let mut file = File::open("salvage_dest.verter", Config::default()).unwrap();
+    assert_eq!(file.read_root().unwrap(), b"root data");
+    assert_eq!(file.read(*new_ptr).unwrap(), vec![0xAB; 300]);
+
+    std::fs::remove_file("salvage_src.verter").unwrap();
+    std::fs::remove_file("salvage_dest.verter").unwrap();
+}
+
+#[test]
+fn salvage_truncated() {
+    let config = Config {
+        chain_format: ChainFormat::V2 { head_checksum: true },
+        ..Config::default()
+    };
+    let mut file = File::open("salvage_truncated_src.verter", config).unwrap();
+    let data = (0..1000u32).map(|i| i as u8).collect::<Vec<_>>();
+    let broken = file.alloc_with(&data).unwrap();
+
+    // Cut the chain off after its third page
+    let third_page = file.chain_pages(broken).unwrap()[2];
+    file.write_page_header(third_page, PageHeader::NextPage(1 << 40)).unwrap();
+    drop(file);
+
+    let report = File::salvage("salvage_truncated_src.verter", "salvage_truncated_dest.verter", config).unwrap();
+    assert_eq!(report.truncated, vec![broken]);
+    let (_, new_ptr) = report.recovered.iter().find(|(old, _)| *old == broken).unwrap();
+    let mut file = File::open("salvage_truncated_dest.verter", config).unwrap();
+    let recovered = file.read(*new_ptr).unwrap();
+    assert_eq!(recovered.len(), 3 * config.page_size - file.meta_size() as usize);
+    assert!(data.starts_with(&recovered));
+
+    std::fs::remove_file("salvage_truncated_src.verter").unwrap();
+    std::fs::remove_file("salvage_truncated_dest.verter").unwrap();
+}
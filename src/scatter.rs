@@ -0,0 +1,118 @@
+use std::ops::Range;
+
+use crate::{Error, File, BYTES_IN_U64};
+
+/// The part of a scatter read request that lies within a single page
+struct Access {
+    /// Where the bytes are in the file
+    offset: u64,
+    len: usize,
+    request: usize,
+    /// Where the bytes go in the request's buffer
+    start: usize
+}
+
+impl File {
+
+    /// Read byte ranges from many chains at once, such as every chain a scene touches when it loads.
+    /// Each request is a chain, a range of its data and the buffer to fill with it.
+    /// The page accesses of every request are sorted by where they are in the file and done in a single sweep,
+    /// with nearby accesses merged into one read of at most `Config::max_working_memory` bytes.
+    /// Fails with `Error::InvalidPointer` if a range goes past the end of its chain.
+    /// Panics if a buffer isn't as long as its range.
+    pub fn read_scatter(&mut self, requests: &mut [(u64, Range<u64>, &mut [u8])]) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        let page_size = self.config.page_size as u64;
+        let meta_size = self.meta_size();
+
+        let mut accesses = Vec::new();
+        for (request, (ptr, range, buf)) in requests.iter_mut().enumerate() {
+            assert_eq!(buf.len() as u64, range.end.saturating_sub(range.start), "buffer must be as long as its range");
+            self.check_if_pointer_valid(*ptr)?;
+            if let Some(data) = self.read_cold(*ptr)? {
+                let data = data.get(range.start as usize..range.end as usize).ok_or(Error::InvalidPointer)?;
+                buf.copy_from_slice(data);
+                continue;
+            }
+
+            let (pages, final_size) = self.chain_layout(*ptr)?;
+            let len = ((pages.len() as u64 - 1) * page_size + final_size).checked_sub(meta_size).ok_or(Error::CorruptedFile)?;
+            if range.start > range.end || range.end > len {
+                return Err(Error::InvalidPointer);
+            }
+            let mut data_offset = meta_size + range.start;
+            let mut start = 0;
+            while start < buf.len() {
+                let page_offset = data_offset % page_size;
+                let len = (buf.len() - start).min((page_size - page_offset) as usize);
+                let offset = pages[(data_offset / page_size) as usize] + BYTES_IN_U64 + page_offset;
+                accesses.push(Access { offset, len, request, start });
+                data_offset += len as u64;
+                start += len;
+            }
+        }
+        accesses.sort_by_key(|access| access.offset);
+
+        // Accesses at most a page apart are read together, skipping over the bytes between them
+        let max_span = self.chunk_size() as u64;
+        let mut span = Vec::new();
+        let mut i = 0;
+        while i < accesses.len() {
+            let span_start = accesses[i].offset;
+            let mut span_end = span_start + accesses[i].len as u64;
+            let mut j = i + 1;
+            while let Some(next) = accesses.get(j) {
+                let next_end = next.offset + next.len as u64;
+                if next.offset > span_end + self.total_page_size() || next_end - span_start > max_span {
+                    break;
+                }
+                span_end = span_end.max(next_end);
+                j += 1;
+            }
+
+            span.resize((span_end - span_start) as usize, 0);
+            self.file.read_exact_at(&mut span, span_start).map_err(Error::IO)?;
+            for access in &accesses[i..j] {
+                let from = (access.offset - span_start) as usize;
+                requests[access.request].2[access.start..access.start + access.len].copy_from_slice(&span[from..from + access.len]);
+            }
+            i = j;
+        }
+        Ok(())
+    }
+
+}
+
+#[test]
+fn read_scatter() {
+    use crate::Config;
+
+    let mut file = File::open("read_scatter.verter", Config { max_working_memory: Some(2000), ..Config::default() }).unwrap();
+    let chains = (0..4u8).map(|i| file.alloc_with(&[i; 10]).unwrap()).collect::<Vec<_>>();
+    // Grow the chains in turn so that their pages are interleaved in the file
+    for round in 0..5 {
+        for (i, ptr) in chains.iter().enumerate() {
+            let data = (0..(round + 1) * 700).map(|j| (i * 64 + j % 61) as u8).collect::<Vec<_>>();
+            file.write(*ptr, &data).unwrap();
+        }
+    }
+    let datas = chains.iter().map(|ptr| file.read(*ptr).unwrap()).collect::<Vec<_>>();
+
+    let ranges = [(3, 0..3500), (0, 100..1900), (2, 1000..1001), (0, 3000..3500), (1, 0..0)];
+    let mut bufs = ranges.iter().map(|(_, range)| vec![0; range.end - range.start]).collect::<Vec<_>>();
+    let mut requests = ranges.iter().zip(&mut bufs)
+        .map(|((chain, range), buf)| (chains[*chain], range.start as u64..range.end as u64, buf.as_mut_slice()))
+        .collect::<Vec<_>>();
+    file.read_scatter(&mut requests).unwrap();
+    for ((chain, range), buf) in ranges.iter().zip(&bufs) {
+        assert_eq!(buf.as_slice(), &datas[*chain][range.clone()]);
+    }
+
+    let mut buf = [0; 10];
+    match file.read_scatter(&mut [(chains[0], 3495..3505, &mut buf)]) {
+        Err(Error::InvalidPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with invalid pointer")
+    }
+
+    std::fs::remove_file("read_scatter.verter").unwrap();
+}
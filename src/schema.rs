@@ -0,0 +1,85 @@
+use crate::{Error, File};
+
+/// Brings the data of a file up to date from the schema version it is stamped with. See `Config::schema_migration`.
+pub type SchemaMigration = fn(&mut File, u32) -> Result<(), Error>;
+
+impl File {
+
+    /// The version of the application's data schema the file is stamped with, or 0 if it was never set.
+    pub fn schema_version(&mut self) -> Result<u32, Error> {
+        self.check_for_external_changes()?;
+        Ok(self.read_u64(self.schema_version_ptr())? as u32)
+    }
+
+    /// Stamp the file with the version of the application's data schema its contents follow.
+    /// Use `Transaction::set_schema_version` to stamp it atomically with the data migrated to the new schema.
+    pub fn set_schema_version(&mut self, version: u32) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        self.write_u64(self.schema_version_ptr(), version as u64)?;
+        self.bump_change_counter()
+    }
+
+    /// Bring a file stamped with an older schema than `Config::schema_version` up to date using `Config::schema_migration`,
+    /// or fail with `Error::OutdatedSchema` if there is no migration.
+    pub(crate) fn check_schema_version(&mut self) -> Result<(), Error> {
+        let Some(expected) = self.config.schema_version else {
+            return Ok(());
+        };
+        let stored = self.schema_version()?;
+        if stored >= expected {
+            return Ok(());
+        }
+        let migration = self.config.schema_migration.ok_or(Error::OutdatedSchema)?;
+        migration(self, stored)?;
+        self.set_schema_version(expected)
+    }
+
+}
+
+#[test]
+fn schema_version() {
+    use crate::Config;
+
+    let v1 = Config {
+        schema_version: Some(1),
+        ..Config::default()
+    };
+    let mut file = File::open("schema_version.verter", v1).unwrap();
+    assert_eq!(file.schema_version().unwrap(), 1);
+    file.write_root(b"old layout").unwrap();
+    drop(file);
+
+    // Files with an older schema are rejected unless there is a migration
+    let v2 = Config {
+        schema_version: Some(2),
+        ..Config::default()
+    };
+    match File::open("schema_version.verter", v2) {
+        Err(Error::OutdatedSchema) => {},
+        Ok(_) | Err(_) => panic!("should error with outdated schema")
+    }
+
+    let migrate = Config {
+        schema_migration: Some(|file, from| {
+            assert_eq!(from, 1);
+            let mut root = file.read_root()?;
+            root.splice(0..3, *b"new");
+            file.write_root(&root)
+        }),
+        ..v2
+    };
+    let mut file = File::open("schema_version.verter", migrate).unwrap();
+    assert_eq!(file.schema_version().unwrap(), 2);
+    assert_eq!(file.read_root().unwrap(), b"new layout");
+    drop(file);
+
+    // Newer files and apps that don't care about schemas open as usual
+    let mut file = File::open("schema_version.verter", v1).unwrap();
+    assert_eq!(file.schema_version().unwrap(), 2);
+    file.set_schema_version(5).unwrap();
+    drop(file);
+    let mut file = File::open("schema_version.verter", Config::default()).unwrap();
+    assert_eq!(file.schema_version().unwrap(), 5);
+
+    std::fs::remove_file("schema_version.verter").unwrap();
+}
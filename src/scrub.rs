@@ -0,0 +1,125 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{Error, File, PageHeader, BYTES_IN_U64};
+
+/// The result of `File::scrub`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScrubReport {
+    /// The number of pages that were checked
+    pub checked: u64,
+    /// The pages that could not be read or have a malformed header, in address order
+    pub bad_pages: Vec<u64>,
+    /// Where to continue the scrub if it was paused before reaching the end of the file
+    pub resume_from: Option<u64>
+}
+
+impl File {
+
+    /// Read every page in the file, checking that it is readable and that its header is well-formed.
+    /// Verter pages carry no checksums, so a page is bad if reading it fails, its header has an unknown type or an
+    /// impossible size, or it links to a page that cannot continue a chain.
+    /// Quarantined pages are skipped. See `File::remap_bad_page`.
+    /// `progress` is called after each page with the number of pages checked so far and the total number of pages.
+    /// Returning `false` from it pauses the scrub, which can be continued with `File::scrub_from`.
+    pub fn scrub<F: FnMut(u64, u64) -> bool>(&mut self, progress: F) -> Result<ScrubReport, Error> {
+        self.scrub_from(self.header_size(), progress)
+    }
+
+    /// Continue a paused scrub from `ScrubReport::resume_from`.
+    pub fn scrub_from<F: FnMut(u64, u64) -> bool>(&mut self, start: u64, mut progress: F) -> Result<ScrubReport, Error> {
+        self.check_for_external_changes()?;
+        if start != self.header_size() && !self.is_page_ptr(start) {
+            return Err(Error::InvalidPointer);
+        }
+        let total = self.file_size()?.saturating_sub(self.header_size()) / self.total_page_size();
+        let mut report = ScrubReport::default();
+        let mut checked = (start - self.header_size()) / self.total_page_size();
+        let quarantined = self.quarantined_pages()?;
+        let mut ptr = start;
+        while checked < total {
+            if !quarantined.contains(&ptr) && !self.page_is_sound(ptr) {
+                report.bad_pages.push(ptr);
+            }
+            report.checked += 1;
+            checked += 1;
+            ptr += self.total_page_size();
+            if !progress(checked, total) && checked < total {
+                report.resume_from = Some(ptr);
+                break;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Check a single page for `File::scrub`
+    pub(crate) fn page_is_sound(&mut self, ptr: u64) -> bool {
+        let header = match self.read_page_header(ptr) {
+            Ok(header) => header,
+            Err(_) => return false
+        };
+        let link_ok = match header {
+            PageHeader::NextPage(next) => self.link_is_sound(next),
+            PageHeader::FinalPage(size) => size <= self.config.page_size as u64,
+            PageHeader::DeletedPage(next) => next == 0 || self.is_page_ptr(next)
+        };
+        if !link_ok {
+            return false;
+        }
+
+        let mut payload = vec![0; self.config.page_size];
+        self.file.seek(SeekFrom::Start(ptr + BYTES_IN_U64)).is_ok() && self.file.read_exact(&mut payload).is_ok()
+    }
+
+    /// Whether a page could continue a chain, ignoring any damage to the page itself, which is reported on its own
+    pub(crate) fn link_is_sound(&mut self, next: u64) -> bool {
+        self.is_page_ptr(next)
+            && !matches!(self.read_page_header(next), Ok(PageHeader::DeletedPage(_)))
+            && !self.is_head_page(next).unwrap_or(true)
+    }
+
+    /// Whether a pointer is the start of a page inside the file
+    pub(crate) fn is_page_ptr(&self, ptr: u64) -> bool {
+        ptr >= self.header_size()
+            && (ptr - self.header_size()).is_multiple_of(self.total_page_size())
+            && self.file_size().is_ok_and(|file_size| ptr + self.total_page_size() <= file_size)
+    }
+
+}
+
+#[test]
+fn scrub() {
+    use crate::Config;
+
+    let mut file = File::open("scrub.verter", Config::default()).unwrap();
+    let chain = file.alloc_with(&[0xAB; 500]).unwrap();
+    let pages = file.chain_pages(chain).unwrap();
+    file.write_u64(pages[1], (0x55 << PageHeader::TYPE_SHIFT) | 3).unwrap();
+    file.write_page_header(pages[3], PageHeader::FinalPage(1000)).unwrap();
+
+    let mut calls = 0;
+    let report = file.scrub(|checked, total| {
+        calls += 1;
+        assert_eq!(checked, calls);
+        assert_eq!(total, 6);
+        true
+    }).unwrap();
+    assert_eq!(report.checked, 6);
+    assert_eq!(report.bad_pages, vec![pages[1], pages[3]]);
+    assert_eq!(report.resume_from, None);
+
+    // Pause after every two pages
+    let mut bad_pages = Vec::new();
+    let mut start = pages[0];
+    loop {
+        let report = file.scrub_from(start, |checked, _| checked % 2 != 0).unwrap();
+        assert!(report.checked <= 2);
+        bad_pages.extend(report.bad_pages);
+        match report.resume_from {
+            Some(resume_from) => start = resume_from,
+            None => break
+        }
+    }
+    assert_eq!(bad_pages, vec![pages[1], pages[3]]);
+
+    std::fs::remove_file("scrub.verter").unwrap();
+}
@@ -0,0 +1,144 @@
+use std::time::{Duration, Instant};
+
+use crate::{Error, File};
+
+/// The number of bytes of a chain searched at a time
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Limits on how much work `File::scan_for` does before giving up
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScanLimits {
+    /// Stop after searching this many bytes of chain data
+    pub max_bytes: Option<u64>,
+    /// Stop after searching for this long
+    pub max_time: Option<Duration>
+}
+
+/// The result of `File::scan_for`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScanReport {
+    /// Every match found, as the pointer to the chain and the offset of the match into the chain's data, in address order
+    pub matches: Vec<(u64, u64)>,
+    /// The number of bytes of chain data searched
+    pub scanned: u64,
+    /// Whether every chain was searched, rather than the scan stopping at a limit or being stopped by the progress callback
+    pub complete: bool
+}
+
+impl File {
+
+    /// Search the data of every chain in the file for a byte pattern, such as to find where an ID ended up while debugging.
+    /// Chains verter uses for its own tables are searched too. Chains are streamed, so they are never held in memory whole.
+    /// `progress` is called after each chain with the number of chains searched so far and the total number of chains.
+    /// Returning `false` from it stops the scan early, as does reaching one of the `limits`.
+    pub fn scan_for<F: FnMut(u64, u64) -> bool>(&mut self, needle: &[u8], limits: ScanLimits, mut progress: F) -> Result<ScanReport, Error> {
+        self.check_for_external_changes()?;
+        let start = Instant::now();
+        let mut heads = self.chain_heads()?;
+        heads.sort();
+        let total = heads.len() as u64;
+        let mut report = ScanReport::default();
+        if needle.is_empty() {
+            report.complete = true;
+            return Ok(report);
+        }
+
+        for (searched, ptr) in heads.into_iter().enumerate() {
+            let within_limits = || {
+                limits.max_bytes.is_none_or(|max_bytes| report.scanned < max_bytes) &&
+                limits.max_time.is_none_or(|max_time| start.elapsed() < max_time)
+            };
+            if !within_limits() {
+                return Ok(report);
+            }
+            if !self.scan_chain(ptr, needle, limits, start, &mut report)? || !progress(searched as u64 + 1, total) {
+                return Ok(report);
+            }
+        }
+        report.complete = true;
+        Ok(report)
+    }
+
+    /// Search a single chain for `File::scan_for`, returning false if a limit was reached partway through
+    fn scan_chain(&mut self, ptr: u64, needle: &[u8], limits: ScanLimits, start: Instant, report: &mut ScanReport) -> Result<bool, Error> {
+        if self.is_cold(ptr)? {
+            let data = self.read(ptr)?;
+            report.matches.extend(find_all(&data, needle).map(|offset| (ptr, offset as u64)));
+            report.scanned += data.len() as u64;
+            return Ok(true);
+        }
+
+        let len = self.chain_len(ptr)?;
+        let (pages, _) = self.chain_layout(ptr)?;
+        let meta_size = self.meta_size();
+        // The end of the previous chunk is kept, so matches spanning two chunks are found
+        let mut window = Vec::with_capacity(CHUNK_SIZE + needle.len());
+        let mut offset = 0;
+        while offset < len {
+            let mut n = CHUNK_SIZE.min((len - offset) as usize);
+            if let Some(max_bytes) = limits.max_bytes {
+                n = n.min(max_bytes.saturating_sub(report.scanned) as usize);
+            }
+            if n == 0 || limits.max_time.is_some_and(|max_time| start.elapsed() >= max_time) {
+                return Ok(false);
+            }
+
+            let carried = window.len();
+            window.resize(carried + n, 0);
+            self.read_at(&pages, meta_size + offset, &mut window[carried..])?;
+            let window_start = offset - carried as u64;
+            report.matches.extend(find_all(&window, needle).map(|pos| (ptr, window_start + pos as u64)));
+            report.scanned += n as u64;
+            offset += n as u64;
+
+            let keep = (needle.len() - 1).min(window.len());
+            window.drain(..window.len() - keep);
+        }
+        Ok(true)
+    }
+
+}
+
+/// The offset of every occurrence of `needle` in `haystack`, including overlapping ones
+fn find_all<'a>(haystack: &'a [u8], needle: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+    haystack.windows(needle.len()).enumerate().filter(move |(_, window)| *window == needle).map(|(pos, _)| pos)
+}
+
+#[test]
+fn scan_for() {
+    use crate::Config;
+
+    let mut file = File::open("scan_for.verter", Config::default()).unwrap();
+    let guid = b"3f2a9c";
+    let mut large = vec![0xAB; 200_000];
+    // Straddle the boundary between two chunks
+    large[CHUNK_SIZE - 2..CHUNK_SIZE + 4].copy_from_slice(guid);
+    large[150_000..150_006].copy_from_slice(guid);
+    let large_ptr = file.alloc_with(&large).unwrap();
+    let small_ptr = file.alloc_with(b"layer 3f2a9c").unwrap();
+    file.alloc_with(b"nothing here").unwrap();
+
+    let mut calls = 0;
+    let report = file.scan_for(guid, ScanLimits::default(), |_, _| {
+        calls += 1;
+        true
+    }).unwrap();
+    assert!(report.complete);
+    assert_eq!(report.matches, vec![(large_ptr, CHUNK_SIZE as u64 - 2), (large_ptr, 150_000), (small_ptr, 6)]);
+    assert_eq!(calls, file.chain_heads().unwrap().len());
+
+    // Stop partway through the large chain
+    let limits = ScanLimits {
+        max_bytes: Some(100_000),
+        ..ScanLimits::default()
+    };
+    let report = file.scan_for(guid, limits, |_, _| true).unwrap();
+    assert!(!report.complete);
+    assert!(report.scanned <= 100_000);
+    assert!(!report.matches.contains(&(large_ptr, 150_000)));
+
+    let report = file.scan_for(guid, ScanLimits::default(), |_, _| false).unwrap();
+    assert!(!report.complete);
+
+    std::fs::remove_file("scan_for.verter").unwrap();
+}
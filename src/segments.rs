@@ -0,0 +1,210 @@
+use std::ffi::OsString;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::Backend;
+
+/// A backend that splits the file's bytes across numbered segment files of at most `segment_size` bytes each,
+/// named `<path>.000`, `<path>.001`, and so on. Offsets into the backend are a single logical address space.
+pub(crate) struct Segments {
+    path: PathBuf,
+    segment_size: u64,
+    segments: Vec<std::fs::File>,
+    pos: u64
+}
+
+impl Segments {
+
+    /// Open the segments of the file at `path`, creating the first segment if there are none.
+    pub(crate) fn open(path: &Path, segment_size: u64) -> std::io::Result<Self> {
+        let mut segments = Self {
+            path: path.to_owned(),
+            segment_size,
+            segments: Vec::new(),
+            pos: 0
+        };
+        while segments.segments.is_empty() || std::fs::exists(segments.segment_path(segments.segments.len()))? {
+            segments.open_segment()?;
+        }
+        Ok(segments)
+    }
+
+    /// Whether the first segment of the file at `path` exists.
+    pub(crate) fn exist(path: &Path) -> std::io::Result<bool> {
+        std::fs::exists(Self::segment_path_of(path, 0))
+    }
+
+    fn segment_path(&self, idx: usize) -> PathBuf {
+        Self::segment_path_of(&self.path, idx)
+    }
+
+    fn segment_path_of(path: &Path, idx: usize) -> PathBuf {
+        let mut segment_path = OsString::from(path.as_os_str());
+        segment_path.push(format!(".{:03}", idx));
+        segment_path.into()
+    }
+
+    fn open_segment(&mut self) -> std::io::Result<()> {
+        let segment = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(self.segment_path(self.segments.len()))?;
+        self.segments.push(segment);
+        Ok(())
+    }
+
+    /// The segment containing the current position, along with the position within it and the bytes left in it
+    fn locate(&self) -> (usize, u64, u64) {
+        let idx = (self.pos / self.segment_size) as usize;
+        let offset = self.pos % self.segment_size;
+        (idx, offset, self.segment_size - offset)
+    }
+
+}
+
+impl Read for Segments {
+
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let (idx, offset, remaining) = self.locate();
+        let Some(segment) = self.segments.get_mut(idx) else {
+            return Ok(0);
+        };
+        let len = buf.len().min(remaining as usize);
+        segment.seek(SeekFrom::Start(offset))?;
+        let n = segment.read(&mut buf[..len])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+}
+
+impl Write for Segments {
+
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let (idx, offset, remaining) = self.locate();
+        while self.segments.len() <= idx {
+            self.open_segment()?;
+        }
+        let len = buf.len().min(remaining as usize);
+        let segment = &mut self.segments[idx];
+        segment.seek(SeekFrom::Start(offset))?;
+        let n = segment.write(&buf[..len])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        for segment in &mut self.segments {
+            segment.flush()?;
+        }
+        Ok(())
+    }
+
+}
+
+impl Seek for Segments {
+
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len()?.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset)
+        };
+        self.pos = new_pos.ok_or(std::io::ErrorKind::InvalidInput)?;
+        Ok(self.pos)
+    }
+
+}
+
+impl Backend for Segments {
+
+    fn len(&self) -> std::io::Result<u64> {
+        let last = self.segments.last().expect("there is always at least one segment");
+        Ok((self.segments.len() as u64 - 1) * self.segment_size + last.len()?)
+    }
+
+    fn sync_data(&self) -> std::io::Result<()> {
+        for segment in &self.segments {
+            segment.sync_data()?;
+        }
+        Ok(())
+    }
+
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        let count = len.div_ceil(self.segment_size).max(1) as usize;
+        while self.segments.len() > count {
+            self.segments.pop();
+            std::fs::remove_file(self.segment_path(self.segments.len()))?;
+        }
+        while self.segments.len() < count {
+            self.open_segment()?;
+        }
+        for segment in &self.segments[..count - 1] {
+            segment.set_len(self.segment_size)?;
+        }
+        self.segments[count - 1].set_len(len - (count as u64 - 1) * self.segment_size)
+    }
+
+    // The first segment always exists and holds the header, so it stands in for the whole file
+    fn lock_shared(&self) -> std::io::Result<()> {
+        self.segments[0].lock_shared()
+    }
+
+    fn lock_exclusive(&self) -> std::io::Result<()> {
+        self.segments[0].lock()
+    }
+
+    fn unlock(&self) -> std::io::Result<()> {
+        self.segments[0].unlock()
+    }
+
+    fn available_space(&self) -> std::io::Result<Option<u64>> {
+        self.segments[0].available_space()
+    }
+
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+        while !buf.is_empty() {
+            let idx = (offset / self.segment_size) as usize;
+            let segment_offset = offset % self.segment_size;
+            let segment = self.segments.get(idx).ok_or(std::io::ErrorKind::UnexpectedEof)?;
+            let len = buf.len().min((self.segment_size - segment_offset) as usize);
+            segment.read_exact_at(&mut buf[..len], segment_offset)?;
+            buf = &mut buf[len..];
+            offset += len as u64;
+        }
+        Ok(())
+    }
+
+}
+
+#[test]
+fn segments() {
+    use crate::{Config, File};
+
+    let config = Config {
+        segment_size: Some(1000),
+        ..Config::default()
+    };
+
+    let mut file = File::open("segments.verter", config).unwrap();
+    let data = (0..5000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let ptr = file.alloc().unwrap();
+    file.write(ptr, &data).unwrap();
+    file.write_root(b"root").unwrap();
+    drop(file);
+
+    let mut file = File::open("segments.verter", config).unwrap();
+    assert_eq!(file.read(ptr).unwrap(), data);
+    assert_eq!(file.read_root().unwrap(), b"root");
+    drop(file);
+
+    let mut idx = 0;
+    while std::fs::exists(format!("segments.verter.{:03}", idx)).unwrap() {
+        assert!(std::fs::metadata(format!("segments.verter.{:03}", idx)).unwrap().len() <= 1000);
+        std::fs::remove_file(format!("segments.verter.{:03}", idx)).unwrap();
+        idx += 1;
+    }
+    assert!(idx > 5);
+}
@@ -0,0 +1,107 @@
+use std::path::Path;
+use std::time::Duration;
+
+use crate::{Config, Error, File};
+
+/// A read-only handle to a file that another process, such as an editor, may be writing to at the same time.
+/// Every read takes a shared lock on the file, picks up the writer's latest changes, and checks the header's change counter afterwards.
+/// A read that raced a writer, such as one that did not lock the file, is retried.
+/// Writers should make their changes inside `File::exclusive`, so that readers never see a change halfway through.
+pub struct SharedReader {
+    file: File
+}
+
+impl SharedReader {
+
+    /// How many times a read that raced a writer is retried before giving up with `Error::ConcurrentModification`
+    const RETRIES: u32 = 8;
+
+    /// Open an existing file for reading alongside a writer.
+    pub fn open<P: AsRef<Path>>(path: P, config: Config) -> Result<Self, Error> {
+        let file = std::fs::File::open(path).map_err(Error::IO)?;
+        file.lock_shared().map_err(Error::IO)?;
+        let file = File::from_backend(Box::new(file), config, false);
+        if let Ok(file) = &file {
+            file.file.unlock().map_err(Error::IO)?;
+        }
+        Ok(Self { file: file? })
+    }
+
+    /// Read the data from a chain.
+    pub fn read(&mut self, ptr: u64) -> Result<Vec<u8>, Error> {
+        self.with(|file| file.read(ptr))
+    }
+
+    /// Read the root chain.
+    pub fn read_root(&mut self) -> Result<Vec<u8>, Error> {
+        self.with(File::read_root)
+    }
+
+    /// Run a read-only operation on the file, retrying it if it raced a writer.
+    /// The operation may run several times. The file is opened read-only, so anything that would modify it fails with an IO error.
+    pub fn with<R, F: FnMut(&mut File) -> Result<R, Error>>(&mut self, mut f: F) -> Result<R, Error> {
+        let mut backoff = Duration::from_micros(100);
+        for _ in 0..=Self::RETRIES {
+            self.file.file.lock_shared().map_err(Error::IO)?;
+            let result = self.file.refresh().and_then(|()| f(&mut self.file));
+            let raced = self.file.check_for_external_changes().is_err();
+            self.file.file.unlock().map_err(Error::IO)?;
+            if !raced {
+                return result;
+            }
+            std::thread::sleep(backoff);
+            backoff *= 2;
+        }
+        Err(Error::ConcurrentModification)
+    }
+
+}
+
+impl File {
+
+    /// Make changes to the file while holding an exclusive lock on it, so that `SharedReader`s in other processes wait for them to finish.
+    pub fn exclusive<R, F: FnOnce(&mut File) -> Result<R, Error>>(&mut self, f: F) -> Result<R, Error> {
+        self.file.lock_exclusive().map_err(Error::IO)?;
+        let result = f(self);
+        self.file.unlock().map_err(Error::IO)?;
+        result
+    }
+
+}
+
+#[test]
+fn shared_reader() {
+    let mut writer = File::open("shared_reader.verter", Config::default()).unwrap();
+    writer.write_root(b"first").unwrap();
+
+    let mut reader = SharedReader::open("shared_reader.verter", Config::default()).unwrap();
+    assert_eq!(reader.read_root().unwrap(), b"first");
+
+    // Readers pick up the writer's changes without refreshing
+    let ptr = writer.exclusive(|file| {
+        let ptr = file.alloc_with(b"frame")?;
+        file.write_root(&ptr.to_le_bytes())?;
+        Ok(ptr)
+    }).unwrap();
+    assert_eq!(reader.read_root().unwrap(), ptr.to_le_bytes());
+    assert_eq!(reader.read(ptr).unwrap(), b"frame");
+
+    // A read that races an unlocked write is retried
+    let mut attempts = 0;
+    let root = reader.with(|file| {
+        attempts += 1;
+        if attempts == 1 {
+            writer.write_root(b"raced").unwrap();
+        }
+        file.read_root()
+    }).unwrap();
+    assert_eq!(root, b"raced");
+    assert_eq!(attempts, 2);
+
+    match reader.with(|file| file.alloc()) {
+        Err(Error::IO(_)) => {},
+        Ok(_) | Err(_) => panic!("should error with io error")
+    }
+
+    std::fs::remove_file("shared_reader.verter").unwrap();
+}
@@ -0,0 +1,228 @@
+use crate::{decode_u64s, encode_u64s, Error, File};
+
+/// The key of a record in a `Slab`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SlabKey(pub u64);
+
+/// Stores many small fixed-size records packed together in single-page chains.
+/// Each slab page starts with a bitmap of occupied slots, followed by the slots themselves.
+/// The list of slab pages is persisted in a directory chain, so the slab can be reopened later using `Slab::open`.
+pub struct Slab {
+    /// Pointer to the directory chain
+    ptr: u64,
+    record_size: usize,
+    /// The number of records that fit in one slab page
+    capacity: usize,
+    /// Pointers to the slab pages
+    pages: Vec<u64>,
+    /// The number of free slots in each slab page
+    free_slots: Vec<usize>
+}
+
+impl Slab {
+
+    /// Create a new, empty slab in the file.
+    pub fn new(file: &mut File, record_size: usize) -> Result<Self, Error> {
+        let capacity = Self::capacity(file, record_size)?;
+        let ptr = file.alloc()?;
+        let slab = Self {
+            ptr,
+            record_size,
+            capacity,
+            pages: Vec::new(),
+            free_slots: Vec::new()
+        };
+        slab.save(file)?;
+        Ok(slab)
+    }
+
+    /// Open a slab previously created with `Slab::new`.
+    pub fn open(file: &mut File, ptr: u64) -> Result<Self, Error> {
+        let directory = decode_u64s(&file.read(ptr)?)?;
+        let Some((record_size, pages)) = directory.split_first() else {
+            return Err(Error::CorruptedFile);
+        };
+        let record_size = *record_size as usize;
+        let capacity = Self::capacity(file, record_size).map_err(|_| Error::CorruptedFile)?;
+
+        let mut free_slots = Vec::with_capacity(pages.len());
+        for page in pages {
+            let data = file.read(*page)?;
+            if data.len() != file.config.page_size {
+                return Err(Error::CorruptedFile);
+            }
+            free_slots.push((0..capacity).filter(|slot| !Self::occupied(&data, *slot)).count());
+        }
+
+        Ok(Self {
+            ptr,
+            record_size,
+            capacity,
+            pages: pages.to_vec(),
+            free_slots
+        })
+    }
+
+    /// The pointer to the slab's directory chain, used to reopen the slab.
+    pub fn ptr(&self) -> u64 {
+        self.ptr
+    }
+
+    pub fn record_size(&self) -> usize {
+        self.record_size
+    }
+
+    /// The number of records in the slab.
+    pub fn len(&self) -> usize {
+        self.pages.len() * self.capacity - self.free_slots.iter().sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Insert a record, returning its key.
+    /// Records shorter than the slab's record size are padded with zeros.
+    pub fn insert(&mut self, file: &mut File, record: &[u8]) -> Result<SlabKey, Error> {
+        if record.len() > self.record_size {
+            return Err(Error::RecordTooLarge);
+        }
+
+        let page_idx = match self.free_slots.iter().position(|free| *free > 0) {
+            Some(page_idx) => page_idx,
+            None => {
+                let page = file.alloc()?;
+                file.write(page, &vec![0; file.config.page_size])?;
+                self.pages.push(page);
+                self.free_slots.push(self.capacity);
+                self.save(file)?;
+                self.pages.len() - 1
+            }
+        };
+
+        let mut data = file.read(self.pages[page_idx])?;
+        let slot = (0..self.capacity).find(|slot| !Self::occupied(&data, *slot)).ok_or(Error::CorruptedFile)?;
+        data[slot / 8] |= 1 << (slot % 8);
+        self.record_mut(&mut data, slot).fill(0);
+        self.record_mut(&mut data, slot)[..record.len()].copy_from_slice(record);
+        file.write(self.pages[page_idx], &data)?;
+        self.free_slots[page_idx] -= 1;
+
+        Ok(SlabKey((page_idx * self.capacity + slot) as u64))
+    }
+
+    /// Read a record. Returns `None` if there is no record with the given key.
+    pub fn get(&self, file: &mut File, key: SlabKey) -> Result<Option<Vec<u8>>, Error> {
+        let Some((page_idx, slot)) = self.locate(key) else {
+            return Ok(None);
+        };
+        let mut data = file.read(self.pages[page_idx])?;
+        if !Self::occupied(&data, slot) {
+            return Ok(None);
+        }
+        Ok(Some(self.record_mut(&mut data, slot).to_vec()))
+    }
+
+    /// Overwrite an existing record. Returns `false` if there is no record with the given key.
+    pub fn update(&mut self, file: &mut File, key: SlabKey, record: &[u8]) -> Result<bool, Error> {
+        if record.len() > self.record_size {
+            return Err(Error::RecordTooLarge);
+        }
+        let Some((page_idx, slot)) = self.locate(key) else {
+            return Ok(false);
+        };
+        let mut data = file.read(self.pages[page_idx])?;
+        if !Self::occupied(&data, slot) {
+            return Ok(false);
+        }
+        self.record_mut(&mut data, slot).fill(0);
+        self.record_mut(&mut data, slot)[..record.len()].copy_from_slice(record);
+        file.write(self.pages[page_idx], &data)?;
+        Ok(true)
+    }
+
+    /// Remove a record. Returns `false` if there is no record with the given key.
+    pub fn remove(&mut self, file: &mut File, key: SlabKey) -> Result<bool, Error> {
+        let Some((page_idx, slot)) = self.locate(key) else {
+            return Ok(false);
+        };
+        let mut data = file.read(self.pages[page_idx])?;
+        if !Self::occupied(&data, slot) {
+            return Ok(false);
+        }
+        data[slot / 8] &= !(1 << (slot % 8));
+        file.write(self.pages[page_idx], &data)?;
+        self.free_slots[page_idx] += 1;
+        Ok(true)
+    }
+
+    /// Remove all records and free the slab's pages.
+    pub fn delete(self, file: &mut File) -> Result<(), Error> {
+        file.delete(self.ptr)?;
+        for page in self.pages {
+            file.delete(page)?;
+        }
+        Ok(())
+    }
+
+    /// The number of records of a given size that fit in a page, along with the occupancy bitmap
+    fn capacity(file: &File, record_size: usize) -> Result<usize, Error> {
+        let page_bits = file.config.page_size * 8;
+        let capacity = page_bits / (record_size * 8 + 1);
+        if capacity == 0 {
+            return Err(Error::RecordTooLarge);
+        }
+        Ok(capacity)
+    }
+
+    fn bitmap_size(&self) -> usize {
+        self.capacity.div_ceil(8)
+    }
+
+    fn occupied(data: &[u8], slot: usize) -> bool {
+        data[slot / 8] & (1 << (slot % 8)) != 0
+    }
+
+    fn record_mut<'a>(&self, data: &'a mut [u8], slot: usize) -> &'a mut [u8] {
+        let start = self.bitmap_size() + slot * self.record_size;
+        &mut data[start..start + self.record_size]
+    }
+
+    fn locate(&self, key: SlabKey) -> Option<(usize, usize)> {
+        let page_idx = (key.0 / self.capacity as u64) as usize;
+        let slot = (key.0 % self.capacity as u64) as usize;
+        (page_idx < self.pages.len()).then_some((page_idx, slot))
+    }
+
+    fn save(&self, file: &mut File) -> Result<(), Error> {
+        let mut directory = vec![self.record_size as u64];
+        directory.extend_from_slice(&self.pages);
+        file.write(self.ptr, &encode_u64s(&directory))
+    }
+
+}
+
+#[test]
+fn slab() {
+    use crate::Config;
+
+    let mut file = File::open("slab.verter", Config::default()).unwrap();
+    let mut slab = Slab::new(&mut file, 12).unwrap();
+    let keys = (0..50u8).map(|i| slab.insert(&mut file, &[i; 12]).unwrap()).collect::<Vec<_>>();
+    assert!(slab.remove(&mut file, keys[3]).unwrap());
+    assert!(slab.update(&mut file, keys[4], &[0xAB; 12]).unwrap());
+    let ptr = slab.ptr();
+    drop(file);
+
+    let mut file = File::open("slab.verter", Config::default()).unwrap();
+    let mut slab = Slab::open(&mut file, ptr).unwrap();
+    assert_eq!(slab.len(), 49);
+    assert_eq!(slab.get(&mut file, keys[3]).unwrap(), None);
+    assert_eq!(slab.get(&mut file, keys[4]).unwrap().unwrap(), vec![0xAB; 12]);
+    assert_eq!(slab.get(&mut file, keys[49]).unwrap().unwrap(), vec![49; 12]);
+
+    // The removed slot gets reused
+    assert_eq!(slab.insert(&mut file, &[0xCD; 12]).unwrap(), keys[3]);
+
+    std::fs::remove_file("slab.verter").unwrap();
+}
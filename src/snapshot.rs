@@ -0,0 +1,185 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use crate::backend::ReadOnlyBytes;
+use crate::memory::ScratchFile;
+use crate::{Config, Error, File};
+
+/// A read-only view of a snapshot of a file, returned by `File::readonly_view`, for processes such as render workers that only read.
+/// The snapshot is a copy of the file mapped into memory, so the handle it was taken from can keep writing
+/// while views read the file as it was when the snapshot was taken.
+/// Views made with `SnapshotView::try_clone` share the mapping, and so do processes forked after the view was made.
+/// Chains in a cold tier can't be read through a view.
+pub struct SnapshotView {
+    file: File,
+    snapshot: SharedSnapshot
+}
+
+/// The mapped copy of the file, deleted once every view of it is dropped
+struct Snapshot {
+    mapping: Mapping,
+    _scratch: ScratchFile
+}
+
+#[derive(Clone)]
+struct SharedSnapshot(Arc<Snapshot>);
+
+impl AsRef<[u8]> for SharedSnapshot {
+
+    fn as_ref(&self) -> &[u8] {
+        self.0.mapping.bytes()
+    }
+
+}
+
+impl File {
+
+    /// Copy the file into a snapshot and open a read-only view of it.
+    /// The copy is made a chunk of at most `Config::max_working_memory` bytes at a time, and is stored in the system's temporary directory.
+    /// Taking a snapshot reads the whole file and writes all of it again, so it costs time and temporary disk space in proportion
+    /// to the size of the file, however little of it the views read. Take one snapshot and share it with `SnapshotView::try_clone`
+    /// rather than taking a snapshot per reader, and prefer a `SharedReader` for readers that don't need an unchanging view of the file.
+    pub fn readonly_view(&mut self) -> Result<SnapshotView, Error> {
+        self.check_for_external_changes()?;
+        let (scratch, mut copy) = ScratchFile::create()?;
+        let len = self.with_stored_bytes(|file| {
+            let len = file.file_size()?;
+            let mut chunk = vec![0; file.chunk_size()];
+            let mut offset = 0;
+            while offset < len {
+                let n = chunk.len().min((len - offset) as usize);
+                file.file.read_exact_at(&mut chunk[..n], offset).map_err(Error::IO)?;
+                copy.write_all(&chunk[..n]).map_err(Error::IO)?;
+                offset += n as u64;
+            }
+            Ok(len)
+        })?;
+
+        let mapping = Mapping::new(&copy, len as usize)?;
+        let snapshot = SharedSnapshot(Arc::new(Snapshot { mapping, _scratch: scratch }));
+        SnapshotView::new(snapshot, self.config)
+    }
+
+}
+
+impl SnapshotView {
+
+    fn new(snapshot: SharedSnapshot, config: Config) -> Result<Self, Error> {
+        let mut file = File::from_backend(Box::new(ReadOnlyBytes::new(snapshot.clone())), config, false)?;
+        file.set_read_only(true);
+        Ok(Self { file, snapshot })
+    }
+
+    /// Open another view of the same snapshot, sharing its mapping.
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        Self::new(self.snapshot.clone(), self.file.config)
+    }
+
+    /// Read the data from a chain.
+    pub fn read(&mut self, ptr: u64) -> Result<Vec<u8>, Error> {
+        self.file.read(ptr)
+    }
+
+    /// Read the root chain.
+    pub fn read_root(&mut self) -> Result<Vec<u8>, Error> {
+        self.file.read_root()
+    }
+
+    /// The snapshot as a read-only file, for reading it in other ways. Anything that would modify it fails with `Error::ReadOnly`.
+    pub fn file(&mut self) -> &mut File {
+        &mut self.file
+    }
+
+}
+
+/// The snapshot file mapped into memory
+#[cfg(unix)]
+struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize
+}
+
+// SAFETY: the mapping is read-only, so it can be read from any thread
+#[cfg(unix)]
+unsafe impl Send for Mapping {}
+#[cfg(unix)]
+unsafe impl Sync for Mapping {}
+
+#[cfg(unix)]
+impl Mapping {
+
+    fn new(file: &std::fs::File, len: usize) -> Result<Self, Error> {
+        use std::os::fd::AsRawFd;
+        // SAFETY: the file descriptor is open for the duration of the call, and the mapping is checked for failure before it is used
+        let ptr = unsafe { libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_SHARED, file.as_raw_fd(), 0) };
+        if ptr == libc::MAP_FAILED {
+            return Err(Error::IO(std::io::Error::last_os_error()));
+        }
+        Ok(Self { ptr, len })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        // SAFETY: the mapping stays valid until it is dropped, and the snapshot file is never written to after it is mapped
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+
+}
+
+#[cfg(unix)]
+impl Drop for Mapping {
+
+    fn drop(&mut self) {
+        // SAFETY: the mapping was made by `Mapping::new` with the same length, and no slices of it outlive `self`
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+
+}
+
+/// The snapshot file read into memory, on platforms where it isn't mapped
+#[cfg(not(unix))]
+struct Mapping(Vec<u8>);
+
+#[cfg(not(unix))]
+impl Mapping {
+
+    fn new(file: &std::fs::File, len: usize) -> Result<Self, Error> {
+        use crate::Backend;
+        let mut bytes = crate::try_zeroed(len)?;
+        file.read_exact_at(&mut bytes, 0).map_err(Error::IO)?;
+        Ok(Self(bytes))
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+}
+
+#[test]
+fn readonly_view() {
+    let mut file = File::open("readonly_view.verter", Config { max_working_memory: Some(1000), ..Config::default() }).unwrap();
+    let frame = file.alloc_with(&[0xAB; 3000]).unwrap();
+    file.write_root(&frame.to_le_bytes()).unwrap();
+
+    let mut view = file.readonly_view().unwrap();
+    let mut worker = view.try_clone().unwrap();
+
+    // The editor keeps writing while the views see the snapshot
+    file.write(frame, b"edited").unwrap();
+    let stroke = file.alloc_with(b"stroke").unwrap();
+    file.write_root(&stroke.to_le_bytes()).unwrap();
+    assert_eq!(view.read_root().unwrap(), frame.to_le_bytes());
+    assert_eq!(worker.read(frame).unwrap(), vec![0xAB; 3000]);
+    drop(view);
+    assert_eq!(worker.read(frame).unwrap(), vec![0xAB; 3000]);
+
+    match worker.file().write(frame, b"edited") {
+        Err(Error::ReadOnly) => {},
+        Ok(_) | Err(_) => panic!("should error with read only")
+    }
+    worker.file().validate().unwrap();
+
+    std::fs::remove_file("readonly_view.verter").unwrap();
+}
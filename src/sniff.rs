@@ -0,0 +1,147 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::format::{header_fields, stored_magic_len, HeaderLayout, DELETED_PAGE, FIELD_SIZE, FINAL_PAGE, FORMAT_VERSION, FROZEN_FLAG, HEADER_CHAIN_FLAG, HEADER_FIELDS, HEAD_FLAG, MAX_MAGIC_LEN, NEXT_PAGE, PAGE_MAP_FLAG, PAGE_TYPE_SHIFT, UNVERSIONED_FIELDS, UNVERSIONED_PAGE_TYPE_SHIFT, VERSION_MASK};
+
+/// The largest page size `sniff` can infer
+const MAX_PAGE_SIZE: u64 = 64 * 1024;
+/// How many bytes from the start of the file `sniff` reads
+const PREFIX_LEN: u64 = MAX_MAGIC_LEN as u64 + HEADER_FIELDS.len() as u64 * FIELD_SIZE + MAX_PAGE_SIZE;
+
+/// What `sniff` found out about a verter file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SniffInfo {
+    /// The magic bytes at the start of the file
+    pub magic: Vec<u8>,
+    /// The version of the file format the file was written with, which is 0 for files from before the format was versioned
+    pub format_version: u64,
+    /// The file's page size, if it could be told from the pages at the start of the file.
+    /// Page sizes aren't stored in the file, so this is the smallest page size the page headers line up with.
+    pub page_size: Option<usize>
+}
+
+/// Check whether `reader` holds a verter file, without opening it as a `File` or knowing the `Config` it was written with,
+/// such as to only show compatible files in a file picker.
+/// Only the header and the first pages are read. Returns `None` if it isn't a verter file.
+/// Files from format version 3 on store the length of their magic bytes. In older files, the magic bytes are found by looking for
+/// where the header would have to end for its root page pointer to point just past it, which also finds files from before the format was versioned.
+pub fn sniff<R: Read + Seek>(reader: &mut R) -> Option<SniffInfo> {
+    let file_len = reader.seek(SeekFrom::End(0)).ok()?;
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    let mut prefix = Vec::new();
+    reader.take(PREFIX_LEN).read_to_end(&mut prefix).ok()?;
+
+    let field = |offset: usize| Some(u64::from_le_bytes(prefix.get(offset..offset + FIELD_SIZE as usize)?.try_into().unwrap()));
+    let fields_len = |format_version: u64| header_fields(format_version).len() * FIELD_SIZE as usize;
+    let root_offset = HEADER_FIELDS.iter().position(|name| *name == "root_page").unwrap() * FIELD_SIZE as usize;
+
+    // Only known versions are accepted, since the end of longer magic bytes followed by a small version can look like a shorter magic length
+    let stores_magic_len = |magic_len: usize| field(magic_len).is_some_and(|field| (3..=FORMAT_VERSION).contains(&(field & VERSION_MASK)) && stored_magic_len(field) == magic_len as u64);
+    // The root chain is the first chain allocated, so it always starts at the first page
+    let root_after_header = |magic_len: usize| field(magic_len + root_offset) == Some((magic_len + fields_len(2)) as u64);
+    let unversioned_root_offset = UNVERSIONED_FIELDS.iter().position(|name| *name == "root_page").unwrap() * FIELD_SIZE as usize;
+    let unversioned = |magic_len: usize| field(magic_len + unversioned_root_offset) == Some((magic_len + fields_len(0)) as u64);
+    let versioned = (1..=MAX_MAGIC_LEN).find(|magic_len| stores_magic_len(*magic_len))
+        .or_else(|| (1..=MAX_MAGIC_LEN).find(|magic_len| root_after_header(*magic_len)))
+        .filter(|magic_len| field(*magic_len).is_some_and(|field| field & VERSION_MASK != 0));
+    let (magic_len, format_version) = match versioned {
+        Some(magic_len) => (magic_len, field(magic_len)? & VERSION_MASK),
+        None => ((1..=MAX_MAGIC_LEN).find(|magic_len| unversioned(*magic_len))?, 0)
+    };
+
+    // Files upgraded from version 0 keep their pages where they were, right after a header of the `HEADER_CHAIN_FIELDS`
+    let layout = HeaderLayout {
+        magic_len: magic_len as u64,
+        format_version,
+        header_chain: format_version != 0 && field(magic_len)? & HEADER_CHAIN_FLAG != 0,
+        page_maps: format_version != 0 && field(magic_len)? & PAGE_MAP_FLAG != 0
+    };
+    Some(SniffInfo {
+        magic: prefix[..magic_len].to_vec(),
+        format_version,
+        page_size: infer_page_size(&prefix, layout, file_len)
+    })
+}
+
+/// Check whether the file at `path` is a verter file. See `sniff`.
+/// Returns `None` if the file can't be read.
+pub fn sniff_path<P: AsRef<Path>>(path: P) -> Option<SniffInfo> {
+    sniff(&mut std::fs::File::open(path).ok()?)
+}
+
+/// Find the smallest page size for which every page header in `prefix` makes sense.
+/// The page headers of files from before the format was versioned have no flags, and their page type starts at `UNVERSIONED_PAGE_TYPE_SHIFT`.
+/// The page map at the start of a file with page maps is in the same place whatever the page size, so its headers are checked instead.
+fn infer_page_size(prefix: &[u8], layout: HeaderLayout, file_len: u64) -> Option<usize> {
+    let unversioned = layout.format_version == 0;
+    let type_shift = if unversioned { UNVERSIONED_PAGE_TYPE_SHIFT } else { PAGE_TYPE_SHIFT };
+    let flags = if unversioned { 0 } else { HEAD_FLAG | FROZEN_FLAG };
+    let header_size = layout.size();
+    file_len.checked_sub(header_size)?;
+    (1..=MAX_PAGE_SIZE).find(|page_size| {
+        let total_page_size = page_size + FIELD_SIZE;
+        let len = layout.pointer_len(*page_size, file_len);
+        let pages_len = len - header_size;
+        if pages_len == 0 || !pages_len.is_multiple_of(total_page_size) || layout.stored_len(*page_size, len) != file_len {
+            return false;
+        }
+        let is_page = |ptr: u64| ptr >= header_size && ptr < len && (ptr - header_size).is_multiple_of(total_page_size);
+        let mut ptr = header_size;
+        while ptr < len {
+            let offset = layout.page_header_offset(*page_size, ptr);
+            let Some(bytes) = prefix.get(offset as usize..(offset + FIELD_SIZE) as usize) else {
+                break;
+            };
+            let header = u64::from_le_bytes(bytes.try_into().unwrap());
+            let value = header & ((1 << type_shift) - 1);
+            let valid = match (header & !flags) >> type_shift {
+                NEXT_PAGE => is_page(value),
+                FINAL_PAGE => value <= *page_size,
+                DELETED_PAGE => header & flags == 0 && (value == 0 || is_page(value)),
+                _ => false
+            };
+            if !valid {
+                return false;
+            }
+            ptr += total_page_size;
+        }
+        true
+    }).map(|page_size| page_size as usize)
+}
+
+#[test]
+fn sniff_files() {
+    use crate::{Config, File};
+
+    let config = Config { magic_bytes: b"STORYBOARD", page_size: 256, ..Config::default() };
+    let mut file = File::open("sniff_files.verter", config).unwrap();
+    file.write_root(&[1; 1000]).unwrap();
+    let junk = file.alloc_with(&[0; 600]).unwrap();
+    file.alloc_with(b"frame").unwrap();
+    file.delete(junk).unwrap();
+    drop(file);
+    assert_eq!(sniff_path("sniff_files.verter"), Some(SniffInfo {
+        magic: b"STORYBOARD".to_vec(),
+        format_version: FORMAT_VERSION,
+        page_size: Some(256)
+    }));
+
+    let bytes = std::fs::read("fixtures/v2.verter").unwrap();
+    let info = sniff(&mut std::io::Cursor::new(bytes)).unwrap();
+    assert_eq!((info.magic.as_slice(), info.format_version, info.page_size), (&b"VERTER__"[..], 2, Some(Config::default().page_size)));
+
+    // Files from before the format was versioned, and the same file once upgraded
+    std::fs::copy("fixtures/v0.verter", "sniff_files_v0.verter").unwrap();
+    let info = sniff_path("sniff_files_v0.verter").unwrap();
+    assert_eq!((info.magic.as_slice(), info.format_version, info.page_size), (&b"VERTER__"[..], 0, Some(Config::default().page_size)));
+    drop(File::open("sniff_files_v0.verter", Config::default()).unwrap());
+    let info = sniff_path("sniff_files_v0.verter").unwrap();
+    assert_eq!((info.magic.as_slice(), info.format_version, info.page_size), (&b"VERTER__"[..], FORMAT_VERSION, Some(Config::default().page_size)));
+
+    assert_eq!(sniff(&mut std::io::Cursor::new(b"STORYBOARD".to_vec())), None);
+    assert_eq!(sniff(&mut std::io::Cursor::new(vec![0xAB; 4096])), None);
+    assert_eq!(sniff_path("sniff_files_missing.verter"), None);
+
+    std::fs::remove_file("sniff_files.verter").unwrap();
+    std::fs::remove_file("sniff_files_v0.verter").unwrap();
+}
@@ -0,0 +1,90 @@
+use crate::{Error, File};
+
+impl File {
+
+    /// Fail with `Error::InsufficientSpace` if `Config::check_free_space` is enabled and the disk
+    /// doesn't have room for `new_pages` more pages, assuming all of them grow the file
+    pub(crate) fn check_free_space(&self, new_pages: usize) -> Result<(), Error> {
+        if !self.config.check_free_space || new_pages == 0 {
+            return Ok(());
+        }
+        match self.file.available_space().map_err(Error::IO)? {
+            Some(available) if new_pages as u64 * self.total_page_size() > available => Err(Error::InsufficientSpace),
+            _ => Ok(())
+        }
+    }
+
+}
+
+#[test]
+fn insufficient_space() {
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+    use crate::{Backend, Config};
+
+    /// An in-memory backend pretending to be on a disk with only 1000 bytes left
+    struct NearlyFullDisk(Cursor<Vec<u8>>);
+
+    impl Read for NearlyFullDisk {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for NearlyFullDisk {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    impl Seek for NearlyFullDisk {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    impl Backend for NearlyFullDisk {
+        fn len(&self) -> std::io::Result<u64> {
+            self.0.len()
+        }
+
+        fn sync_data(&self) -> std::io::Result<()> {
+            Ok(())
+        }
+
+        fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+            self.0.read_exact_at(buf, offset)
+        }
+
+        fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+            self.0.set_len(len)
+        }
+
+        fn available_space(&self) -> std::io::Result<Option<u64>> {
+            Ok(Some(1000))
+        }
+    }
+
+    let config = Config {
+        check_free_space: true,
+        ..Config::default()
+    };
+    let mut file = File::from_backend(Box::new(NearlyFullDisk(Cursor::new(Vec::new()))), config, true).unwrap();
+    let ptr = file.alloc_with(&[0xAB; 500]).unwrap();
+    match file.write(ptr, &[0xCD; 5000]) {
+        Err(Error::InsufficientSpace) => {},
+        Ok(_) | Err(_) => panic!("should error with insufficient space")
+    }
+    assert_eq!(file.read(ptr).unwrap(), vec![0xAB; 500]);
+    file.validate().unwrap();
+
+    // Real files report the free space of their disk
+    let mut file = File::open("insufficient_space.verter", config).unwrap();
+    let ptr = file.alloc_with(&[0xAB; 5000]).unwrap();
+    assert_eq!(file.read(ptr).unwrap(), vec![0xAB; 5000]);
+
+    std::fs::remove_file("insufficient_space.verter").unwrap();
+}
@@ -0,0 +1,136 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use crate::{Error, File};
+
+/// A change to a chain, sent to the receivers returned by `File::subscribe`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeEvent {
+    /// The chain's data was written to
+    Written(u64),
+    /// The chain was deleted
+    Deleted(u64)
+}
+
+impl ChangeEvent {
+
+    /// The pointer to the chain that changed.
+    pub fn ptr(&self) -> u64 {
+        match self {
+            Self::Written(ptr) | Self::Deleted(ptr) => *ptr
+        }
+    }
+
+}
+
+/// The senders of every subscription, along with the changes held back until the current transaction commits
+#[derive(Default)]
+pub(crate) struct Subscribers {
+    senders: Vec<(u64, Sender<ChangeEvent>)>,
+    deferred: Option<Vec<ChangeEvent>>
+}
+
+impl Subscribers {
+
+    pub(crate) fn notify(&mut self, event: ChangeEvent) {
+        if self.senders.is_empty() {
+            return;
+        }
+        match &mut self.deferred {
+            Some(deferred) => deferred.push(event),
+            None => self.send(event)
+        }
+    }
+
+    fn send(&mut self, event: ChangeEvent) {
+        // Subscriptions whose receiver was dropped are removed, and so are those to deleted chains, whose pages may go to another chain
+        self.senders.retain(|(ptr, sender)| *ptr != event.ptr() || (sender.send(event).is_ok() && !matches!(event, ChangeEvent::Deleted(_))));
+    }
+
+    /// Hold changes back until `Subscribers::flush` or `Subscribers::discard`
+    pub(crate) fn defer(&mut self) {
+        self.deferred = Some(Vec::new());
+    }
+
+    /// The number of changes held back so far
+    pub(crate) fn deferred_len(&self) -> usize {
+        self.deferred.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Forget the changes held back after the first `len`
+    pub(crate) fn truncate(&mut self, len: usize) {
+        if let Some(deferred) = &mut self.deferred {
+            deferred.truncate(len);
+        }
+    }
+
+    /// Send the changes held back, and stop holding changes back
+    pub(crate) fn flush(&mut self) {
+        for event in self.deferred.take().unwrap_or_default() {
+            self.send(event);
+        }
+    }
+
+    /// Forget the changes held back, and stop holding changes back
+    pub(crate) fn discard(&mut self) {
+        self.deferred = None;
+    }
+
+}
+
+impl File {
+
+    /// Get told about every change to a chain, such as to refresh a view of it without polling.
+    /// A `ChangeEvent` is sent for every write and for the deletion of the chain, once the change is made,
+    /// or for changes made in a transaction, once it commits. Changes of rolled back transactions are never sent.
+    /// Only changes made through this handle are seen. The subscription ends when the chain is deleted or the receiver is dropped.
+    pub fn subscribe(&mut self, ptr: u64) -> Result<Receiver<ChangeEvent>, Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        let (sender, receiver) = channel();
+        self.hooks.subscribers.senders.push((ptr, sender));
+        Ok(receiver)
+    }
+
+}
+
+#[test]
+fn subscribe() {
+    use crate::Config;
+
+    let mut file = File::open("subscribe.verter", Config::default()).unwrap();
+    let timeline = file.alloc_with(b"timeline").unwrap();
+    let canvas = file.alloc_with(b"canvas").unwrap();
+    let timeline_changes = file.subscribe(timeline).unwrap();
+    let canvas_changes = file.subscribe(canvas).unwrap();
+
+    file.write(timeline, b"timeline with frame").unwrap();
+    file.append(timeline, b"s").unwrap();
+    assert_eq!(timeline_changes.try_iter().collect::<Vec<_>>(), vec![ChangeEvent::Written(timeline); 2]);
+    assert!(canvas_changes.try_recv().is_err());
+
+    // Changes made in a transaction are only sent once it commits, and never if it is rolled back
+    let mut transaction = file.transaction().unwrap();
+    transaction.write(canvas, b"stroke").unwrap();
+    let savepoint = transaction.savepoint();
+    transaction.write(timeline, b"discarded").unwrap();
+    transaction.rollback_to(savepoint).unwrap();
+    assert!(canvas_changes.try_recv().is_err());
+    transaction.commit().unwrap();
+    assert_eq!(canvas_changes.try_iter().collect::<Vec<_>>(), vec![ChangeEvent::Written(canvas)]);
+    assert!(timeline_changes.try_recv().is_err());
+
+    let mut transaction = file.transaction().unwrap();
+    transaction.delete(canvas).unwrap();
+    transaction.rollback().unwrap();
+    assert!(canvas_changes.try_recv().is_err());
+    file.delete(canvas).unwrap();
+    assert_eq!(canvas_changes.try_recv(), Ok(ChangeEvent::Deleted(canvas)));
+    assert_eq!(canvas_changes.try_recv(), Err(std::sync::mpsc::TryRecvError::Disconnected));
+
+    // Dropped receivers are unsubscribed
+    drop(timeline_changes);
+    file.write(timeline, b"unobserved").unwrap();
+    assert!(file.hooks().subscribers.senders.is_empty());
+
+    std::fs::remove_file("subscribe.verter").unwrap();
+}
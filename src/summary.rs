@@ -0,0 +1,155 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::dedup::content_hash;
+use crate::{Error, File, BYTES_IN_U64};
+
+/// The summary stored at the start of every chain in `ChainFormat::V2`, after the chain's metadata.
+/// Stored as the length of the chain's data, the number of pages in the chain, and a checksum of the data in the head page or 0.
+const SUMMARY_SIZE: u64 = 3 * BYTES_IN_U64;
+
+/// How chains are laid out in their pages.
+/// Must be the same every time the file is opened.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChainFormat {
+    /// Chains only hold their data, after their metadata if `Config::chain_meta` is enabled
+    #[default]
+    V1,
+    /// The head page of every chain also holds a `ChainSummary`, so a chain's length is known without walking it
+    /// and a chain whose tail was damaged can still be partially recovered by `File::salvage`.
+    V2 {
+        /// Whether the summary includes a checksum of the data in the head page, which has to be recalculated on every write
+        head_checksum: bool
+    }
+}
+
+/// What the head page of a chain in `ChainFormat::V2` records about the chain
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChainSummary {
+    /// The number of bytes of data in the chain
+    pub len: u64,
+    /// The number of pages in the chain
+    pub pages: u64,
+    /// The checksum of the data in the head page, if `head_checksum` is enabled and the chain wasn't written with `WriteOpts::checksum` turned off
+    pub head_checksum: Option<u64>
+}
+
+impl File {
+
+    /// Get the summary stored in the head page of a chain.
+    /// Requires `Config::chain_format` to be `ChainFormat::V2`.
+    pub fn chain_summary(&mut self, ptr: u64) -> Result<ChainSummary, Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        self.read_summary(ptr)
+    }
+
+    /// The number of bytes at the start of every chain taken up by its summary
+    pub(crate) fn summary_size(&self) -> u64 {
+        match self.config.chain_format {
+            ChainFormat::V1 => 0,
+            ChainFormat::V2 { .. } => SUMMARY_SIZE
+        }
+    }
+
+    /// Encode the summary of a chain holding `data`, which doesn't include the chain's metadata or summary.
+    /// Empty in `ChainFormat::V1`.
+    pub(crate) fn encode_summary(&self, data: &[u8]) -> Vec<u8> {
+        if self.summary_size() == 0 {
+            return Vec::new();
+        }
+        let meta_size = self.meta_size();
+        let head_len = data.len().min(self.config.page_size.saturating_sub(meta_size as usize));
+        let pages = self.pages_needed(meta_size + data.len() as u64);
+        self.summary_bytes(data.len() as u64, pages, &data[..head_len])
+    }
+
+    /// Recalculate the summary of a chain made of the given pages holding `len` bytes of data, after its data was edited in place
+    pub(crate) fn write_summary(&mut self, pages: &[u64], len: u64) -> Result<(), Error> {
+        if self.summary_size() == 0 {
+            return Ok(());
+        }
+        let meta_size = self.meta_size();
+        let mut head = vec![0; len.min((self.config.page_size as u64).saturating_sub(meta_size)) as usize];
+        self.read_at(pages, meta_size, &mut head)?;
+        let summary = self.summary_bytes(len, pages.len() as u64, &head);
+        self.check_writable()?;
+        self.file.seek(SeekFrom::Start(pages[0] + BYTES_IN_U64 + self.summary_offset())).map_err(Error::IO)?;
+        self.file.write_all(&summary).map_err(Error::IO)
+    }
+
+    fn summary_bytes(&self, len: u64, pages: u64, head: &[u8]) -> Vec<u8> {
+        let checksum = match self.config.chain_format {
+            ChainFormat::V2 { head_checksum: true } => content_hash(head),
+            ChainFormat::V1 | ChainFormat::V2 { head_checksum: false } => 0
+        };
+        let mut bytes = Vec::with_capacity(SUMMARY_SIZE as usize);
+        bytes.extend_from_slice(&len.to_le_bytes());
+        bytes.extend_from_slice(&pages.to_le_bytes());
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Read the summary of a chain without checking the pointer
+    pub(crate) fn read_summary(&mut self, ptr: u64) -> Result<ChainSummary, Error> {
+        let ChainFormat::V2 { head_checksum } = self.config.chain_format else {
+            return Err(Error::MetadataDisabled);
+        };
+        let mut bytes = [0; SUMMARY_SIZE as usize];
+        self.file.seek(SeekFrom::Start(ptr + BYTES_IN_U64 + self.summary_offset())).map_err(Error::IO)?;
+        self.file.read_exact(&mut bytes).map_err(Error::IO)?;
+        Ok(ChainSummary {
+            len: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            pages: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            head_checksum: head_checksum.then(|| u64::from_le_bytes(bytes[16..24].try_into().unwrap())).filter(|checksum| *checksum != 0)
+        })
+    }
+
+    /// The offset of the summary into a chain's data, which comes after the chain's metadata
+    fn summary_offset(&self) -> u64 {
+        self.meta_size() - self.summary_size()
+    }
+
+}
+
+#[test]
+fn chain_summary() {
+    use crate::Config;
+
+    let config = Config {
+        chain_meta: true,
+        chain_format: ChainFormat::V2 { head_checksum: true },
+        ..Config::default()
+    };
+
+    let mut file = File::open("chain_summary.verter", config).unwrap();
+    let empty = file.alloc().unwrap();
+    assert_eq!(file.chain_summary(empty).unwrap(), ChainSummary { len: 0, pages: 1, head_checksum: Some(content_hash(&[])) });
+    let ptr = file.alloc_with(&[0xAB; 300]).unwrap();
+    file.patch(ptr, &[(0, b"png"), (400, b"end")]).unwrap();
+    drop(file);
+
+    let mut file = File::open("chain_summary.verter", config).unwrap();
+    let mut expected = vec![0xAB; 300];
+    expected[..3].copy_from_slice(b"png");
+    expected.resize(400, 0);
+    expected.extend_from_slice(b"end");
+    assert_eq!(file.read(ptr).unwrap(), expected);
+    let summary = file.chain_summary(ptr).unwrap();
+    assert_eq!(summary.len, 403);
+    assert_eq!(summary.pages, file.chain_pages(ptr).unwrap().len() as u64);
+    assert_eq!(summary.head_checksum, Some(content_hash(&expected[..120 - file.meta_size() as usize])));
+    assert_eq!(file.chain_file(ptr).unwrap().len().unwrap(), 403);
+
+    let imported = file.alloc_with(b"small").unwrap();
+    file.write(imported, b"").unwrap();
+    assert_eq!(file.chain_summary(imported).unwrap().len, 0);
+    assert_eq!(file.read(imported).unwrap(), b"");
+    file.validate().unwrap();
+
+    match File::open("chain_summary.verter", Config::default()).unwrap().chain_summary(ptr) {
+        Err(Error::MetadataDisabled) => {},
+        Ok(_) | Err(_) => panic!("should error with metadata disabled")
+    }
+
+    std::fs::remove_file("chain_summary.verter").unwrap();
+}
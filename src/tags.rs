@@ -0,0 +1,130 @@
+use crate::{decode_u64s, Error, File, BYTES_IN_U64};
+
+impl File {
+
+    /// Save a copy of the root chain's data under a name, as a restore point. Replaces any existing tag with the same name.
+    /// Only the root chain itself is copied, so chains it points to must be kept unchanged by the application
+    /// for the tagged root to stay meaningful.
+    pub fn tag(&mut self, name: &str) -> Result<(), Error> {
+        self.check_for_external_changes()?;
+        let root = match self.root_page()? {
+            0 => Vec::new(),
+            root_page => self.read_chain(root_page)?
+        };
+        let mut table = self.read_tag_table()?;
+        let chain = match table.iter().find(|(tag, _)| tag == name) {
+            Some((_, chain)) => *chain,
+            None => {
+                let chain = self.alloc()?;
+                table.push((name.to_owned(), chain));
+                self.write_tag_table(&table)?;
+                chain
+            }
+        };
+        self.write_chain(chain, &root)
+    }
+
+    /// The names of every tag, in the order they were first created.
+    pub fn list_tags(&mut self) -> Result<Vec<String>, Error> {
+        Ok(self.read_tag_table()?.into_iter().map(|(name, _)| name).collect())
+    }
+
+    /// Read the root chain's data as it was when the tag was saved.
+    /// Returns `None` if there is no tag with the given name.
+    pub fn read_root_at_tag(&mut self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        self.check_for_external_changes()?;
+        match self.read_tag_table()?.into_iter().find(|(tag, _)| tag == name) {
+            Some((_, chain)) => self.read_chain(chain).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    /// Delete a tag. Returns `false` if there is no tag with the given name.
+    pub fn remove_tag(&mut self, name: &str) -> Result<bool, Error> {
+        self.check_for_external_changes()?;
+        let mut table = self.read_tag_table()?;
+        let Some(idx) = table.iter().position(|(tag, _)| tag == name) else {
+            return Ok(false);
+        };
+        let (_, chain) = table.remove(idx);
+        self.write_tag_table(&table)?;
+        self.delete_chain(chain)?;
+        Ok(true)
+    }
+
+    /// The tag table chain and the chains holding every tagged root
+    pub(crate) fn tag_chains(&mut self) -> Result<Vec<u64>, Error> {
+        let table_chain = self.read_u64(self.tag_table_ptr())?;
+        if table_chain == 0 {
+            return Ok(Vec::new());
+        }
+        let mut chains = vec![table_chain];
+        chains.extend(self.read_tag_table()?.into_iter().map(|(_, chain)| chain));
+        Ok(chains)
+    }
+
+    /// The tag table is a list of entries, each encoded as (name length, name bytes, chain)
+    pub(crate) fn read_tag_table(&mut self) -> Result<Vec<(String, u64)>, Error> {
+        let chain = self.read_u64(self.tag_table_ptr())?;
+        if chain == 0 {
+            return Ok(Vec::new());
+        }
+        let data = self.read_chain(chain)?;
+        let mut data = data.as_slice();
+        let mut table = Vec::new();
+        while !data.is_empty() {
+            let (name_len, rest) = data.split_at_checked(BYTES_IN_U64 as usize).ok_or(Error::CorruptedFile)?;
+            let name_len = u64::from_le_bytes(name_len.try_into().unwrap()) as usize;
+            let (name, rest) = rest.split_at_checked(name_len).ok_or(Error::CorruptedFile)?;
+            let name = String::from_utf8(name.to_vec()).map_err(|_| Error::CorruptedFile)?;
+            let (chain, rest) = rest.split_at_checked(BYTES_IN_U64 as usize).ok_or(Error::CorruptedFile)?;
+            table.push((name, decode_u64s(chain)?[0]));
+            data = rest;
+        }
+        Ok(table)
+    }
+
+    pub(crate) fn write_tag_table(&mut self, table: &[(String, u64)]) -> Result<(), Error> {
+        let mut chain = self.read_u64(self.tag_table_ptr())?;
+        if chain == 0 {
+            chain = self.alloc()?;
+            self.write_u64(self.tag_table_ptr(), chain)?;
+        }
+        let mut data = Vec::new();
+        for (name, tag_chain) in table {
+            data.extend_from_slice(&(name.len() as u64).to_le_bytes());
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(&tag_chain.to_le_bytes());
+        }
+        self.write_chain(chain, &data)
+    }
+
+}
+
+#[test]
+fn tags() {
+    use crate::Config;
+
+    let mut file = File::open("tags.verter", Config::default()).unwrap();
+    file.write_root(b"first draft").unwrap();
+    file.tag("before-big-refactor").unwrap();
+    file.write_root(b"refactored").unwrap();
+    file.tag("after").unwrap();
+    file.write_root(b"refactored again").unwrap();
+    drop(file);
+
+    let mut file = File::open("tags.verter", Config::default()).unwrap();
+    assert_eq!(file.list_tags().unwrap(), vec!["before-big-refactor", "after"]);
+    assert_eq!(file.read_root_at_tag("before-big-refactor").unwrap().unwrap(), b"first draft");
+    assert_eq!(file.read_root_at_tag("after").unwrap().unwrap(), b"refactored");
+    assert_eq!(file.read_root_at_tag("missing").unwrap(), None);
+
+    // Tagged roots survive garbage collection
+    file.gc(&[], |_| Vec::new()).unwrap();
+    assert!(file.remove_tag("after").unwrap());
+    assert!(!file.remove_tag("after").unwrap());
+    assert_eq!(file.read_root_at_tag("before-big-refactor").unwrap().unwrap(), b"first draft");
+    file.validate().unwrap();
+
+    std::fs::remove_file("tags.verter").unwrap();
+}
@@ -0,0 +1,209 @@
+//! Tools for testing how verter, and applications built on it, behave when things go wrong.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::Backend;
+
+/// A change that has not been synced yet
+#[derive(Clone)]
+enum Pending {
+    Write(u64, Vec<u8>),
+    SetLen(u64)
+}
+
+struct SimState {
+    /// What the file holds as seen by reads
+    current: Vec<u8>,
+    /// What the file holds on durable storage, as of the last sync
+    durable: Vec<u8>,
+    /// Every change since the last sync, in order
+    pending: Vec<Pending>,
+    syncs: u64
+}
+
+fn apply(bytes: &mut Vec<u8>, change: &Pending) {
+    match change {
+        Pending::Write(offset, data) => {
+            let end = *offset as usize + data.len();
+            if bytes.len() < end {
+                bytes.resize(end, 0);
+            }
+            bytes[*offset as usize..end].copy_from_slice(data);
+        },
+        Pending::SetLen(len) => bytes.resize(*len as usize, 0)
+    }
+}
+
+/// An in-memory backend that keeps track of which writes have been synced, to simulate a machine crashing at any point.
+/// Writes only become durable when the file is synced with `Backend::sync_data`, such as by `File::flush`.
+/// Use the `CrashHandle` returned alongside it to get what would be left on the disk after a crash.
+/// Every crash is deterministic, so a failing test can be replayed exactly.
+pub struct CrashSim {
+    state: Arc<Mutex<SimState>>,
+    pos: u64
+}
+
+/// Inspects a `CrashSim` after it was moved into a `File`
+#[derive(Clone)]
+pub struct CrashHandle {
+    state: Arc<Mutex<SimState>>
+}
+
+impl CrashSim {
+
+    /// Create an empty simulated file, which `File::open_backend` initiates like a new file.
+    pub fn new() -> (Self, CrashHandle) {
+        Self::from_bytes(Vec::new())
+    }
+
+    /// Create a simulated file holding the given bytes, all of which are durable, such as an image returned by `CrashHandle::crash`.
+    pub fn from_bytes(bytes: Vec<u8>) -> (Self, CrashHandle) {
+        let state = Arc::new(Mutex::new(SimState { current: bytes.clone(), durable: bytes, pending: Vec::new(), syncs: 0 }));
+        (Self { state: state.clone(), pos: 0 }, CrashHandle { state })
+    }
+
+}
+
+impl CrashHandle {
+
+    /// The number of times the file was synced.
+    pub fn syncs(&self) -> u64 {
+        self.state.lock().unwrap().syncs
+    }
+
+    /// The number of writes and resizes made since the file was last synced.
+    pub fn unsynced(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    /// The bytes left on the disk if the machine crashed now and every unsynced change was lost.
+    pub fn crash(&self) -> Vec<u8> {
+        self.crash_keeping(|_| false)
+    }
+
+    /// The bytes left on the disk if the machine crashed now after only the first `n` unsynced changes reached it,
+    /// as on a disk that writes in order.
+    pub fn crash_after(&self, n: usize) -> Vec<u8> {
+        self.crash_keeping(|idx| idx < n)
+    }
+
+    /// The bytes left on the disk if the machine crashed now after only the unsynced changes `keep` picks by index reached it,
+    /// as on a disk that reorders writes.
+    pub fn crash_keeping<F: FnMut(usize) -> bool>(&self, mut keep: F) -> Vec<u8> {
+        let state = self.state.lock().unwrap();
+        let mut bytes = state.durable.clone();
+        for (idx, change) in state.pending.iter().enumerate() {
+            if keep(idx) {
+                apply(&mut bytes, change);
+            }
+        }
+        bytes
+    }
+
+}
+
+impl Read for CrashSim {
+
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let state = self.state.lock().unwrap();
+        let start = (self.pos as usize).min(state.current.len());
+        let n = buf.len().min(state.current.len() - start);
+        buf[..n].copy_from_slice(&state.current[start..start + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+}
+
+impl Write for CrashSim {
+
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        let change = Pending::Write(self.pos, buf.to_vec());
+        apply(&mut state.current, &change);
+        state.pending.push(change);
+        self.pos += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+}
+
+impl Seek for CrashSim {
+
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let len = self.state.lock().unwrap().current.len() as u64;
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset)
+        };
+        self.pos = new_pos.ok_or(std::io::ErrorKind::InvalidInput)?;
+        Ok(self.pos)
+    }
+
+}
+
+impl Backend for CrashSim {
+
+    fn len(&self) -> std::io::Result<u64> {
+        Ok(self.state.lock().unwrap().current.len() as u64)
+    }
+
+    fn sync_data(&self) -> std::io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.durable = state.current.clone();
+        state.pending.clear();
+        state.syncs += 1;
+        Ok(())
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        let state = self.state.lock().unwrap();
+        let start = usize::try_from(offset).map_err(|_| std::io::ErrorKind::UnexpectedEof)?;
+        let bytes = state.current.get(start..start + buf.len()).ok_or(std::io::ErrorKind::UnexpectedEof)?;
+        buf.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let change = Pending::SetLen(len);
+        apply(&mut state.current, &change);
+        state.pending.push(change);
+        Ok(())
+    }
+
+}
+
+#[test]
+fn crash_sim() {
+    use crate::{Config, File};
+
+    let (sim, handle) = CrashSim::new();
+    let mut file = File::open_backend(Box::new(sim), Config::default()).unwrap();
+    file.write_root(b"saved").unwrap();
+    file.flush().unwrap();
+    let ptr = file.alloc_with(&[0xAB; 500]).unwrap();
+    file.write_root(&ptr.to_le_bytes()).unwrap();
+    assert!(handle.unsynced() > 0);
+
+    // Unsynced changes are lost in a crash
+    let mut crashed = File::open_backend(Box::new(CrashSim::from_bytes(handle.crash()).0), Config::default()).unwrap();
+    assert_eq!(crashed.read_root().unwrap(), b"saved");
+
+    // Wherever an in-order disk stops, the root stays readable
+    for n in 0..=handle.unsynced() {
+        let mut crashed = File::open_backend(Box::new(CrashSim::from_bytes(handle.crash_after(n)).0), Config::default()).unwrap();
+        crashed.read_root().unwrap();
+    }
+
+    file.flush().unwrap();
+    assert_eq!(handle.unsynced(), 0);
+    let mut crashed = File::open_backend(Box::new(CrashSim::from_bytes(handle.crash()).0), Config::default()).unwrap();
+    assert_eq!(crashed.read(ptr).unwrap(), vec![0xAB; 500]);
+}
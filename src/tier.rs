@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+
+use crate::{decode_u64s, encode_u64s, Error, File};
+
+impl File {
+
+    /// Set the file that demoted chains are moved to, such as a file on slower archival storage.
+    /// The same cold file must be set every time this file is opened, since this file stores pointers into it.
+    pub fn set_cold_tier(&mut self, cold: File) {
+        self.cold = Some(Box::new(cold));
+    }
+
+    /// Move a chain's data to the cold tier, freeing all but the head page of the chain.
+    /// The chain keeps its pointer, and can still be read, written and deleted as usual.
+    /// Fails with `Error::FrozenChain` for frozen chains and `Error::ReservedChain` for verter's internal chains, before either file is touched.
+    pub fn demote(&mut self, ptr: u64) -> Result<(), Error> {
+        self.check_tier_move(ptr)?;
+        let mut table = self.read_cold_table()?;
+        if table.contains_key(&ptr) {
+            return Ok(());
+        }
+
+        let data = self.read_chain(ptr)?;
+        let cold = self.cold.as_mut().ok_or(Error::NoColdTier)?;
+        let cold_ptr = cold.alloc()?;
+        cold.write(cold_ptr, &data)?;
+
+        table.insert(ptr, cold_ptr);
+        self.write_cold_table(&table)?;
+        self.write_chain(ptr, &[])
+    }
+
+    /// Move a demoted chain's data back into this file. Fails like `File::demote` for chains that can't be moved.
+    pub fn promote(&mut self, ptr: u64) -> Result<(), Error> {
+        self.check_tier_move(ptr)?;
+        let mut table = self.read_cold_table()?;
+        let Some(cold_ptr) = table.remove(&ptr) else {
+            return Ok(());
+        };
+
+        let cold = self.cold.as_mut().ok_or(Error::NoColdTier)?;
+        let data = cold.read(cold_ptr)?;
+        self.write_chain(ptr, &data)?;
+        self.write_cold_table(&table)?;
+        self.cold.as_mut().ok_or(Error::NoColdTier)?.delete(cold_ptr)
+    }
+
+    /// Return an error if a chain can't be moved between tiers, before anything is written to either file
+    fn check_tier_move(&mut self, ptr: u64) -> Result<(), Error> {
+        self.check_writable()?;
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        self.check_not_reserved(ptr)?;
+        self.check_not_frozen(ptr)?;
+        self.cold.as_mut().ok_or(Error::NoColdTier)?.check_writable()
+    }
+
+    /// Check whether a chain's data is stored in the cold tier.
+    pub fn is_cold(&mut self, ptr: u64) -> Result<bool, Error> {
+        Ok(self.cold_ptr(ptr)?.is_some())
+    }
+
+    /// Read a chain from the cold tier, or `None` if the chain is not cold
+    pub(crate) fn read_cold(&mut self, ptr: u64) -> Result<Option<Vec<u8>>, Error> {
+        let Some(cold_ptr) = self.cold_ptr(ptr)? else {
+            return Ok(None);
+        };
+        self.cold.as_mut().ok_or(Error::NoColdTier)?.read(cold_ptr).map(Some)
+    }
+
+    /// Write a chain in the cold tier, returning `false` if the chain is not cold
+    pub(crate) fn write_cold(&mut self, ptr: u64, data: &[u8]) -> Result<bool, Error> {
+        let Some(cold_ptr) = self.cold_ptr(ptr)? else {
+            return Ok(false);
+        };
+        self.check_not_frozen(ptr)?;
+        self.cold.as_mut().ok_or(Error::NoColdTier)?.write(cold_ptr, data)?;
+        Ok(true)
+    }
+
+    /// Delete the cold tier copy of a chain that is about to be deleted
+    pub(crate) fn forget_cold(&mut self, ptr: u64) -> Result<(), Error> {
+        let mut table = self.read_cold_table()?;
+        let Some(cold_ptr) = table.remove(&ptr) else {
+            return Ok(());
+        };
+        self.write_cold_table(&table)?;
+        self.cold.as_mut().ok_or(Error::NoColdTier)?.delete(cold_ptr)
+    }
+
+    fn cold_ptr(&mut self, ptr: u64) -> Result<Option<u64>, Error> {
+        Ok(self.read_cold_table()?.get(&ptr).copied())
+    }
+
+    /// The cold table maps chains in this file to the chains in the cold tier holding their data
+    pub(crate) fn read_cold_table(&mut self) -> Result<BTreeMap<u64, u64>, Error> {
+        let chain = self.read_u64(self.cold_table_ptr())?;
+        if chain == 0 {
+            return Ok(BTreeMap::new());
+        }
+        let entries = decode_u64s(&self.read_chain(chain)?)?;
+        if !entries.len().is_multiple_of(2) {
+            return Err(Error::CorruptedFile);
+        }
+        Ok(entries.chunks_exact(2).map(|entry| (entry[0], entry[1])).collect())
+    }
+
+    pub(crate) fn write_cold_table(&mut self, table: &BTreeMap<u64, u64>) -> Result<(), Error> {
+        let mut chain = self.read_u64(self.cold_table_ptr())?;
+        if chain == 0 {
+            chain = self.alloc()?;
+            self.write_u64(self.cold_table_ptr(), chain)?;
+        }
+        let entries = table.iter().flat_map(|(ptr, cold_ptr)| [*ptr, *cold_ptr]).collect::<Vec<_>>();
+        self.write_chain(chain, &encode_u64s(&entries))
+    }
+
+}
+
+#[test]
+fn tiering() {
+    use crate::Config;
+
+    let mut file = File::open("tiering_hot.verter", Config::default()).unwrap();
+    file.set_cold_tier(File::open("tiering_cold.verter", Config::default()).unwrap());
+    let archived = file.alloc().unwrap();
+    file.write(archived, &[0xAB; 1000]).unwrap();
+
+    file.demote(archived).unwrap();
+    let hot_size = std::fs::metadata("tiering_hot.verter").unwrap().len();
+    assert!(file.is_cold(archived).unwrap());
+    assert_eq!(file.read(archived).unwrap(), vec![0xAB; 1000]);
+
+    // The freed pages get reused instead of growing the hot file
+    let recent = file.alloc().unwrap();
+    file.write(recent, &[0xCD; 500]).unwrap();
+    assert_eq!(std::fs::metadata("tiering_hot.verter").unwrap().len(), hot_size);
+    drop(file);
+
+    let mut file = File::open("tiering_hot.verter", Config::default()).unwrap();
+    match file.read(archived) {
+        Err(Error::NoColdTier) => {},
+        Ok(_) | Err(_) => panic!("should error with no cold tier")
+    }
+    file.set_cold_tier(File::open("tiering_cold.verter", Config::default()).unwrap());
+    file.write(archived, b"still cold").unwrap();
+    file.promote(archived).unwrap();
+    assert!(!file.is_cold(archived).unwrap());
+    assert_eq!(file.read(archived).unwrap(), b"still cold");
+
+    // Chains that can't be changed are refused before either file is touched
+    file.demote(archived).unwrap();
+    let cold_size = std::fs::metadata("tiering_cold.verter").unwrap().len();
+    let cold_table = file.read_u64(file.cold_table_ptr()).unwrap();
+    let frozen = file.alloc_with(b"frozen").unwrap();
+    file.freeze(frozen).unwrap();
+    for result in [file.demote(cold_table), file.promote(cold_table)] {
+        match result {
+            Err(Error::ReservedChain) => {},
+            Ok(_) | Err(_) => panic!("should error with reserved chain")
+        }
+    }
+    for result in [file.demote(frozen), file.promote(frozen)] {
+        match result {
+            Err(Error::FrozenChain) => {},
+            Ok(_) | Err(_) => panic!("should error with frozen chain")
+        }
+    }
+    let other = file.alloc_with(b"other").unwrap();
+    file.set_read_only(true);
+    match file.demote(other) {
+        Err(Error::ReadOnly) => {},
+        Ok(_) | Err(_) => panic!("should error with read only")
+    }
+    file.set_read_only(false);
+    assert_eq!(std::fs::metadata("tiering_cold.verter").unwrap().len(), cold_size);
+    assert!(file.is_cold(archived).unwrap());
+    assert_eq!(file.read(archived).unwrap(), b"still cold");
+    file.validate().unwrap();
+
+    std::fs::remove_file("tiering_hot.verter").unwrap();
+    std::fs::remove_file("tiering_cold.verter").unwrap();
+}
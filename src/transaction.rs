@@ -0,0 +1,573 @@
+use crate::{decode_u64s, encode_u64s, Error, File, Recovery, BYTES_IN_U64};
+
+/// A group of changes to a file that either all take effect or are all reverted.
+/// Writes go straight to the file, with the previous data of every chain kept in memory so it can be restored.
+/// Deleting a chain is deferred until the transaction commits.
+/// Dropping a transaction without committing it rolls it back.
+pub struct Transaction<'a> {
+    file: &'a mut File,
+    /// Chains allocated in the transaction, freed if it is rolled back
+    allocated: Vec<u64>,
+    /// The data of chains before their first write since the transaction or its latest savepoint started, restored in reverse order on rollback.
+    /// Header fields changed in the transaction are kept here too, with the field's offset as their pointer.
+    originals: Vec<(u64, Vec<u8>)>,
+    /// Chains deleted in the transaction, only freed once it commits
+    deleted: Vec<u64>,
+    /// The most recent savepoint
+    latest_savepoint: Savepoint,
+    finished: bool
+}
+
+/// A point within a transaction that it can be partially rolled back to with `Transaction::rollback_to`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Savepoint {
+    allocated: usize,
+    originals: usize,
+    deleted: usize,
+    /// The number of change notifications held back for subscribers
+    changes: usize
+}
+
+/// The changes of a prepared transaction, staged in the file until it is committed or aborted
+struct Staged {
+    allocated: Vec<u64>,
+    originals: Vec<(u64, Vec<u8>)>,
+    deleted: Vec<u64>
+}
+
+impl Staged {
+
+    /// Encoded as the allocated chains and the deleted chains, each as (count, pointers),
+    /// followed by every original encoded as (pointer, length, bytes)
+    fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for list in [&self.allocated, &self.deleted] {
+            data.extend_from_slice(&(list.len() as u64).to_le_bytes());
+            data.extend_from_slice(&encode_u64s(list));
+        }
+        for (ptr, original) in &self.originals {
+            data.extend_from_slice(&ptr.to_le_bytes());
+            data.extend_from_slice(&(original.len() as u64).to_le_bytes());
+            data.extend_from_slice(original);
+        }
+        data
+    }
+
+    fn decode(mut data: &[u8]) -> Result<Self, Error> {
+        let mut lists = Vec::new();
+        for _ in 0..2 {
+            let (count, rest) = data.split_at_checked(BYTES_IN_U64 as usize).ok_or(Error::CorruptedFile)?;
+            let count = u64::from_le_bytes(count.try_into().unwrap()) as usize;
+            let (list, rest) = rest.split_at_checked(count * BYTES_IN_U64 as usize).ok_or(Error::CorruptedFile)?;
+            lists.push(decode_u64s(list)?);
+            data = rest;
+        }
+        let mut originals = Vec::new();
+        while !data.is_empty() {
+            let (prefix, rest) = data.split_at_checked(2 * BYTES_IN_U64 as usize).ok_or(Error::CorruptedFile)?;
+            let prefix = decode_u64s(prefix)?;
+            let (original, rest) = rest.split_at_checked(prefix[1] as usize).ok_or(Error::CorruptedFile)?;
+            originals.push((prefix[0], original.to_vec()));
+            data = rest;
+        }
+        let deleted = lists.pop().unwrap();
+        let allocated = lists.pop().unwrap();
+        Ok(Self { allocated, originals, deleted })
+    }
+
+}
+
+impl File {
+
+    /// Start a transaction.
+    /// Fails with `Error::PreparedTransaction` if a prepared transaction has not been committed or aborted yet.
+    pub fn transaction(&mut self) -> Result<Transaction<'_>, Error> {
+        self.check_for_external_changes()?;
+        if self.has_prepared_transaction()? {
+            return Err(Error::PreparedTransaction);
+        }
+        self.hooks.subscribers.defer();
+        Ok(Transaction {
+            file: self,
+            allocated: Vec::new(),
+            originals: Vec::new(),
+            deleted: Vec::new(),
+            latest_savepoint: Savepoint::default(),
+            finished: false
+        })
+    }
+
+    /// Whether a transaction was prepared with `Transaction::prepare` but not yet committed or aborted,
+    /// such as after a crash in the middle of a two-phase commit.
+    pub fn has_prepared_transaction(&mut self) -> Result<bool, Error> {
+        Ok(self.read_u64(self.prepared_transaction_ptr())? != 0)
+    }
+
+    /// Commit the prepared transaction, making its changes permanent.
+    /// Returns `false` if there is no prepared transaction.
+    pub fn commit_prepared(&mut self) -> Result<bool, Error> {
+        self.check_for_external_changes()?;
+        let Some(staged) = self.read_staged()? else {
+            return Ok(false);
+        };
+        self.as_one_change(|file| {
+            for ptr in staged.deleted {
+                file.delete(ptr)?;
+            }
+            file.clear_staged()
+        })?;
+        Ok(true)
+    }
+
+    /// Abort the prepared transaction, reverting its changes.
+    /// Returns `false` if there is no prepared transaction.
+    pub fn abort_prepared(&mut self) -> Result<bool, Error> {
+        self.check_for_external_changes()?;
+        let Some(staged) = self.read_staged()? else {
+            return Ok(false);
+        };
+        self.as_one_change(|file| {
+            file.revert(&staged.originals, &staged.allocated)?;
+            file.clear_staged()
+        })?;
+        Ok(true)
+    }
+
+    /// Whether opening the file rolled back a transaction that was left prepared, as requested by `Config::recovery`.
+    pub fn recovered(&self) -> bool {
+        self.recovered
+    }
+
+    /// Roll back a transaction left prepared by an unclean shutdown, unless `Config::recovery` leaves it to the application
+    pub(crate) fn recover(&mut self) -> Result<(), Error> {
+        if self.config.recovery != Recovery::Manual && self.has_prepared_transaction()? {
+            self.abort_prepared()?;
+            self.recovered = true;
+        }
+        Ok(())
+    }
+
+    /// The chains allocated or deleted by the prepared transaction, which are kept alive until it is committed or aborted
+    pub(crate) fn prepared_chains(&mut self) -> Result<Vec<u64>, Error> {
+        Ok(match self.read_staged()? {
+            Some(staged) => staged.allocated.into_iter().chain(staged.deleted).collect(),
+            None => Vec::new()
+        })
+    }
+
+    fn read_staged(&mut self) -> Result<Option<Staged>, Error> {
+        let chain = self.read_u64(self.prepared_transaction_ptr())?;
+        if chain == 0 {
+            return Ok(None);
+        }
+        Staged::decode(&self.read_chain(chain)?).map(Some)
+    }
+
+    fn clear_staged(&mut self) -> Result<(), Error> {
+        let chain = self.read_u64(self.prepared_transaction_ptr())?;
+        self.write_u64(self.prepared_transaction_ptr(), 0)?;
+        self.delete_chain(chain)?;
+        self.file.sync_data().map_err(Error::IO)
+    }
+
+    /// Restore the original data of chains in reverse order, then free the chains that were allocated
+    fn revert(&mut self, originals: &[(u64, Vec<u8>)], allocated: &[u64]) -> Result<(), Error> {
+        self.as_one_change(|file| file.revert_unsynced(originals, allocated))
+    }
+
+    fn revert_unsynced(&mut self, originals: &[(u64, Vec<u8>)], allocated: &[u64]) -> Result<(), Error> {
+        let id_counters = self.id_counter_chain_if_any()?;
+        for (ptr, original) in originals.iter().rev() {
+            if self.is_header_field(*ptr) {
+                let original = original.as_slice().try_into().map_err(|_| Error::CorruptedFile)?;
+                self.write_u64(*ptr, u64::from_le_bytes(original))?;
+                self.bump_change_counter()?;
+            } else if *ptr == id_counters {
+                // The ID counters are one of verter's own chains, which `File::write` refuses
+                self.write_chain(*ptr, original)?;
+            } else {
+                self.write(*ptr, original)?;
+            }
+        }
+        for ptr in allocated {
+            self.delete(*ptr)?;
+        }
+        Ok(())
+    }
+
+}
+
+impl Transaction<'_> {
+
+    /// Allocate a new chain, which is freed again if the transaction is rolled back.
+    pub fn alloc(&mut self) -> Result<u64, Error> {
+        let ptr = self.file.alloc()?;
+        self.allocated.push(ptr);
+        Ok(ptr)
+    }
+
+    /// Read the data from a chain, including changes made earlier in the transaction.
+    pub fn read(&mut self, ptr: u64) -> Result<Vec<u8>, Error> {
+        if self.deleted.contains(&ptr) {
+            return Err(Error::DeletedPointer);
+        }
+        self.file.read(ptr)
+    }
+
+    /// Write data to a chain.
+    pub fn write(&mut self, ptr: u64, data: &[u8]) -> Result<(), Error> {
+        if self.deleted.contains(&ptr) {
+            return Err(Error::DeletedPointer);
+        }
+        self.keep_original(ptr)?;
+        self.file.write(ptr, data)
+    }
+
+    /// Take the next ID from one of the file's durable counters, like `File::next_id`.
+    /// The counter is turned back if the transaction is rolled back, so the IDs are handed out again.
+    pub fn next_id(&mut self, counter_slot: usize) -> Result<u64, Error> {
+        let chain = self.file.id_counter_chain()?;
+        self.keep_original(chain)?;
+        self.file.next_id(counter_slot)
+    }
+
+    /// The schema version the file is stamped with, including changes made earlier in the transaction.
+    pub fn schema_version(&mut self) -> Result<u32, Error> {
+        self.file.schema_version()
+    }
+
+    /// Stamp the file with a schema version like `File::set_schema_version`, so that it only changes if the data migrated to it is committed.
+    pub fn set_schema_version(&mut self, version: u32) -> Result<(), Error> {
+        self.keep_original_field(self.file.schema_version_ptr())?;
+        self.file.set_schema_version(version)
+    }
+
+    /// Delete a chain once the transaction commits.
+    pub fn delete(&mut self, ptr: u64) -> Result<(), Error> {
+        if self.deleted.contains(&ptr) {
+            return Err(Error::DeletedPointer);
+        }
+        self.file.check_if_pointer_valid(ptr)?;
+        self.file.check_not_protected(ptr)?;
+        self.deleted.push(ptr);
+        Ok(())
+    }
+
+    /// Read the root chain.
+    pub fn read_root(&mut self) -> Result<Vec<u8>, Error> {
+        match self.file.root_page()? {
+            0 => Ok(Vec::new()),
+            root_page => self.read(root_page)
+        }
+    }
+
+    /// Write to the root chain.
+    pub fn write_root(&mut self, data: &[u8]) -> Result<(), Error> {
+        let root_page = self.file.root_page_or_alloc()?;
+        self.write(root_page, data)
+    }
+
+    /// Whether a chain was allocated or written, or a header field changed, since the transaction or its latest savepoint started
+    fn changed_since_savepoint(&self, ptr: u64) -> bool {
+        let savepoint = self.latest_savepoint;
+        self.allocated[savepoint.allocated..].contains(&ptr) || self.originals[savepoint.originals..].iter().any(|(original, _)| *original == ptr)
+    }
+
+    /// Keep the data of a chain from before its first write since the transaction or its latest savepoint started
+    fn keep_original(&mut self, ptr: u64) -> Result<(), Error> {
+        if !self.changed_since_savepoint(ptr) {
+            let original = self.file.read(ptr)?;
+            self.originals.push((ptr, original));
+        }
+        Ok(())
+    }
+
+    /// Keep the value of a header field from before its first change since the transaction or its latest savepoint started.
+    /// Header fields are never at the start of a page, so the field's offset can't be mistaken for a chain.
+    fn keep_original_field(&mut self, field: u64) -> Result<(), Error> {
+        if !self.changed_since_savepoint(field) {
+            let original = self.file.read_u64(field)?;
+            self.originals.push((field, original.to_le_bytes().to_vec()));
+        }
+        Ok(())
+    }
+
+    /// Mark the current state of the transaction, so that later changes can be undone with `Transaction::rollback_to`.
+    pub fn savepoint(&mut self) -> Savepoint {
+        self.latest_savepoint = Savepoint {
+            allocated: self.allocated.len(),
+            originals: self.originals.len(),
+            deleted: self.deleted.len(),
+            changes: self.file.hooks.subscribers.deferred_len()
+        };
+        self.latest_savepoint
+    }
+
+    /// Revert every change made since the savepoint, keeping the transaction open.
+    /// The savepoint stays usable, but savepoints taken after it become invalid and must not be rolled back to.
+    pub fn rollback_to(&mut self, savepoint: Savepoint) -> Result<(), Error> {
+        let originals = self.originals.split_off(savepoint.originals.min(self.originals.len()));
+        let allocated = self.allocated.split_off(savepoint.allocated.min(self.allocated.len()));
+        self.deleted.truncate(savepoint.deleted);
+        self.latest_savepoint = savepoint;
+        let result = self.file.revert(&originals, &allocated);
+        self.file.hooks.subscribers.truncate(savepoint.changes);
+        result
+    }
+
+    /// Make the transaction's changes permanent.
+    pub fn commit(mut self) -> Result<(), Error> {
+        self.finished = true;
+        self.file.hooks.subscribers.flush();
+        let deleted = std::mem::take(&mut self.deleted);
+        self.file.as_one_change(|file| {
+            for ptr in deleted {
+                file.delete(ptr)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Revert every change made in the transaction.
+    pub fn rollback(mut self) -> Result<(), Error> {
+        self.finished = true;
+        let result = self.file.revert(&self.originals, &self.allocated);
+        self.file.hooks.subscribers.discard();
+        result
+    }
+
+    /// Durably stage the transaction as the first phase of a two-phase commit, syncing it to disk.
+    /// The transaction must then be resolved with `File::commit_prepared` or `File::abort_prepared`,
+    /// which also works after reopening the file if the process crashed in between.
+    /// Other changes should not be made to the chains the transaction touched until it is resolved.
+    pub fn prepare(mut self) -> Result<(), Error> {
+        self.finished = true;
+        // The changes stay in the file from now on, unless the transaction is aborted later
+        self.file.hooks.subscribers.flush();
+        let staged = Staged {
+            allocated: std::mem::take(&mut self.allocated),
+            originals: std::mem::take(&mut self.originals),
+            deleted: std::mem::take(&mut self.deleted)
+        };
+        let chain = self.file.alloc_with(&staged.encode())?;
+        self.file.write_u64(self.file.prepared_transaction_ptr(), chain)?;
+        self.file.file.sync_data().map_err(Error::IO)
+    }
+
+}
+
+impl Drop for Transaction<'_> {
+
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.file.revert(&self.originals, &self.allocated);
+            self.file.hooks.subscribers.discard();
+        }
+    }
+
+}
+
+#[test]
+fn transaction() {
+    use crate::Config;
+
+    let mut file = File::open("transaction.verter", Config::default()).unwrap();
+    let layer = file.alloc_with(b"layer").unwrap();
+    file.write_root(&layer.to_le_bytes()).unwrap();
+
+    let mut transaction = file.transaction().unwrap();
+    let frame = transaction.alloc().unwrap();
+    transaction.write(frame, b"frame").unwrap();
+    transaction.write(layer, b"layer with frame").unwrap();
+    transaction.write(layer, b"layer with frames").unwrap();
+    transaction.delete(layer).unwrap();
+    match transaction.read(layer) {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+    transaction.rollback().unwrap();
+    assert_eq!(file.read(layer).unwrap(), b"layer");
+    match file.read(frame) {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+
+    // Dropping a transaction rolls it back
+    let mut transaction = file.transaction().unwrap();
+    transaction.write_root(b"").unwrap();
+    drop(transaction);
+    assert_eq!(file.read_root().unwrap(), layer.to_le_bytes());
+
+    let mut transaction = file.transaction().unwrap();
+    transaction.write(layer, b"renamed layer").unwrap();
+    transaction.commit().unwrap();
+    assert_eq!(file.read(layer).unwrap(), b"renamed layer");
+    file.validate().unwrap();
+
+    std::fs::remove_file("transaction.verter").unwrap();
+}
+
+#[test]
+fn savepoints() {
+    use crate::Config;
+
+    let mut file = File::open("savepoints.verter", Config::default()).unwrap();
+    let mut transaction = file.transaction().unwrap();
+    transaction.write_root(b"imported scene").unwrap();
+    let asset = transaction.alloc().unwrap();
+    transaction.write(asset, b"first asset").unwrap();
+
+    let savepoint = transaction.savepoint();
+    transaction.write(asset, b"broken asset").unwrap();
+    transaction.write_root(b"broken scene").unwrap();
+    let broken = transaction.alloc().unwrap();
+    transaction.delete(asset).unwrap();
+    transaction.rollback_to(savepoint).unwrap();
+    assert_eq!(transaction.read(asset).unwrap(), b"first asset");
+    assert_eq!(transaction.read_root().unwrap(), b"imported scene");
+    match transaction.read(broken) {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+
+    // The savepoint can be rolled back to again
+    transaction.write(asset, b"second try").unwrap();
+    transaction.rollback_to(savepoint).unwrap();
+    assert_eq!(transaction.read(asset).unwrap(), b"first asset");
+    transaction.commit().unwrap();
+    assert_eq!(file.read_root().unwrap(), b"imported scene");
+    assert_eq!(file.read(asset).unwrap(), b"first asset");
+
+    // Rolling back the whole transaction undoes changes from before its savepoints too
+    let mut transaction = file.transaction().unwrap();
+    transaction.write_root(b"first").unwrap();
+    transaction.savepoint();
+    transaction.write_root(b"second").unwrap();
+    transaction.rollback().unwrap();
+    assert_eq!(file.read_root().unwrap(), b"imported scene");
+    file.validate().unwrap();
+
+    std::fs::remove_file("savepoints.verter").unwrap();
+}
+
+#[test]
+fn prepared_transaction() {
+    use crate::Config;
+
+    let mut file = File::open("prepared_transaction.verter", Config::default()).unwrap();
+    let layer = file.alloc_with(b"layer").unwrap();
+    file.write_root(&layer.to_le_bytes()).unwrap();
+
+    let mut transaction = file.transaction().unwrap();
+    let frame = transaction.alloc().unwrap();
+    transaction.write(layer, &frame.to_le_bytes()).unwrap();
+    transaction.prepare().unwrap();
+    drop(file);
+
+    // The prepared transaction survives reopening the file, and blocks new ones until it is resolved
+    let mut file = File::open("prepared_transaction.verter", Config::default()).unwrap();
+    assert!(file.has_prepared_transaction().unwrap());
+    match file.transaction() {
+        Err(Error::PreparedTransaction) => {},
+        Ok(_) | Err(_) => panic!("should error with prepared transaction")
+    }
+    file.gc(&[], |data| decode_u64s(data).unwrap()).unwrap();
+    assert!(file.abort_prepared().unwrap());
+    assert!(!file.abort_prepared().unwrap());
+    assert_eq!(file.read(layer).unwrap(), b"layer");
+    match file.read(frame) {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+
+    let mut transaction = file.transaction().unwrap();
+    transaction.write_root(b"").unwrap();
+    transaction.delete(layer).unwrap();
+    transaction.prepare().unwrap();
+    assert!(file.commit_prepared().unwrap());
+    assert!(!file.has_prepared_transaction().unwrap());
+    assert!(file.root_is_empty().unwrap());
+    match file.read(layer) {
+        Err(Error::DeletedPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with deleted pointer")
+    }
+    file.validate().unwrap();
+
+    std::fs::remove_file("prepared_transaction.verter").unwrap();
+}
+
+#[test]
+fn transactional_header() {
+    use crate::Config;
+
+    let mut file = File::open("transactional_header.verter", Config::default()).unwrap();
+    let scene = file.alloc_with(b"scene v1").unwrap();
+    file.set_schema_version(1).unwrap();
+
+    // A migration that fails partway leaves neither the data nor the stamp changed
+    let mut transaction = file.transaction().unwrap();
+    transaction.write(scene, b"scene v2").unwrap();
+    transaction.set_schema_version(2).unwrap();
+    assert_eq!(transaction.schema_version().unwrap(), 2);
+    transaction.next_id(0).unwrap();
+    transaction.rollback().unwrap();
+    assert_eq!(file.schema_version().unwrap(), 1);
+    assert_eq!(file.read(scene).unwrap(), b"scene v1");
+
+    // Savepoints and prepared transactions restore header fields too
+    let mut transaction = file.transaction().unwrap();
+    transaction.set_schema_version(2).unwrap();
+    let savepoint = transaction.savepoint();
+    transaction.set_schema_version(3).unwrap();
+    transaction.rollback_to(savepoint).unwrap();
+    assert_eq!(transaction.schema_version().unwrap(), 2);
+    transaction.write(scene, b"scene v2").unwrap();
+    transaction.prepare().unwrap();
+    drop(file);
+    let mut file = File::open("transactional_header.verter", Config::default()).unwrap();
+    assert!(file.abort_prepared().unwrap());
+    assert_eq!(file.schema_version().unwrap(), 1);
+    assert_eq!(file.next_id(0).unwrap(), 1);
+
+    let mut transaction = file.transaction().unwrap();
+    transaction.write(scene, b"scene v2").unwrap();
+    transaction.set_schema_version(2).unwrap();
+    transaction.commit().unwrap();
+    assert_eq!((file.schema_version().unwrap(), file.read(scene).unwrap()), (2, b"scene v2".to_vec()));
+    file.validate().unwrap();
+
+    std::fs::remove_file("transactional_header.verter").unwrap();
+}
+
+#[test]
+fn recovery() {
+    use crate::{Config, Recovery, ValidationStatus};
+
+    let mut file = File::open("recovery.verter", Config::default()).unwrap();
+    let layer = file.alloc_with(b"layer").unwrap();
+    for recovery in [Recovery::Verify, Recovery::VerifyInBackground] {
+        let mut transaction = file.transaction().unwrap();
+        transaction.write(layer, b"half-committed layer").unwrap();
+        transaction.prepare().unwrap();
+        drop(file);
+
+        // The transaction is rolled back on open, and the file is checked before or after opening it
+        file = File::open("recovery.verter", Config { recovery, ..Config::default() }).unwrap();
+        assert!(file.recovered());
+        assert!(!file.has_prepared_transaction().unwrap());
+        assert_eq!(file.read(layer).unwrap(), b"layer");
+        match (recovery, file.wait_for_validation()) {
+            (Recovery::Verify, ValidationStatus::NotRequested) | (Recovery::VerifyInBackground, ValidationStatus::Valid) => {},
+            (_, status) => panic!("unexpected validation status {status:?}")
+        }
+    }
+
+    // Files shut down cleanly are opened as they are
+    drop(file);
+    let file = File::open("recovery.verter", Config { recovery: Recovery::VerifyInBackground, ..Config::default() }).unwrap();
+    assert!(!file.recovered());
+    assert!(matches!(file.validation_status(), ValidationStatus::NotRequested));
+    drop(file);
+
+    std::fs::remove_file("recovery.verter").unwrap();
+}
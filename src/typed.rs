@@ -0,0 +1,194 @@
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use crate::{Error, File};
+
+/// A fixed-size number that can be stored in a chain with `File::write_slice`.
+/// Elements are stored little-endian and packed without padding, so a chain written on one machine reads the same on any other.
+pub trait Element: Copy {
+
+    /// The number of bytes an element takes up in a chain
+    const SIZE: usize;
+
+    /// Encode the element into exactly `SIZE` bytes.
+    fn write_le(self, bytes: &mut [u8]);
+
+    /// Decode an element from exactly `SIZE` bytes.
+    fn read_le(bytes: &[u8]) -> Self;
+
+}
+
+macro_rules! impl_element {
+    ($($ty:ty),*) => {
+        $(
+            impl Element for $ty {
+
+                const SIZE: usize = std::mem::size_of::<$ty>();
+
+                fn write_le(self, bytes: &mut [u8]) {
+                    bytes.copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_le(bytes: &[u8]) -> Self {
+                    Self::from_le_bytes(bytes.try_into().unwrap())
+                }
+
+            }
+        )*
+    };
+}
+
+impl_element!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+fn decode_elements<T: Element>(bytes: &[u8]) -> Vec<T> {
+    bytes.chunks_exact(T::SIZE).map(T::read_le).collect()
+}
+
+impl File {
+
+    /// Write an array of numbers to a chain, such as the values of a keyframe track.
+    pub fn write_slice<T: Element>(&mut self, ptr: u64, elements: &[T]) -> Result<(), Error> {
+        let mut data = vec![0; elements.len() * T::SIZE];
+        for (element, bytes) in elements.iter().zip(data.chunks_exact_mut(T::SIZE)) {
+            element.write_le(bytes);
+        }
+        self.write(ptr, &data)
+    }
+
+    /// Read an array of numbers written with `File::write_slice`.
+    /// Fails with `Error::CorruptedFile` if the chain's length isn't a whole number of elements.
+    pub fn read_slice<T: Element>(&mut self, ptr: u64) -> Result<Vec<T>, Error> {
+        let data = self.read(ptr)?;
+        if !data.len().is_multiple_of(T::SIZE) {
+            return Err(Error::CorruptedFile);
+        }
+        Ok(decode_elements(&data))
+    }
+
+    /// Read a range of the elements of an array written with `File::write_slice`, only reading the pages the range covers.
+    /// Fails with `Error::InvalidPointer` if the range goes past the end of the array.
+    pub fn read_slice_range<T: Element>(&mut self, ptr: u64, range: Range<usize>) -> Result<Vec<T>, Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        let cold = self.read_cold(ptr)?;
+        let len = match &cold {
+            Some(data) => data.len() as u64,
+            None => self.chain_len(ptr)?
+        };
+        if range.start > range.end || (range.end * T::SIZE) as u64 > len {
+            return Err(Error::InvalidPointer);
+        }
+
+        let mut bytes = vec![0; range.len() * T::SIZE];
+        match cold {
+            Some(data) => bytes.copy_from_slice(&data[range.start * T::SIZE..range.end * T::SIZE]),
+            None => {
+                let (pages, _) = self.chain_layout(ptr)?;
+                let offset = self.meta_size() + (range.start * T::SIZE) as u64;
+                self.read_at(&pages, offset, &mut bytes)?;
+            }
+        }
+        Ok(decode_elements(&bytes))
+    }
+
+    /// Binary search a chain holding sorted records of `record_size` bytes each, such as keyframes sorted by time,
+    /// like `slice::binary_search_by`. `cmp` compares a record to the one being searched for.
+    /// Returns `Ok` with the index of a matching record, or `Err` with the index a matching record could be inserted at to keep the records sorted.
+    /// Only the page headers and the records compared are read, so even huge tracks are searched without reading their data.
+    /// Fails with `Error::CorruptedFile` if the chain's length isn't a whole number of records.
+    /// Panics if `record_size` is 0.
+    pub fn binary_search_records<F: FnMut(&[u8]) -> Ordering>(&mut self, ptr: u64, record_size: usize, mut cmp: F) -> Result<Result<u64, u64>, Error> {
+        assert!(record_size > 0, "record size must not be 0");
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        let cold = self.read_cold(ptr)?;
+        let (pages, len) = match &cold {
+            Some(data) => (Vec::new(), data.len() as u64),
+            None => {
+                let (pages, final_size) = self.chain_layout(ptr)?;
+                let len = (pages.len() as u64 - 1) * self.config.page_size as u64 + final_size;
+                (pages, len.checked_sub(self.meta_size()).ok_or(Error::CorruptedFile)?)
+            }
+        };
+        if !len.is_multiple_of(record_size as u64) {
+            return Err(Error::CorruptedFile);
+        }
+
+        let mut record = vec![0; record_size];
+        let mut low = 0;
+        let mut high = len / record_size as u64;
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let offset = mid * record_size as u64;
+            match &cold {
+                Some(data) => record.copy_from_slice(&data[offset as usize..offset as usize + record_size]),
+                None => self.read_at(&pages, self.meta_size() + offset, &mut record)?
+            }
+            match cmp(&record) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Ok(Ok(mid))
+            }
+        }
+        Ok(Err(low))
+    }
+
+}
+
+#[test]
+fn typed_slices() {
+    use crate::Config;
+
+    let mut file = File::open("typed_slices.verter", Config::default()).unwrap();
+    let track = file.alloc().unwrap();
+    let values = (0..100).map(|i| i as f32 * 0.5).collect::<Vec<_>>();
+    file.write_slice(track, &values).unwrap();
+    assert_eq!(file.read_slice::<f32>(track).unwrap(), values);
+    assert_eq!(file.read_slice_range::<f32>(track, 40..45).unwrap(), values[40..45]);
+    assert_eq!(file.read_slice_range::<f32>(track, 100..100).unwrap(), Vec::<f32>::new());
+    match file.read_slice_range::<f32>(track, 90..101) {
+        Err(Error::InvalidPointer) => {},
+        Ok(_) | Err(_) => panic!("should error with invalid pointer")
+    }
+
+    // Stored little-endian, whatever the machine
+    let frames = file.alloc().unwrap();
+    file.write_slice::<u16>(frames, &[1, 0x0203]).unwrap();
+    assert_eq!(file.read(frames).unwrap(), [1, 0, 3, 2]);
+    match file.read_slice::<u32>(frames) {
+        Ok(_) => {},
+        Err(err) => panic!("{:?}", err)
+    }
+    file.write(frames, &[1, 2, 3]).unwrap();
+    match file.read_slice::<u16>(frames) {
+        Err(Error::CorruptedFile) => {},
+        Ok(_) | Err(_) => panic!("should error with corrupted file")
+    }
+
+    std::fs::remove_file("typed_slices.verter").unwrap();
+}
+
+#[test]
+fn binary_search_records() {
+    use crate::Config;
+
+    // Keyframes of (time, value), sorted by time
+    let mut file = File::open("binary_search_records.verter", Config::default()).unwrap();
+    let track = file.alloc().unwrap();
+    let keyframes = (0..500u64).flat_map(|i| [i * 10, i * i]).collect::<Vec<_>>();
+    file.write_slice(track, &keyframes).unwrap();
+    let by_time = |time: u64| move |record: &[u8]| u64::from_le_bytes(record[..8].try_into().unwrap()).cmp(&time);
+
+    assert_eq!(file.binary_search_records(track, 16, by_time(2500)).unwrap(), Ok(250));
+    assert_eq!(file.binary_search_records(track, 16, by_time(0)).unwrap(), Ok(0));
+    assert_eq!(file.binary_search_records(track, 16, by_time(2505)).unwrap(), Err(251));
+    assert_eq!(file.binary_search_records(track, 16, by_time(10_000)).unwrap(), Err(500));
+    let empty = file.alloc().unwrap();
+    assert_eq!(file.binary_search_records(empty, 16, by_time(5)).unwrap(), Err(0));
+    match file.binary_search_records(track, 24, by_time(5)) {
+        Err(Error::CorruptedFile) => {},
+        Ok(_) | Err(_) => panic!("should error with corrupted file")
+    }
+
+    std::fs::remove_file("binary_search_records.verter").unwrap();
+}
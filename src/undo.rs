@@ -0,0 +1,134 @@
+use crate::{Error, File, BYTES_IN_U64};
+
+/// Encode a list of operations as length-prefixed blobs
+fn encode_ops(ops: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for op in ops {
+        data.extend_from_slice(&(op.len() as u64).to_le_bytes());
+        data.extend_from_slice(op);
+    }
+    data
+}
+
+fn decode_ops(mut data: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+    let mut ops = Vec::new();
+    while !data.is_empty() {
+        if data.len() < BYTES_IN_U64 as usize {
+            return Err(Error::CorruptedFile);
+        }
+        let (len, rest) = data.split_at(BYTES_IN_U64 as usize);
+        let len = u64::from_le_bytes(len.try_into().unwrap()) as usize;
+        if rest.len() < len {
+            return Err(Error::CorruptedFile);
+        }
+        let (op, rest) = rest.split_at(len);
+        ops.push(op.to_vec());
+        data = rest;
+    }
+    Ok(ops)
+}
+
+impl File {
+
+    /// Record an operation in the undo history.
+    /// The operation is an arbitrary blob describing how to revert a change made by the application.
+    /// Clears the redo history, and drops the oldest operations once there are more than `Config::undo_depth`.
+    pub fn record_undo(&mut self, op: &[u8]) -> Result<(), Error> {
+        let mut undo = self.read_op_stack(self.undo_chain_ptr())?;
+        undo.push(op.to_vec());
+        if undo.len() > self.config.undo_depth {
+            let excess = undo.len() - self.config.undo_depth;
+            undo.drain(..excess);
+        }
+        self.write_op_stack(self.undo_chain_ptr(), &undo)?;
+        self.write_op_stack(self.redo_chain_ptr(), &[])
+    }
+
+    /// Pop the most recent operation from the undo history, moving it to the redo history.
+    /// Returns `None` if there is nothing to undo.
+    pub fn undo(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        self.move_op(self.undo_chain_ptr(), self.redo_chain_ptr())
+    }
+
+    /// Pop the most recently undone operation from the redo history, moving it back to the undo history.
+    /// Returns `None` if there is nothing to redo.
+    pub fn redo(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        self.move_op(self.redo_chain_ptr(), self.undo_chain_ptr())
+    }
+
+    /// The number of operations that can currently be undone.
+    pub fn undo_len(&mut self) -> Result<usize, Error> {
+        Ok(self.read_op_stack(self.undo_chain_ptr())?.len())
+    }
+
+    /// The number of operations that can currently be redone.
+    pub fn redo_len(&mut self) -> Result<usize, Error> {
+        Ok(self.read_op_stack(self.redo_chain_ptr())?.len())
+    }
+
+    fn move_op(&mut self, from_ptr: u64, to_ptr: u64) -> Result<Option<Vec<u8>>, Error> {
+        let mut from = self.read_op_stack(from_ptr)?;
+        let Some(op) = from.pop() else {
+            return Ok(None);
+        };
+        let mut to = self.read_op_stack(to_ptr)?;
+        to.push(op.clone());
+
+        self.write_op_stack(to_ptr, &to)?;
+        self.write_op_stack(from_ptr, &from)?;
+
+        Ok(Some(op))
+    }
+
+    /// Read one of the operation stacks, given the pointer to its header field
+    pub(crate) fn read_op_stack(&mut self, field_ptr: u64) -> Result<Vec<Vec<u8>>, Error> {
+        let chain = self.read_u64(field_ptr)?;
+        if chain == 0 {
+            return Ok(Vec::new());
+        }
+        decode_ops(&self.read(chain)?)
+    }
+
+    /// Write one of the operation stacks, allocating its chain if it does not exist yet
+    pub(crate) fn write_op_stack(&mut self, field_ptr: u64, ops: &[Vec<u8>]) -> Result<(), Error> {
+        let mut chain = self.read_u64(field_ptr)?;
+        if chain == 0 {
+            if ops.is_empty() {
+                return Ok(());
+            }
+            chain = self.alloc()?;
+            self.write_u64(field_ptr, chain)?;
+        }
+        self.write_chain(chain, &encode_ops(ops))
+    }
+
+}
+
+#[test]
+fn undo_redo() {
+    use crate::Config;
+
+    let config = Config {
+        undo_depth: 3,
+        ..Config::default()
+    };
+
+    let mut file = File::open("undo_redo.verter", config).unwrap();
+    for op in [b"op1", b"op2", b"op3", b"op4"] {
+        file.record_undo(op).unwrap();
+    }
+    assert_eq!(file.undo().unwrap().unwrap(), b"op4");
+    drop(file);
+
+    let mut file = File::open("undo_redo.verter", config).unwrap();
+    assert_eq!(file.redo().unwrap().unwrap(), b"op4");
+    assert_eq!(file.undo().unwrap().unwrap(), b"op4");
+    assert_eq!(file.undo().unwrap().unwrap(), b"op3");
+    assert_eq!(file.undo().unwrap().unwrap(), b"op2");
+    assert_eq!(file.undo().unwrap(), None); // op1 fell out of the history
+
+    file.record_undo(b"op5").unwrap();
+    assert_eq!(file.redo().unwrap(), None);
+
+    std::fs::remove_file("undo_redo.verter").unwrap();
+}
@@ -0,0 +1,305 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::segments::Segments;
+use crate::{Backend, Config, Error, File, PageHeader};
+
+/// How thoroughly a file is checked when it is opened
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Validation {
+    /// Only check the magic bytes
+    #[default]
+    Fast,
+    /// Check the structure of the entire file. See `File::validate`.
+    Full,
+    /// Only check the magic bytes, then check the structure of the entire file on a worker thread,
+    /// so that huge files open instantly but latent corruption is still found soon after.
+    /// See `File::validation_status` and `File::on_validated`.
+    /// Only applies to `File::open`, since the worker reads the file through its own handle. Other ways of opening a file act like `Fast`.
+    Background
+}
+
+/// What opening a file does when it finds a transaction left prepared by an unclean shutdown, such as a crash partway through a two-phase commit.
+/// See `Transaction::prepare`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Recovery {
+    /// Leave the prepared transaction for the application to resolve with `File::commit_prepared` or `File::abort_prepared`
+    #[default]
+    Manual,
+    /// Roll the prepared transaction back, then check the structure of the entire file before the file is opened
+    Verify,
+    /// Roll the prepared transaction back, then check the structure of the entire file on a worker thread like `Validation::Background`,
+    /// so that the file opens straight away. See `File::validation_status`.
+    /// Only applies to `File::open`. Other ways of opening a file only roll the transaction back.
+    VerifyInBackground
+}
+
+/// How far the background validation of a file opened with `Validation::Background` has got
+#[derive(Clone, Debug)]
+pub enum ValidationStatus {
+    /// The file is not being validated in the background
+    NotRequested,
+    Running,
+    /// The whole file was checked and nothing was wrong
+    Valid,
+    /// The check found corruption, or could not be completed
+    Failed(Arc<Error>)
+}
+
+/// The state shared between a file and the thread validating it in the background
+#[derive(Default)]
+pub(crate) struct BackgroundValidation {
+    state: Mutex<BackgroundState>,
+    done: Condvar
+}
+
+#[derive(Default)]
+struct BackgroundState {
+    result: Option<ValidationStatus>,
+    callbacks: Vec<Box<dyn FnOnce(ValidationStatus) + Send>>
+}
+
+impl BackgroundValidation {
+
+    /// How many times a validation that raced a writer is retried before giving up with `Error::ConcurrentModification`
+    const RETRIES: u32 = 8;
+
+    /// Start validating the file at `path` on a new thread
+    pub(crate) fn spawn(path: PathBuf, config: Config) -> Arc<Self> {
+        let validation = Arc::new(Self::default());
+        let shared = validation.clone();
+        std::thread::spawn(move || {
+            let status = match Self::run(path, config) {
+                Ok(()) => ValidationStatus::Valid,
+                Err(err) => ValidationStatus::Failed(Arc::new(err))
+            };
+            let callbacks = {
+                let mut state = shared.state.lock().unwrap();
+                state.result = Some(status.clone());
+                std::mem::take(&mut state.callbacks)
+            };
+            shared.done.notify_all();
+            for callback in callbacks {
+                callback(status.clone());
+            }
+        });
+        validation
+    }
+
+    /// Validate the file through a read-only handle of its own, retrying like `SharedReader` if a writer changed the file in the middle
+    fn run(path: PathBuf, config: Config) -> Result<(), Error> {
+        let backend: Box<dyn Backend> = match config.segment_size {
+            Some(segment_size) => Box::new(Segments::open(&path, segment_size).map_err(Error::IO)?),
+            None => Box::new(std::fs::File::open(&path).map_err(Error::IO)?)
+        };
+        let mut file = File::from_backend(backend, Config { validation: Validation::Fast, heatmap: false, ..config }, false)?;
+        file.set_read_only(true);
+
+        let mut backoff = Duration::from_micros(100);
+        for _ in 0..=Self::RETRIES {
+            file.file.lock_shared().map_err(Error::IO)?;
+            let result = file.refresh().and_then(|()| file.validate());
+            let raced = file.check_for_external_changes().is_err();
+            file.file.unlock().map_err(Error::IO)?;
+            if !raced {
+                return result;
+            }
+            std::thread::sleep(backoff);
+            backoff *= 2;
+        }
+        Err(Error::ConcurrentModification)
+    }
+
+    fn status(&self) -> ValidationStatus {
+        self.state.lock().unwrap().result.clone().unwrap_or(ValidationStatus::Running)
+    }
+
+}
+
+impl File {
+
+    /// Check the structure of the entire file, returning `Error::CorruptedFile` if anything is wrong.
+    /// Verifies that every page header parses, that every chain is well-formed and shares no pages with other chains,
+    /// that the free list contains exactly the deleted pages, and that the pointers in the file header are valid.
+    pub fn validate(&mut self) -> Result<(), Error> {
+        let file_size = self.file_size()?;
+        if file_size < self.header_size() || !(file_size - self.header_size()).is_multiple_of(self.total_page_size()) {
+            return Err(Error::CorruptedFile);
+        }
+
+        // Quarantined pages are out of use and may hold anything
+        let quarantined = self.quarantined_pages()?;
+        let mut headers = HashMap::new();
+        let mut ptr = self.header_size();
+        while ptr < file_size {
+            if !quarantined.contains(&ptr) {
+                headers.insert(ptr, self.read_page_header(ptr)?);
+            }
+            ptr += self.total_page_size();
+        }
+
+        // Chains
+        let mut referenced = HashSet::new();
+        for header in headers.values() {
+            match *header {
+                PageHeader::NextPage(next) => {
+                    if !matches!(headers.get(&next), Some(PageHeader::NextPage(_) | PageHeader::FinalPage(_))) || !referenced.insert(next) {
+                        return Err(Error::CorruptedFile);
+                    }
+                },
+                PageHeader::FinalPage(size) => {
+                    if size > self.config.page_size as u64 {
+                        return Err(Error::CorruptedFile);
+                    }
+                },
+                PageHeader::DeletedPage(_) => {}
+            }
+        }
+        // Every chain must be reachable from a head page, which rules out cycles
+        let mut in_chains = 0;
+        for (head, header) in &headers {
+            if matches!(header, PageHeader::DeletedPage(_)) {
+                continue;
+            }
+            // Only pages no other page links to may be marked as chain heads
+            if self.is_head_page(*head)? == referenced.contains(head) {
+                return Err(Error::CorruptedFile);
+            }
+            if referenced.contains(head) {
+                continue;
+            }
+            in_chains += self.chain_pages(*head)?.len();
+        }
+        let allocated = headers.values().filter(|header| !matches!(header, PageHeader::DeletedPage(_))).count();
+        if in_chains != allocated {
+            return Err(Error::CorruptedFile);
+        }
+
+        // Free list
+        let deleted = headers.values().filter(|header| matches!(header, PageHeader::DeletedPage(_))).count();
+        let mut free_pages = HashSet::new();
+        let mut free_page = self.first_free_page()?;
+        while free_page != 0 {
+            let Some(PageHeader::DeletedPage(next)) = headers.get(&free_page) else {
+                return Err(Error::CorruptedFile);
+            };
+            if !free_pages.insert(free_page) {
+                return Err(Error::CorruptedFile);
+            }
+            free_page = *next;
+        }
+        if free_pages.len() != deleted {
+            return Err(Error::CorruptedFile);
+        }
+
+        // Header pointers
+        let root = self.root_page()?;
+        for chain in self.internal_chains()?.into_iter().chain(std::iter::once(root).filter(|root| *root != 0)) {
+            if !matches!(headers.get(&chain), Some(PageHeader::NextPage(_) | PageHeader::FinalPage(_))) || referenced.contains(&chain) {
+                return Err(Error::CorruptedFile);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// How far the background validation requested with `Validation::Background` has got.
+    pub fn validation_status(&self) -> ValidationStatus {
+        match &self.background_validation {
+            Some(validation) => validation.status(),
+            None => ValidationStatus::NotRequested
+        }
+    }
+
+    /// Wait for the background validation requested with `Validation::Background` to finish, returning its outcome.
+    pub fn wait_for_validation(&self) -> ValidationStatus {
+        let Some(validation) = &self.background_validation else {
+            return ValidationStatus::NotRequested;
+        };
+        let state = validation.state.lock().unwrap();
+        let state = validation.done.wait_while(state, |state| state.result.is_none()).unwrap();
+        state.result.clone().unwrap()
+    }
+
+    /// Register a callback called on the validating thread with the outcome of the background validation requested with `Validation::Background`.
+    /// If the validation has already finished, or was never requested, the callback is called straight away.
+    /// Writers should make their changes inside `File::exclusive` while the validation runs, so that a change halfway through is never mistaken for corruption.
+    pub fn on_validated<F: FnOnce(ValidationStatus) + Send + 'static>(&mut self, callback: F) {
+        let Some(validation) = &self.background_validation else {
+            return callback(ValidationStatus::NotRequested);
+        };
+        let mut state = validation.state.lock().unwrap();
+        match state.result.clone() {
+            Some(status) => {
+                drop(state);
+                callback(status);
+            },
+            None => state.callbacks.push(Box::new(callback))
+        }
+    }
+
+}
+
+#[test]
+fn full_validation() {
+    use crate::Config;
+
+    let config = Config {
+        validation: Validation::Full,
+        ..Config::default()
+    };
+
+    let mut file = File::open("full_validation.verter", config).unwrap();
+    let a = file.alloc().unwrap();
+    file.write(a, &[1; 500]).unwrap();
+    let b = file.alloc().unwrap();
+    file.write(b, &[2; 500]).unwrap();
+    file.delete(a).unwrap();
+    file.record_undo(b"op").unwrap();
+    drop(file);
+
+    let mut file = File::open("full_validation.verter", config).unwrap();
+    // Make two chains share a page
+    let c = file.alloc().unwrap();
+    let shared = file.chain_pages(b).unwrap()[1];
+    file.write_page_header(c, PageHeader::NextPage(shared)).unwrap();
+    drop(file);
+
+    match File::open("full_validation.verter", config) {
+        Err(Error::CorruptedFile) => {},
+        Ok(_) | Err(_) => panic!("should error with corrupted file")
+    }
+    assert!(File::open("full_validation.verter", Config::default()).is_ok());
+
+    std::fs::remove_file("full_validation.verter").unwrap();
+}
+
+#[test]
+fn background_validation() {
+    use std::sync::mpsc::channel;
+
+    let config = Config {
+        validation: Validation::Background,
+        ..Config::default()
+    };
+
+    let mut file = File::open("background_validation.verter", config).unwrap();
+    assert!(matches!(file.wait_for_validation(), ValidationStatus::Valid));
+    let a = file.alloc_with(&[1; 500]).unwrap();
+    let c = file.alloc().unwrap();
+    let shared = file.chain_pages(a).unwrap()[1];
+    file.write_page_header(c, PageHeader::NextPage(shared)).unwrap();
+    drop(file);
+    assert!(matches!(File::open("background_validation.verter", Config::default()).unwrap().validation_status(), ValidationStatus::NotRequested));
+
+    // The file opens despite the corruption, which is reported once the worker finds it
+    let mut file = File::open("background_validation.verter", config).unwrap();
+    let (sender, receiver) = channel();
+    file.on_validated(move |status| sender.send(status).unwrap());
+    assert!(matches!(receiver.recv().unwrap(), ValidationStatus::Failed(err) if matches!(*err, Error::CorruptedFile)));
+    assert!(matches!(file.validation_status(), ValidationStatus::Failed(_)));
+
+    std::fs::remove_file("background_validation.verter").unwrap();
+}
@@ -0,0 +1,183 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::{ChainFormat, Error, File, BYTES_IN_U64};
+
+/// A chain presented as a seekable file, so that code expecting a file, such as an embedded database's VFS layer, can store its data in a chain.
+/// Reads only touch the pages they cover, and writes are applied with `File::patch`.
+pub struct ChainFile<'a> {
+    file: &'a mut File,
+    ptr: u64,
+    pos: u64
+}
+
+pub(crate) fn to_io_error(err: Error) -> std::io::Error {
+    match err {
+        Error::IO(err) => err,
+        err => std::io::Error::other(format!("{:?}", err))
+    }
+}
+
+impl File {
+
+    /// Access a chain as a seekable file, starting at the beginning of its data.
+    pub fn chain_file(&mut self, ptr: u64) -> Result<ChainFile<'_>, Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        Ok(ChainFile { file: self, ptr, pos: 0 })
+    }
+
+    /// The number of bytes of data stored in a chain
+    pub(crate) fn chain_len(&mut self, ptr: u64) -> Result<u64, Error> {
+        if self.is_cold(ptr)? {
+            return Ok(self.read(ptr)?.len() as u64);
+        }
+        if self.config.chain_format != ChainFormat::V1 {
+            // The summary saves walking the whole chain
+            return Ok(self.read_summary(ptr)?.len);
+        }
+        let (pages, final_size) = self.chain_layout(ptr)?;
+        let len = (pages.len() as u64 - 1) * self.config.page_size as u64 + final_size;
+        len.checked_sub(self.meta_size()).ok_or(Error::CorruptedFile)
+    }
+
+    /// Read bytes from an offset into the data of a chain made of the given pages
+    pub(crate) fn read_at(&mut self, pages: &[u64], offset: u64, mut buf: &mut [u8]) -> Result<(), Error> {
+        let page_size = self.config.page_size as u64;
+        let mut page_idx = (offset / page_size) as usize;
+        let mut page_offset = offset % page_size;
+        while !buf.is_empty() {
+            let page = *pages.get(page_idx).ok_or(Error::CorruptedFile)?;
+            let n = buf.len().min((page_size - page_offset) as usize);
+            self.file.seek(SeekFrom::Start(page + BYTES_IN_U64 + page_offset)).map_err(Error::IO)?;
+            self.file.read_exact(&mut buf[..n]).map_err(Error::IO)?;
+            buf = &mut buf[n..];
+            page_idx += 1;
+            page_offset = 0;
+        }
+        Ok(())
+    }
+
+}
+
+impl ChainFile<'_> {
+
+    /// The pointer to the chain.
+    pub fn ptr(&self) -> u64 {
+        self.ptr
+    }
+
+    /// The number of bytes of data in the chain.
+    pub fn len(&mut self) -> Result<u64, Error> {
+        self.file.chain_len(self.ptr)
+    }
+
+    /// Whether the chain holds no data.
+    pub fn is_empty(&mut self) -> Result<bool, Error> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Truncate or extend the chain's data. Extending fills the new bytes with zeros.
+    pub fn set_len(&mut self, len: u64) -> Result<(), Error> {
+        let old_len = self.len()?;
+        if len > old_len {
+            self.file.patch(self.ptr, &[(len, &[])])
+        } else if len < old_len {
+            let mut data = self.file.read(self.ptr)?;
+            data.truncate(len as usize);
+            self.file.write(self.ptr, &data)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Make sure written data has reached the disk.
+    pub fn sync(&mut self) -> Result<(), Error> {
+        self.file.file.sync_data().map_err(Error::IO)
+    }
+
+    fn read_inner(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let len = self.len()?;
+        let n = (len.saturating_sub(self.pos) as usize).min(buf.len());
+        if n == 0 {
+            return Ok(0);
+        }
+
+        if self.file.is_cold(self.ptr)? {
+            let data = self.file.read(self.ptr)?;
+            buf[..n].copy_from_slice(&data[self.pos as usize..self.pos as usize + n]);
+        } else {
+            let (pages, _) = self.file.chain_layout(self.ptr)?;
+            let offset = self.file.meta_size() + self.pos;
+            self.file.read_at(&pages, offset, &mut buf[..n])?;
+        }
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+}
+
+impl Read for ChainFile<'_> {
+
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.read_inner(buf).map_err(to_io_error)
+    }
+
+}
+
+impl Write for ChainFile<'_> {
+
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.patch(self.ptr, &[(self.pos, buf)]).map_err(to_io_error)?;
+        self.pos += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+}
+
+impl Seek for ChainFile<'_> {
+
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len().map_err(to_io_error)?.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset)
+        };
+        self.pos = new_pos.ok_or(std::io::ErrorKind::InvalidInput)?;
+        Ok(self.pos)
+    }
+
+}
+
+#[test]
+fn chain_file() {
+    use crate::Config;
+
+    let mut file = File::open("chain_file.verter", Config::default()).unwrap();
+    let ptr = file.alloc().unwrap();
+    let mut db = file.chain_file(ptr).unwrap();
+    db.write_all(&[0xAB; 1000]).unwrap();
+    db.seek(SeekFrom::Start(500)).unwrap();
+    db.write_all(b"page").unwrap();
+    db.seek(SeekFrom::End(-4)).unwrap();
+    db.write_all(b"tail").unwrap();
+
+    let mut buf = [0; 8];
+    db.seek(SeekFrom::Start(498)).unwrap();
+    db.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, &[0xAB, 0xAB, b'p', b'a', b'g', b'e', 0xAB, 0xAB]);
+
+    db.set_len(1004).unwrap();
+    db.seek(SeekFrom::Start(996)).unwrap();
+    let mut rest = Vec::new();
+    db.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"tail\0\0\0\0");
+
+    db.set_len(10).unwrap();
+    assert_eq!(file.read(ptr).unwrap(), vec![0xAB; 10]);
+
+    std::fs::remove_file("chain_file.verter").unwrap();
+}
@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+
+use crate::dedup::content_hash;
+use crate::{decode_u64s, encode_u64s, Error, File, BYTES_IN_U64};
+
+/// The version of the token format written by `WeakPtr::encode`
+const TOKEN_VERSION: u8 = 1;
+/// The length of the checksum at the end of a token, in bytes
+const TOKEN_CHECKSUM_LEN: usize = 4;
+
+/// A reference to a chain that does not keep it alive.
+/// Upgrading it with `File::upgrade` only succeeds while the chain it was made from is still allocated,
+/// even if the chain's page has since been reused for another chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WeakPtr {
+    ptr: u64,
+    /// The number of times the chain's head page had been freed when the weak pointer was made
+    generation: u64
+}
+
+impl WeakPtr {
+
+    /// The pointer to the chain, which may no longer be allocated.
+    pub fn ptr(&self) -> u64 {
+        self.ptr
+    }
+
+    /// Serialize the weak pointer, so it can be stored inside a chain.
+    pub fn to_bytes(&self) -> [u8; 2 * BYTES_IN_U64 as usize] {
+        let mut bytes = [0; 2 * BYTES_IN_U64 as usize];
+        bytes[..8].copy_from_slice(&self.ptr.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.generation.to_le_bytes());
+        bytes
+    }
+
+    /// Deserialize a weak pointer produced by `WeakPtr::to_bytes`.
+    pub fn from_bytes(bytes: [u8; 2 * BYTES_IN_U64 as usize]) -> Self {
+        Self {
+            ptr: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            generation: u64::from_le_bytes(bytes[8..].try_into().unwrap())
+        }
+    }
+
+    /// Encode the weak pointer as a short hex token, for embedding in documents outside the file, such as copied clips or links between projects.
+    /// The token holds a format version, the pointer and its generation as variable-length integers, and a checksum,
+    /// so that `WeakPtr::decode` catches mangled tokens and `File::upgrade` catches tokens to chains that were deleted since.
+    pub fn encode(&self) -> String {
+        let mut bytes = vec![TOKEN_VERSION];
+        for mut value in [self.ptr, self.generation] {
+            while value >= 0x80 {
+                bytes.push(value as u8 | 0x80);
+                value >>= 7;
+            }
+            bytes.push(value as u8);
+        }
+        let checksum = content_hash(&bytes) as u32;
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Decode a token produced by `WeakPtr::encode`.
+    /// Fails with `Error::InvalidPointer` if the token is malformed or its checksum doesn't match,
+    /// and with `Error::UnsupportedVersion` if it was encoded by a newer version of verter.
+    pub fn decode(token: &str) -> Result<Self, Error> {
+        if !token.len().is_multiple_of(2) || !token.is_ascii() {
+            return Err(Error::InvalidPointer);
+        }
+        let bytes = (0..token.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&token[i..i + 2], 16).map_err(|_| Error::InvalidPointer))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (body, checksum) = bytes.split_at_checked(bytes.len().saturating_sub(TOKEN_CHECKSUM_LEN)).ok_or(Error::InvalidPointer)?;
+        if body.is_empty() || checksum.len() != TOKEN_CHECKSUM_LEN || (content_hash(body) as u32).to_le_bytes() != checksum {
+            return Err(Error::InvalidPointer);
+        }
+        if body[0] != TOKEN_VERSION {
+            return Err(Error::UnsupportedVersion);
+        }
+
+        let mut values = [0u64; 2];
+        let mut rest = &body[1..];
+        for value in &mut values {
+            let mut shift = 0;
+            loop {
+                let (byte, tail) = rest.split_first().ok_or(Error::InvalidPointer)?;
+                rest = tail;
+                if shift > 63 {
+                    return Err(Error::InvalidPointer);
+                }
+                *value |= ((byte & 0x7F) as u64) << shift;
+                shift += 7;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+        }
+        if !rest.is_empty() {
+            return Err(Error::InvalidPointer);
+        }
+        Ok(Self { ptr: values[0], generation: values[1] })
+    }
+
+}
+
+impl File {
+
+    /// Make a weak reference to a chain.
+    pub fn downgrade(&mut self, ptr: u64) -> Result<WeakPtr, Error> {
+        self.check_for_external_changes()?;
+        self.check_if_pointer_valid(ptr)?;
+        let mut table = self.read_generation_table()?;
+        if let Some(generation) = table.get(&ptr) {
+            return Ok(WeakPtr { ptr, generation: *generation });
+        }
+        table.insert(ptr, 0);
+        self.write_generation_table(&table)?;
+        Ok(WeakPtr { ptr, generation: 0 })
+    }
+
+    /// Get the pointer to the chain a weak reference refers to, or `None` if the chain was deleted.
+    pub fn upgrade(&mut self, weak: WeakPtr) -> Result<Option<u64>, Error> {
+        self.check_for_external_changes()?;
+        match self.check_if_pointer_valid(weak.ptr) {
+            Ok(()) => {},
+            Err(Error::DeletedPointer | Error::InvalidPointer) => return Ok(None),
+            Err(err) => return Err(err)
+        }
+        let generation = self.read_generation_table()?.get(&weak.ptr).copied().unwrap_or(0);
+        Ok((generation == weak.generation).then_some(weak.ptr))
+    }
+
+    /// Invalidate the weak references to a chain that is being deleted.
+    /// Only chains that have been downgraded are tracked, so the table stays small.
+    pub(crate) fn bump_generation(&mut self, ptr: u64) -> Result<(), Error> {
+        if self.read_u64(self.generation_table_ptr())? == 0 {
+            return Ok(());
+        }
+        let mut table = self.read_generation_table()?;
+        if let Some(generation) = table.get_mut(&ptr) {
+            *generation += 1;
+            self.write_generation_table(&table)?;
+        }
+        Ok(())
+    }
+
+    fn read_generation_table(&mut self) -> Result<BTreeMap<u64, u64>, Error> {
+        let chain = self.read_u64(self.generation_table_ptr())?;
+        if chain == 0 {
+            return Ok(BTreeMap::new());
+        }
+        let entries = decode_u64s(&self.read_chain(chain)?)?;
+        if !entries.len().is_multiple_of(2) {
+            return Err(Error::CorruptedFile);
+        }
+        Ok(entries.chunks_exact(2).map(|entry| (entry[0], entry[1])).collect())
+    }
+
+    fn write_generation_table(&mut self, table: &BTreeMap<u64, u64>) -> Result<(), Error> {
+        let mut chain = self.read_u64(self.generation_table_ptr())?;
+        if chain == 0 {
+            chain = self.alloc()?;
+            self.write_u64(self.generation_table_ptr(), chain)?;
+        }
+        let entries = table.iter().flat_map(|(ptr, generation)| [*ptr, *generation]).collect::<Vec<_>>();
+        self.write_chain(chain, &encode_u64s(&entries))
+    }
+
+}
+
+#[test]
+fn weak_ptr() {
+    use crate::Config;
+
+    let mut file = File::open("weak_ptr.verter", Config::default()).unwrap();
+    let thumbnail = file.alloc().unwrap();
+    let weak = file.downgrade(thumbnail).unwrap();
+    assert_eq!(file.upgrade(weak).unwrap(), Some(thumbnail));
+    drop(file);
+
+    let mut file = File::open("weak_ptr.verter", Config::default()).unwrap();
+    let weak = WeakPtr::from_bytes(weak.to_bytes());
+    file.delete(thumbnail).unwrap();
+    assert_eq!(file.upgrade(weak).unwrap(), None);
+
+    // The page gets reused by a different chain, which the weak pointer must not upgrade to
+    let other = file.alloc().unwrap();
+    assert_eq!(other, thumbnail);
+    assert_eq!(file.upgrade(weak).unwrap(), None);
+    let new_weak = file.downgrade(other).unwrap();
+    assert_eq!(file.upgrade(new_weak).unwrap(), Some(other));
+
+    std::fs::remove_file("weak_ptr.verter").unwrap();
+}
+
+#[test]
+fn weak_ptr_tokens() {
+    use crate::Config;
+
+    let mut file = File::open("weak_ptr_tokens.verter", Config::default()).unwrap();
+    let clip = file.alloc_with(b"clip").unwrap();
+    let token = file.downgrade(clip).unwrap().encode();
+    assert!(token.len() < 24);
+    let weak = WeakPtr::decode(&token).unwrap();
+    assert_eq!(file.upgrade(weak).unwrap(), Some(clip));
+
+    // Mangled tokens are caught by the checksum
+    let mut mangled = token.clone().into_bytes();
+    mangled[3] = if mangled[3] == b'0' { b'1' } else { b'0' };
+    for token in [String::from_utf8(mangled).unwrap(), token[..token.len() - 2].to_owned(), "not a token".to_owned(), String::new()] {
+        match WeakPtr::decode(&token) {
+            Err(Error::InvalidPointer) => {},
+            Ok(_) | Err(_) => panic!("should error with invalid pointer")
+        }
+    }
+
+    // Stale tokens no longer upgrade once the chain is deleted, even after its page is reused
+    file.delete(clip).unwrap();
+    file.alloc().unwrap();
+    assert_eq!(file.upgrade(WeakPtr::decode(&token).unwrap()).unwrap(), None);
+
+    std::fs::remove_file("weak_ptr_tokens.verter").unwrap();
+}
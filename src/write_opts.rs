@@ -0,0 +1,111 @@
+use crate::{ChainFormat, Error, File};
+
+/// Options for a single write with `File::write_with`, overriding the file's `Config`.
+/// Options left as `None` use the file's settings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WriteOpts {
+    /// Compress the data with zstd at this level, like `File::write_compressed`, so the chain must be read back with `File::read_compressed`.
+    /// Leave it as `None` to store data that is already compressed, such as PNGs, as is.
+    #[cfg(feature = "compression")]
+    pub compression: Option<i32>,
+    /// Whether to store a checksum of the chain's head page in its summary, overriding `ChainFormat::V2::head_checksum`.
+    /// Has no effect in `ChainFormat::V1`, where chains have no summary.
+    pub checksum: Option<bool>,
+    /// Overrides `Config::wipe_freed_bytes` for the pages the write frees
+    pub wipe_freed_bytes: Option<bool>,
+    /// Overrides `Config::check_free_space`
+    pub check_free_space: Option<bool>
+}
+
+/// How a write with `File::write_with_outcome` changed a chain, for keeping track of sizes without querying the chain again
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WriteOutcome {
+    /// The number of pages the chain took from the free list or the end of the file
+    pub pages_allocated: u64,
+    /// The number of pages the chain no longer needed and gave back to the free list
+    pub pages_freed: u64,
+    /// The number of bytes of data the chain holds after the write
+    pub final_len: u64
+}
+
+impl File {
+
+    /// Write data to a page chain like `File::write`, reporting how many pages the chain grew or shrunk by.
+    /// Chains in the cold tier aren't stored in the file's pages, so their writes never allocate or free any.
+    pub fn write_with_outcome(&mut self, ptr: u64, data: &[u8]) -> Result<WriteOutcome, Error> {
+        self.write_reporting(ptr, data)
+    }
+
+    /// Write data to a page chain with options overriding the file's defaults for this write only.
+    pub fn write_with(&mut self, ptr: u64, data: &[u8], opts: WriteOpts) -> Result<(), Error> {
+        let config = self.config;
+        if let (Some(checksum), ChainFormat::V2 { head_checksum }) = (opts.checksum, &mut self.config.chain_format) {
+            *head_checksum = checksum;
+        }
+        if let Some(wipe_freed_bytes) = opts.wipe_freed_bytes {
+            self.config.wipe_freed_bytes = wipe_freed_bytes;
+        }
+        if let Some(check_free_space) = opts.check_free_space {
+            self.config.check_free_space = check_free_space;
+        }
+
+        #[cfg(feature = "compression")]
+        let result = match opts.compression {
+            Some(level) => self.write_compressed_at(ptr, data, level),
+            None => self.write(ptr, data)
+        };
+        #[cfg(not(feature = "compression"))]
+        let result = self.write(ptr, data);
+
+        self.config = config;
+        result
+    }
+
+}
+
+#[test]
+fn write_with() {
+    use crate::Config;
+
+    let config = Config {
+        chain_format: ChainFormat::V2 { head_checksum: true },
+        ..Config::default()
+    };
+    let mut file = File::open("write_with.verter", config).unwrap();
+    let checked = file.alloc_with(b"checked").unwrap();
+    let unchecked = file.alloc().unwrap();
+    file.write_with(unchecked, b"unchecked", WriteOpts { checksum: Some(false), ..WriteOpts::default() }).unwrap();
+    assert!(file.chain_summary(checked).unwrap().head_checksum.is_some());
+    assert_eq!(file.chain_summary(unchecked).unwrap().head_checksum, None);
+    assert_eq!(file.read(unchecked).unwrap(), b"unchecked");
+
+    // The file's own settings apply again after the write
+    file.write(unchecked, b"checked now").unwrap();
+    assert!(file.chain_summary(unchecked).unwrap().head_checksum.is_some());
+
+    #[cfg(feature = "compression")]
+    {
+        let text = b"frame frame frame frame frame frame frame frame".repeat(10);
+        let compressed = file.alloc().unwrap();
+        file.write_with(compressed, &text, WriteOpts { compression: Some(19), ..WriteOpts::default() }).unwrap();
+        assert!(file.read(compressed).unwrap().len() < text.len());
+        assert_eq!(file.read_compressed(compressed).unwrap(), text);
+    }
+
+    std::fs::remove_file("write_with.verter").unwrap();
+}
+
+#[test]
+fn write_with_outcome() {
+    use crate::Config;
+
+    let mut file = File::open("write_with_outcome.verter", Config::default()).unwrap();
+    let layer = file.alloc().unwrap();
+    assert_eq!(file.write_with_outcome(layer, &[1; 300]).unwrap(), WriteOutcome { pages_allocated: 2, pages_freed: 0, final_len: 300 });
+    assert_eq!(file.write_with_outcome(layer, &[2; 250]).unwrap(), WriteOutcome { pages_allocated: 0, pages_freed: 0, final_len: 250 });
+    assert_eq!(file.write_with_outcome(layer, b"").unwrap(), WriteOutcome { pages_allocated: 0, pages_freed: 2, final_len: 0 });
+    assert_eq!(file.read(layer).unwrap(), b"");
+    file.validate().unwrap();
+
+    std::fs::remove_file("write_with_outcome.verter").unwrap();
+}